@@ -131,10 +131,12 @@ bool nnue_load(const char* filename, NNUENetwork* net) {
         (long)NNUE_OUTPUT_BUCKETS * 2 * NNUE_HIDDEN_SIZE * sizeof(int16_t) +
         (long)NNUE_OUTPUT_BUCKETS * sizeof(int16_t);
     long expected_size = expected_data_size + 48;
+    // Save format 2 appends "SMND", a u32 LE length and the description text
+    long trailer_size = file_size - expected_size;
     
     printf("info string NNUE file size: %ld bytes, expected: %ld bytes\n", file_size, expected_size);
     
-    if (file_size != expected_size) {
+    if (trailer_size < 0 || (trailer_size > 0 && trailer_size < 8)) {
         fprintf(stderr, "info string NNUE file size mismatch! Got %ld, expected %ld\n", file_size, expected_size);
         fclose(file);
         return false;
@@ -171,6 +173,31 @@ bool nnue_load(const char* filename, NNUENetwork* net) {
         return false;
     }
     
+    net->description[0] = '\0';
+    if (trailer_size > 0) {
+        unsigned char header[8];
+        fseek(file, expected_size, SEEK_SET);
+        if (fread(header, 1, 8, file) != 8 || memcmp(header, "SMND", 4) != 0) {
+            fclose(file);
+            fprintf(stderr, "info string NNUE file has %ld unexpected bytes after the weights\n", trailer_size);
+            return false;
+        }
+        long length = (long)header[4] | (long)header[5] << 8 | (long)header[6] << 16 | (long)header[7] << 24;
+        if (length != trailer_size - 8) {
+            fclose(file);
+            fprintf(stderr, "info string NNUE description length %ld does not match the file\n", length);
+            return false;
+        }
+        size_t keep = length < (long)sizeof(net->description) - 1 ? (size_t)length : sizeof(net->description) - 1;
+        if (fread(net->description, 1, keep, file) != keep) {
+            fclose(file);
+            fprintf(stderr, "info string Failed to read NNUE description\n");
+            return false;
+        }
+        net->description[keep] = '\0';
+        printf("info string NNUE net: %s\n", net->description);
+    }
+    
     fclose(file);
     net->loaded = true;
     printf("info string NNUE loaded successfully from %s\n", filename);
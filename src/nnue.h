@@ -59,6 +59,9 @@ typedef struct {
     // Output layer biases: [OUTPUT_BUCKETS]
     alignas(64) int16_t output_biases[NNUE_OUTPUT_BUCKETS];
 
+    // Description stored after the weights by the trainer (save format 2), "" if none
+    char description[256];
+
     bool loaded;
 } NNUENetwork;
 
@@ -278,6 +278,9 @@ void uci_loop() {
             printf("option name LMR_StatHigh2 type spin default 14621 min 0 max 49000\n");
             printf("option name SyzygyPath type string default <empty>\n");
             printf("option name SyzygyProbeLimit type spin default 7 min 0 max 7\n");
+            if (nnue_network->loaded && nnue_network->description[0] != '\0') {
+                printf("info string NNUE net: %s\n", nnue_network->description);
+            }
             printf("uciok\n");
             fflush(stdout);
         } else if (strcmp(line, "isready") == 0) {
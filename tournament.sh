@@ -118,7 +118,12 @@ case "$MODE" in
 
     ENGINE_ARGS=""
 
-    for checkpoint in "$CHECKPOINTS_DIR"/*; do
+    # Checkpoints liegen in checkpoints/<run>/ (oder flach mit --flat-output)
+    for checkpoint in "$CHECKPOINTS_DIR"/* "$CHECKPOINTS_DIR"/*/*; do
+        # Run-Verzeichnis: seine Checkpoints kommen über das zweite Muster
+        if compgen -G "$checkpoint/*/quantised.bin" > /dev/null || [ "$(basename "$checkpoint")" = optimiser_state ]; then
+            continue
+        fi
         if [ -d "$checkpoint" ]; then
             checkpoint_name=$(basename "$checkpoint")
             target_dir="$ARCHIVE_DIR/$checkpoint_name"
@@ -0,0 +1,98 @@
+//! `--dump-activations`: the side to move's hidden layer for a sample of
+//! positions, as a matrix for analysis outside the trainer (e.g. numpy).
+//!
+//! File layout: magic `SMAC`, u32 LE rows, u32 LE columns, then rows x
+//! columns f32 LE values in row-major order, one row per position. In numpy:
+//! `np.fromfile(path, np.float32, offset=12).reshape(rows, cols)`.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+pub const MAGIC: &[u8; 4] = b"SMAC";
+/// Bytes before the first value.
+pub const HEADER_BYTES: usize = 12;
+/// Positions dumped when `--dump-count` is not given.
+pub const DEFAULT_COUNT: usize = 1024;
+
+/// Writes `rows`, which must all have `columns` values.
+pub fn write(path: &str, columns: usize, rows: &[Vec<f32>]) -> io::Result<()> {
+    if let Some(row) = rows.iter().find(|row| row.len() != columns) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("activation row has {} values, expected {}", row.len(), columns),
+        ));
+    }
+    let dim = |n: usize| u32::try_from(n).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "matrix too large"));
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&dim(rows.len())?.to_le_bytes())?;
+    out.write_all(&dim(columns)?.to_le_bytes())?;
+    for value in rows.iter().flatten() {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        inference::QuantisedNet,
+        net::{NUM_INPUT_BUCKETS, NetShape, QA},
+        test_util::{STARTPOS, board, temp_path},
+    };
+
+    /// Three hidden neurons: always on, counting the side to move's pawns
+    /// (2.5 of them fill it), and always off.
+    fn handcrafted_net() -> QuantisedNet {
+        let shape = NetShape {
+            hl_size: 3,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: 1,
+            single_perspective: true,
+            output_factoriser: false,
+        };
+        let mut values = vec![0i16; 768 * NUM_INPUT_BUCKETS * 3];
+        for feature in 0..768 * NUM_INPUT_BUCKETS {
+            let (colour, piece) = ((feature % 768) / 384, (feature % 384) / 64);
+            if (colour, piece) == (0, 0) {
+                values[feature * 3 + 1] = 102;
+            }
+        }
+        values.extend([QA, 0, -5, 0, 0, 0, 0]);
+        QuantisedNet::from_values(shape, &values, QA).unwrap()
+    }
+
+    #[test]
+    fn dumps_a_row_of_hidden_activations_per_position() {
+        let net = handcrafted_net();
+        let boards = [board(STARTPOS, 0, "0.5"), board("4k3/8/8/8/8/8/PP6/4K3 w - - 0 1", 0, "0.5")];
+        let rows: Vec<Vec<f32>> = boards.iter().map(|board| net.stm_activations(board)).collect();
+        let two_pawns = 204.0 / f32::from(QA);
+        assert_eq!(rows, [vec![1.0, 1.0, 0.0], vec![1.0, two_pawns * two_pawns, 0.0]]);
+
+        let path = temp_path("activations.bin");
+        write(path.to_str().unwrap(), net.shape.hl_size, &rows).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER_BYTES + 2 * 3 * 4);
+        assert_eq!(&bytes[..4], MAGIC);
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!((u32_at(4), u32_at(8)), (2, 3));
+        let values: Vec<f32> =
+            bytes[HEADER_BYTES..].chunks(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(values, rows.concat());
+    }
+
+    #[test]
+    fn rows_of_the_wrong_width_are_rejected() {
+        let path = temp_path("activations-ragged.bin");
+        let error = write(path.to_str().unwrap(), 3, &[vec![0.0; 3], vec![0.0; 2]]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,39 @@
+//! Thread pinning for `--pin-threads`.
+
+/// Cores for `threads` pinned threads, counting down from the last core so
+/// they stay clear of core 0, where the OS tends to put interrupts. Wraps
+/// around when there are more threads than cores.
+pub fn assign_cores(num_cores: usize, threads: usize) -> Vec<usize> {
+    if num_cores == 0 {
+        return Vec::new();
+    }
+    (0..threads).map(|i| num_cores - 1 - i % num_cores).collect()
+}
+
+/// Number of cores the current thread may be pinned to, if the platform
+/// supports affinity at all.
+pub fn available_cores() -> Option<usize> {
+    core_affinity::get_core_ids().map(|ids| ids.len()).filter(|&n| n > 0)
+}
+
+/// Pins the calling thread to the `core`th available core.
+pub fn pin_current(core: usize) -> bool {
+    core_affinity::get_core_ids().and_then(|ids| ids.get(core).copied()).is_some_and(core_affinity::set_for_current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigns_cores_from_the_last_one_down() {
+        assert_eq!(assign_cores(8, 3), vec![7, 6, 5]);
+        assert_eq!(assign_cores(8, 1), vec![7]);
+    }
+
+    #[test]
+    fn wraps_around_with_more_threads_than_cores() {
+        assert_eq!(assign_cores(2, 5), vec![1, 0, 1, 0, 1]);
+        assert_eq!(assign_cores(0, 2), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,62 @@
+//! Simple named-tensor file format for float weights the trainer keeps
+//! outside of bullet's optimiser state (e.g. the EMA shadow).
+//!
+//! Layout (little endian): magic `SMWT`, u32 tensor count, then per tensor a
+//! u16 id length, the id bytes, a u32 value count and the f32 values.
+
+use std::{fs, io, io::Read, path::Path};
+
+const MAGIC: &[u8; 4] = b"SMWT";
+
+/// Writes through a temp file next to `path`, so a crash never leaves a
+/// truncated archive behind.
+pub fn write(path: impl AsRef<Path>, tensors: &[(String, Vec<f32>)]) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&(tensors.len() as u32).to_le_bytes());
+    for (id, values) in tensors {
+        bytes.extend_from_slice(&(id.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(id.as_bytes());
+        bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        bytes.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+    }
+    let path = path.as_ref();
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+/// Whether the file at `path` starts with the archive magic.
+pub fn is_archive(path: impl AsRef<Path>) -> io::Result<bool> {
+    let mut magic = [0u8; 4];
+    match fs::File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<(String, Vec<f32>)>> {
+    let bytes = fs::read(path)?;
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    if bytes.get(..4) != Some(MAGIC) {
+        return Err(invalid("not a tensor archive"));
+    }
+
+    let mut pos = 4;
+    let mut take = |n: usize| -> io::Result<&[u8]> {
+        let slice = bytes.get(pos..pos + n).ok_or_else(|| invalid("truncated tensor archive"))?;
+        pos += n;
+        Ok(slice)
+    };
+    let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut tensors = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let id_len = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        let id = String::from_utf8(take(id_len.into())?.to_vec()).map_err(|_| invalid("bad tensor id"))?;
+        let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let values = take(4 * len)?.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect();
+        tensors.push((id, values));
+    }
+    Ok(tensors)
+}
@@ -0,0 +1,104 @@
+//! The bullet backend this binary was built with, and whether it can run on
+//! this machine. The backend is a compile-time choice (the `hip` feature);
+//! `--no-default-features` builds bullet's CPU backend.
+
+use std::path::Path;
+
+pub const NAME: &str = if cfg!(feature = "hip") { "hip" } else { "cpu" };
+pub const IS_GPU: bool = cfg!(feature = "hip");
+
+/// Rough cost of the CPU backend against a GPU, for the warning.
+pub const CPU_SLOWDOWN: &str = "one to two orders of magnitude slower than on a GPU";
+
+pub const CPU_BUILD: &str = "cargo build --release --no-default-features";
+
+/// Why this run would not train on a GPU, for `--require-gpu`: a CPU build,
+/// or a GPU build without a usable device.
+pub fn gpu_missing() -> Option<String> {
+    gpu_missing_for(IS_GPU, unavailable())
+}
+
+fn gpu_missing_for(is_gpu: bool, unavailable: Option<String>) -> Option<String> {
+    if !is_gpu {
+        return Some("this binary uses the cpu backend (built with --no-default-features)".to_string());
+    }
+    unavailable
+}
+
+/// Why the compiled GPU backend cannot run here, checked up front because
+/// bullet only reports a missing device as a backend panic.
+pub fn unavailable() -> Option<String> {
+    if !IS_GPU {
+        return None;
+    }
+    // ROCm exposes every usable device through the kernel fusion driver
+    if cfg!(target_os = "linux") && !Path::new("/dev/kfd").exists() {
+        return Some("no ROCm device found (/dev/kfd is missing)".to_string());
+    }
+    None
+}
+
+/// The backend a run trains on, or why it cannot: `--cpu` needs a CPU build,
+/// `--require-gpu` a GPU and a GPU build a usable device.
+pub fn select(cpu: bool, require_gpu: bool) -> Result<&'static str, String> {
+    select_for(IS_GPU, unavailable(), cpu, require_gpu)
+}
+
+/// [`select`] for a build with or without the GPU backend, whose device check
+/// found `unavailable`.
+fn select_for(is_gpu: bool, unavailable: Option<String>, cpu: bool, require_gpu: bool) -> Result<&'static str, String> {
+    let name = if is_gpu { "hip" } else { "cpu" };
+    if cpu && is_gpu {
+        return Err(format!(
+            "--cpu: this binary uses the {} backend; rebuild with `{}` for the CPU backend",
+            name, CPU_BUILD
+        ));
+    }
+    if require_gpu {
+        if let Some(reason) = gpu_missing_for(is_gpu, unavailable.clone()) {
+            return Err(format!("--require-gpu: {}", reason));
+        }
+    }
+    if let Some(reason) = unavailable.filter(|_| is_gpu) {
+        return Err(format!(
+            "{}, but this binary uses the {} backend. For a CPU run, rebuild with `{}` (expect it to be {})",
+            reason, name, CPU_BUILD, CPU_SLOWDOWN
+        ));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_selects_the_cpu_backend_of_a_cpu_build() {
+        match select(true, false) {
+            Ok(name) => assert!(!IS_GPU && name == "cpu"),
+            Err(e) => assert!(IS_GPU && e.contains(CPU_BUILD), "{}", e),
+        }
+    }
+
+    #[test]
+    fn a_cpu_build_never_passes_require_gpu() {
+        assert_eq!(select_for(false, None, false, false), Ok("cpu"));
+        assert_eq!(select_for(false, None, true, false), Ok("cpu"));
+        assert_eq!(
+            select_for(false, None, false, true),
+            Err("--require-gpu: this binary uses the cpu backend (built with --no-default-features)".to_string())
+        );
+    }
+
+    #[test]
+    fn require_gpu_fails_without_a_device() {
+        let missing = || Some("no ROCm device found (/dev/kfd is missing)".to_string());
+        assert_eq!(
+            select_for(true, missing(), false, true),
+            Err("--require-gpu: no ROCm device found (/dev/kfd is missing)".to_string())
+        );
+        // without the flag the missing device is still an error, with a hint
+        assert!(select_for(true, missing(), false, false).unwrap_err().contains(CPU_BUILD));
+        assert_eq!(select_for(true, None, false, true), Ok("hip"));
+    }
+}
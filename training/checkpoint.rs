@@ -0,0 +1,533 @@
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use crate::{info, manifest, net, resume, verbose};
+
+/// Per-checkpoint metadata written next to the weights, e.g. the val loss.
+pub const CHECKPOINT_METADATA: &str = "checkpoint.meta";
+
+/// Rough on-disk size of one checkpoint: raw weights plus the two AdamW
+/// moment buffers and the weights copy in `optimiser_state`, plus the i16 net.
+pub fn estimate_checkpoint_bytes(
+    hl_size: usize,
+    input_buckets: usize,
+    output_buckets: usize,
+    single_perspective: bool,
+) -> u64 {
+    let l1_inputs = if single_perspective { hl_size } else { 2 * hl_size };
+    let params = 768 * input_buckets * hl_size + 768 * hl_size + hl_size + l1_inputs * output_buckets + output_buckets;
+    let quantised = 768 * input_buckets * hl_size + hl_size + l1_inputs * output_buckets + output_buckets;
+    (params * 4 * 4 + quantised * 2) as u64
+}
+
+pub fn has_room_for_save(available: u64, checkpoint_bytes: u64, min_free_bytes: u64) -> bool {
+    available >= checkpoint_bytes.saturating_add(min_free_bytes)
+}
+
+/// `<net_id>-latest` points at the newest checkpoint of a run. Pointers are
+/// to checkpoint directories, so `<net_id>-latest/quantised.bin` is the
+/// newest engine net and `<net_id>-latest/optimiser_state/weights.bin` what
+/// `--load` resumes from.
+pub const LATEST: &str = "latest";
+/// `<net_id>-best` points at the saved checkpoint with the lowest val loss.
+pub const BEST: &str = "best";
+
+/// Points `<output_dir>/<net_id>-<pointer>` at the checkpoint directory
+/// `name`, so consumers have a stable path to e.g. the newest net. On unix
+/// this is a relative symlink swapped in atomically; elsewhere the files are
+/// copied.
+pub fn update_pointer(output_dir: &str, net_id: &str, pointer: &str, name: &str) -> io::Result<()> {
+    let link = Path::new(output_dir).join(format!("{}-{}", net_id, pointer));
+
+    #[cfg(unix)]
+    {
+        let tmp = Path::new(output_dir).join(format!(".{}-{}.tmp", net_id, pointer));
+        let _ = fs::remove_file(&tmp);
+        std::os::unix::fs::symlink(name, &tmp)?;
+        if fs::symlink_metadata(&link).is_ok_and(|meta| !meta.file_type().is_symlink()) {
+            fs::remove_dir_all(&link)?;
+        }
+        fs::rename(&tmp, &link)
+    }
+
+    #[cfg(not(unix))]
+    {
+        if link.exists() {
+            fs::remove_dir_all(&link)?;
+        }
+        fs::create_dir_all(&link)?;
+        for entry in fs::read_dir(Path::new(output_dir).join(name))? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), link.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a checkpoint saved with `val_loss` beats the `best` one so far,
+/// which it then becomes.
+pub fn is_new_best(best: &mut Option<f32>, val_loss: Option<f32>) -> bool {
+    match val_loss {
+        Some(loss) if best.is_none_or(|best| loss < best) => {
+            *best = Some(loss);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// `--snapshot-on-best`: calls `write` with `val_loss` only when it beats
+/// `best`, and only a snapshot that was written becomes the new best, so a
+/// failed one is retried at the next loss as low.
+pub fn snapshot_if_best<E>(
+    best: &mut Option<f32>,
+    val_loss: Option<f32>,
+    write: impl FnOnce(f32) -> Result<(), E>,
+) -> Option<(f32, Result<(), E>)> {
+    let loss = val_loss.filter(|&loss| best.is_none_or(|best| loss < best))?;
+    let result = write(loss);
+    if result.is_ok() {
+        *best = Some(loss);
+    }
+    Some((loss, result))
+}
+
+/// Superbatches of the checkpoints saved for `net_id` in `output_dir`, as
+/// `<net_id>-<superbatch>` directories (or `.wgts` files), sorted.
+pub fn existing_checkpoints(output_dir: &str, net_id: &str) -> io::Result<Vec<usize>> {
+    let entries = match fs::read_dir(output_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut found = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name();
+        found.extend(name.to_str().and_then(|name| checkpoint_superbatch(name, net_id)));
+    }
+    found.sort_unstable();
+    found.dedup();
+    Ok(found)
+}
+
+/// Superbatch of a `<net_id>-<superbatch>` directory or `.wgts` file name.
+pub fn checkpoint_superbatch(name: &str, net_id: &str) -> Option<usize> {
+    name.strip_prefix(net_id)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .map(|rest| rest.strip_suffix(".wgts").unwrap_or(rest))
+        .and_then(|n| n.parse::<usize>().ok())
+}
+
+/// One `--list-checkpoints` row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointInfo {
+    pub superbatch: usize,
+    pub path: PathBuf,
+    /// Total size, including everything under a checkpoint directory.
+    pub bytes: u64,
+    /// Last modification as Unix seconds, if the filesystem reports it.
+    pub modified: Option<u64>,
+    /// From the checkpoint's [`CHECKPOINT_METADATA`], if the run measured one.
+    pub val_loss: Option<f32>,
+}
+
+/// The checkpoints of `net_id` in `output_dir`, sorted by superbatch. The
+/// `-latest` and `-best` links are not: their names have no superbatch.
+pub fn list_checkpoints(output_dir: &str, net_id: &str) -> io::Result<Vec<CheckpointInfo>> {
+    let entries = match fs::read_dir(output_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut found = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(superbatch) = entry.file_name().to_str().and_then(|name| checkpoint_superbatch(name, net_id)) else {
+            continue;
+        };
+        let path = entry.path();
+        let modified =
+            entry.metadata()?.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+        let val_loss = fs::read_to_string(path.join(CHECKPOINT_METADATA))
+            .ok()
+            .and_then(|text| resume::parse_metadata(&text).get("val_loss")?.parse().ok());
+        found.push(CheckpointInfo { superbatch, bytes: size_on_disk(&path)?, path, modified, val_loss });
+    }
+    found.sort_by_key(|c| c.superbatch);
+    Ok(found)
+}
+
+fn size_on_disk(path: &Path) -> io::Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    fs::read_dir(path)?.try_fold(0, |total, entry| Ok(total + size_on_disk(&entry?.path())?))
+}
+
+/// The `--list-checkpoints` table.
+pub struct CheckpointTable<'a>(pub &'a [CheckpointInfo]);
+
+impl fmt::Display for CheckpointTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>10} {:>11}  {:<16}  {:>9}  path", "superbatch", "size", "modified (UTC)", "val loss")?;
+        for c in self.0 {
+            let modified = c.modified.map_or("-".to_string(), |secs| {
+                let (year, month, day) = net::utc_date(secs);
+                format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, secs / 3600 % 24, secs / 60 % 60)
+            });
+            let val_loss = c.val_loss.map_or("-".to_string(), |loss| format!("{:.6}", loss));
+            writeln!(
+                f,
+                "{:>10} {:>8.1} MB  {:<16}  {:>9}  {}",
+                c.superbatch,
+                mb(c.bytes),
+                modified,
+                val_loss,
+                c.path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Downloads a checkpoint to a temp file and returns its path, to be loaded
+/// like a local file. If the server publishes `<url>.sha256` (as
+/// `sha256sum` writes it), the download must match it. The temp file is
+/// removed again on any failure, so a failed download leaves nothing behind.
+pub fn download_weights(url: &str) -> Result<PathBuf, String> {
+    static DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+    let n = DOWNLOADS.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("sleepmind-{}-{}.wgts", std::process::id(), n));
+    download_checked(url, &path).map(|()| path)
+}
+
+/// [`download_weights`] into `path`.
+fn download_checked(url: &str, path: &Path) -> Result<(), String> {
+    let result = download(url, path).and_then(|written| {
+        if written == 0 {
+            return Err("not a valid checkpoint (0 bytes)".to_string());
+        }
+        match published_checksum(url)? {
+            Some(expected) => {
+                let actual = manifest::sha256_file(path).map_err(|e| e.to_string())?;
+                if actual != expected {
+                    return Err(format!("checksum mismatch: {}.sha256 has {}, the download {}", url, expected, actual));
+                }
+                info!("Checksum:      matches {}.sha256", url);
+            }
+            None => verbose!("No {}.sha256 published, download not checksummed", url),
+        }
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// The run metadata next to the checkpoint at `url`, looked up as
+/// [`resume::previous_metadata`] does for a local one.
+pub fn fetch_metadata(url: &str, run_name: &str) -> Result<HashMap<String, String>, String> {
+    let dir = url.rsplit_once('/').map_or(url, |(dir, _)| dir);
+    let checkpoint = dir.strip_suffix("/optimiser_state").unwrap_or(dir);
+    let run_dir = checkpoint.rsplit_once('/').map_or(checkpoint, |(dir, _)| dir);
+    let meta_url = format!("{}/{}.meta", run_dir, run_name);
+    let text = get(&meta_url)?.into_string().map_err(|e| format!("{}: {}", meta_url, e))?;
+    Ok(resume::parse_metadata(&text))
+}
+
+fn get(url: &str) -> Result<ureq::Response, String> {
+    match ureq::get(url).call() {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(code, response)) => Err(format!("{}: HTTP {} {}", url, code, response.status_text())),
+        Err(e) => Err(format!("{}: {}", url, e)),
+    }
+}
+
+/// The hash of `<url>.sha256`, or `None` if the server has no such file.
+fn published_checksum(url: &str) -> Result<Option<String>, String> {
+    let sidecar = format!("{}.sha256", url);
+    let text = match ureq::get(&sidecar).call() {
+        Ok(response) => response.into_string().map_err(|e| format!("{}: {}", sidecar, e))?,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(e) => return Err(format!("{}: {}", sidecar, e)),
+    };
+    match text.split_whitespace().next() {
+        Some(hash) if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Ok(Some(hash.to_ascii_lowercase()))
+        }
+        _ => Err(format!("{} does not start with a sha256 hash", sidecar)),
+    }
+}
+
+/// Streams `url` into `path` and returns the bytes written, reporting
+/// progress every tenth of the download (every 64 MB without a length).
+fn download(url: &str, path: &Path) -> Result<u64, String> {
+    let response = get(url)?;
+    let total: Option<u64> = response.header("Content-Length").and_then(|v| v.parse().ok());
+    let step = total.map_or(64 << 20, |total| (total / 10).max(1));
+
+    let mut file = fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut reader = response.into_reader();
+    let mut buf = vec![0u8; 1 << 20];
+    let mut written: u64 = 0;
+    loop {
+        let n = reader.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        if (written + n as u64) / step > written / step {
+            match total {
+                Some(total) => info!("  {:.1} / {:.1} MB", mb(written + n as u64), mb(total)),
+                None => info!("  {:.1} MB", mb(written + n as u64)),
+            }
+        }
+        written += n as u64;
+    }
+
+    if let Some(total) = total {
+        if written != total {
+            return Err(format!("truncated download: got {} of {} bytes", written, total));
+        }
+    }
+    Ok(written)
+}
+
+pub fn mb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    /// Answers `GET <path>` with `(status, body)`, every other path with a
+    /// 404, and returns the server's base URL. `length` overrides the
+    /// Content-Length, to cut a download short.
+    fn serve(routes: Vec<(&'static str, u16, Vec<u8>, Option<usize>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body, length) = match routes.iter().find(|route| route.0 == path) {
+                    Some((_, status, body, length)) => (*status, body.as_slice(), length.unwrap_or(body.len())),
+                    None => (404, &b"not found"[..], 9),
+                };
+                let head = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, length);
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        url
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("sleepmind-test-{}-{}.wgts", name, std::process::id()))
+    }
+
+    #[test]
+    fn saves_only_with_room_for_the_checkpoint_and_the_reserve() {
+        let checkpoint = estimate_checkpoint_bytes(768, 10, 8, false);
+        let reserve = 1024 * 1024 * 1024;
+        assert!(has_room_for_save(checkpoint + reserve, checkpoint, reserve));
+        assert!(!has_room_for_save(checkpoint + reserve - 1, checkpoint, reserve));
+        assert!(has_room_for_save(checkpoint, checkpoint, 0));
+        assert!(!has_room_for_save(u64::MAX - 1, u64::MAX, 1));
+    }
+
+    #[test]
+    fn estimate_grows_with_the_second_accumulator() {
+        assert!(estimate_checkpoint_bytes(768, 10, 8, false) > estimate_checkpoint_bytes(768, 10, 8, true));
+    }
+
+    #[test]
+    fn pointers_follow_the_newest_and_the_best_save() {
+        let dir = env::temp_dir().join(format!("sleepmind-test-{}-pointers", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let output = dir.to_str().unwrap();
+        let mut best = None;
+        for (superbatch, val_loss) in [(1, 0.5), (2, 0.3), (3, 0.4)] {
+            let name = format!("tiny-{}", superbatch);
+            fs::create_dir_all(dir.join(&name)).unwrap();
+            fs::write(dir.join(&name).join("quantised.bin"), [superbatch as u8]).unwrap();
+            update_pointer(output, "tiny", LATEST, &name).unwrap();
+            if is_new_best(&mut best, Some(val_loss)) {
+                update_pointer(output, "tiny", BEST, &name).unwrap();
+            }
+        }
+        assert_eq!(fs::read(dir.join("tiny-latest/quantised.bin")).unwrap(), [3]);
+        assert_eq!(fs::read(dir.join("tiny-best/quantised.bin")).unwrap(), [2]);
+        assert_eq!(list_checkpoints(output, "tiny").unwrap().len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_save_without_a_val_loss_is_never_the_best() {
+        let mut best = None;
+        assert!(!is_new_best(&mut best, None));
+        assert!(is_new_best(&mut best, Some(0.2)));
+        assert!(!is_new_best(&mut best, Some(0.2)));
+        assert!(!is_new_best(&mut best, None));
+        assert_eq!(best, Some(0.2));
+    }
+
+    #[test]
+    fn snapshots_only_when_the_val_loss_improves() {
+        let mut best = None;
+        let mut written = Vec::new();
+        for (superbatch, val_loss) in [0.5, 0.6, 0.4, 0.4, 0.45, 0.3].into_iter().enumerate() {
+            let snapshot = snapshot_if_best(&mut best, Some(val_loss), |loss| {
+                written.push((superbatch + 1, loss));
+                Ok::<(), String>(())
+            });
+            assert_eq!(snapshot.is_some(), written.last() == Some(&(superbatch + 1, val_loss)));
+        }
+        assert_eq!(written, [(1, 0.5), (3, 0.4), (6, 0.3)]);
+        // superbatches without a val loss never snapshot
+        assert!(
+            snapshot_if_best(&mut best, None, |_| -> Result<(), String> { panic!("written without a loss") }).is_none()
+        );
+    }
+
+    #[test]
+    fn a_failed_snapshot_is_not_the_best() {
+        let mut best = Some(0.5);
+        assert_eq!(snapshot_if_best(&mut best, Some(0.4), |_| Err("disk full")), Some((0.4, Err("disk full"))));
+        assert_eq!(best, Some(0.5));
+        assert_eq!(snapshot_if_best(&mut best, Some(0.4), |_| Ok::<(), &str>(())), Some((0.4, Ok(()))));
+        assert_eq!(best, Some(0.4));
+    }
+
+    #[test]
+    fn finds_existing_checkpoints_of_the_net_only() {
+        let dir = env::temp_dir().join(format!("sleepmind-test-{}-existing", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for name in ["tiny-10", "tiny-2", "tiny-latest", "tinier-3", "other-4"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("tiny-7.wgts"), b"").unwrap();
+        fs::write(dir.join("tiny.meta"), b"").unwrap();
+        assert_eq!(existing_checkpoints(dir.to_str().unwrap(), "tiny").unwrap(), vec![2, 7, 10]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(existing_checkpoints(dir.to_str().unwrap(), "tiny").unwrap(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn parses_the_superbatch_of_a_checkpoint_name() {
+        assert_eq!(checkpoint_superbatch("tiny-10", "tiny"), Some(10));
+        assert_eq!(checkpoint_superbatch("tiny-7.wgts", "tiny"), Some(7));
+        for name in ["tiny-latest", "tiny-best", "tiny", "tiny.meta", "tinier-3", "tiny-3.bin", "other-4"] {
+            assert_eq!(checkpoint_superbatch(name, "tiny"), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn lists_checkpoints_by_superbatch_with_their_val_loss() {
+        let dir = env::temp_dir().join(format!("sleepmind-test-{}-listing", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, val_loss) in
+            [("tiny-20", Some("0.031")), ("tiny-3", None), ("tiny-100", Some("0.029")), ("tinier-5", None)]
+        {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join("quantised.bin"), [0; 10]).unwrap();
+            if let Some(loss) = val_loss {
+                fs::write(dir.join(name).join(CHECKPOINT_METADATA), format!("superbatch=1\nval_loss={}\n", loss))
+                    .unwrap();
+            }
+        }
+        update_pointer(dir.to_str().unwrap(), "tiny", LATEST, "tiny-100").unwrap();
+        let listed = list_checkpoints(dir.to_str().unwrap(), "tiny").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let rows: Vec<(usize, Option<f32>)> = listed.iter().map(|c| (c.superbatch, c.val_loss)).collect();
+        assert_eq!(rows, [(3, None), (20, Some(0.031)), (100, Some(0.029))]);
+        assert_eq!(listed[0].bytes, 10);
+        assert!(listed[0].path.ends_with("tiny-3") && listed.iter().all(|c| c.modified.is_some()));
+
+        let table = CheckpointTable(&listed).to_string();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.lines().nth(2).unwrap().contains("  0.031000  "), "{}", table);
+    }
+
+    const BODY_SHA256: &str = "8a008a5fca6cac16762abfcc2641c6cdcf82478406871e00f7e86d78884c4192";
+
+    #[test]
+    fn downloads_a_checkpoint() {
+        let url = serve(vec![("/net.wgts", 200, vec![7; 256], None)]);
+        let path = download_weights(&format!("{}/net.wgts", url)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![7; 256]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn checks_a_published_checksum() {
+        let sidecar = format!("{}  net.wgts\n", BODY_SHA256).into_bytes();
+        let url = serve(vec![("/net.wgts", 200, vec![7; 256], None), ("/net.wgts.sha256", 200, sidecar, None)]);
+        let path = scratch("checksum");
+        download_checked(&format!("{}/net.wgts", url), &path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let url =
+            serve(vec![("/net.wgts", 200, vec![8; 256], None), ("/net.wgts.sha256", 200, BODY_SHA256.into(), None)]);
+        let path = scratch("mismatch");
+        let error = download_checked(&format!("{}/net.wgts", url), &path).unwrap_err();
+        assert!(error.contains("checksum mismatch"), "{}", error);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn reports_the_http_status_and_leaves_no_file() {
+        let url = serve(vec![("/forbidden.wgts", 403, b"no".to_vec(), None)]);
+        for (name, expected) in [("forbidden", "HTTP 403"), ("missing", "HTTP 404")] {
+            let path = scratch(name);
+            let error = download_checked(&format!("{}/{}.wgts", url, name), &path).unwrap_err();
+            assert!(error.contains(expected), "{}", error);
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn rejects_a_truncated_download() {
+        let url = serve(vec![("/net.wgts", 200, vec![7; 100], Some(256))]);
+        let path = scratch("truncated");
+        assert!(download_checked(&format!("{}/net.wgts", url), &path).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn fetches_the_run_metadata_next_to_a_checkpoint() {
+        let url = serve(vec![("/runs/tiny/tiny.meta", 200, b"hl_size=768\n".to_vec(), None)]);
+        let metadata =
+            fetch_metadata(&format!("{}/runs/tiny/tiny-10/optimiser_state/weights.bin", url), "tiny").unwrap();
+        assert_eq!(metadata["hl_size"], "768");
+    }
+}
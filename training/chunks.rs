@@ -0,0 +1,217 @@
+//! `--chunk-superbatches`: save and verify a checkpoint every N superbatches,
+//! so a preempted run continues by itself when the same command is rerun.
+//!
+//! Training writes a sentinel `<run>.running` next to the run metadata and
+//! removes it when the run ends; finding one at startup means the previous
+//! process was killed. Each chunk checkpoint that passes [`verify`] is recorded
+//! in `<run>.chunk`, and a restart after a preemption loads it and picks up at
+//! the next superbatch.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{checkpoint, resume};
+
+/// Bullet's quantised net inside a checkpoint directory.
+const QUANTISED: &str = "quantised.bin";
+
+/// The last checkpoint that passed [`verify`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkState {
+    pub superbatch: usize,
+    pub checkpoint: PathBuf,
+}
+
+impl ChunkState {
+    /// The `--load` path a resume uses, as `--recover-on-divergence` does.
+    pub fn weights(&self) -> PathBuf {
+        self.checkpoint.join("optimiser_state").join("weights.bin")
+    }
+}
+
+/// What to do with a run that has `--chunk-superbatches`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Startup {
+    /// No sentinel: a new run, or the previous one ended.
+    Fresh,
+    /// The sentinel is this process's own: a `--recover-on-divergence`
+    /// restart, which keeps the pid and brings its own `--load`.
+    Restarted,
+    /// Preempted before the first chunk was verified, so it starts over.
+    PreemptedBeforeChunk,
+    /// Preempted; resume after the recorded chunk.
+    Preempted(ChunkState),
+}
+
+/// Whether `superbatch` ends a chunk. The last superbatch is bullet's final
+/// save and ends the run, so it never needs resuming from.
+pub fn is_chunk_boundary(superbatch: usize, chunk: usize, end: usize) -> bool {
+    superbatch.is_multiple_of(chunk) && superbatch < end
+}
+
+/// Decides from the sentinel's pid, if there is a sentinel, and the recorded
+/// chunk how the run starts.
+pub fn startup(sentinel_pid: Option<u32>, own_pid: u32, state: Option<ChunkState>) -> Startup {
+    match (sentinel_pid, state) {
+        (None, _) => Startup::Fresh,
+        (Some(pid), _) if pid == own_pid => Startup::Restarted,
+        (Some(_), None) => Startup::PreemptedBeforeChunk,
+        (Some(_), Some(state)) => Startup::Preempted(state),
+    }
+}
+
+pub fn sentinel_path(output_dir: &str, run_name: &str) -> PathBuf {
+    Path::new(output_dir).join(format!("{}.running", run_name))
+}
+
+pub fn state_path(output_dir: &str, run_name: &str) -> PathBuf {
+    Path::new(output_dir).join(format!("{}.chunk", run_name))
+}
+
+/// [`startup`] for the files in `output_dir`. A sentinel that cannot be
+/// parsed still counts as a preemption.
+pub fn detect(output_dir: &str, run_name: &str) -> io::Result<Startup> {
+    let sentinel = match fs::read_to_string(sentinel_path(output_dir, run_name)) {
+        Ok(text) => Some(resume::parse_metadata(&text).get("pid").and_then(|pid| pid.parse().ok()).unwrap_or(0)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let state = match fs::read_to_string(state_path(output_dir, run_name)) {
+        Ok(text) => {
+            let entries = resume::parse_metadata(&text);
+            let superbatch = entries.get("superbatch").and_then(|n| n.parse().ok());
+            superbatch
+                .zip(entries.get("checkpoint"))
+                .map(|(superbatch, dir)| ChunkState { superbatch, checkpoint: PathBuf::from(dir) })
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    Ok(startup(sentinel, process::id(), state))
+}
+
+/// Marks the run as in progress.
+pub fn write_sentinel(output_dir: &str, run_name: &str) -> io::Result<()> {
+    fs::write(sentinel_path(output_dir, run_name), resume::format_metadata(&[("pid", process::id().to_string())]))
+}
+
+/// Records `state` as the chunk to resume from, replacing the previous one
+/// with a rename so a preemption mid-write leaves the old record intact.
+pub fn write_state(output_dir: &str, run_name: &str, state: &ChunkState) -> io::Result<()> {
+    let path = state_path(output_dir, run_name);
+    let partial = path.with_extension("chunk.partial");
+    let entries =
+        [("superbatch", state.superbatch.to_string()), ("checkpoint", state.checkpoint.display().to_string())];
+    fs::write(&partial, resume::format_metadata(&entries))?;
+    fs::rename(&partial, &path)
+}
+
+/// Removes the sentinel and the chunk record once the run has ended.
+pub fn finish(output_dir: &str, run_name: &str) -> io::Result<()> {
+    for path in [sentinel_path(output_dir, run_name), state_path(output_dir, run_name)] {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the checkpoint saved for `superbatch` was written in full: the
+/// quantised net and all optimiser files are non-empty, the three optimiser
+/// buffers have the same size, and its metadata names the same superbatch.
+pub fn verify(checkpoint_dir: &Path, superbatch: usize) -> Result<(), String> {
+    let size = |path: PathBuf| match fs::metadata(&path) {
+        Ok(meta) if meta.len() > 0 => Ok(meta.len()),
+        Ok(_) => Err(format!("{} is empty", path.display())),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    };
+    size(checkpoint_dir.join(QUANTISED))?;
+    let optimiser = checkpoint_dir.join("optimiser_state");
+    let sizes = resume::OPTIMISER_FILES.map(|file| size(optimiser.join(file)));
+    let sizes: Vec<u64> = sizes.into_iter().collect::<Result<_, _>>()?;
+    if sizes.windows(2).any(|pair| pair[0] != pair[1]) {
+        return Err(format!("{} buffers differ in size: {:?}", optimiser.display(), sizes));
+    }
+    let metadata = checkpoint_dir.join(checkpoint::CHECKPOINT_METADATA);
+    let text = fs::read_to_string(&metadata).map_err(|e| format!("{}: {}", metadata.display(), e))?;
+    match resume::parse_metadata(&text).get("superbatch").and_then(|n| n.parse::<usize>().ok()) {
+        Some(saved) if saved == superbatch => Ok(()),
+        saved => Err(format!("{} is for superbatch {:?}, expected {}", metadata.display(), saved, superbatch)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn chunks_end_every_n_superbatches_before_the_last() {
+        let boundaries: Vec<usize> = (1..=10).filter(|&superbatch| is_chunk_boundary(superbatch, 3, 10)).collect();
+        assert_eq!(boundaries, [3, 6, 9]);
+        let boundaries: Vec<usize> = (1..=10).filter(|&superbatch| is_chunk_boundary(superbatch, 5, 10)).collect();
+        assert_eq!(boundaries, [5]);
+    }
+
+    #[test]
+    fn the_sentinel_and_the_chunk_decide_the_startup() {
+        let state = ChunkState { superbatch: 6, checkpoint: PathBuf::from("out/net-6") };
+        assert_eq!(startup(None, 7, Some(state.clone())), Startup::Fresh);
+        assert_eq!(startup(Some(7), 7, Some(state.clone())), Startup::Restarted);
+        assert_eq!(startup(Some(3), 7, None), Startup::PreemptedBeforeChunk);
+        assert_eq!(startup(Some(3), 7, Some(state.clone())), Startup::Preempted(state.clone()));
+        assert_eq!(state.weights(), Path::new("out/net-6/optimiser_state/weights.bin"));
+    }
+
+    #[test]
+    fn a_killed_run_is_detected_from_its_files() {
+        let dir = temp_path("chunks");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.to_str().unwrap();
+        assert_eq!(detect(output, "run").unwrap(), Startup::Fresh);
+
+        // this process's own sentinel is a restart, another's a preemption
+        write_sentinel(output, "run").unwrap();
+        assert_eq!(detect(output, "run").unwrap(), Startup::Restarted);
+        fs::write(sentinel_path(output, "run"), "pid=1\n").unwrap();
+        assert_eq!(detect(output, "run").unwrap(), Startup::PreemptedBeforeChunk);
+        let state = ChunkState { superbatch: 4, checkpoint: dir.join("net-4") };
+        write_state(output, "run", &state).unwrap();
+        assert_eq!(detect(output, "run").unwrap(), Startup::Preempted(state.clone()));
+        fs::write(sentinel_path(output, "run"), "garbage").unwrap();
+        assert_eq!(detect(output, "run").unwrap(), Startup::Preempted(state));
+
+        finish(output, "run").unwrap();
+        assert_eq!(detect(output, "run").unwrap(), Startup::Fresh);
+        finish(output, "run").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verifies_a_complete_checkpoint_only() {
+        let dir = temp_path("chunk-verify");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("optimiser_state")).unwrap();
+        fs::write(dir.join(QUANTISED), [1; 8]).unwrap();
+        for file in resume::OPTIMISER_FILES {
+            fs::write(dir.join("optimiser_state").join(file), [0; 16]).unwrap();
+        }
+        fs::write(dir.join(checkpoint::CHECKPOINT_METADATA), "superbatch=4\n").unwrap();
+        assert_eq!(verify(&dir, 4), Ok(()));
+        assert!(verify(&dir, 5).unwrap_err().contains("is for superbatch Some(4), expected 5"));
+
+        let last = dir.join("optimiser_state").join(resume::OPTIMISER_FILES[2]);
+        fs::write(&last, [0; 12]).unwrap();
+        assert!(verify(&dir, 4).unwrap_err().contains("buffers differ in size"));
+        fs::write(&last, []).unwrap();
+        assert!(verify(&dir, 4).unwrap_err().ends_with("is empty"));
+        fs::remove_file(dir.join(QUANTISED)).unwrap();
+        assert!(verify(&dir, 4).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+//! The `training` binary's subcommands. `train` is the trainer itself and
+//! the default when no command is named, so existing command lines keep
+//! working; the others are the data tools a run needs around it, which
+//! used to be shell scripts around `bullet-utils` and the engine.
+
+use std::{fmt, io};
+
+use crate::data::DataError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Train,
+    Convert,
+    Shuffle,
+    Datagen,
+    Inspect,
+}
+
+impl Command {
+    /// The command named by `args[1]`, and `args` without that name. Without
+    /// one, the arguments are the trainer's.
+    pub fn split(args: &[String]) -> (Command, Vec<String>) {
+        let command = match args.get(1).map(String::as_str) {
+            Some("train") => Self::Train,
+            Some("convert") => Self::Convert,
+            Some("shuffle") => Self::Shuffle,
+            Some("datagen") => Self::Datagen,
+            Some("inspect") => Self::Inspect,
+            _ => return (Self::Train, args.to_vec()),
+        };
+        let mut rest = args.to_vec();
+        rest.remove(1);
+        (command, rest)
+    }
+}
+
+/// Why a data tool failed.
+#[derive(Debug)]
+pub enum ToolError {
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    Data(DataError),
+    /// A `convert` input line that is not `<fen> | <eval> | <wdl>`.
+    BadLine {
+        path: String,
+        line: u64,
+        message: String,
+    },
+    /// `datagen` instances that could not be started or did not succeed.
+    Generator(String),
+}
+
+impl ToolError {
+    pub fn io(path: &str) -> impl FnOnce(io::Error) -> ToolError + '_ {
+        move |error| ToolError::Io { path: path.to_string(), error }
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{}: {}", path, error),
+            Self::Data(e) => write!(f, "{}", e),
+            Self::BadLine { path, line, message } => write!(f, "{} line {}: {}", path, line, message),
+            Self::Generator(e) => write!(f, "datagen: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+impl From<DataError> for ToolError {
+    fn from(e: DataError) -> Self {
+        Self::Data(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::args;
+
+    #[test]
+    fn a_named_command_is_taken_off_the_arguments() {
+        assert_eq!(Command::split(&args(&["shuffle", "-i", "a.data"])), (Command::Shuffle, args(&["-i", "a.data"])));
+        assert_eq!(Command::split(&args(&["train", "-s", "2"])), (Command::Train, args(&["-s", "2"])));
+        assert_eq!(Command::split(&args(&["inspect"])), (Command::Inspect, args(&[])));
+    }
+
+    #[test]
+    fn without_a_command_the_arguments_are_the_trainers() {
+        for trainer in [&["-d", "a.data", "convert"][..], &["--help"], &[]] {
+            assert_eq!(Command::split(&args(trainer)), (Command::Train, args(trainer)), "{:?}", trainer);
+        }
+    }
+}
@@ -0,0 +1,1983 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activations, config_file, data,
+    grow::ProgressiveHl,
+    logging::Level,
+    lr_schedule::PlateauSettings,
+    net::{self, NUM_OUTPUT_BUCKETS},
+    quant_scales,
+    schedule::{self, SizingConflict},
+};
+
+// finetune preset: a gentle, short run on top of a loaded net, its length
+// counted from --start so a resumed checkpoint still gets the whole run
+const FINETUNE_SUPERBATCHES: usize = 40;
+const FINETUNE_LR: f32 = 0.0001;
+
+/// Batches buffered between the loader and the trainer (`--batch-queue`).
+pub const DEFAULT_BATCH_QUEUE: usize = 32;
+
+/// Positions `--compare-loss` scores when not given a count.
+pub const DEFAULT_COMPARE_LOSS_POSITIONS: usize = 4096;
+
+/// `--accumulate-metrics` window when `--metric-window` is not given.
+pub const DEFAULT_METRIC_WINDOW: usize = 10;
+
+/// Where runs write their checkpoints, each in its own subdirectory unless `--flat-output`.
+pub const OUTPUT_ROOT: &str = "checkpoints";
+
+pub const USAGE: &str = "\
+SleepMind NNUE Trainer
+
+Usage: training [train] [OPTIONS]
+       training <COMMAND> [OPTIONS]
+
+Commands:
+  train                    Train a net (the default when no command is given)
+  datagen                  Generate text training data with the engine's self-play generator
+  convert                  Convert text training data to bulletformat records
+  shuffle                  Shuffle bulletformat data files into one
+  inspect                  Summarise a bulletformat data file
+Run `training <COMMAND> --help` for a command's options.
+
+Train options:
+      --config <PATH>      Read flags from a TOML file, one `flag = value` per line without the
+                           dashes (`lr = 0.001`, `single-perspective = true`); tables only group
+                           them. Flags on the command line override the file
+  -d, --data <PATH>        Training data file (default: data/baseline.data)
+      --data-format <direct|binpack>
+                           direct: bulletformat records (default); binpack: a Stockfish binpack,
+                           read by bullet's SfBinpackLoader. Binpack has no fixed record size,
+                           so features that read --data by record index are refused
+      --dataset-manifest <PATH>
+                           Verify the data against `sha256sum`-style checksums first
+      --val-split <F>      Hold out the last fraction F of the data for validation loss
+      --snapshot-on-best <PATH>
+                           With --val-split: write the quantised net to PATH (atomically) each
+                           time the val loss reaches a new best, for an external tester
+      --holdout-file <PATH>
+                           Data never trained on, scored once on the final net and recorded
+                           as holdout_loss in the summary
+      --compare-quant-scales <LIST>
+                           After training, quantise the net at each comma-separated qa (1-255)
+                           and print the error against the float eval, with a recommendation
+  -s, --superbatches <N>   Number of superbatches (default: 640)
+      --start <N>          Start superbatch (default: 1, use for resuming)
+  -l, --load <PATH|URL>    Load weights from a checkpoint's optimiser_state/weights.bin or an http(s)
+                           URL; older weights.fp32 archives and quantised v1 nets are upgraded, with
+                           missing factorisers zeroed; a download must match <URL>.sha256 if the
+                           server has one
+      --init-from-average <A,B,...>
+                           Start training from the float mean of these weight files
+  -n, --name <NAME>        Network ID for output (default: sleepmind)
+      --run-name <NAME>    Name for run-level files such as the metadata (default: --name)
+                           (alias: --checkpoint-prefix)
+  -t, --threads <N>        Number of threads (default: 2)
+      --batch-queue <N>    Batches buffered between the loader and the trainer (default: 32)
+      --max-ram-mb <N>     Refuse settings estimated to need more RAM than N MB; lowers the default
+                           batch queue to fit
+      --cpu                Require bullet's CPU backend (a build with --no-default-features)
+      --require-gpu        Abort at startup unless a GPU backend and device are available,
+                           instead of warning and training on the CPU
+      --deterministic      Debug mode: one thread, one queued batch; slow, for bit-exact reruns
+      --pin-threads        Pin the data loader thread to a core (no-op where unsupported)
+      --save-rate <N>      Save checkpoint every N superbatches (default: 10)
+      --chunk-superbatches <N>
+                           Also save and verify a checkpoint every N superbatches; rerunning the
+                           same command after a preemption resumes from the last verified one
+      --record-git-state   Store the trainer's git commit and dirty flag in the run metadata
+      --also-save-fp32     Also write float weights (weights.fp32) into every checkpoint
+      --stop-at-loss <F>   Stop with a save once the smoothed sampled loss is <= F
+      --reduce-on-plateau <FACTOR:PATIENCE:MIN_DELTA>
+                           Multiply the LR by FACTOR, holding it there instead of following the
+                           cosine curve, when the smoothed sampled loss has not improved by
+                           MIN_DELTA in PATIENCE superbatches
+      --recover-on-divergence <N>
+                           On a NaN or spiking sampled loss, restart from the last checkpoint
+                           with the LR halved, up to N times; then abort
+      --force              Overwrite existing checkpoints of this --name at or after --start
+      --final-only-save    Skip interval checkpoints, only write the final net
+      --flat-output        Write checkpoints straight into checkpoints/ instead of
+                           checkpoints/<run name>/
+      --hl-size <N>        Hidden layer width (default: 768)
+      --single-perspective Only build the side-to-move accumulator (l1 input = hl_size)
+      --output-factoriser  Share an l1 column across output buckets, merged into l1w on save
+      --hidden-dropout <P> Dropout probability on the hidden activations while training (default: 0)
+      --progressive-hl <START:END:AT>
+                           Train a START-wide hidden layer up to superbatch AT, then widen it
+                           to END (--hl-size) and train on; new units start small
+      --lr <F>             Initial learning rate (default: 0.001)
+      --schedule-anchor <FIRST:LAST>
+                           Pin the cosine LR curve to run from superbatch FIRST to LAST,
+                           whatever --start and --superbatches are
+      --final-lr <F>       Final learning rate of the cosine decay (default: lr * 0.3^5)
+      --sparsity-report <T>
+                           With --load: count input features whose merged weights are all below T, no training
+      --sparsity-list <PATH>
+                           Also write the near-zero feature indices (bucket * 768 + feature), one per line
+      --compare-loss [N]   With --load: print the net's loss on N sampled positions (default: 4096),
+                           no training; --compare-loss-positions <N> is an alias
+      --dataset-stats <PATH>
+                           Print results, eval, bucket, check and phase statistics of a data file, no training
+      --print-feature-coverage
+                           Count which of the 768 * buckets input features the first superbatch
+                           of --data activates, and list never-seen buckets; no training
+      --list-checkpoints   Print the checkpoints of --name in the run's output directory with
+                           size, modification time and val loss, no training
+      --print-memory-plan  Print the estimated memory for weights, gradients, AdamW moments,
+                           activations and batch queues with these settings, no training
+      --diff <A> <B>       Print per-tensor mean/max absolute differences and the L2 distance
+                           between two nets of this architecture (float weights), no training
+      --fen-list <PATH>    Print `<fen>,<cp>` for each FEN in PATH with the integer engine eval, no training
+      --eval-net <PATH>    Quantised net (e.g. quantised.bin) for --fen-list
+      --export-piece-values
+                           Print the piece values implied by --eval-net (eval delta from adding
+                           each piece to a kings-only board), no training
+      --eval-symmetry-check
+                           Compare the --eval-net eval of positions from --data with the same
+                           positions and the other side to move (mean and max cp), no training
+      --dump-activations <PATH>
+                           Write the --eval-net stm hidden layer of positions from --data to PATH
+                           as an f32 matrix (header: SMAC, rows, columns), no training
+      --dump-count <N>     Positions for --dump-activations (default: 1024)
+      --fen-output <PATH>  Write the --fen-list results to PATH instead of stdout
+      --replay-log <PATH>  Print the LR/save/report/stop decisions for a recorded bullet log.txt, no training
+      --resume-safe        With --load: check the resume continues the saved run (PASS/FAIL), no training
+      --lr-find            Sweep the LR over a short run and suggest one; saves nothing
+      --l1-lr <F>          Initial learning rate of the output layer (default: same as --lr)
+      --export-c-header <PATH>
+                           Write the net constants as a C header for the engine
+      --quantize-only <PATH>
+                           Quantise a float checkpoint to an engine net and exit (needs --export-net)
+      --export-net <PATH>  Output path for --quantize-only
+      --save-format-version <N>
+                           On-disk net layout (default: 1, or 2 with --net-description).
+                           1: no header, l0w with the factoriser merged, l0b, l1w
+                           bucket-major, l1b; i16, padded to 64 bytes (src/nnue.c)
+                           2: 1 followed by a description the engine prints on load
+      --net-description <TEXT>
+                           Description stored in the net, implies format 2 (with an explicit
+                           --save-format-version 2 the default is net id, superbatch and date)
+      --validate-shapes-against-header
+                           With --load: check the architecture in the run's metadata against this
+                           one and the loaded tensor shapes
+      --check-nan          With --load: refuse to start if any loaded weight is NaN/Inf
+      --finetune           With --load: low LR, short schedule preset (explicit flags win)
+      --min-free-mb <N>    Extra free disk space required on top of one checkpoint (default: 0)
+      --batch-size <N>     Positions per batch (default: 16384)
+      --batches-per-superbatch <N>
+                           Batches per superbatch (default: 6104); may be given with
+                           --positions-per-superbatch only if both describe the same size
+      --positions-per-superbatch <N>
+                           Superbatch size in positions; batches per superbatch is
+                           derived from the batch size (default: 16384 * 6104)
+      --superbatch-equals-epoch
+                           Size each superbatch to one pass over the (train) data
+      --target-from <eval|wdl|blend>
+                           Train on the eval, the game result, or a --wdl blend (default: blend)
+      --wdl <F>            WDL proportion for --target-from blend (default: 0.0)
+      --wdl-by-phase <O:E> Per-position WDL proportion, O in the opening, E in the
+                           endgame, interpolated by game phase (e.g. 0.0:0.4)
+      --wdl-smooth <EPS>   Pull game results towards 0.5 by EPS, in [0, 0.5); the eval
+                           part of the target is unchanged (default: 0)
+      --target-noise <SIGMA>
+                           Add Gaussian noise of SIGMA cp to each training score before the
+                           sigmoid, redrawn every pass; not label smoothing, not gradient noise
+      --target-noise-seed <N>
+                           Seed of the --target-noise draws (default: 0)
+      --resumeable-seed <N>
+                           Seed the --target-noise and --reweight-buckets draws of superbatch S
+                           with N xor S: different every superbatch, the same after a resume
+      --target-clamp-report
+                           Report the share of targets at the sigmoid clamp bounds
+      --filter-eval-max <CP>
+                           Skip positions whose |eval| exceeds CP centipawns
+      --filter-no-check    Skip positions where the side to move is in check
+      --skip-bad-records   Drop records with an impossible feature set (not 2-32 pieces, one king
+                           each) instead of aborting at the first one
+      --holdout-buckets <LIST>
+                           Skip positions in these output buckets, e.g. 0,1 (their l1 columns don't train)
+      --subsample <F>      Train on about a fraction F of the training records, chosen by a hash of
+                           each record so every pass and rerun keeps the same ones
+      --subsample-seed <N> Seed of the --subsample hash, to pick a different subset (default: 0)
+      --reweight-buckets   Weight positions inversely to their output bucket's frequency in a
+                           data sample (mean weight 1, at most 16) by repeating them in batches
+      --report-interval <N> Print throughput summary every N superbatches (default: 1)
+      --loss-by-bucket     Report the sampled loss per output bucket at each report
+      --accumulate-metrics Also log the val loss averaged over the last --metric-window superbatches
+      --metric-window <N>  Window for --accumulate-metrics, implies it (default: 10)
+      --engine-scale <F>   Eval scale recorded for the engine in the run metadata
+                           (default: the training eval scale, 400)
+      --loss-target-scale <F>
+                           Centipawn scale of the sigmoid in the loss, for both targets and
+                           outputs (default: the eval scale, 400). Smaller values saturate
+                           large evals sooner so they weigh less. The net's output keeps the
+                           eval scale, and only that is recorded for the engine
+      --loss-clip <F>      Cap each position's loss at F, in (0, 1), so mislabelled outliers
+                           cannot dominate a batch; reports how often the cap is hit
+      --io-retries <N>     Retry failed data reads N times with backoff (default: 0)
+      --warm-cache         Read the data file once before training to fill the page cache,
+                           skipped when a quick probe finds it cached already
+      --record-size <BYTES> Check that --data is a whole number of records of this size; the
+                           loaders always read 32-byte bulletformat records (default: 32)
+      --ema <DECAY>        Keep an EMA of the weights, updated every superbatch
+      --export-ema         Also write quantised-ema.bin with each checkpoint (needs --ema)
+      --weights-histogram <PATH>
+                           Write 20-bin histograms of l0w/l0f/l1w as CSV at the final save
+      --summary-json <PATH> Write a JSON run summary, updated at each report and at the end
+      --sanity-startpos <CP>
+                           Fail the run if the final net scores the start position beyond +-CP
+      --dump-config <PATH> Write the fully resolved settings as a --config file to PATH, no
+                           training. Every run also writes them next to its checkpoints, as
+                           <run>.toml, so --config <run>.toml repeats the run
+      --print-layer-lr     Print the effective LR of every weight tensor each report
+      --profile            Print where loader time goes each report and a summary table
+  -q, --quiet              Only print errors and the final summary
+  -v, --verbose            Also print weight stats, bucket occupancy and device info
+      --strict             Stop with an error on any warning, e.g. a dirty git tree, less data than
+                           a superbatch, a skipped or failed save, a stalled loader or a CPU backend.
+                           Warnings confirming --deterministic, --force, --io-retries,
+                           --skip-bad-records, --recover-on-divergence or --chunk-superbatches
+                           are not promoted
+  -h, --help               Show this help
+
+Examples:
+  # First training run with 10 superbatches
+  training -d data/hce_games.data -s 10 -n sleepmind_v1
+
+  # Continue training from checkpoint
+  training -d data/more_games.data -s 50 --start 11 -n sleepmind_v1 \\
+      -l checkpoints/sleepmind_v1/sleepmind_v1-10/optimiser_state/weights.bin
+
+  # Settings kept in a file, with a shorter schedule for a smoke test
+  training --config runs/sleepmind_v2.toml -s 2
+
+  # Nudge a strong net on fresh data
+  training -d data/fresh.data --finetune -l checkpoints/sleepmind_v1/sleepmind_v1-640.wgts -n sleepmind_v1_ft";
+
+/// How `--data` is encoded, and so which loader reads it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataFormat {
+    /// Fixed-size bulletformat records, also read by the trainer's own tools.
+    Direct,
+    /// Stockfish binpack, which only bullet's loader decodes.
+    Binpack,
+}
+
+impl FromStr for DataFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "direct" => Ok(Self::Direct),
+            "binpack" => Ok(Self::Binpack),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Direct => "direct",
+            Self::Binpack => "binpack",
+        })
+    }
+}
+
+/// What the net is trained towards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TargetSource {
+    /// Only the recorded eval (WDL proportion 0).
+    Eval,
+    /// Only the game result (WDL proportion 1).
+    Wdl,
+    /// `--wdl` of the result, the rest eval.
+    Blend,
+}
+
+impl TargetSource {
+    pub fn wdl_proportion(self, blend: f32) -> f32 {
+        match self {
+            Self::Eval => 0.0,
+            Self::Wdl => 1.0,
+            Self::Blend => blend,
+        }
+    }
+}
+
+impl FromStr for TargetSource {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "eval" => Ok(Self::Eval),
+            "wdl" => Ok(Self::Wdl),
+            "blend" => Ok(Self::Blend),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for TargetSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eval => "eval",
+            Self::Wdl => "wdl",
+            Self::Blend => "blend",
+        })
+    }
+}
+
+/// Fully resolved settings for one training run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub dataset_path: String,
+    pub dataset_manifest: Option<String>,
+    /// Fraction of records at the end of the data file held out for validation.
+    pub val_split: Option<f32>,
+    /// Scored once at the end of the run, unlike the val loss reported throughout.
+    pub holdout_file: Option<String>,
+    /// Feature transformer scales to compare on the final net; empty when off.
+    pub compare_quant_scales: Vec<i16>,
+    /// Overwritten with the current quantised net whenever the val loss improves.
+    pub snapshot_on_best: Option<String>,
+    pub superbatches: usize,
+    pub start_superbatch: usize,
+    pub load_weights: Option<String>,
+    pub init_from_average: Vec<String>,
+    pub net_id: String,
+    /// Prefix of run-level files (metadata, logs); nets are named by `net_id`.
+    pub run_name: String,
+    pub threads: usize,
+    /// `None` leaves the default, which `--max-ram-mb` may lower.
+    pub batch_queue: Option<usize>,
+    /// RAM budget for the batch queue, loader buffers and host-side weights.
+    pub max_ram_mb: Option<u64>,
+    pub pin_threads: bool,
+    /// Forces `threads = 1` and a batch queue of one (debugging only).
+    pub deterministic: bool,
+    pub cpu: bool,
+    pub require_gpu: bool,
+    pub save_rate: usize,
+    /// Skip interval saves; bullet's final save still happens.
+    pub final_only_save: bool,
+    /// `--chunk-superbatches`: verified resume points, see `chunks.rs`.
+    pub chunk_superbatches: Option<usize>,
+    pub force: bool,
+    pub stop_at_loss: Option<f32>,
+    /// Restarts left for `--recover-on-divergence`; `Some(0)` still detects and aborts.
+    pub recover_on_divergence: Option<usize>,
+    pub reduce_on_plateau: Option<PlateauSettings>,
+    pub also_save_fp32: bool,
+    pub record_git_state: bool,
+    /// `--hl-size`: the hidden layer width of the finished net.
+    pub full_hl_size: usize,
+    pub single_perspective: bool,
+    /// Train a shared `l1f` column on top of the per-bucket `l1` weights.
+    pub output_factoriser: bool,
+    pub hidden_dropout: f32,
+    /// Hidden layer growth; [`Config::hl_size`] is the width this run trains at.
+    pub progressive_hl: Option<ProgressiveHl>,
+    pub initial_lr: f32,
+    pub final_lr: f32,
+    /// `(first, last)` superbatches of the cosine curve, see `--schedule-anchor`.
+    pub schedule_anchor: Option<(usize, usize)>,
+    /// Initial learning rate for `l1w`/`l1b`; decays with the same schedule.
+    pub l1_lr: Option<f32>,
+    pub lr_find: bool,
+    /// Positions to measure the loaded net's loss on instead of training.
+    pub compare_loss_positions: Option<usize>,
+    pub sparsity_report: Option<f32>,
+    pub sparsity_list: Option<String>,
+    pub resume_safe: bool,
+    pub replay_log: Option<String>,
+    pub dataset_stats: Option<String>,
+    pub print_feature_coverage: bool,
+    /// FENs to score with the quantised net at `eval_net`, no training.
+    pub fen_list: Option<String>,
+    pub eval_net: Option<String>,
+    pub export_piece_values: bool,
+    pub eval_symmetry_check: bool,
+    pub dump_activations: Option<String>,
+    pub dump_count: usize,
+    pub print_memory_plan: bool,
+    pub list_checkpoints: bool,
+    /// Two nets to compare with `--diff`.
+    pub diff: Option<(String, String)>,
+    /// Where `--fen-list` writes `<fen>,<cp>` lines; stdout when unset.
+    pub fen_output: Option<String>,
+    pub check_nan: bool,
+    pub validate_shapes_against_header: bool,
+    /// Float weights to convert to an engine net at `export_net`, without training.
+    pub export_c_header: Option<String>,
+    pub quantize_only: Option<String>,
+    pub export_net: Option<String>,
+    /// On-disk layout of engine nets, one of `net::SAVE_FORMATS`.
+    pub save_format_version: u32,
+    /// Text for save format 2 nets; generated per save when unset.
+    pub net_description: Option<String>,
+    pub finetune: bool,
+    /// Finetune defaults that were applied because the user left them unset.
+    pub finetune_defaults: Vec<String>,
+    pub report_interval: usize,
+    pub loss_by_bucket: bool,
+    /// `--accumulate-metrics` window in superbatches.
+    pub metric_window: Option<usize>,
+    pub target_clamp_report: bool,
+    pub min_free_mb: u64,
+    /// `checkpoints/<run_name>`, or `checkpoints` with `--flat-output`.
+    pub output_directory: String,
+    pub batch_size: usize,
+    pub batches_per_superbatch: usize,
+    /// What `--positions-per-superbatch` asked for, if given; the effective
+    /// value is `batch_size * batches_per_superbatch`.
+    pub requested_positions_per_superbatch: Option<usize>,
+    /// Replace `batches_per_superbatch` with one epoch once the data is counted.
+    pub superbatch_equals_epoch: bool,
+    /// `(opening, endgame)` WDL proportions for the phase-aware target blend.
+    pub wdl_by_phase: Option<(f32, f32)>,
+    pub target_from: TargetSource,
+    /// WDL proportion used by [`TargetSource::Blend`].
+    pub wdl: f32,
+    /// Label smoothing on the game result only: 1 -> 1 - eps, 0 -> eps.
+    pub wdl_smooth: f32,
+    /// Standard deviation in centipawns of the noise added to training scores.
+    pub target_noise: Option<f32>,
+    pub target_noise_seed: u64,
+    /// Base of the per-superbatch seeds, see `loader::SuperbatchSeed`.
+    pub resumeable_seed: Option<u64>,
+    pub filter_eval_max: Option<i16>,
+    pub filter_no_check: bool,
+    /// Drop records with an impossible feature set instead of aborting.
+    pub skip_bad_records: bool,
+    /// Output buckets whose positions are left out of training.
+    pub holdout_buckets: Vec<usize>,
+    /// Fraction of training records kept, by a seeded hash of each record.
+    pub subsample: Option<f32>,
+    pub subsample_seed: u64,
+    pub reweight_buckets: bool,
+    pub log_level: Level,
+    /// Warnings end the run, see `logging::warning`.
+    pub strict: bool,
+    /// `--record-size`: only checked against the size of `--data`.
+    pub record_size: usize,
+    pub data_format: DataFormat,
+    /// Retries for transient data read errors; nonzero reads through the
+    /// trainer's own loader instead of bullet's.
+    pub io_retries: usize,
+    pub warm_cache: bool,
+    /// Scale the engine should use to turn net output into centipawns, if it
+    /// differs from the training eval scale.
+    pub engine_scale: Option<f32>,
+    /// Sigmoid scale of the loss, see `--loss-target-scale`; `None` uses the eval scale.
+    pub loss_target_scale: Option<f32>,
+    /// Cap on each position's squared error before the batch reduction.
+    pub loss_clip: Option<f32>,
+    /// Decay of the per-superbatch weight EMA, in `(0, 1)`.
+    pub ema: Option<f32>,
+    pub export_ema: bool,
+    pub profile: bool,
+    pub print_layer_lr: bool,
+    pub weights_histogram: Option<String>,
+    pub summary_json: Option<String>,
+    /// Largest startpos eval magnitude in cp the final net may have.
+    pub sanity_startpos: Option<i32>,
+    /// Write this config as TOML here instead of training.
+    pub dump_config: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// `--help` was given; the caller should print [`USAGE`] and exit.
+    HelpRequested,
+    MissingValue(String),
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    UnknownFlag(String),
+    FinetuneWithoutLoad,
+    Conflict(&'static str, &'static str),
+    /// The first flag only makes sense together with the second.
+    Requires(&'static str, &'static str),
+    /// The superbatch sizing flags contradict each other.
+    Sizing(SizingConflict),
+    /// A `--config` file could not be read or does not hold flags.
+    ConfigFile {
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HelpRequested => write!(f, "help requested"),
+            Self::MissingValue(flag) => write!(f, "{} requires a value", flag),
+            Self::InvalidValue { flag, value } => write!(f, "invalid value for {}: {}", flag, value),
+            Self::UnknownFlag(flag) => write!(f, "unknown option: {}", flag),
+            Self::FinetuneWithoutLoad => write!(f, "--finetune requires --load <PATH>"),
+            Self::Conflict(a, b) => write!(f, "{} and {} cannot be combined", a, b),
+            Self::Requires(a, b) => write!(f, "{} requires {}", a, b),
+            Self::Sizing(conflict) => write!(f, "{}", conflict),
+            Self::ConfigFile { path, message } => write!(f, "config file {}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Parses the full argument list (including the program name at index 0),
+    /// with the flags of any `--config` file in front of the others.
+    pub fn from_args(args: &[String]) -> Result<Config, ConfigError> {
+        let expanded = config_file::expand(args)?;
+        let args = &expanded[..];
+        let mut dataset_path = "data/baseline.data".to_string();
+        let mut dataset_manifest: Option<String> = None;
+        let mut val_split: Option<f32> = None;
+        let mut holdout_file: Option<String> = None;
+        let mut compare_quant_scales: Vec<i16> = Vec::new();
+        let mut snapshot_on_best: Option<String> = None;
+        let mut superbatches: Option<usize> = None;
+        let mut start_superbatch: usize = 1;
+        let mut load_weights: Option<String> = None;
+        let mut init_from_average: Vec<String> = Vec::new();
+        let mut net_id = "sleepmind".to_string();
+        let mut run_name: Option<String> = None;
+        let mut threads: usize = 2;
+        let mut batch_queue: Option<usize> = None;
+        let mut max_ram_mb: Option<u64> = None;
+        let mut pin_threads = false;
+        let mut deterministic = false;
+        let mut cpu = false;
+        let mut require_gpu = false;
+        let mut save_rate: usize = 10;
+        let mut final_only_save = false;
+        let mut chunk_superbatches: Option<usize> = None;
+        let mut flat_output = false;
+        let mut force = false;
+        let mut stop_at_loss: Option<f32> = None;
+        let mut recover_on_divergence: Option<usize> = None;
+        let mut reduce_on_plateau: Option<PlateauSettings> = None;
+        let mut hl_size = net::HL_SIZE;
+        let mut progressive_hl: Option<ProgressiveHl> = None;
+        let mut also_save_fp32 = false;
+        let mut record_git_state = false;
+        let mut single_perspective = false;
+        let mut output_factoriser = false;
+        let mut hidden_dropout: f32 = 0.0;
+        let mut initial_lr: Option<f32> = None;
+        let mut final_lr: Option<f32> = None;
+        let mut l1_lr: Option<f32> = None;
+        let mut lr_find = false;
+        let mut compare_loss_positions: Option<usize> = None;
+        let mut sparsity_report: Option<f32> = None;
+        let mut sparsity_list: Option<String> = None;
+        let mut resume_safe = false;
+        let mut replay_log: Option<String> = None;
+        let mut dataset_stats: Option<String> = None;
+        let mut print_feature_coverage = false;
+        let mut fen_list: Option<String> = None;
+        let mut eval_net: Option<String> = None;
+        let mut export_piece_values = false;
+        let mut eval_symmetry_check = false;
+        let mut dump_activations: Option<String> = None;
+        let mut dump_count: Option<usize> = None;
+        let mut print_memory_plan = false;
+        let mut list_checkpoints = false;
+        let mut diff: Option<(String, String)> = None;
+        let mut fen_output: Option<String> = None;
+        let mut check_nan = false;
+        let mut validate_shapes_against_header = false;
+        let mut export_c_header: Option<String> = None;
+        let mut quantize_only: Option<String> = None;
+        let mut export_net: Option<String> = None;
+        let mut save_format_version: Option<u32> = None;
+        let mut net_description: Option<String> = None;
+        let mut finetune = false;
+        let mut report_interval: usize = 1;
+        let mut loss_by_bucket = false;
+        let mut accumulate_metrics = false;
+        let mut metric_window: Option<usize> = None;
+        let mut target_clamp_report = false;
+        let mut min_free_mb: u64 = 0;
+        let mut positions_per_superbatch: Option<usize> = None;
+        let mut batch_size = schedule::DEFAULT_BATCH_SIZE;
+        let mut batches_per_superbatch: Option<usize> = None;
+        let mut superbatch_equals_epoch = false;
+        let mut wdl_by_phase: Option<(f32, f32)> = None;
+        let mut schedule_anchor: Option<(usize, usize)> = None;
+        let mut target_from: Option<TargetSource> = None;
+        let mut wdl: Option<f32> = None;
+        let mut wdl_smooth: f32 = 0.0;
+        let mut filter_eval_max: Option<i16> = None;
+        let mut filter_no_check = false;
+        let mut skip_bad_records = false;
+        let mut holdout_buckets: Vec<usize> = Vec::new();
+        let mut subsample: Option<f32> = None;
+        let mut subsample_seed: Option<u64> = None;
+        let mut target_noise: Option<f32> = None;
+        let mut target_noise_seed: Option<u64> = None;
+        let mut resumeable_seed: Option<u64> = None;
+        let mut reweight_buckets = false;
+        let mut quiet = false;
+        let mut record_size = data::RECORD_SIZE;
+        let mut data_format = DataFormat::Direct;
+        let mut engine_scale: Option<f32> = None;
+        let mut loss_target_scale: Option<f32> = None;
+        let mut loss_clip: Option<f32> = None;
+        let mut io_retries: usize = 0;
+        let mut warm_cache = false;
+        let mut ema: Option<f32> = None;
+        let mut export_ema = false;
+        let mut profile = false;
+        let mut print_layer_lr = false;
+        let mut weights_histogram: Option<String> = None;
+        let mut summary_json: Option<String> = None;
+        let mut dump_config: Option<String> = None;
+        let mut sanity_startpos: Option<i32> = None;
+        let mut verbose = false;
+        let mut strict = false;
+
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            match flag {
+                "--data" | "-d" => dataset_path = value(args, &mut i)?,
+                "--dataset-manifest" => dataset_manifest = Some(value(args, &mut i)?),
+                "--val-split" => {
+                    let fraction: f32 = value(args, &mut i)?;
+                    if !(fraction > 0.0 && fraction < 1.0) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--val-split".to_string(),
+                            value: fraction.to_string(),
+                        });
+                    }
+                    val_split = Some(fraction);
+                }
+                "--holdout-file" => holdout_file = Some(value(args, &mut i)?),
+                "--compare-quant-scales" => {
+                    let raw: String = value(args, &mut i)?;
+                    let invalid =
+                        || ConfigError::InvalidValue { flag: "--compare-quant-scales".to_string(), value: raw.clone() };
+                    // a later list replaces an earlier one, so the command line overrides a --config file
+                    compare_quant_scales.clear();
+                    for part in raw.split(',') {
+                        let qa: i16 = part.trim().parse().map_err(|_| invalid())?;
+                        if !(1..=quant_scales::MAX_SCALE).contains(&qa) {
+                            return Err(invalid());
+                        }
+                        compare_quant_scales.push(qa);
+                    }
+                    compare_quant_scales.sort_unstable();
+                    compare_quant_scales.dedup();
+                }
+                "--snapshot-on-best" => snapshot_on_best = Some(value(args, &mut i)?),
+                "--superbatches" | "-s" => superbatches = Some(value(args, &mut i)?),
+                "--start" => start_superbatch = value(args, &mut i)?,
+                "--load" | "-l" => load_weights = Some(value(args, &mut i)?),
+                "--init-from-average" => {
+                    let raw: String = value(args, &mut i)?;
+                    init_from_average =
+                        raw.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect();
+                    if init_from_average.len() < 2 {
+                        return Err(ConfigError::InvalidValue { flag: "--init-from-average".to_string(), value: raw });
+                    }
+                }
+                "--name" | "-n" => net_id = value(args, &mut i)?,
+                "--run-name" | "--checkpoint-prefix" => run_name = Some(value(args, &mut i)?),
+                "--threads" | "-t" => threads = value(args, &mut i)?,
+                "--batch-queue" => {
+                    let queue: usize = value(args, &mut i)?;
+                    if queue == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--batch-queue".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                    batch_queue = Some(queue);
+                }
+                "--pin-threads" => pin_threads = true,
+                "--deterministic" => deterministic = true,
+                "--cpu" => cpu = true,
+                "--require-gpu" => require_gpu = true,
+                "--save-rate" => save_rate = value(args, &mut i)?,
+                "--final-only-save" => final_only_save = true,
+                "--chunk-superbatches" => {
+                    let chunk: usize = value(args, &mut i)?;
+                    if chunk == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--chunk-superbatches".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                    chunk_superbatches = Some(chunk);
+                }
+                "--flat-output" => flat_output = true,
+                "--force" => force = true,
+                "--stop-at-loss" => stop_at_loss = Some(positive_value(args, &mut i)?),
+                "--recover-on-divergence" => recover_on_divergence = Some(value(args, &mut i)?),
+                "--reduce-on-plateau" => {
+                    let raw: String = value(args, &mut i)?;
+                    let invalid =
+                        || ConfigError::InvalidValue { flag: "--reduce-on-plateau".to_string(), value: raw.clone() };
+                    let mut parts = raw.split(':');
+                    let (Some(factor), Some(patience), Some(min_delta), None) =
+                        (parts.next(), parts.next(), parts.next(), parts.next())
+                    else {
+                        return Err(invalid());
+                    };
+                    let factor: f32 = factor.parse().map_err(|_| invalid())?;
+                    let patience: usize = patience.parse().map_err(|_| invalid())?;
+                    let min_delta: f32 = min_delta.parse().map_err(|_| invalid())?;
+                    if !(factor > 0.0 && factor < 1.0) || patience == 0 || min_delta.is_nan() || min_delta < 0.0 {
+                        return Err(invalid());
+                    }
+                    reduce_on_plateau = Some(PlateauSettings { factor, patience, min_delta });
+                }
+                "--also-save-fp32" => also_save_fp32 = true,
+                "--record-git-state" => record_git_state = true,
+                "--hl-size" => {
+                    hl_size = value(args, &mut i)?;
+                    if hl_size == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--hl-size".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                }
+                "--single-perspective" => single_perspective = true,
+                "--progressive-hl" => {
+                    let raw: String = value(args, &mut i)?;
+                    let invalid =
+                        || ConfigError::InvalidValue { flag: "--progressive-hl".to_string(), value: raw.clone() };
+                    let mut parts = raw.split(':');
+                    let (Some(start), Some(end), Some(at), None) =
+                        (parts.next(), parts.next(), parts.next(), parts.next())
+                    else {
+                        return Err(invalid());
+                    };
+                    let start: usize = start.parse().map_err(|_| invalid())?;
+                    let end: usize = end.parse().map_err(|_| invalid())?;
+                    let at: usize = at.parse().map_err(|_| invalid())?;
+                    if start == 0 || start >= end || at == 0 {
+                        return Err(invalid());
+                    }
+                    progressive_hl = Some(ProgressiveHl { start, end, at });
+                }
+                "--output-factoriser" => output_factoriser = true,
+                "--hidden-dropout" => {
+                    hidden_dropout = value(args, &mut i)?;
+                    if !(0.0..1.0).contains(&hidden_dropout) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--hidden-dropout".to_string(),
+                            value: hidden_dropout.to_string(),
+                        });
+                    }
+                }
+                "--lr" => initial_lr = Some(positive_value(args, &mut i)?),
+                "--final-lr" => final_lr = Some(positive_value(args, &mut i)?),
+                "--l1-lr" => l1_lr = Some(positive_value(args, &mut i)?),
+                "--lr-find" => lr_find = true,
+                "--compare-loss" | "--compare-loss-positions" => {
+                    compare_loss_positions =
+                        Some(optional_value(args, &mut i)?.unwrap_or(DEFAULT_COMPARE_LOSS_POSITIONS))
+                }
+                "--sparsity-report" => sparsity_report = Some(value(args, &mut i)?),
+                "--sparsity-list" => sparsity_list = Some(value(args, &mut i)?),
+                "--resume-safe" => resume_safe = true,
+                "--replay-log" => replay_log = Some(value(args, &mut i)?),
+                "--dataset-stats" => dataset_stats = Some(value(args, &mut i)?),
+                "--print-feature-coverage" => print_feature_coverage = true,
+                "--fen-list" => fen_list = Some(value(args, &mut i)?),
+                "--eval-net" => eval_net = Some(value(args, &mut i)?),
+                "--export-piece-values" => export_piece_values = true,
+                "--eval-symmetry-check" => eval_symmetry_check = true,
+                "--dump-activations" => dump_activations = Some(value(args, &mut i)?),
+                "--dump-count" => {
+                    let count: usize = value(args, &mut i)?;
+                    if count == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--dump-count".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                    dump_count = Some(count);
+                }
+                "--print-memory-plan" => print_memory_plan = true,
+                "--list-checkpoints" => list_checkpoints = true,
+                "--diff" => {
+                    let a = value(args, &mut i)?;
+                    diff = Some((a, value(args, &mut i)?));
+                }
+                "--fen-output" => fen_output = Some(value(args, &mut i)?),
+                "--check-nan" => check_nan = true,
+                "--validate-shapes-against-header" => validate_shapes_against_header = true,
+                "--export-c-header" => export_c_header = Some(value(args, &mut i)?),
+                "--quantize-only" => quantize_only = Some(value(args, &mut i)?),
+                "--export-net" => export_net = Some(value(args, &mut i)?),
+                "--net-description" => {
+                    let text: String = value(args, &mut i)?;
+                    if text.len() > net::MAX_DESCRIPTION_LEN || text.contains(['\n', '\r']) {
+                        return Err(ConfigError::InvalidValue { flag: "--net-description".to_string(), value: text });
+                    }
+                    net_description = Some(text);
+                }
+                "--save-format-version" => {
+                    let version = value(args, &mut i)?;
+                    if !net::is_supported_save_format(version) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--save-format-version".to_string(),
+                            value: version.to_string(),
+                        });
+                    }
+                    save_format_version = Some(version);
+                }
+                "--finetune" => finetune = true,
+                "--min-free-mb" => min_free_mb = value(args, &mut i)?,
+                "--max-ram-mb" => max_ram_mb = Some(value(args, &mut i)?),
+                "--positions-per-superbatch" => positions_per_superbatch = Some(value(args, &mut i)?),
+                "--batch-size" => {
+                    batch_size = value(args, &mut i)?;
+                    if batch_size == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--batch-size".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                }
+                "--batches-per-superbatch" => {
+                    let batches: usize = value(args, &mut i)?;
+                    if batches == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--batches-per-superbatch".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                    batches_per_superbatch = Some(batches);
+                }
+                "--superbatch-equals-epoch" => superbatch_equals_epoch = true,
+                "--schedule-anchor" => {
+                    let raw: String = value(args, &mut i)?;
+                    let invalid =
+                        || ConfigError::InvalidValue { flag: "--schedule-anchor".to_string(), value: raw.clone() };
+                    let (first, last) = raw.split_once(':').ok_or_else(invalid)?;
+                    let first: usize = first.parse().map_err(|_| invalid())?;
+                    let last: usize = last.parse().map_err(|_| invalid())?;
+                    if first >= last {
+                        return Err(invalid());
+                    }
+                    schedule_anchor = Some((first, last));
+                }
+                "--wdl-by-phase" => {
+                    let raw: String = value(args, &mut i)?;
+                    let invalid =
+                        || ConfigError::InvalidValue { flag: "--wdl-by-phase".to_string(), value: raw.clone() };
+                    let (opening, endgame) = raw.split_once(':').ok_or_else(invalid)?;
+                    let opening: f32 = opening.parse().map_err(|_| invalid())?;
+                    let endgame: f32 = endgame.parse().map_err(|_| invalid())?;
+                    if !(0.0..=1.0).contains(&opening) || !(0.0..=1.0).contains(&endgame) {
+                        return Err(invalid());
+                    }
+                    wdl_by_phase = Some((opening, endgame));
+                }
+                "--filter-eval-max" => filter_eval_max = Some(value(args, &mut i)?),
+                "--filter-no-check" => filter_no_check = true,
+                "--skip-bad-records" => skip_bad_records = true,
+                "--holdout-buckets" => {
+                    let raw: String = value(args, &mut i)?;
+                    let invalid =
+                        || ConfigError::InvalidValue { flag: "--holdout-buckets".to_string(), value: raw.clone() };
+                    holdout_buckets.clear();
+                    for part in raw.split(',') {
+                        let bucket: usize = part.trim().parse().map_err(|_| invalid())?;
+                        if bucket >= NUM_OUTPUT_BUCKETS {
+                            return Err(invalid());
+                        }
+                        holdout_buckets.push(bucket);
+                    }
+                    holdout_buckets.sort_unstable();
+                    holdout_buckets.dedup();
+                    if holdout_buckets.len() == NUM_OUTPUT_BUCKETS {
+                        return Err(invalid());
+                    }
+                }
+                "--subsample" => {
+                    let fraction: f32 = value(args, &mut i)?;
+                    if !(fraction > 0.0 && fraction <= 1.0) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--subsample".to_string(),
+                            value: fraction.to_string(),
+                        });
+                    }
+                    subsample = Some(fraction);
+                }
+                "--subsample-seed" => subsample_seed = Some(value(args, &mut i)?),
+                "--target-noise" => {
+                    let sigma: f32 = value(args, &mut i)?;
+                    if !(sigma >= 0.0 && sigma.is_finite()) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--target-noise".to_string(),
+                            value: sigma.to_string(),
+                        });
+                    }
+                    // 0 is the same as off
+                    target_noise = (sigma > 0.0).then_some(sigma);
+                }
+                "--target-noise-seed" => target_noise_seed = Some(value(args, &mut i)?),
+                "--resumeable-seed" => resumeable_seed = Some(value(args, &mut i)?),
+                "--reweight-buckets" => reweight_buckets = true,
+                "--target-from" => target_from = Some(value(args, &mut i)?),
+                "--wdl" => {
+                    let proportion: f32 = value(args, &mut i)?;
+                    if !(0.0..=1.0).contains(&proportion) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--wdl".to_string(),
+                            value: proportion.to_string(),
+                        });
+                    }
+                    wdl = Some(proportion);
+                }
+                "--wdl-smooth" => {
+                    wdl_smooth = value(args, &mut i)?;
+                    if !(0.0..0.5).contains(&wdl_smooth) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--wdl-smooth".to_string(),
+                            value: wdl_smooth.to_string(),
+                        });
+                    }
+                }
+                "--report-interval" => report_interval = value(args, &mut i)?,
+                "--loss-by-bucket" => loss_by_bucket = true,
+                "--accumulate-metrics" => accumulate_metrics = true,
+                "--metric-window" => {
+                    let window: usize = value(args, &mut i)?;
+                    if window == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--metric-window".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                    metric_window = Some(window);
+                }
+                "--target-clamp-report" => target_clamp_report = true,
+                "--record-size" => {
+                    record_size = value(args, &mut i)?;
+                    if record_size == 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--record-size".to_string(),
+                            value: "0".to_string(),
+                        });
+                    }
+                }
+                "--data-format" => data_format = value(args, &mut i)?,
+                "--io-retries" => io_retries = value(args, &mut i)?,
+                "--warm-cache" => warm_cache = true,
+                "--engine-scale" => engine_scale = Some(positive_value(args, &mut i)?),
+                "--loss-target-scale" => {
+                    let scale: f32 = value(args, &mut i)?;
+                    if !(scale > 0.0 && scale.is_finite()) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--loss-target-scale".to_string(),
+                            value: scale.to_string(),
+                        });
+                    }
+                    loss_target_scale = Some(scale);
+                }
+                "--loss-clip" => {
+                    let clip: f32 = value(args, &mut i)?;
+                    if !(clip > 0.0 && clip < 1.0) {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--loss-clip".to_string(),
+                            value: clip.to_string(),
+                        });
+                    }
+                    loss_clip = Some(clip);
+                }
+                "--ema" => {
+                    let decay: f32 = value(args, &mut i)?;
+                    if !(decay > 0.0 && decay < 1.0) {
+                        return Err(ConfigError::InvalidValue { flag: "--ema".to_string(), value: decay.to_string() });
+                    }
+                    ema = Some(decay);
+                }
+                "--export-ema" => export_ema = true,
+                "--profile" => profile = true,
+                "--print-layer-lr" | "--print-every-layer-lr" => print_layer_lr = true,
+                "--weights-histogram" => weights_histogram = Some(value(args, &mut i)?),
+                "--summary-json" => summary_json = Some(value(args, &mut i)?),
+                "--dump-config" => dump_config = Some(value(args, &mut i)?),
+                "--sanity-startpos" => {
+                    let cp: i32 = value(args, &mut i)?;
+                    if cp < 0 {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--sanity-startpos".to_string(),
+                            value: cp.to_string(),
+                        });
+                    }
+                    sanity_startpos = Some(cp);
+                }
+                "--quiet" | "-q" => quiet = true,
+                "--verbose" | "-v" => verbose = true,
+                "--strict" => strict = true,
+                "--help" | "-h" => return Err(ConfigError::HelpRequested),
+                _ => return Err(ConfigError::UnknownFlag(flag.to_string())),
+            }
+            i += 1;
+        }
+
+        if quiet && verbose {
+            return Err(ConfigError::Conflict("--quiet", "--verbose"));
+        }
+        let log_level = if quiet {
+            Level::Quiet
+        } else if verbose {
+            Level::Verbose
+        } else {
+            Level::Normal
+        };
+
+        if wdl_by_phase.is_some() && target_from.is_some() {
+            return Err(ConfigError::Conflict("--wdl-by-phase", "--target-from"));
+        }
+        if wdl_by_phase.is_some() && wdl.is_some() {
+            return Err(ConfigError::Conflict("--wdl-by-phase", "--wdl"));
+        }
+        if wdl.is_some() && target_from.is_some_and(|t| t != TargetSource::Blend) {
+            return Err(ConfigError::Conflict("--wdl", "--target-from eval|wdl"));
+        }
+
+        if wdl_smooth > 0.0 && target_from == Some(TargetSource::Eval) {
+            return Err(ConfigError::Conflict("--wdl-smooth", "--target-from eval"));
+        }
+
+        if data_format == DataFormat::Binpack {
+            // these read --data by record index, which a binpack file has no fixed size for
+            let by_record = [
+                ("--record-size", record_size != data::RECORD_SIZE),
+                ("--val-split", val_split.is_some()),
+                ("--holdout-file", holdout_file.is_some()),
+                ("--io-retries", io_retries > 0),
+                ("--superbatch-equals-epoch", superbatch_equals_epoch),
+                ("--reweight-buckets", reweight_buckets),
+                ("--compare-loss", compare_loss_positions.is_some()),
+                ("--lr-find", lr_find),
+                ("--loss-by-bucket", loss_by_bucket),
+                ("--loss-clip", loss_clip.is_some()),
+                ("--stop-at-loss", stop_at_loss.is_some()),
+                ("--recover-on-divergence", recover_on_divergence.is_some()),
+                ("--reduce-on-plateau", reduce_on_plateau.is_some()),
+                ("--compare-quant-scales", !compare_quant_scales.is_empty()),
+                ("--print-feature-coverage", print_feature_coverage),
+                ("--eval-symmetry-check", eval_symmetry_check),
+                ("--dump-activations", dump_activations.is_some()),
+            ];
+            if let Some((flag, _)) = by_record.into_iter().find(|&(_, set)| set) {
+                return Err(ConfigError::Conflict(flag, "--data-format binpack"));
+            }
+        }
+        if superbatch_equals_epoch && positions_per_superbatch.is_some() {
+            return Err(ConfigError::Conflict("--superbatch-equals-epoch", "--positions-per-superbatch"));
+        }
+
+        if export_ema && ema.is_none() {
+            return Err(ConfigError::Requires("--export-ema", "--ema"));
+        }
+
+        if quantize_only.is_some() != export_net.is_some() {
+            return Err(if quantize_only.is_some() {
+                ConfigError::Requires("--quantize-only", "--export-net")
+            } else {
+                ConfigError::Requires("--export-net", "--quantize-only")
+            });
+        }
+
+        if compare_loss_positions.is_some() && load_weights.is_none() {
+            return Err(ConfigError::Requires("--compare-loss", "--load"));
+        }
+        if compare_loss_positions.is_some() && lr_find {
+            return Err(ConfigError::Conflict("--compare-loss", "--lr-find"));
+        }
+
+        if sparsity_report.is_some() && load_weights.is_none() {
+            return Err(ConfigError::Requires("--sparsity-report", "--load"));
+        }
+        if sparsity_list.is_some() && sparsity_report.is_none() {
+            return Err(ConfigError::Requires("--sparsity-list", "--sparsity-report"));
+        }
+        if !init_from_average.is_empty() && load_weights.is_some() {
+            return Err(ConfigError::Conflict("--init-from-average", "--load"));
+        }
+        if holdout_file.as_ref() == Some(&dataset_path) {
+            return Err(ConfigError::InvalidValue {
+                flag: "--holdout-file".to_string(),
+                value: format!("{} is the training data", dataset_path),
+            });
+        }
+        if snapshot_on_best.is_some() && val_split.is_none() {
+            return Err(ConfigError::Requires("--snapshot-on-best", "--val-split"));
+        }
+        if chunk_superbatches.is_some() && final_only_save {
+            return Err(ConfigError::Conflict("--chunk-superbatches", "--final-only-save"));
+        }
+        if progressive_hl.is_some() && ema.is_some() {
+            return Err(ConfigError::Conflict("--progressive-hl", "--ema"));
+        }
+        if progressive_hl.is_some() && validate_shapes_against_header {
+            return Err(ConfigError::Conflict("--progressive-hl", "--validate-shapes-against-header"));
+        }
+        if cpu && require_gpu {
+            return Err(ConfigError::Conflict("--cpu", "--require-gpu"));
+        }
+        if fen_list.is_some() && eval_net.is_none() {
+            return Err(ConfigError::Requires("--fen-list", "--eval-net"));
+        }
+        if export_piece_values && eval_net.is_none() {
+            return Err(ConfigError::Requires("--export-piece-values", "--eval-net"));
+        }
+        if dump_activations.is_some() && eval_net.is_none() {
+            return Err(ConfigError::Requires("--dump-activations", "--eval-net"));
+        }
+        if dump_count.is_some() && dump_activations.is_none() {
+            return Err(ConfigError::Requires("--dump-count", "--dump-activations"));
+        }
+        if eval_symmetry_check && eval_net.is_none() {
+            return Err(ConfigError::Requires("--eval-symmetry-check", "--eval-net"));
+        }
+        if fen_output.is_some() && fen_list.is_none() {
+            return Err(ConfigError::Requires("--fen-output", "--fen-list"));
+        }
+        if validate_shapes_against_header && load_weights.is_none() {
+            return Err(ConfigError::Requires("--validate-shapes-against-header", "--load"));
+        }
+        if resumeable_seed.is_some() && target_noise_seed.is_some() {
+            return Err(ConfigError::Conflict("--resumeable-seed", "--target-noise-seed"));
+        }
+        if target_noise_seed.is_some() && target_noise.is_none() {
+            return Err(ConfigError::Requires("--target-noise-seed", "--target-noise"));
+        }
+        if subsample_seed.is_some() && subsample.is_none() {
+            return Err(ConfigError::Requires("--subsample-seed", "--subsample"));
+        }
+        // format 1 unless a description asks for format 2, so nets keep
+        // loading in engine builds that predate it
+        let save_format_version = save_format_version.unwrap_or(if net_description.is_some() {
+            net::DESCRIPTION_FORMAT
+        } else {
+            net::DEFAULT_SAVE_FORMAT
+        });
+        if net_description.is_some() && save_format_version < net::DESCRIPTION_FORMAT {
+            return Err(ConfigError::Conflict("--net-description", "--save-format-version 1"));
+        }
+        if resume_safe && load_weights.is_none() {
+            return Err(ConfigError::Requires("--resume-safe", "--load"));
+        }
+
+        if finetune && load_weights.is_none() {
+            return Err(ConfigError::FinetuneWithoutLoad);
+        }
+
+        // finetune only fills in what the user left unspecified
+        let mut finetune_defaults = Vec::new();
+        if finetune {
+            if superbatches.is_none() {
+                let end = start_superbatch + FINETUNE_SUPERBATCHES - 1;
+                superbatches = Some(end);
+                finetune_defaults.push(format!("superbatches={}", end));
+            }
+            if initial_lr.is_none() {
+                initial_lr = Some(FINETUNE_LR);
+                finetune_defaults.push(format!("lr={}", FINETUNE_LR));
+            }
+            if final_lr.is_none() {
+                final_lr = Some(FINETUNE_LR * 0.3);
+                finetune_defaults.push(format!("final-lr={}", FINETUNE_LR * 0.3));
+            }
+        }
+        let initial_lr = initial_lr.unwrap_or(0.001);
+        let batches_per_superbatch = schedule::resolve_batches_per_superbatch(
+            batch_size,
+            batches_per_superbatch,
+            positions_per_superbatch,
+            superbatch_equals_epoch,
+        )
+        .map_err(ConfigError::Sizing)?;
+
+        let superbatches = superbatches.unwrap_or(640);
+        if start_superbatch == 0 || start_superbatch > superbatches {
+            return Err(ConfigError::InvalidValue {
+                flag: "--start".to_string(),
+                value: format!("{}: superbatches are numbered from 1 to {}", start_superbatch, superbatches),
+            });
+        }
+        if let Some(growth) = progressive_hl {
+            // the finished net is the one the engine reads, so that is where growth ends
+            if growth.end != hl_size {
+                return Err(ConfigError::InvalidValue {
+                    flag: "--progressive-hl".to_string(),
+                    value: format!("{}: growth has to end at --hl-size {}", growth, hl_size),
+                });
+            }
+            if growth.at >= superbatches {
+                return Err(ConfigError::InvalidValue {
+                    flag: "--progressive-hl".to_string(),
+                    value: format!(
+                        "{}: superbatch {} leaves no superbatch to train at {}",
+                        growth, growth.at, growth.end
+                    ),
+                });
+            }
+        }
+
+        let run_name = run_name.unwrap_or_else(|| net_id.clone());
+        let output_directory =
+            if flat_output { OUTPUT_ROOT.to_string() } else { format!("{}/{}", OUTPUT_ROOT, run_name) };
+
+        Ok(Config {
+            dataset_path,
+            dataset_manifest,
+            val_split,
+            holdout_file,
+            compare_quant_scales,
+            snapshot_on_best,
+            superbatches,
+            start_superbatch,
+            load_weights,
+            init_from_average,
+            run_name,
+            net_id,
+            threads: if deterministic { 1 } else { threads },
+            batch_queue: if deterministic { Some(1) } else { batch_queue },
+            max_ram_mb,
+            pin_threads,
+            deterministic,
+            cpu,
+            require_gpu,
+            save_rate,
+            final_only_save,
+            chunk_superbatches,
+            force,
+            stop_at_loss,
+            recover_on_divergence,
+            reduce_on_plateau,
+            also_save_fp32,
+            record_git_state,
+            full_hl_size: hl_size,
+            single_perspective,
+            output_factoriser,
+            hidden_dropout,
+            progressive_hl,
+            initial_lr,
+            final_lr: final_lr.unwrap_or(initial_lr * 0.3f32.powi(5)),
+            l1_lr,
+            lr_find,
+            compare_loss_positions,
+            sparsity_report,
+            sparsity_list,
+            resume_safe,
+            replay_log,
+            dataset_stats,
+            print_feature_coverage,
+            fen_list,
+            eval_net,
+            export_piece_values,
+            eval_symmetry_check,
+            dump_activations,
+            dump_count: dump_count.unwrap_or(activations::DEFAULT_COUNT),
+            print_memory_plan,
+            list_checkpoints,
+            diff,
+            fen_output,
+            check_nan,
+            validate_shapes_against_header,
+            export_c_header,
+            quantize_only,
+            export_net,
+            save_format_version,
+            net_description,
+            finetune,
+            finetune_defaults,
+            report_interval,
+            loss_by_bucket,
+            metric_window: metric_window.or(accumulate_metrics.then_some(DEFAULT_METRIC_WINDOW)),
+            target_clamp_report,
+            min_free_mb,
+            output_directory,
+            batch_size,
+            batches_per_superbatch,
+            requested_positions_per_superbatch: positions_per_superbatch,
+            superbatch_equals_epoch,
+            wdl_by_phase,
+            schedule_anchor,
+            target_from: target_from.unwrap_or(TargetSource::Blend),
+            wdl: wdl.unwrap_or(0.0),
+            wdl_smooth,
+            filter_eval_max,
+            filter_no_check,
+            skip_bad_records,
+            holdout_buckets,
+            subsample,
+            subsample_seed: subsample_seed.unwrap_or(0),
+            target_noise,
+            target_noise_seed: target_noise_seed.unwrap_or(0),
+            resumeable_seed,
+            reweight_buckets,
+            log_level,
+            strict,
+            record_size,
+            data_format,
+            io_retries,
+            warm_cache,
+            engine_scale,
+            loss_target_scale,
+            loss_clip,
+            ema,
+            export_ema,
+            profile,
+            print_layer_lr,
+            weights_histogram,
+            summary_json,
+            sanity_startpos,
+            dump_config,
+        })
+    }
+
+    /// The config as a `--config` file: every setting under its flag's name,
+    /// so `--config <run>.toml` repeats the run. `dump_config` is left out so
+    /// loading the file trains instead of dumping again, as are the values
+    /// other flags derive (the output directory, finetune's defaults, a
+    /// superbatch sized by positions or by the epoch). `--diff` takes two
+    /// values, which a file cannot give one flag, so it is not written either.
+    /// Floats are written as the shortest decimal that reads back as the same
+    /// f32, so `--lr 0.002` stays 0.002.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        use toml::Value;
+
+        let float = |f: f32| Value::Float(f.to_string().parse().unwrap_or(f64::from(f)));
+        // seeds may not fit a TOML integer, and the flag reads a string just as well
+        let int = |n: u64| i64::try_from(n).map_or_else(|_| Value::String(n.to_string()), Value::Integer);
+        let size = |n: usize| int(n as u64);
+        let text = |s: &str| Value::String(s.to_string());
+
+        fn set(table: &mut toml::Table, flag: &str, value: impl Into<Option<Value>>) {
+            if let Some(value) = value.into() {
+                table.insert(flag.to_string(), value);
+            }
+        }
+        let mut table = toml::Table::new();
+        let switches = [
+            ("pin-threads", self.pin_threads),
+            ("deterministic", self.deterministic),
+            ("cpu", self.cpu),
+            ("require-gpu", self.require_gpu),
+            ("final-only-save", self.final_only_save),
+            ("flat-output", self.output_directory == OUTPUT_ROOT),
+            ("force", self.force),
+            ("also-save-fp32", self.also_save_fp32),
+            ("record-git-state", self.record_git_state),
+            ("single-perspective", self.single_perspective),
+            ("output-factoriser", self.output_factoriser),
+            ("lr-find", self.lr_find),
+            ("resume-safe", self.resume_safe),
+            ("print-feature-coverage", self.print_feature_coverage),
+            ("export-piece-values", self.export_piece_values),
+            ("eval-symmetry-check", self.eval_symmetry_check),
+            ("print-memory-plan", self.print_memory_plan),
+            ("list-checkpoints", self.list_checkpoints),
+            ("check-nan", self.check_nan),
+            ("validate-shapes-against-header", self.validate_shapes_against_header),
+            ("finetune", self.finetune),
+            ("loss-by-bucket", self.loss_by_bucket),
+            ("target-clamp-report", self.target_clamp_report),
+            ("superbatch-equals-epoch", self.superbatch_equals_epoch),
+            ("filter-no-check", self.filter_no_check),
+            ("skip-bad-records", self.skip_bad_records),
+            ("reweight-buckets", self.reweight_buckets),
+            ("quiet", self.log_level == Level::Quiet),
+            ("verbose", self.log_level == Level::Verbose),
+            ("strict", self.strict),
+            ("warm-cache", self.warm_cache),
+            ("export-ema", self.export_ema),
+            ("profile", self.profile),
+            ("print-layer-lr", self.print_layer_lr),
+        ];
+        for (flag, on) in switches {
+            set(&mut table, flag, Value::Boolean(on));
+        }
+
+        set(&mut table, "data", text(&self.dataset_path));
+        set(&mut table, "data-format", text(&self.data_format.to_string()));
+        set(&mut table, "record-size", size(self.record_size));
+        set(&mut table, "io-retries", size(self.io_retries));
+        set(&mut table, "dataset-manifest", self.dataset_manifest.as_deref().map(text));
+        set(&mut table, "val-split", self.val_split.map(float));
+        set(&mut table, "holdout-file", self.holdout_file.as_deref().map(text));
+        set(&mut table, "snapshot-on-best", self.snapshot_on_best.as_deref().map(text));
+        if !self.compare_quant_scales.is_empty() {
+            let scales = self.compare_quant_scales.iter().map(|&qa| Value::Integer(qa.into())).collect();
+            set(&mut table, "compare-quant-scales", Value::Array(scales));
+        }
+
+        set(&mut table, "name", text(&self.net_id));
+        set(&mut table, "run-name", text(&self.run_name));
+        set(&mut table, "start", size(self.start_superbatch));
+        set(&mut table, "load", self.load_weights.as_deref().map(text));
+        if !self.init_from_average.is_empty() {
+            set(
+                &mut table,
+                "init-from-average",
+                Value::Array(self.init_from_average.iter().map(|path| text(path)).collect()),
+            );
+        }
+
+        // finetune fills these in again, from the same --start
+        let defaulted = |flag: &str| self.finetune_defaults.iter().any(|d| d.split('=').next() == Some(flag));
+        for (flag, value) in [
+            ("superbatches", size(self.superbatches)),
+            ("lr", float(self.initial_lr)),
+            ("final-lr", float(self.final_lr)),
+        ] {
+            if !defaulted(flag) {
+                set(&mut table, flag, value);
+            }
+        }
+        set(&mut table, "l1-lr", self.l1_lr.map(float));
+        set(
+            &mut table,
+            "schedule-anchor",
+            self.schedule_anchor.map(|(first, last)| text(&format!("{}:{}", first, last))),
+        );
+        set(
+            &mut table,
+            "reduce-on-plateau",
+            self.reduce_on_plateau.map(|p| text(&format!("{}:{}:{}", p.factor, p.patience, p.min_delta))),
+        );
+        set(&mut table, "batch-size", size(self.batch_size));
+        match self.requested_positions_per_superbatch {
+            Some(positions) => set(&mut table, "positions-per-superbatch", size(positions)),
+            None if self.superbatch_equals_epoch => {}
+            None => set(&mut table, "batches-per-superbatch", size(self.batches_per_superbatch)),
+        }
+
+        set(&mut table, "threads", size(self.threads));
+        set(&mut table, "batch-queue", self.batch_queue.map(size));
+        set(&mut table, "max-ram-mb", self.max_ram_mb.map(int));
+        set(&mut table, "min-free-mb", int(self.min_free_mb));
+        set(&mut table, "save-rate", size(self.save_rate));
+        set(&mut table, "chunk-superbatches", self.chunk_superbatches.map(size));
+        set(&mut table, "stop-at-loss", self.stop_at_loss.map(float));
+        set(&mut table, "recover-on-divergence", self.recover_on_divergence.map(size));
+        set(&mut table, "hidden-dropout", float(self.hidden_dropout));
+        set(&mut table, "hl-size", size(self.full_hl_size));
+        set(&mut table, "progressive-hl", self.progressive_hl.map(|growth| text(&growth.to_string())));
+        set(&mut table, "save-format-version", int(self.save_format_version.into()));
+        set(&mut table, "net-description", self.net_description.as_deref().map(text));
+
+        match self.wdl_by_phase {
+            Some((opening, endgame)) => set(&mut table, "wdl-by-phase", text(&format!("{}:{}", opening, endgame))),
+            None => {
+                set(&mut table, "target-from", text(&self.target_from.to_string()));
+                if self.target_from == TargetSource::Blend {
+                    set(&mut table, "wdl", float(self.wdl));
+                }
+            }
+        }
+        set(&mut table, "wdl-smooth", float(self.wdl_smooth));
+        set(&mut table, "engine-scale", self.engine_scale.map(float));
+        set(&mut table, "loss-target-scale", self.loss_target_scale.map(float));
+        set(&mut table, "loss-clip", self.loss_clip.map(float));
+        set(&mut table, "ema", self.ema.map(float));
+        set(&mut table, "filter-eval-max", self.filter_eval_max.map(|max| Value::Integer(max.into())));
+        if !self.holdout_buckets.is_empty() {
+            set(
+                &mut table,
+                "holdout-buckets",
+                Value::Array(self.holdout_buckets.iter().map(|&bucket| size(bucket)).collect()),
+            );
+        }
+        if let Some(fraction) = self.subsample {
+            set(&mut table, "subsample", float(fraction));
+            set(&mut table, "subsample-seed", int(self.subsample_seed));
+        }
+        if let Some(sigma) = self.target_noise {
+            set(&mut table, "target-noise", float(sigma));
+            if self.resumeable_seed.is_none() {
+                set(&mut table, "target-noise-seed", int(self.target_noise_seed));
+            }
+        }
+        set(&mut table, "resumeable-seed", self.resumeable_seed.map(int));
+
+        set(&mut table, "report-interval", size(self.report_interval));
+        set(&mut table, "metric-window", self.metric_window.map(size));
+        set(&mut table, "compare-loss", self.compare_loss_positions.map(size));
+        set(&mut table, "sparsity-report", self.sparsity_report.map(float));
+        set(&mut table, "sparsity-list", self.sparsity_list.as_deref().map(text));
+        set(&mut table, "replay-log", self.replay_log.as_deref().map(text));
+        set(&mut table, "dataset-stats", self.dataset_stats.as_deref().map(text));
+        set(&mut table, "fen-list", self.fen_list.as_deref().map(text));
+        set(&mut table, "fen-output", self.fen_output.as_deref().map(text));
+        set(&mut table, "eval-net", self.eval_net.as_deref().map(text));
+        if let Some(path) = &self.dump_activations {
+            set(&mut table, "dump-activations", text(path));
+            set(&mut table, "dump-count", size(self.dump_count));
+        }
+        set(&mut table, "export-c-header", self.export_c_header.as_deref().map(text));
+        set(&mut table, "quantize-only", self.quantize_only.as_deref().map(text));
+        set(&mut table, "export-net", self.export_net.as_deref().map(text));
+        set(&mut table, "weights-histogram", self.weights_histogram.as_deref().map(text));
+        set(&mut table, "summary-json", self.summary_json.as_deref().map(text));
+        set(&mut table, "sanity-startpos", self.sanity_startpos.map(|cp| Value::Integer(cp.into())));
+        toml::to_string(&table)
+    }
+
+    /// WDL proportion of the targets, when it does not depend on the position.
+    pub fn target_wdl_proportion(&self) -> f32 {
+        self.target_from.wdl_proportion(self.wdl)
+    }
+
+    /// Whether the loader computes the targets itself rather than leaving the
+    /// WDL blend to bullet.
+    pub fn loader_blends_wdl(&self) -> bool {
+        self.wdl_by_phase.is_some() || self.wdl_smooth > 0.0
+    }
+
+    /// Hidden layer width the graph is built with: `--progressive-hl`'s start
+    /// width until its transition, `--hl-size` after it.
+    pub fn hl_size(&self) -> usize {
+        self.progressive_hl.map_or(self.full_hl_size, |growth| growth.width(self.start_superbatch))
+    }
+
+    /// Bytes per record the loaders read from `--data`; `None` for binpack,
+    /// which is variable-length.
+    pub fn data_record_size(&self) -> Option<usize> {
+        match self.data_format {
+            DataFormat::Direct => Some(data::RECORD_SIZE),
+            DataFormat::Binpack => None,
+        }
+    }
+
+    /// WDL proportion for bullet's schedule. A phase-aware or smoothed blend
+    /// is folded into the targets by the loader instead, so the schedule then
+    /// uses 0.
+    pub fn wdl_proportion(&self) -> f32 {
+        if self.loader_blends_wdl() { 0.0 } else { self.target_wdl_proportion() }
+    }
+}
+
+/// Consumes the value following the flag at `args[*i]`.
+pub(crate) fn value<T: FromStr>(args: &[String], i: &mut usize) -> Result<T, ConfigError> {
+    let flag = &args[*i];
+    *i += 1;
+    let raw = args.get(*i).ok_or_else(|| ConfigError::MissingValue(flag.clone()))?;
+    raw.parse().map_err(|_| ConfigError::InvalidValue { flag: flag.clone(), value: raw.clone() })
+}
+
+/// [`value`] for a flag whose value may be left out: the next argument is
+/// its value unless there is none or it is another flag.
+fn optional_value<T: FromStr>(args: &[String], i: &mut usize) -> Result<Option<T>, ConfigError> {
+    match args.get(*i + 1) {
+        Some(next) if !next.starts_with('-') => value(args, i).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// [`value`] for a float that has to be finite and above 0.
+fn positive_value(args: &[String], i: &mut usize) -> Result<f32, ConfigError> {
+    let flag = args[*i].clone();
+    let parsed: f32 = value(args, i)?;
+    if !(parsed > 0.0 && parsed.is_finite()) {
+        return Err(ConfigError::InvalidValue { flag, value: args[*i].clone() });
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        let args: Vec<String> = std::iter::once("training").chain(args.iter().copied()).map(String::from).collect();
+        Config::from_args(&args)
+    }
+
+    #[test]
+    fn defaults_without_flags() {
+        let config = parse(&[]).unwrap();
+        assert_eq!(config.dataset_path, "data/baseline.data");
+        assert_eq!(config.net_id, "sleepmind");
+        assert_eq!(config.run_name, "sleepmind");
+        assert_eq!(config.output_directory, "checkpoints/sleepmind");
+        assert_eq!((config.start_superbatch, config.superbatches), (1, 640));
+        assert_eq!(config.save_rate, 10);
+        assert_eq!(config.batch_size, schedule::DEFAULT_BATCH_SIZE);
+        assert_eq!(config.batches_per_superbatch, schedule::DEFAULT_BATCHES_PER_SUPERBATCH);
+        assert_eq!(config.initial_lr, 0.001);
+        assert_eq!(config.final_lr, 0.001 * 0.3f32.powi(5));
+        assert_eq!(config.load_weights, None);
+        assert_eq!(config.log_level, Level::Normal);
+        assert!(!config.finetune && config.finetune_defaults.is_empty());
+    }
+
+    #[test]
+    fn flags_override_defaults() {
+        let config =
+            parse(&["-d", "a.data", "-n", "net", "-s", "20", "--start", "5", "--lr", "0.002", "--save-rate", "4"])
+                .unwrap();
+        assert_eq!(config.dataset_path, "a.data");
+        assert_eq!(config.net_id, "net");
+        assert_eq!(config.output_directory, "checkpoints/net");
+        assert_eq!((config.start_superbatch, config.superbatches), (5, 20));
+        assert_eq!(config.initial_lr, 0.002);
+        assert_eq!(config.save_rate, 4);
+        // long and short forms are the same flag, the last one wins
+        assert_eq!(parse(&["--name", "a", "-n", "b"]).unwrap().net_id, "b");
+        assert_eq!(parse(&["--quiet"]).unwrap().log_level, Level::Quiet);
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        assert_eq!(parse(&["--no-such-flag"]), Err(ConfigError::UnknownFlag("--no-such-flag".to_string())));
+        assert_eq!(parse(&["stray"]), Err(ConfigError::UnknownFlag("stray".to_string())));
+    }
+
+    #[test]
+    fn rejects_missing_and_invalid_values() {
+        assert_eq!(parse(&["--superbatches"]), Err(ConfigError::MissingValue("--superbatches".to_string())));
+        assert_eq!(parse(&["-d"]), Err(ConfigError::MissingValue("-d".to_string())));
+        assert_eq!(
+            parse(&["--lr", "fast"]),
+            Err(ConfigError::InvalidValue { flag: "--lr".to_string(), value: "fast".to_string() })
+        );
+        assert_eq!(
+            parse(&["--batch-size", "0"]),
+            Err(ConfigError::InvalidValue { flag: "--batch-size".to_string(), value: "0".to_string() })
+        );
+        assert_eq!(
+            parse(&["--record-size", "0"]),
+            Err(ConfigError::InvalidValue { flag: "--record-size".to_string(), value: "0".to_string() })
+        );
+    }
+
+    #[test]
+    fn sizing_flags_resolve_to_one_batch_count() {
+        let sizing = |args: &[&str]| parse(args).map(|config| (config.batch_size, config.batches_per_superbatch));
+        assert_eq!(sizing(&[]), Ok((schedule::DEFAULT_BATCH_SIZE, schedule::DEFAULT_BATCHES_PER_SUPERBATCH)));
+        assert_eq!(sizing(&["--batch-size", "1000"]), Ok((1000, schedule::DEFAULT_BATCHES_PER_SUPERBATCH)));
+        assert_eq!(sizing(&["--batch-size", "1000", "--batches-per-superbatch", "20"]), Ok((1000, 20)));
+        assert_eq!(sizing(&["--batch-size", "1000", "--positions-per-superbatch", "20400"]), Ok((1000, 20)));
+        // both given and agreeing is fine
+        assert_eq!(
+            sizing(&["--batch-size", "1000", "--batches-per-superbatch", "20", "--positions-per-superbatch", "20400"]),
+            Ok((1000, 20))
+        );
+        assert!(sizing(&["--superbatch-equals-epoch", "--batch-size", "1000"]).is_ok());
+    }
+
+    #[test]
+    fn contradicting_sizing_flags_are_errors() {
+        assert_eq!(
+            parse(&["--batch-size", "1000", "--batches-per-superbatch", "20", "--positions-per-superbatch", "30000"]),
+            Err(ConfigError::Sizing(SizingConflict::BatchesWithPositions {
+                batches: 20,
+                batch_size: 1000,
+                positions: 30_000
+            }))
+        );
+        assert_eq!(
+            parse(&["--superbatch-equals-epoch", "--batches-per-superbatch", "20"]),
+            Err(ConfigError::Sizing(SizingConflict::BatchesWithEpoch))
+        );
+        assert_eq!(
+            parse(&["--superbatch-equals-epoch", "--positions-per-superbatch", "20000"]),
+            Err(ConfigError::Conflict("--superbatch-equals-epoch", "--positions-per-superbatch"))
+        );
+        assert!(matches!(parse(&["--batches-per-superbatch", "0"]), Err(ConfigError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn target_noise_of_zero_is_off() {
+        assert_eq!(parse(&["--target-noise", "0"]).unwrap().target_noise, None);
+        let config = parse(&["--target-noise", "12.5", "--target-noise-seed", "4"]).unwrap();
+        assert_eq!((config.target_noise, config.target_noise_seed), (Some(12.5), 4));
+    }
+
+    #[test]
+    fn help_wins_over_other_flags() {
+        assert_eq!(parse(&["-s", "5", "--help"]), Err(ConfigError::HelpRequested));
+    }
+
+    #[test]
+    fn log_level_from_quiet_and_verbose() {
+        assert_eq!(parse(&["--quiet"]).unwrap().log_level, Level::Quiet);
+        assert_eq!(parse(&["--verbose"]).unwrap().log_level, Level::Verbose);
+        assert_eq!(parse(&["--quiet", "--verbose"]), Err(ConfigError::Conflict("--quiet", "--verbose")));
+    }
+
+    #[test]
+    fn finetune_fills_in_unset_values() {
+        let config = parse(&["--finetune", "--load", "net.wgts"]).unwrap();
+        assert_eq!(config.superbatches, FINETUNE_SUPERBATCHES);
+        assert_eq!(config.initial_lr, FINETUNE_LR);
+        assert_eq!(config.final_lr, FINETUNE_LR * 0.3);
+        assert_eq!(config.finetune_defaults.len(), 3);
+    }
+
+    #[test]
+    fn finetune_keeps_explicit_values() {
+        let config = parse(&["--finetune", "--load", "net.wgts", "-s", "100", "--lr", "0.01"]).unwrap();
+        assert_eq!(config.superbatches, 100);
+        assert_eq!(config.initial_lr, 0.01);
+        assert_eq!(config.final_lr, FINETUNE_LR * 0.3);
+        assert_eq!(config.finetune_defaults, vec![format!("final-lr={}", FINETUNE_LR * 0.3)]);
+    }
+
+    #[test]
+    fn finetune_length_counts_from_start() {
+        let config = parse(&["--finetune", "--load", "net.wgts", "--start", "641"]).unwrap();
+        assert_eq!(config.superbatches, 640 + FINETUNE_SUPERBATCHES);
+    }
+
+    #[test]
+    fn finetune_requires_load() {
+        assert_eq!(parse(&["--finetune"]), Err(ConfigError::FinetuneWithoutLoad));
+    }
+
+    #[test]
+    fn compare_loss_takes_positions_and_needs_load() {
+        let config = parse(&["--load", "net.bin", "--compare-loss", "512"]).unwrap();
+        assert_eq!(config.compare_loss_positions, Some(512));
+        let config = parse(&["--compare-loss", "--load", "net.bin"]).unwrap();
+        assert_eq!(config.compare_loss_positions, Some(DEFAULT_COMPARE_LOSS_POSITIONS));
+        let config = parse(&["--load", "net.bin", "--compare-loss"]).unwrap();
+        assert_eq!(config.compare_loss_positions, Some(DEFAULT_COMPARE_LOSS_POSITIONS));
+        let config = parse(&["--load", "net.bin", "--compare-loss-positions", "512"]).unwrap();
+        assert_eq!(config.compare_loss_positions, Some(512));
+        assert_eq!(
+            parse(&["--load", "net.bin", "--compare-loss", "many"]),
+            Err(ConfigError::InvalidValue { flag: "--compare-loss".to_string(), value: "many".to_string() })
+        );
+        assert_eq!(parse(&["--compare-loss"]), Err(ConfigError::Requires("--compare-loss", "--load")));
+    }
+
+    #[test]
+    fn run_name_and_net_id_resolve_independently() {
+        let config = parse(&["--name", "engine", "--run-name", "lr-sweep-3"]).unwrap();
+        assert_eq!((config.net_id.as_str(), config.run_name.as_str()), ("engine", "lr-sweep-3"));
+        assert_eq!(config.output_directory, "checkpoints/lr-sweep-3");
+        assert_eq!(parse(&["--checkpoint-prefix", "a", "-n", "b"]).unwrap().run_name, "a");
+        assert_eq!(parse(&["-n", "b"]).unwrap().run_name, "b");
+    }
+
+    #[test]
+    fn flat_output_writes_straight_into_the_root() {
+        assert_eq!(parse(&["--run-name", "lr-sweep-3", "--flat-output"]).unwrap().output_directory, OUTPUT_ROOT);
+    }
+
+    #[test]
+    fn pin_threads_is_off_unless_given() {
+        assert!(!parse(&[]).unwrap().pin_threads);
+        assert!(parse(&["--pin-threads"]).unwrap().pin_threads);
+    }
+
+    #[test]
+    fn target_source_selects_the_wdl_proportion() {
+        assert_eq!(TargetSource::Eval.wdl_proportion(0.3), 0.0);
+        assert_eq!(TargetSource::Wdl.wdl_proportion(0.3), 1.0);
+        assert_eq!(TargetSource::Blend.wdl_proportion(0.3), 0.3);
+        for source in [TargetSource::Eval, TargetSource::Wdl, TargetSource::Blend] {
+            assert_eq!(source.to_string().parse(), Ok(source));
+        }
+    }
+
+    #[test]
+    fn target_source_parses_and_conflicts_with_a_forced_wdl() {
+        let config = parse(&["--target-from", "wdl"]).unwrap();
+        assert_eq!(config.target_from.wdl_proportion(config.wdl), 1.0);
+        let config = parse(&["--target-from", "blend", "--wdl", "0.4"]).unwrap();
+        assert_eq!(config.target_from.wdl_proportion(config.wdl), 0.4);
+        assert_eq!(
+            parse(&["--target-from", "eval", "--wdl", "0.4"]),
+            Err(ConfigError::Conflict("--wdl", "--target-from eval|wdl"))
+        );
+        assert!(matches!(parse(&["--target-from", "score"]), Err(ConfigError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn hidden_dropout_is_a_probability_below_one() {
+        assert_eq!(parse(&["--hidden-dropout", "0.1"]).unwrap().hidden_dropout, 0.1);
+        assert_eq!(parse(&[]).unwrap().hidden_dropout, 0.0);
+        for p in ["1", "-0.1"] {
+            assert!(matches!(parse(&["--hidden-dropout", p]), Err(ConfigError::InvalidValue { .. })), "{}", p);
+        }
+    }
+
+    #[test]
+    fn save_format_version_must_be_supported() {
+        assert_eq!(parse(&["--save-format-version", "2"]).unwrap().save_format_version, 2);
+        assert_eq!(
+            parse(&["--save-format-version", "3"]),
+            Err(ConfigError::InvalidValue { flag: "--save-format-version".to_string(), value: "3".to_string() })
+        );
+        assert_eq!(
+            parse(&["--save-format-version", "1", "--net-description", "x"]),
+            Err(ConfigError::Conflict("--net-description", "--save-format-version 1"))
+        );
+    }
+
+    #[test]
+    fn hl_size_sets_the_width_growth_ends_at() {
+        assert_eq!(parse(&[]).unwrap().hl_size(), net::HL_SIZE);
+        let config = parse(&["--hl-size", "512", "-s", "10", "--progressive-hl", "256:512:4"]).unwrap();
+        assert_eq!((config.full_hl_size, config.hl_size()), (512, 256));
+        assert_eq!(
+            parse(&["-s", "10", "--progressive-hl", "256:512:4"]),
+            Err(ConfigError::InvalidValue {
+                flag: "--progressive-hl".to_string(),
+                value: format!("256:512:4: growth has to end at --hl-size {}", net::HL_SIZE),
+            })
+        );
+        assert_eq!(
+            parse(&["--hl-size", "0"]),
+            Err(ConfigError::InvalidValue { flag: "--hl-size".to_string(), value: "0".to_string() })
+        );
+    }
+
+    /// `config` dumped to a file and read back with `--config`.
+    fn reload(config: &Config, name: &str) -> Config {
+        let path = crate::test_util::temp_path(name);
+        std::fs::write(&path, config.to_toml().unwrap()).unwrap();
+        let reloaded = parse(&["--config", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        reloaded.unwrap()
+    }
+
+    #[test]
+    fn dumped_config_reads_back_the_same() {
+        let config = parse(&[
+            "-d",
+            "a.data",
+            "-n",
+            "net",
+            "-s",
+            "40",
+            "--lr",
+            "0.002",
+            "--schedule-anchor",
+            "1:40",
+            "--reduce-on-plateau",
+            "0.5:3:0.001",
+            "--wdl-by-phase",
+            "0.2:0.6",
+            "--holdout-buckets",
+            "0,7",
+            "--target-noise",
+            "20",
+            "--hl-size",
+            "512",
+            "--cpu",
+            "--quiet",
+            "--dump-config",
+            "run.toml",
+        ])
+        .unwrap();
+        let text = config.to_toml().unwrap();
+        assert!(text.contains("\nlr = 0.002\n") && text.contains("\nwdl-by-phase = \"0.2:0.6\"\n"), "{}", text);
+        assert!(!text.contains("dump-config"), "{}", text);
+        assert_eq!(reload(&config, "dump-a.toml"), Config { dump_config: None, ..config });
+    }
+
+    #[test]
+    fn derived_settings_read_back_from_the_flags_they_came_from() {
+        let runs: [&[&str]; 5] = [
+            &["--load", "a.wgts", "--finetune", "--start", "5", "--lr", "0.0002", "--run-name", "ft", "--flat-output"],
+            &["--superbatch-equals-epoch", "--target-from", "eval", "--deterministic", "--verbose"],
+            &[
+                "--positions-per-superbatch",
+                "1000000",
+                "--batch-size",
+                "4096",
+                "--target-noise",
+                "8",
+                "--resumeable-seed",
+                "9",
+            ],
+            &["--subsample", "0.25", "--subsample-seed", "18446744073709551615", "--accumulate-metrics"],
+            &[
+                "--eval-net",
+                "a.bin",
+                "--dump-activations",
+                "acts.csv",
+                "--compare-quant-scales",
+                "255,64",
+                "--init-from-average",
+                "a.wgts,b.wgts",
+            ],
+        ];
+        for args in runs {
+            let config = parse(args).unwrap();
+            assert_eq!(reload(&config, "dump-b.toml"), config, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn the_start_superbatch_is_one_of_the_run() {
+        assert_eq!(parse(&["--start", "40", "-s", "40"]).unwrap().start_superbatch, 40);
+        for (start, superbatches) in [("0", "40"), ("41", "40")] {
+            assert_eq!(
+                parse(&["--start", start, "-s", superbatches]),
+                Err(ConfigError::InvalidValue {
+                    flag: "--start".to_string(),
+                    value: format!("{}: superbatches are numbered from 1 to 40", start),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn scales_and_rates_are_positive() {
+        let config =
+            parse(&["--engine-scale", "380", "--lr", "0.002", "--final-lr", "1e-5", "--l1-lr", "0.01"]).unwrap();
+        assert_eq!(
+            (config.engine_scale, config.initial_lr, config.final_lr, config.l1_lr),
+            (Some(380.0), 0.002, 1e-5, Some(0.01))
+        );
+        assert_eq!(parse(&["--stop-at-loss", "0.01"]).unwrap().stop_at_loss, Some(0.01));
+        for flag in ["--engine-scale", "--stop-at-loss", "--lr", "--final-lr", "--l1-lr"] {
+            for value in ["0", "-400", "NaN", "inf"] {
+                assert_eq!(
+                    parse(&[flag, value]),
+                    Err(ConfigError::InvalidValue { flag: flag.to_string(), value: value.to_string() }),
+                    "{} {}",
+                    flag,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn binpack_data_refuses_record_indexed_features() {
+        assert!(parse(&["--data-format", "binpack"]).is_ok());
+        for extra in [&["--val-split", "0.1"][..], &["--holdout-file", "h.data"], &["--lr-find"]] {
+            let args: Vec<&str> = ["--data-format", "binpack"].into_iter().chain(extra.iter().copied()).collect();
+            assert!(
+                matches!(parse(&args), Err(ConfigError::Conflict(flag, "--data-format binpack")) if flag == extra[0]),
+                "{:?}",
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn format_2_is_opt_in() {
+        assert_eq!(parse(&[]).unwrap().save_format_version, 1);
+        let config = parse(&["--net-description", "tiny net"]).unwrap();
+        assert_eq!((config.save_format_version, config.net_description.as_deref()), (2, Some("tiny net")));
+        assert!(matches!(parse(&["--net-description", "two\nlines"]), Err(ConfigError::InvalidValue { .. })));
+    }
+}
@@ -0,0 +1,117 @@
+//! `--config <PATH>`: trainer flags from a TOML file, so a run's settings
+//! can be kept and versioned along with the experiment.
+//!
+//! Each key is a long flag without its dashes, `_` and `-` alike, with the
+//! value the flag takes: `lr = 0.001` is `--lr 0.001`. `true` sets a switch
+//! and `false` leaves it off, arrays become comma-separated lists, and tables
+//! only group keys, so `superbatches = 400` under `[schedule]` is still
+//! `--superbatches 400`. The file's flags go in front of the command line's,
+//! and since the last occurrence of a flag wins, the command line overrides
+//! the file.
+
+use std::fs;
+
+use crate::{
+    config::ConfigError,
+    net::{BUCKET_LAYOUT, NUM_OUTPUT_BUCKETS},
+};
+
+pub const FLAG: &str = "--config";
+
+/// Architecture keys a file may record but not change: the bucket layout is
+/// compiled into the trainer, so they are checked against this build and
+/// dropped. `hl-size` is an ordinary flag.
+fn compiled_value(key: &str) -> Option<String> {
+    Some(match key {
+        "output-buckets" => NUM_OUTPUT_BUCKETS.to_string(),
+        "bucket-layout" => BUCKET_LAYOUT.map(|b| b.to_string()).join(","),
+        _ => return None,
+    })
+}
+
+/// The flags a config file sets, tables in key order.
+pub fn flags(text: &str) -> Result<Vec<String>, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+        let message = e.message().trim().replace('\n', ", ");
+        match e.span() {
+            Some(span) => format!("line {}: {}", text[..span.start].matches('\n').count() + 1, message),
+            None => message,
+        }
+    })?;
+    let mut out = Vec::new();
+    add_table(&table, &mut out)?;
+    Ok(out)
+}
+
+fn add_table(table: &toml::Table, out: &mut Vec<String>) -> Result<(), String> {
+    for (key, value) in table {
+        let key = key.replace('_', "-");
+        if format!("--{}", key) == FLAG {
+            return Err("a config file cannot load another one".to_string());
+        }
+        let raw = match value {
+            toml::Value::Table(section) => {
+                add_table(section, out)?;
+                continue;
+            }
+            toml::Value::Boolean(set) => {
+                if *set {
+                    out.push(format!("--{}", key));
+                }
+                continue;
+            }
+            value => flag_value(&key, value)?,
+        };
+        match compiled_value(&key) {
+            Some(built) if built == raw => {}
+            Some(built) => {
+                return Err(format!(
+                    "{} is {} in this build, not {}; it is changed by rebuilding the trainer",
+                    key, built, raw
+                ));
+            }
+            None => out.extend([format!("--{}", key), raw]),
+        }
+    }
+    Ok(())
+}
+
+/// `value` as it would be written after the flag on the command line.
+fn flag_value(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Array(values) => {
+            let parts = values.iter().map(|value| match value {
+                toml::Value::String(_) | toml::Value::Integer(_) | toml::Value::Float(_) => flag_value(key, value),
+                other => Err(format!("{}: list entry {} is not a number or string", key, other)),
+            });
+            Ok(parts.collect::<Result<Vec<_>, _>>()?.join(","))
+        }
+        other => Err(format!("{} = {} is not a flag value", key, other)),
+    }
+}
+
+/// `args` (including the program name) with every `--config PATH` replaced
+/// by that file's flags, which are moved in front of the command line's own.
+pub fn expand(args: &[String]) -> Result<Vec<String>, ConfigError> {
+    let (program, rest) = match args.split_first() {
+        Some((program, rest)) => (program, rest),
+        None => return Ok(Vec::new()),
+    };
+    let mut from_files = Vec::new();
+    let mut command_line = Vec::with_capacity(rest.len());
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        if arg != FLAG {
+            command_line.push(arg.clone());
+            continue;
+        }
+        let path = iter.next().ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+        let fail = |message: String| ConfigError::ConfigFile { path: path.clone(), message };
+        let text = fs::read_to_string(path).map_err(|e| fail(e.to_string()))?;
+        from_files.extend(flags(&text).map_err(fail)?);
+    }
+    Ok([program.clone()].into_iter().chain(from_files).chain(command_line).collect())
+}
@@ -0,0 +1,277 @@
+//! `training convert`: text training data as the engine's datagen writes it,
+//! one `<fen> | <eval> | <wdl>` line per position with eval and WDL from
+//! white's point of view, to the bulletformat records the trainer reads.
+//! Replaces `bullet-utils convert --from text` and the ply filter of
+//! process_training_data.sh.
+
+use std::{
+    fmt, fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bullet::game::formats::bulletformat::{BulletFormat, ChessBoard};
+
+use crate::{
+    commands::ToolError,
+    config::{ConfigError, value},
+    data,
+};
+
+pub const USAGE: &str = "\
+Convert text training data (`<fen> | <eval> | <wdl>` lines) to bulletformat records
+
+Usage: training convert --input <PATH> --output <PATH> [OPTIONS]
+
+Options:
+  -i, --input <PATH>       Text file, or a directory searched for .txt files; repeat for more
+  -o, --output <PATH>      Data file to write, the inputs one after another (shuffle it next)
+      --min-ply <N>        Drop positions before game ply N, counted from the FEN's move number
+                           (e.g. 12 for datagen's random opening moves; default: 0, keep all)
+      --skip-bad-lines     Drop and count malformed lines instead of stopping at the first
+  -h, --help               Show this help";
+
+/// Records written per write call.
+const WRITE_BATCH: usize = 16384;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConvertOptions {
+    pub inputs: Vec<String>,
+    pub output: String,
+    pub min_ply: u32,
+    pub skip_bad_lines: bool,
+}
+
+impl ConvertOptions {
+    /// Parses `convert`'s arguments, the program name at index 0.
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut inputs = Vec::new();
+        let mut output: Option<String> = None;
+        let mut min_ply = 0;
+        let mut skip_bad_lines = false;
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--input" | "-i" => inputs.push(value(args, &mut i)?),
+                "--output" | "-o" => output = Some(value(args, &mut i)?),
+                "--min-ply" => min_ply = value(args, &mut i)?,
+                "--skip-bad-lines" => skip_bad_lines = true,
+                "--help" | "-h" => return Err(ConfigError::HelpRequested),
+                flag => return Err(ConfigError::UnknownFlag(flag.to_string())),
+            }
+            i += 1;
+        }
+
+        if inputs.is_empty() {
+            return Err(ConfigError::MissingValue("--input".to_string()));
+        }
+        let output = output.ok_or_else(|| ConfigError::MissingValue("--output".to_string()))?;
+        Ok(Self { inputs, output, min_ply, skip_bad_lines })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConvertSummary {
+    pub files: usize,
+    pub positions: u64,
+    /// Dropped by `--min-ply`.
+    pub early: u64,
+    /// Dropped by `--skip-bad-lines`.
+    pub bad: u64,
+}
+
+impl fmt::Display for ConvertSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Converted {} positions from {} file(s)", self.positions, self.files)?;
+        if self.early > 0 {
+            write!(f, ", dropped {} before --min-ply", self.early)?;
+        }
+        if self.bad > 0 {
+            write!(f, ", skipped {} malformed lines", self.bad)?;
+        }
+        Ok(())
+    }
+}
+
+/// Game ply of a FEN: two per full move, plus one with black to move.
+pub fn ply(fen: &str) -> Result<u32, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let fullmove: u32 = match fields.get(5) {
+        Some(n) => n.parse().map_err(|_| format!("move number '{}' is not a number", n))?,
+        None => return Err("no move number to count plies from".to_string()),
+    };
+    Ok(2 * fullmove.saturating_sub(1) + u32::from(fields.get(1) == Some(&"b")))
+}
+
+/// The record of one line, or `None` for a position before `min_ply`.
+pub fn parse_line(line: &str, min_ply: u32) -> Result<Option<ChessBoard>, String> {
+    let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+    let [fen, score, wdl] = fields[..] else {
+        return Err("expected `<fen> | <eval> | <wdl>`".to_string());
+    };
+    data::parse_fen(fen)?;
+    if min_ply > 0 && ply(fen)? < min_ply {
+        return Ok(None);
+    }
+    // checked here, as bullet's parser prints the whole line for either
+    score.parse::<i16>().map_err(|_| format!("eval '{}' is not an i16", score))?;
+    if !matches!(wdl, "1.0" | "1" | "0.5" | "1/2" | "0.0" | "0") {
+        return Err(format!("result '{}' is not 1.0, 0.5 or 0.0", wdl));
+    }
+    let board: ChessBoard = line.parse()?;
+    data::check_record(&board)?;
+    Ok(Some(board))
+}
+
+/// `path` if it is a file, else the .txt files below it in name order.
+fn text_files(path: &Path, out: &mut Vec<PathBuf>) -> Result<(), ToolError> {
+    let name = path.display().to_string();
+    if !fs::metadata(path).map_err(ToolError::io(&name))?.is_dir() {
+        out.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(ToolError::io(&name))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()
+        .map_err(ToolError::io(&name))?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            text_files(&entry, out)?;
+        } else if entry.extension().is_some_and(|ext| ext == "txt") {
+            out.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Converts every input into `options.output`, written under a temporary
+/// name and renamed once complete.
+pub fn run(options: &ConvertOptions) -> Result<ConvertSummary, ToolError> {
+    let mut files = Vec::new();
+    for input in &options.inputs {
+        text_files(Path::new(input), &mut files)?;
+    }
+
+    let partial = format!("{}.partial", options.output);
+    let mut writer = BufWriter::new(fs::File::create(&partial).map_err(ToolError::io(&partial))?);
+    let mut summary = ConvertSummary { files: files.len(), ..ConvertSummary::default() };
+    let mut batch = Vec::with_capacity(WRITE_BATCH);
+    for file in &files {
+        let path = file.display().to_string();
+        let reader = BufReader::new(fs::File::open(file).map_err(ToolError::io(&path))?);
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(ToolError::io(&path))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_line(&line, options.min_ply) {
+                Ok(Some(board)) => batch.push(board),
+                Ok(None) => summary.early += 1,
+                Err(_) if options.skip_bad_lines => summary.bad += 1,
+                Err(message) => {
+                    let _ = fs::remove_file(&partial);
+                    return Err(ToolError::BadLine { path, line: index as u64 + 1, message });
+                }
+            }
+            if batch.len() == WRITE_BATCH {
+                writer.write_all(ChessBoard::as_bytes_slice(&batch)).map_err(ToolError::io(&partial))?;
+                summary.positions += batch.len() as u64;
+                batch.clear();
+            }
+        }
+    }
+    writer.write_all(ChessBoard::as_bytes_slice(&batch)).map_err(ToolError::io(&partial))?;
+    summary.positions += batch.len() as u64;
+    writer.flush().map_err(ToolError::io(&partial))?;
+    drop(writer);
+    fs::rename(&partial, &options.output).map_err(ToolError::io(&options.output))?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{STARTPOS, args, temp_path};
+
+    #[test]
+    fn convert_needs_inputs_and_an_output() {
+        let options =
+            ConvertOptions::from_args(&args(&["-i", "a.txt", "-i", "dir", "-o", "a.data", "--min-ply", "12"])).unwrap();
+        assert_eq!(
+            options,
+            ConvertOptions {
+                inputs: vec!["a.txt".to_string(), "dir".to_string()],
+                output: "a.data".to_string(),
+                min_ply: 12,
+                skip_bad_lines: false,
+            }
+        );
+        assert_eq!(
+            ConvertOptions::from_args(&args(&["-o", "a.data"])),
+            Err(ConfigError::MissingValue("--input".to_string()))
+        );
+        assert_eq!(
+            ConvertOptions::from_args(&args(&["-i", "a.txt"])),
+            Err(ConfigError::MissingValue("--output".to_string()))
+        );
+    }
+
+    #[test]
+    fn lines_are_checked_and_filtered_by_ply() {
+        assert_eq!(ply(STARTPOS), Ok(0));
+        assert_eq!(ply("4k3/8/8/8/8/8/8/4K3 b - - 0 7"), Ok(13));
+        assert!(ply("4k3/8/8/8/8/8/8/4K3 w - -").is_err());
+
+        let line = format!("{} | 25 | 0.5", STARTPOS);
+        assert!(parse_line(&line, 0).unwrap().is_some());
+        assert_eq!(parse_line(&line, 1), Ok(None));
+        assert_eq!(parse_line(&format!("{} | 25", STARTPOS), 0), Err("expected `<fen> | <eval> | <wdl>`".to_string()));
+        assert_eq!(
+            parse_line(&format!("{} | 99999 | 0.5", STARTPOS), 0),
+            Err("eval '99999' is not an i16".to_string())
+        );
+        assert_eq!(
+            parse_line(&format!("{} | 25 | 2", STARTPOS), 0),
+            Err("result '2' is not 1.0, 0.5 or 0.0".to_string())
+        );
+    }
+
+    #[test]
+    fn a_directory_converts_its_text_files_in_name_order() {
+        let dir = temp_path("convert-dir");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let late = "4k3/8/8/8/8/8/8/4K3 w - - 0 20";
+        fs::write(dir.join("b.txt"), format!("{} | 10 | 1.0\n\nnot a position\n", STARTPOS)).unwrap();
+        fs::write(dir.join("nested/a.txt"), format!("{} | -30 | 0.0\n", late)).unwrap();
+        fs::write(dir.join("notes.md"), "ignored").unwrap();
+        let output = dir.join("out.data").display().to_string();
+        let options = |skip_bad_lines| ConvertOptions {
+            inputs: vec![dir.display().to_string()],
+            output: output.clone(),
+            min_ply: 0,
+            skip_bad_lines,
+        };
+
+        match run(&options(false)) {
+            Err(ToolError::BadLine { line: 3, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(!Path::new(&output).exists() && !Path::new(&format!("{}.partial", output)).exists());
+
+        let summary = run(&ConvertOptions { min_ply: 2, ..options(true) }).unwrap();
+        assert_eq!(summary, ConvertSummary { files: 2, positions: 1, early: 1, bad: 1 });
+        assert_eq!(
+            fs::read(&output).unwrap(),
+            ChessBoard::as_bytes_slice(&[parse_line(&format!("{} | -30 | 0.0", late), 0).unwrap().unwrap()])
+        );
+        assert_eq!(
+            summary.to_string(),
+            "Converted 1 positions from 2 file(s), dropped 1 before --min-ply, skipped 1 malformed lines"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! `--print-feature-coverage`: which input features the data ever activates.
+//! A feature that never appears gets no gradient, so its `l0w` column stays
+//! at init; whole unseen king buckets point at data or bucket-layout trouble.
+
+use std::fmt;
+
+use bullet::game::formats::bulletformat::ChessBoard;
+
+use crate::{data, inference};
+
+/// Features per input bucket.
+pub const BUCKET_FEATURES: usize = 768;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureCoverage {
+    pub positions: u64,
+    seen: Vec<bool>,
+    /// Tally the other side's accumulator too (dual perspective).
+    both_perspectives: bool,
+}
+
+impl FeatureCoverage {
+    pub fn new(input_buckets: usize, both_perspectives: bool) -> Self {
+        Self { positions: 0, seen: vec![false; BUCKET_FEATURES * input_buckets], both_perspectives }
+    }
+
+    pub fn add(&mut self, board: &ChessBoard) {
+        self.positions += 1;
+        let perspectives: &[u8] = if self.both_perspectives { &[0, 1] } else { &[0] };
+        for &perspective in perspectives {
+            let king = inference::king_bucket(if perspective == 1 { board.opp_ksq } else { board.ksq });
+            for (colour, piece, square) in data::pieces(board) {
+                if let Some(seen) =
+                    self.seen.get_mut(inference::feature_index(perspective, colour, piece, square, king))
+                {
+                    *seen = true;
+                }
+            }
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn covered(&self) -> usize {
+        self.seen.iter().filter(|&&s| s).count()
+    }
+
+    /// Features seen in each input bucket.
+    pub fn covered_by_bucket(&self) -> Vec<usize> {
+        self.seen.chunks(BUCKET_FEATURES).map(|bucket| bucket.iter().filter(|&&s| s).count()).collect()
+    }
+
+    /// Input buckets none of whose features were seen.
+    pub fn unseen_buckets(&self) -> Vec<usize> {
+        self.covered_by_bucket().iter().enumerate().filter(|(_, &n)| n == 0).map(|(b, _)| b).collect()
+    }
+}
+
+impl fmt::Display for FeatureCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |n: usize, of: usize| 100.0 * n as f64 / of.max(1) as f64;
+        writeln!(
+            f,
+            "Feature coverage over {} positions: {} of {} features ({:.1}%)",
+            self.positions,
+            self.covered(),
+            self.total(),
+            percent(self.covered(), self.total())
+        )?;
+        writeln!(f, "Input buckets:")?;
+        for (bucket, covered) in self.covered_by_bucket().into_iter().enumerate() {
+            writeln!(
+                f,
+                "  {:>2}: {:>4} of {} ({:5.1}%)",
+                bucket,
+                covered,
+                BUCKET_FEATURES,
+                percent(covered, BUCKET_FEATURES)
+            )?;
+        }
+        match self.unseen_buckets().as_slice() {
+            [] => write!(f, "Every input bucket is used"),
+            unseen => write!(f, "Never-seen input buckets: {:?}", unseen),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::NUM_INPUT_BUCKETS,
+        test_util::{STARTPOS, board},
+    };
+
+    /// Kings on e1/e8 (bucket 3) with all 32 pieces, then kings on a1/a8
+    /// (bucket 0) with a white rook on h1.
+    fn tally(both_perspectives: bool) -> FeatureCoverage {
+        let mut coverage = FeatureCoverage::new(NUM_INPUT_BUCKETS, both_perspectives);
+        coverage.add(&board(STARTPOS, 0, "0.5"));
+        coverage.add(&board("k7/8/8/8/8/8/8/K6R w - - 0 1", 0, "0.5"));
+        coverage
+    }
+
+    #[test]
+    fn tallies_the_features_seen_per_bucket() {
+        let coverage = tally(false);
+        assert_eq!((coverage.positions, coverage.covered(), coverage.total()), (2, 35, 7680));
+        assert_eq!(coverage.covered_by_bucket(), [3, 0, 0, 32, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(coverage.unseen_buckets(), [1, 2, 4, 5, 6, 7, 8, 9]);
+        let report = coverage.to_string();
+        assert!(report.starts_with("Feature coverage over 2 positions: 35 of 7680 features (0.5%)\n"), "{}", report);
+        assert!(report.ends_with("Never-seen input buckets: [1, 2, 4, 5, 6, 7, 8, 9]"), "{}", report);
+    }
+
+    #[test]
+    fn the_other_perspective_adds_its_own_features() {
+        // the start position looks the same from both sides; the lone rook does not
+        assert_eq!(tally(true).covered_by_bucket(), [4, 0, 0, 32, 0, 0, 0, 0, 0, 0]);
+    }
+}
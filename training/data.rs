@@ -0,0 +1,374 @@
+//! Helpers for bulletformat `ChessBoard` records, the format written by
+//! `bullet-utils convert` and read by `DirectSequentialDataLoader`.
+//!
+//! Records are stored from the side to move's point of view: piece colour 0
+//! is "ours", `score` is the stm-relative eval in centipawns and `result` is
+//! 0/1/2 for a stm loss/draw/win.
+
+use std::{
+    fmt, fs,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use bullet::game::formats::bulletformat::ChessBoard;
+
+pub const STARTPOS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Size in bytes of one record as read by `DirectSequentialDataLoader`.
+pub const RECORD_SIZE: usize = std::mem::size_of::<ChessBoard>();
+
+pub const PAWN: u8 = 0;
+pub const KNIGHT: u8 = 1;
+pub const BISHOP: u8 = 2;
+pub const ROOK: u8 = 3;
+pub const QUEEN: u8 = 4;
+pub const KING: u8 = 5;
+
+/// Phase weight of each piece type; the starting position sums to `MAX_PHASE`.
+const PHASE_WEIGHTS: [u32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: u32 = 24;
+
+/// Iterates `(colour, piece, square)` over all pieces of a record.
+pub fn pieces(board: &ChessBoard) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+    let mut occ = board.occ;
+    let mut idx = 0;
+    std::iter::from_fn(move || {
+        if occ == 0 {
+            return None;
+        }
+        let square = occ.trailing_zeros() as u8;
+        let nibble = (board.pcs[idx / 2] >> (4 * (idx & 1))) & 0b1111;
+        occ &= occ - 1;
+        idx += 1;
+        Some((nibble >> 3, nibble & 0b111, square))
+    })
+}
+
+/// Checks a record decodes to a legal feature set: 2 to 32 pieces, each a
+/// real piece type, and one king per side. A generator bug that breaks this
+/// would otherwise hand the net garbage indices without any error.
+pub fn check_record(board: &ChessBoard) -> Result<(), String> {
+    let count = board.occ.count_ones();
+    if !(2..=32).contains(&count) {
+        return Err(format!("{} active features per perspective, expected 2 to 32", count));
+    }
+    let mut kings = [0; 2];
+    for (colour, piece, square) in pieces(board) {
+        if piece > KING {
+            return Err(format!("invalid piece code {} on square {}", piece, square));
+        }
+        if piece == KING {
+            kings[usize::from(colour)] += 1;
+        }
+    }
+    if kings != [1, 1] {
+        return Err(format!("{} kings for the side to move and {} for the other side", kings[0], kings[1]));
+    }
+    Ok(())
+}
+
+/// Game phase in `[0, 1]`: 1 with all non-pawn material on the board, 0 with
+/// bare kings and pawns. Knights and bishops count 1, rooks 2, queens 4.
+pub fn game_phase(board: &ChessBoard) -> f32 {
+    let phase: u32 = pieces(board).map(|(_, piece, _)| PHASE_WEIGHTS[usize::from(piece)]).sum();
+    phase.min(MAX_PHASE) as f32 / MAX_PHASE as f32
+}
+
+/// Output bucket as chosen by bullet's `MaterialCount<N>`.
+pub fn material_bucket(board: &ChessBoard, num_buckets: usize) -> usize {
+    let divisor = 32usize.div_ceil(num_buckets);
+    ((board.occ.count_ones() as usize).saturating_sub(2) / divisor).min(num_buckets - 1)
+}
+
+/// Game result from the side to move's perspective in `[0, 1]`.
+pub fn result(board: &ChessBoard) -> f32 {
+    f32::from(board.result) / 2.0
+}
+
+/// Whether the side to move's king is attacked. Opposing pawns move down the
+/// board, since records are oriented with the side to move as white.
+pub fn in_check(board: &ChessBoard) -> bool {
+    let mut squares = [None; 64];
+    let mut king = None;
+    for (colour, piece, square) in pieces(board) {
+        squares[usize::from(square)] = Some((colour, piece));
+        if colour == 0 && piece == KING {
+            king = Some(square);
+        }
+    }
+    let Some(king) = king else { return false };
+    let (rank, file) = (i8::try_from(king / 8).unwrap(), i8::try_from(king % 8).unwrap());
+    let enemy_at = |r: i8, f: i8, kinds: &[u8]| {
+        (0..8).contains(&r)
+            && (0..8).contains(&f)
+            && squares[usize::try_from(r * 8 + f).unwrap()].is_some_and(|(c, p)| c == 1 && kinds.contains(&p))
+    };
+
+    let knight = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+    let around = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+    if enemy_at(rank + 1, file - 1, &[PAWN]) || enemy_at(rank + 1, file + 1, &[PAWN]) {
+        return true;
+    }
+    if knight.iter().any(|&(dr, df)| enemy_at(rank + dr, file + df, &[KNIGHT])) {
+        return true;
+    }
+    if around.iter().any(|&(dr, df)| enemy_at(rank + dr, file + df, &[KING])) {
+        return true;
+    }
+
+    // sliders: walk each ray to the first piece
+    around.iter().any(|&(dr, df)| {
+        let sliders: &[u8] = if dr == 0 || df == 0 { &[ROOK, QUEEN] } else { &[BISHOP, QUEEN] };
+        let (mut r, mut f) = (rank + dr, file + df);
+        while (0..8).contains(&r) && (0..8).contains(&f) {
+            if squares[usize::try_from(r * 8 + f).unwrap()].is_some() {
+                return enemy_at(r, f, sliders);
+            }
+            r += dr;
+            f += df;
+        }
+        false
+    })
+}
+
+pub fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[derive(Debug)]
+pub enum DataError {
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    /// The file length is not a whole number of records.
+    Misaligned {
+        path: String,
+        len: u64,
+        record_size: usize,
+    },
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{}: {}", path, error),
+            Self::Misaligned { path, record_size: 0, .. } => write!(f, "{}: a record size of 0 bytes", path),
+            Self::Misaligned { path, len, record_size } => write!(
+                f,
+                "{}: size {} is not a multiple of the record size {} ({} trailing bytes); wrong --record-size or truncated file?",
+                path,
+                len,
+                record_size,
+                len % *record_size as u64
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+/// Number of records in a file, rejecting files that do not divide evenly.
+pub fn count_records(path: &str, record_size: usize) -> Result<u64, DataError> {
+    let len = fs::metadata(path).map_err(|error| DataError::Io { path: path.to_string(), error })?.len();
+    check_record_alignment(path, len, record_size)
+}
+
+pub fn check_record_alignment(path: &str, len: u64, record_size: usize) -> Result<u64, DataError> {
+    if record_size == 0 || !len.is_multiple_of(record_size as u64) {
+        return Err(DataError::Misaligned { path: path.to_string(), len, record_size });
+    }
+    Ok(len / record_size as u64)
+}
+
+/// `(train, validation)` record ranges for `--val-split`: the last `fraction`
+/// of the file, rounded to whole records, is held out for validation.
+pub fn split_records(total: u64, fraction: f32) -> (Range<u64>, Range<u64>) {
+    let val = ((total as f64 * f64::from(fraction)).round() as u64).min(total);
+    (0..total - val, total - val..total)
+}
+
+/// Reads up to `n` records spread evenly through the file.
+pub fn sample_records(path: &str, n: usize) -> Result<Vec<ChessBoard>, DataError> {
+    let records = count_records(path, RECORD_SIZE)?;
+    sample_records_in(path, 0..records, n)
+}
+
+/// Reads up to `n` records spread evenly through `range` (in records).
+pub fn sample_records_in(path: &str, range: Range<u64>, n: usize) -> Result<Vec<ChessBoard>, DataError> {
+    let io_error = |error| DataError::Io { path: path.to_string(), error };
+    let records = range.end - range.start;
+    let mut file = fs::File::open(path).map_err(io_error)?;
+    let n = n.min(records as usize);
+    let mut samples = Vec::with_capacity(n);
+    let mut buf = [0u8; RECORD_SIZE];
+    for i in 0..n as u64 {
+        let index = range.start + i * records / n as u64;
+        file.seek(SeekFrom::Start(index * RECORD_SIZE as u64)).map_err(io_error)?;
+        file.read_exact(&mut buf).map_err(io_error)?;
+        // SAFETY: ChessBoard is a plain-old-data record of integers, valid
+        // for any bit pattern, and `buf` is exactly one record long
+        samples.push(unsafe { std::ptr::read_unaligned(buf.as_ptr().cast::<ChessBoard>()) });
+    }
+    Ok(samples)
+}
+
+/// Calls `f` on every record of the file in order and returns the count.
+pub fn for_each_record(path: &str, f: impl FnMut(&ChessBoard)) -> Result<u64, DataError> {
+    for_each_record_up_to(path, u64::MAX, f)
+}
+
+/// Like [`for_each_record`], stopping after the first `limit` records.
+pub fn for_each_record_up_to(path: &str, limit: u64, mut f: impl FnMut(&ChessBoard)) -> Result<u64, DataError> {
+    let io_error = |error| DataError::Io { path: path.to_string(), error };
+    let records = count_records(path, RECORD_SIZE)?.min(limit);
+    let mut reader = BufReader::with_capacity(1 << 20, fs::File::open(path).map_err(io_error)?);
+    let mut buf = [0u8; RECORD_SIZE];
+    for _ in 0..records {
+        reader.read_exact(&mut buf).map_err(io_error)?;
+        // SAFETY: as in `sample_records_in`
+        f(&unsafe { std::ptr::read_unaligned(buf.as_ptr().cast::<ChessBoard>()) });
+    }
+    Ok(records)
+}
+
+/// FEN of a record from the side to move's point of view (always "w"). Castling
+/// rights and en passant are not stored, so they are left empty.
+pub fn to_fen(board: &ChessBoard) -> String {
+    let mut squares = [None; 64];
+    for (colour, piece, square) in pieces(board) {
+        let c = b"pnbrqk"[usize::from(piece)] as char;
+        squares[usize::from(square)] = Some(if colour == 0 { c.to_ascii_uppercase() } else { c });
+    }
+
+    let mut fen = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match squares[rank * 8 + file] {
+                Some(c) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(c);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+    fen.push_str(" w - - 0 1");
+    fen
+}
+
+/// Parses a FEN (castling, en passant and move counters optional) into a
+/// record with a zero score and a drawn result. Bullet's own parser skips
+/// characters it does not know, so the board is checked here first.
+pub fn parse_fen(fen: &str) -> Result<ChessBoard, String> {
+    let mut parts = fen.split_whitespace();
+    let board = parts.next().ok_or("empty FEN")?;
+    match parts.next() {
+        Some("w" | "b") => {}
+        Some(stm) => return Err(format!("side to move '{}' is not w or b", stm)),
+        None => return Err("missing side to move".to_string()),
+    }
+
+    let ranks: Vec<&str> = board.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("{} ranks, expected 8", ranks.len()));
+    }
+    let mut kings = [0; 2];
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut files = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c.to_digit(10).unwrap(),
+                'P' | 'N' | 'B' | 'R' | 'Q' | 'p' | 'n' | 'b' | 'r' | 'q' => files += 1,
+                'K' | 'k' => {
+                    kings[usize::from(c == 'k')] += 1;
+                    files += 1;
+                }
+                _ => return Err(format!("unexpected '{}' in rank {}", c, 8 - i)),
+            }
+        }
+        if files != 8 {
+            return Err(format!("rank {} has {} files", 8 - i, files));
+        }
+    }
+    if kings != [1, 1] {
+        return Err(format!("{} white and {} black kings", kings[0], kings[1]));
+    }
+
+    format!("{} | 0 | 0.5", fen).parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{STARTPOS, board};
+
+    #[test]
+    fn alignment_counts_whole_records() {
+        assert_eq!(check_record_alignment("a.data", 320, 32).unwrap(), 10);
+        assert_eq!(check_record_alignment("a.data", 0, 32).unwrap(), 0);
+        assert_eq!(check_record_alignment("a.data", 96, 48).unwrap(), 2);
+    }
+
+    #[test]
+    fn alignment_rejects_a_partial_record() {
+        let error = check_record_alignment("a.data", 330, 32).unwrap_err();
+        assert!(matches!(error, DataError::Misaligned { len: 330, record_size: 32, .. }));
+        assert!(error.to_string().contains("(10 trailing bytes)"), "{}", error);
+        assert!(check_record_alignment("a.data", 320, 48).is_err());
+    }
+
+    #[test]
+    fn alignment_rejects_a_zero_record_size() {
+        let error = check_record_alignment("a.data", 320, 0).unwrap_err();
+        assert_eq!(error.to_string(), "a.data: a record size of 0 bytes");
+    }
+
+    #[test]
+    fn split_holds_out_the_last_fraction() {
+        assert_eq!(split_records(1000, 0.1), (0..900, 900..1000));
+        assert_eq!(split_records(1000, 0.0), (0..1000, 1000..1000));
+        assert_eq!(split_records(1000, 1.0), (0..0, 0..1000));
+    }
+
+    #[test]
+    fn split_rounds_to_whole_records() {
+        assert_eq!(split_records(7, 0.5), (0..3, 3..7));
+        assert_eq!(split_records(3, 0.1), (0..3, 3..3));
+        assert_eq!(split_records(0, 0.2), (0..0, 0..0));
+    }
+
+    #[test]
+    fn rejects_an_impossible_feature_count() {
+        assert_eq!(check_record(&board(STARTPOS, 0, "0.5")), Ok(()));
+        let mut record = board(STARTPOS, 0, "0.5");
+        record.occ = u64::MAX;
+        assert_eq!(check_record(&record), Err("64 active features per perspective, expected 2 to 32".to_string()));
+        record.occ = 1;
+        assert_eq!(check_record(&record), Err("1 active features per perspective, expected 2 to 32".to_string()));
+    }
+
+    #[test]
+    fn rejects_a_bad_piece_or_king_count() {
+        let no_white_king = board("4k3/8/8/8/8/8/8/3QQ3 w - - 0 1", 0, "0.5");
+        assert_eq!(
+            check_record(&no_white_king),
+            Err("0 kings for the side to move and 1 for the other side".to_string())
+        );
+        let mut record = board(STARTPOS, 0, "0.5");
+        // the piece nibble of a1 is 7, past the king
+        record.pcs[0] = (record.pcs[0] & 0xf0) | 7;
+        assert_eq!(check_record(&record), Err("invalid piece code 7 on square 0".to_string()));
+    }
+}
@@ -0,0 +1,223 @@
+//! `training datagen`: runs the engine's self-play generator (`make
+//! training`, src/training_main.c) in parallel and collects its text output
+//! into one file, as generate_training_data.sh does.
+//!
+//! Each instance plays its share of the games with `-o <output>.part<i>`,
+//! which the generator writes to as `<output>.part<i>.<pid>`. Only lines
+//! holding a position are copied, and the instance files are removed once
+//! copied. An instance's stdout and stderr
+//! go to `<output>.part<i>.log`, which is kept if the instance fails.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    process::Command,
+    thread,
+};
+
+use crate::{
+    commands::ToolError,
+    config::{ConfigError, value},
+    info,
+};
+
+pub const USAGE: &str = "\
+Generate text training data with the engine's self-play generator
+
+Usage: training datagen --output <PATH> [OPTIONS] [-- <GENERATOR OPTIONS>]
+
+Options:
+  -o, --output <PATH>      Text file the positions are appended to
+  -n, --games <N>          Games to play in total, split over the instances (default: 10000)
+  -c, --concurrency <N>    Generator instances to run at once (default: one per CPU)
+      --generator <PATH>   The generator binary (default: build/training, from `make training`)
+  -h, --help               Show this help
+
+Everything after `--` is passed to each instance, e.g. `-- -N 5000 -r 12 -p 50 -e 4 -a 10`;
+see the generator's own --help. Convert the output with `training convert`.";
+
+pub const DEFAULT_GENERATOR: &str = "build/training";
+const DEFAULT_GAMES: u64 = 10000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatagenOptions {
+    pub output: String,
+    pub games: u64,
+    pub concurrency: usize,
+    pub generator: String,
+    /// Passed to every instance after its own `-o` and `-n`.
+    pub generator_args: Vec<String>,
+}
+
+impl DatagenOptions {
+    /// Parses `datagen`'s arguments, the program name at index 0.
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut output: Option<String> = None;
+        let mut games = DEFAULT_GAMES;
+        let mut concurrency: Option<usize> = None;
+        let mut generator = DEFAULT_GENERATOR.to_string();
+        let mut generator_args = Vec::new();
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--output" | "-o" => output = Some(value(args, &mut i)?),
+                "--games" | "-n" => games = value(args, &mut i)?,
+                "--concurrency" | "-c" => concurrency = Some(value(args, &mut i)?),
+                "--generator" => generator = value(args, &mut i)?,
+                "--" => {
+                    generator_args = args[i + 1..].to_vec();
+                    break;
+                }
+                "--help" | "-h" => return Err(ConfigError::HelpRequested),
+                flag => return Err(ConfigError::UnknownFlag(flag.to_string())),
+            }
+            i += 1;
+        }
+
+        let output = output.ok_or_else(|| ConfigError::MissingValue("--output".to_string()))?;
+        let concurrency = concurrency.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        if concurrency == 0 {
+            return Err(ConfigError::InvalidValue { flag: "--concurrency".to_string(), value: "0".to_string() });
+        }
+        for own in ["-o", "--output", "-n", "--num-games"] {
+            if generator_args.iter().any(|arg| arg == own) {
+                return Err(ConfigError::InvalidValue {
+                    flag: "--".to_string(),
+                    value: format!("{} is set by datagen itself", own),
+                });
+            }
+        }
+        Ok(Self { output, games, concurrency, generator, generator_args })
+    }
+}
+
+/// Games of each instance: an even split, the first ones taking the remainder.
+pub fn split_games(games: u64, instances: usize) -> Vec<u64> {
+    let (each, extra) = (games / instances as u64, games % instances as u64);
+    (0..instances as u64).map(|i| each + u64::from(i < extra)).filter(|&n| n > 0).collect()
+}
+
+/// Runs the instances, appends their positions to `options.output` and
+/// returns how many were appended.
+pub fn run(options: &DatagenOptions) -> Result<u64, ToolError> {
+    if !Path::new(&options.generator).is_file() {
+        return Err(ToolError::Generator(format!("{} not found; build it with `make training`", options.generator)));
+    }
+
+    let mut children = Vec::new();
+    for (i, games) in split_games(options.games, options.concurrency).into_iter().enumerate() {
+        let prefix = format!("{}.part{}", options.output, i);
+        let log_path = format!("{}.log", prefix);
+        let log = fs::File::create(&log_path).map_err(ToolError::io(&log_path))?;
+        let stderr = log.try_clone().map_err(ToolError::io(&log_path))?;
+        let child = Command::new(&options.generator)
+            .args(["-o", &prefix, "-n", &games.to_string()])
+            .args(&options.generator_args)
+            .stdout(log)
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| ToolError::Generator(format!("cannot start {}: {}", options.generator, e)))?;
+        info!("Instance {}: {} games -> {}.*", i, games, prefix);
+        children.push((prefix, log_path, child));
+    }
+
+    let mut failed = Vec::new();
+    let mut positions = 0;
+    let out = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&options.output)
+        .map_err(ToolError::io(&options.output))?;
+    let mut writer = BufWriter::new(out);
+    for (prefix, log_path, mut child) in children {
+        let status = child.wait().map_err(|e| ToolError::Generator(format!("waiting for {}: {}", prefix, e)))?;
+        // the generator names its file `<prefix>.<pid>`
+        let path = format!("{}.{}", prefix, child.id());
+        match fs::File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(ToolError::io(&path))?;
+                    if line.contains('|') {
+                        writeln!(writer, "{}", line).map_err(ToolError::io(&options.output))?;
+                        positions += 1;
+                    }
+                }
+                let _ = fs::remove_file(&path);
+            }
+            Err(e) if status.success() => return Err(ToolError::Io { path, error: e }),
+            Err(_) => {}
+        }
+        if status.success() {
+            let _ = fs::remove_file(&log_path);
+        } else {
+            failed.push(format!("{} ({}, see {})", prefix, status, log_path));
+        }
+    }
+    writer.flush().map_err(ToolError::io(&options.output))?;
+
+    if !failed.is_empty() {
+        return Err(ToolError::Generator(format!(
+            "{} instance(s) failed, their positions so far were kept: {}",
+            failed.len(),
+            failed.join(", ")
+        )));
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::args;
+
+    #[test]
+    fn generator_options_follow_the_separator() {
+        let options =
+            DatagenOptions::from_args(&args(&["-o", "out.txt", "-n", "50", "-c", "3", "--", "-r", "12", "--x"]))
+                .unwrap();
+        assert_eq!(
+            options,
+            DatagenOptions {
+                output: "out.txt".to_string(),
+                games: 50,
+                concurrency: 3,
+                generator: DEFAULT_GENERATOR.to_string(),
+                generator_args: vec!["-r".to_string(), "12".to_string(), "--x".to_string()],
+            }
+        );
+        assert_eq!(DatagenOptions::from_args(&args(&["-o", "out.txt"])).unwrap().games, DEFAULT_GAMES);
+    }
+
+    #[test]
+    fn datagen_needs_an_output_and_keeps_its_own_flags() {
+        assert_eq!(DatagenOptions::from_args(&args(&[])), Err(ConfigError::MissingValue("--output".to_string())));
+        assert!(matches!(
+            DatagenOptions::from_args(&args(&["-o", "a", "-c", "0"])),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        for own in ["-o", "-n", "--num-games"] {
+            let parsed = DatagenOptions::from_args(&args(&["-o", "a", "--", own, "1"]));
+            assert_eq!(
+                parsed,
+                Err(ConfigError::InvalidValue {
+                    flag: "--".to_string(),
+                    value: format!("{} is set by datagen itself", own)
+                })
+            );
+        }
+        assert_eq!(
+            DatagenOptions::from_args(&args(&["-o", "a", "-r"])),
+            Err(ConfigError::UnknownFlag("-r".to_string()))
+        );
+    }
+
+    #[test]
+    fn games_are_split_evenly_with_no_idle_instances() {
+        assert_eq!(split_games(10, 3), [4, 3, 3]);
+        assert_eq!(split_games(9, 3), [3, 3, 3]);
+        assert_eq!(split_games(2, 4), [1, 1]);
+        assert_eq!(split_games(0, 2), Vec::<u64>::new());
+    }
+}
@@ -0,0 +1,139 @@
+//! `--dataset-stats`: a one-pass summary of a data file, to check its balance
+//! and how it spreads over the output buckets before training on it.
+
+use std::fmt;
+
+use bullet::game::formats::bulletformat::ChessBoard;
+
+use crate::data;
+
+/// Width of an eval histogram bin in centipawns.
+pub const EVAL_BIN_CP: i32 = 200;
+/// Bins on each side of 0; evals beyond the outermost bins land in them.
+pub const EVAL_BINS_PER_SIDE: i32 = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatasetStats {
+    pub positions: u64,
+    /// Side to move `[losses, draws, wins]`.
+    pub results: [u64; 3],
+    /// Counts per [`EVAL_BIN_CP`] bin from `-EVAL_BINS_PER_SIDE` bins up.
+    pub eval_bins: Vec<u64>,
+    pub buckets: Vec<u64>,
+    pub in_check: u64,
+    phase_sum: f64,
+}
+
+impl DatasetStats {
+    pub fn new(num_buckets: usize) -> Self {
+        Self {
+            positions: 0,
+            results: [0; 3],
+            eval_bins: vec![0; 2 * EVAL_BINS_PER_SIDE as usize],
+            buckets: vec![0; num_buckets],
+            in_check: 0,
+            phase_sum: 0.0,
+        }
+    }
+
+    /// Index into `eval_bins` for a score in centipawns.
+    pub fn eval_bin(score: i16) -> usize {
+        let bin = i32::from(score).div_euclid(EVAL_BIN_CP).clamp(-EVAL_BINS_PER_SIDE, EVAL_BINS_PER_SIDE - 1);
+        (bin + EVAL_BINS_PER_SIDE) as usize
+    }
+
+    pub fn add(&mut self, board: &ChessBoard) {
+        self.positions += 1;
+        self.results[usize::from(board.result.min(2))] += 1;
+        self.eval_bins[Self::eval_bin(board.score)] += 1;
+        let bucket = data::material_bucket(board, self.buckets.len());
+        self.buckets[bucket] += 1;
+        self.in_check += u64::from(data::in_check(board));
+        self.phase_sum += f64::from(data::game_phase(board));
+    }
+
+    pub fn mean_phase(&self) -> f64 {
+        self.phase_sum / self.positions.max(1) as f64
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.positions.max(1) as f64
+    }
+}
+
+impl fmt::Display for DatasetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Positions:     {}", self.positions)?;
+        let [losses, draws, wins] = self.results;
+        writeln!(
+            f,
+            "Results (stm): {:.1}% win, {:.1}% draw, {:.1}% loss",
+            self.percent(wins),
+            self.percent(draws),
+            self.percent(losses)
+        )?;
+        writeln!(f, "In check:      {:.2}%", self.percent(self.in_check))?;
+        writeln!(f, "Mean phase:    {:.3} (1 = all pieces, 0 = kings and pawns)", self.mean_phase())?;
+        writeln!(f, "Output buckets:")?;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            writeln!(f, "  {:>2}: {:>12} ({:5.1}%)", bucket, count, self.percent(count))?;
+        }
+        writeln!(f, "Eval (cp, stm):")?;
+        let last = self.eval_bins.len() - 1;
+        for (i, &count) in self.eval_bins.iter().enumerate() {
+            let low = (i as i32 - EVAL_BINS_PER_SIDE) * EVAL_BIN_CP;
+            let range = match i {
+                0 => format!("< {}", low + EVAL_BIN_CP),
+                _ if i == last => format!(">= {}", low),
+                _ => format!("{}..{}", low, low + EVAL_BIN_CP),
+            };
+            writeln!(f, "  {:>12}: {:>12} ({:5.1}%)", range, count, self.percent(count))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        net::NUM_OUTPUT_BUCKETS,
+        test_util::{STARTPOS, board, write_records},
+    };
+
+    #[test]
+    fn eval_bins_clamp_at_the_outermost_bins() {
+        assert_eq!(DatasetStats::eval_bin(0), 10);
+        assert_eq!(DatasetStats::eval_bin(199), 10);
+        assert_eq!(DatasetStats::eval_bin(-1), 9);
+        assert_eq!(DatasetStats::eval_bin(-200), 9);
+        assert_eq!(DatasetStats::eval_bin(i16::MAX), 19);
+        assert_eq!(DatasetStats::eval_bin(i16::MIN), 0);
+    }
+
+    #[test]
+    fn aggregates_a_small_file() {
+        let path = write_records(
+            "dataset-stats.data",
+            &[
+                board(STARTPOS, 50, "1.0"),
+                board(STARTPOS, -250, "0.5"),
+                board("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1", 3000, "1.0"),
+                board("4k3/8/8/8/8/8/8/4r1K1 w - - 0 1", -900, "0.0"),
+            ],
+        );
+        let mut stats = DatasetStats::new(NUM_OUTPUT_BUCKETS);
+        data::for_each_record(path.to_str().unwrap(), |board| stats.add(board)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(stats.positions, 4);
+        assert_eq!(stats.results, [1, 1, 2]);
+        assert_eq!(stats.in_check, 1);
+        assert_eq!((stats.buckets[0], stats.buckets[7]), (2, 2));
+        let bins: Vec<(usize, u64)> = stats.eval_bins.iter().copied().enumerate().filter(|&(_, n)| n > 0).collect();
+        assert_eq!(bins, [(5, 1), (8, 1), (10, 1), (19, 1)]);
+        assert!(stats.to_string().contains("Results (stm): 50.0% win, 25.0% draw, 25.0% loss"), "{}", stats);
+    }
+}
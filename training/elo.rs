@@ -0,0 +1,96 @@
+//! Score and Elo estimates from a set of game results.
+//!
+//! Only the aggregation lives here: the engine is C with no library entry
+//! point, so games between two nets are still played by `tournament.sh`.
+
+use std::fmt;
+
+/// z for a two-sided 95% interval.
+const Z_95: f64 = 1.959_964;
+
+/// Game results from the first net's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchResult {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+/// Elo difference for an expected score in `(0, 1)`.
+pub fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl MatchResult {
+    pub fn games(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, draws counting half.
+    pub fn score(&self) -> Option<f64> {
+        let games = self.games();
+        (games > 0).then(|| (self.wins as f64 + 0.5 * self.draws as f64) / games as f64)
+    }
+
+    /// Elo estimate and the half-width of its 95% interval. `None` without
+    /// games or for a clean sweep, where the estimate is unbounded.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let score = self.score()?;
+        if score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+
+        let games = self.games() as f64;
+        let deviation = |points: f64, count: u64| count as f64 * (points - score).powi(2);
+        let variance = (deviation(1.0, self.wins) + deviation(0.5, self.draws) + deviation(0.0, self.losses)) / games;
+        let margin = Z_95 * (variance / games).sqrt();
+
+        let low = elo_from_score((score - margin).max(f64::EPSILON));
+        let high = elo_from_score((score + margin).min(1.0 - f64::EPSILON));
+        Some((elo_from_score(score), (high - low) / 2.0))
+    }
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        if let Some(score) = self.score() {
+            write!(f, " ({:.1}%)", 100.0 * score)?;
+        }
+        match self.elo() {
+            Some((elo, error)) => write!(f, ", Elo {:+.1} +/- {:.1}", elo, error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_draws_as_half_a_point() {
+        let result = MatchResult { wins: 60, draws: 20, losses: 20 };
+        assert_eq!(result.games(), 100);
+        assert!((result.score().unwrap() - 0.7).abs() < 1e-12);
+        let (elo, error) = result.elo().unwrap();
+        assert!((elo - 147.19).abs() < 0.01, "{}", elo);
+        assert!((error - 66.01).abs() < 0.01, "{}", error);
+        assert_eq!(result.to_string(), "+60 =20 -20 (70.0%), Elo +147.2 +/- 66.0");
+    }
+
+    #[test]
+    fn an_even_match_is_zero_elo() {
+        let (elo, _) = MatchResult { wins: 10, draws: 30, losses: 10 }.elo().unwrap();
+        assert!(elo.abs() < 1e-9);
+        assert!((elo_from_score(0.25) + elo_from_score(0.75)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_estimate_without_games_or_for_a_sweep() {
+        assert_eq!(MatchResult::default().score(), None);
+        assert_eq!(MatchResult::default().to_string(), "+0 =0 -0");
+        assert_eq!(MatchResult { wins: 5, draws: 0, losses: 0 }.elo(), None);
+        assert_eq!(MatchResult { wins: 0, draws: 0, losses: 3 }.to_string(), "+0 =0 -3 (0.0%)");
+    }
+}
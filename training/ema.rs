@@ -0,0 +1,99 @@
+//! Exponential moving average of the trainable weights.
+
+use std::{io, path::Path};
+
+use crate::{archive, net::FloatNet};
+
+pub struct Ema {
+    pub decay: f32,
+    /// `None` until the first update, which copies the weights as-is.
+    pub shadow: Option<FloatNet>,
+}
+
+impl Ema {
+    pub fn new(decay: f32) -> Self {
+        Self { decay, shadow: None }
+    }
+
+    /// `shadow = decay * shadow + (1 - decay) * current`, per value.
+    pub fn update(&mut self, current: &FloatNet) {
+        let Some(shadow) = &mut self.shadow else {
+            self.shadow = Some(current.clone());
+            return;
+        };
+        for id in current.tensor_ids() {
+            let (s, c) = (shadow.get_mut(id).unwrap(), current.get(id).unwrap());
+            update_values(s, c, self.decay);
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let Some(shadow) = &self.shadow else { return Ok(()) };
+        archive::write(path, &shadow.named_tensors())
+    }
+
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut shadow = FloatNet::default();
+        for (id, values) in archive::read(path)? {
+            if let Some(tensor) = shadow.get_mut(&id) {
+                *tensor = values;
+            }
+        }
+        self.shadow = Some(shadow);
+        Ok(())
+    }
+}
+
+pub fn update_values(shadow: &mut [f32], current: &[f32], decay: f32) {
+    for (s, &c) in shadow.iter_mut().zip(current) {
+        *s = decay * *s + (1.0 - decay) * c;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn net(value: f32) -> FloatNet {
+        FloatNet {
+            l0w: vec![value; 4],
+            l0f: vec![value; 2],
+            l0b: vec![value],
+            l1w: vec![value; 2],
+            l1b: vec![value],
+            l1f: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn update_blends_towards_the_current_weights() {
+        let mut shadow = vec![1.0, 0.0];
+        update_values(&mut shadow, &[0.0, 1.0], 0.75);
+        assert_eq!(shadow, vec![0.75, 0.25]);
+    }
+
+    #[test]
+    fn first_update_copies_then_averages() {
+        let mut ema = Ema::new(0.5);
+        ema.update(&net(2.0));
+        assert_eq!(ema.shadow, Some(net(2.0)));
+        ema.update(&net(4.0));
+        assert_eq!(ema.shadow, Some(net(3.0)));
+    }
+
+    #[test]
+    fn shadow_round_trips_through_a_checkpoint() {
+        let path = temp_path("ema.fp32");
+        let mut ema = Ema::new(0.9);
+        ema.update(&net(0.25));
+        ema.save(&path).unwrap();
+
+        let mut restored = Ema::new(0.9);
+        restored.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restored.shadow, ema.shadow);
+    }
+}
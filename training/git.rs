@@ -0,0 +1,69 @@
+//! `--record-git-state`: which code a run was trained with.
+
+use std::{path::Path, process::Command};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitState {
+    pub commit: String,
+    /// Uncommitted changes to tracked or untracked files.
+    pub dirty: bool,
+}
+
+impl GitState {
+    /// The run metadata entries for this state.
+    pub fn metadata(&self) -> [(&'static str, String); 2] {
+        [("git_commit", self.commit.clone()), ("git_dirty", self.dirty.to_string())]
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// State of the repository containing `dir`, or `None` if `git` is missing
+/// or `dir` is not inside a work tree.
+pub fn state(dir: &Path) -> Option<GitState> {
+    let commit = git(dir, &["rev-parse", "HEAD"])?;
+    let dirty = !git(dir, &["status", "--porcelain"])?.is_empty();
+    Some(GitState { commit, dirty })
+}
+
+/// The trainer's own source tree, as built.
+pub fn source_state() -> Option<GitState> {
+    state(Path::new(env!("CARGO_MANIFEST_DIR")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn metadata_carries_the_commit_and_dirty_flag() {
+        let state = GitState { commit: "0f759bc".to_string(), dirty: true };
+        assert_eq!(state.metadata(), [("git_commit", "0f759bc".to_string()), ("git_dirty", "true".to_string())]);
+    }
+
+    #[test]
+    fn reads_the_state_of_a_work_tree() {
+        let dir = temp_path("git-state");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        if git(&dir, &["init", "-q"]).is_none() {
+            // no git on this machine: `state` is None, which the trainer warns about
+            assert_eq!(state(&dir), None);
+            return;
+        }
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        git(&dir, &["add", "a.txt"]).unwrap();
+        git(&dir, &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", "a"]).unwrap();
+        let clean = state(&dir).unwrap();
+        assert_eq!((clean.commit.len(), clean.dirty), (40, false));
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        assert_eq!(state(&dir), Some(GitState { dirty: true, ..clean }));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
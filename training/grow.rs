@@ -0,0 +1,175 @@
+//! `--progressive-hl`: train a narrow hidden layer first and widen it partway
+//! through the run, net2net style.
+//!
+//! bullet builds its graph once, so the growth re-runs the trainer binary as
+//! `--recover-on-divergence` does: after the transition superbatch the narrow
+//! net is widened, written as a float archive, and the run continues from it
+//! in a graph of the full width. A new hidden unit gets small input weights
+//! and zero output weights, so the widened net evaluates exactly as the narrow
+//! one did and the new units are grown in by training.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    loader,
+    net::{FloatNet, NetShape},
+};
+
+/// Bound of the uniform `l0w` values a new hidden unit starts with.
+pub const NEW_UNIT_SCALE: f32 = 0.01;
+/// Seed of those values, so a rerun widens the same net identically.
+const NEW_UNIT_SEED: u64 = 0x6772_6f77;
+
+/// `--progressive-hl <START:END:AT>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgressiveHl {
+    pub start: usize,
+    pub end: usize,
+    /// Last superbatch trained at `start`; the next one is trained at `end`.
+    pub at: usize,
+}
+
+impl ProgressiveHl {
+    /// Hidden layer width of a run starting at `start_superbatch`.
+    pub fn width(&self, start_superbatch: usize) -> usize {
+        if start_superbatch <= self.at { self.start } else { self.end }
+    }
+}
+
+impl fmt::Display for ProgressiveHl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.start, self.end, self.at)
+    }
+}
+
+/// `net` of `shape` with its hidden layer widened to `hl_size`. Existing
+/// units keep their weights; in a dual-perspective net both accumulator
+/// halves of `l1` grow, each keeping its place in front of its new units.
+pub fn widen(net: &FloatNet, shape: &NetShape, hl_size: usize) -> Result<FloatNet, String> {
+    net.check_shape(shape)?;
+    if hl_size <= shape.hl_size {
+        return Err(format!("cannot widen hl_size {} to {}", shape.hl_size, hl_size));
+    }
+    let (old, new) = (shape.hl_size, hl_size);
+    let wide = NetShape { hl_size, ..*shape };
+
+    // per input column: the old units, then the new ones
+    let grow_columns = |values: &[f32], init: &dyn Fn(usize) -> f32| -> Vec<f32> {
+        let mut out = Vec::with_capacity(values.len() / old * new);
+        for (column, units) in values.chunks(old).enumerate() {
+            out.extend_from_slice(units);
+            out.extend((old..new).map(|unit| init(column * new + unit)));
+        }
+        out
+    };
+    let new_unit = |index: usize| {
+        let draw = loader::mix(NEW_UNIT_SEED, index as u64) >> 40;
+        (draw as f32 / (1u64 << 24) as f32 * 2.0 - 1.0) * NEW_UNIT_SCALE
+    };
+    let zero = |_: usize| 0.0;
+
+    // l1 inputs: each accumulator half gets its new units appended, at zero
+    let halves = wide.l1_inputs() / new;
+    let buckets = shape.output_buckets;
+    let mut l1w = vec![0.0; wide.l1_inputs() * buckets];
+    for half in 0..halves {
+        for unit in 0..old {
+            let (from, to) = ((half * old + unit) * buckets, (half * new + unit) * buckets);
+            l1w[to..to + buckets].copy_from_slice(&net.l1w[from..from + buckets]);
+        }
+    }
+    let l1f = if net.l1f.is_empty() {
+        Vec::new()
+    } else {
+        net.l1f.chunks(old).flat_map(|units| units.iter().copied().chain((old..new).map(|_| 0.0))).collect()
+    };
+
+    let widened = FloatNet {
+        l0w: grow_columns(&net.l0w, &new_unit),
+        l0f: grow_columns(&net.l0f, &zero),
+        l0b: grow_columns(&net.l0b, &zero),
+        l1w,
+        l1b: net.l1b.clone(),
+        l1f,
+    };
+    widened.check_shape(&wide)?;
+    Ok(widened)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inference::QuantisedNet,
+        net::{self, NUM_INPUT_BUCKETS, QA},
+        test_util::{STARTPOS, board},
+    };
+
+    /// Every input bucket, so any board evaluates, and two output buckets.
+    fn tiny_shape(hl_size: usize) -> NetShape {
+        NetShape {
+            hl_size,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: 2,
+            single_perspective: false,
+            output_factoriser: false,
+        }
+    }
+
+    /// Distinct small weights, so each one shows where it ended up.
+    fn tiny_net() -> FloatNet {
+        let ramp = |len: usize, scale: f32| (0..len).map(|i| (i % 97) as f32 * scale - 0.2).collect::<Vec<f32>>();
+        FloatNet {
+            l0w: ramp(768 * NUM_INPUT_BUCKETS * 2, 0.004),
+            l0f: ramp(768 * 2, 0.002),
+            l0b: vec![0.3, 0.1],
+            l1w: vec![0.5, -0.5, 0.25, -0.25, 1.0, -1.0, 0.75, -0.75],
+            l1b: vec![0.1, -0.1],
+            l1f: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn widening_keeps_every_weight_in_place() {
+        let (narrow, shape) = (tiny_net(), tiny_shape(2));
+        let wide = widen(&narrow, &shape, 3).unwrap();
+        wide.check_shape(&tiny_shape(3)).unwrap();
+
+        for (column, (old, new)) in narrow.l0w.chunks(2).zip(wide.l0w.chunks(3)).enumerate() {
+            assert_eq!(&new[..2], old, "column {}", column);
+            assert!(new[2] != 0.0 && new[2].abs() <= NEW_UNIT_SCALE, "column {}: {}", column, new[2]);
+        }
+        assert!(narrow.l0f.chunks(2).zip(wide.l0f.chunks(3)).all(|(old, new)| new == [old[0], old[1], 0.0]));
+        assert_eq!(wide.l0b, [0.3, 0.1, 0.0]);
+        // l1 inputs (stm 0, stm 1, stm new, ntm 0, ntm 1, ntm new) x 2 buckets
+        assert_eq!(wide.l1w, [0.5, -0.5, 0.25, -0.25, 0.0, 0.0, 1.0, -1.0, 0.75, -0.75, 0.0, 0.0]);
+        assert_eq!(wide.l1b, narrow.l1b);
+        assert_eq!(widen(&narrow, &shape, 3).unwrap(), wide);
+    }
+
+    #[test]
+    fn the_widened_net_evaluates_as_the_narrow_one() {
+        let (narrow, shape) = (tiny_net(), tiny_shape(2));
+        let wide = widen(&narrow, &shape, 4).unwrap();
+        let engine = |net: &FloatNet, shape: NetShape| {
+            QuantisedNet::from_values(shape, &net::quantise(net, &shape, 1.0).unwrap(), QA).unwrap()
+        };
+        let (narrow, wide) = (engine(&narrow, shape), engine(&wide, tiny_shape(4)));
+        for fen in [STARTPOS, "4k3/8/8/3q4/8/8/PPP5/4K3 w - - 0 1", "k7/8/8/8/8/8/8/K6R w - - 0 1"] {
+            let board = board(fen, 0, "0.5");
+            assert_eq!(wide.eval(&board, 400), narrow.eval(&board, 400), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn only_a_wider_layer_of_the_right_shape_is_grown() {
+        let net = tiny_net();
+        assert_eq!(widen(&net, &tiny_shape(2), 2), Err("cannot widen hl_size 2 to 2".to_string()));
+        assert!(widen(&net, &tiny_shape(3), 4).is_err());
+        let growth = ProgressiveHl { start: 2, end: 4, at: 5 };
+        assert_eq!((growth.width(1), growth.width(5), growth.width(6)), (2, 2, 4));
+        assert_eq!(growth.to_string(), "2:4:5");
+    }
+}
@@ -0,0 +1,250 @@
+//! Integer inference on a quantised net, mirroring the engine's `nnue.c`
+//! (feature indexing, SCReLU, output buckets), so nets can be scored without
+//! building the engine.
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use bullet::game::formats::bulletformat::ChessBoard;
+
+use crate::{
+    data,
+    net::{self, BUCKET_LAYOUT, NetShape, QA, QB},
+};
+
+/// Input bucket and horizontal mirroring for a king on `square`, seen from
+/// the side whose back rank is rank 1.
+pub fn king_bucket(square: u8) -> (usize, bool) {
+    let (rank, file) = (usize::from(square / 8), usize::from(square % 8));
+    let mirrored = file >= 4;
+    let file = if mirrored { 7 - file } else { file };
+    (BUCKET_LAYOUT[rank * 4 + file], mirrored)
+}
+
+/// Feature index of a piece for `perspective` 0 (side to move) or 1, as the
+/// engine's `get_feature_index` with the record's stm as white.
+pub fn feature_index(perspective: u8, colour: u8, piece: u8, square: u8, king: (usize, bool)) -> usize {
+    let (bucket, mirrored) = king;
+    let square = if perspective == 1 { square ^ 56 } else { square };
+    let square = if mirrored { square ^ 7 } else { square };
+    bucket * 768 + usize::from(colour ^ perspective) * 384 + usize::from(piece) * 64 + usize::from(square)
+}
+
+pub struct QuantisedNet {
+    pub shape: NetShape,
+    /// Save format 2 description, if the file has one.
+    pub description: Option<String>,
+    /// Feature transformer scale: [`QA`] for engine nets.
+    qa: i16,
+    ft_weights: Vec<i16>,
+    ft_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_biases: Vec<i16>,
+}
+
+impl QuantisedNet {
+    /// Reads a net as written by [`net::write_quantised`](crate::net::write_quantised),
+    /// with or without a description after it.
+    pub fn read(path: impl AsRef<Path>, shape: NetShape) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let (bytes, description) = net::split_description(&bytes, shape.quantised_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let values: Vec<i16> = bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        Ok(Self { description, ..Self::from_values(shape, &values, QA)? })
+    }
+
+    /// A net from [`net::quantise_at`] output, quantised with `qa`.
+    pub fn from_values(shape: NetShape, values: &[i16], qa: i16) -> io::Result<Self> {
+        let lens = [
+            768 * shape.input_buckets * shape.hl_size,
+            shape.hl_size,
+            shape.output_buckets * shape.l1_inputs(),
+            shape.output_buckets,
+        ];
+        let expected: usize = lens.iter().sum();
+        if values.len() < expected || values.len() > expected.next_multiple_of(32) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("net has {} values, expected {} for hl_size {}", values.len(), expected, shape.hl_size),
+            ));
+        }
+
+        let mut rest = values;
+        let mut take = |len: usize| {
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            head.to_vec()
+        };
+        Ok(Self {
+            shape,
+            description: None,
+            qa,
+            ft_weights: take(lens[0]),
+            ft_biases: take(lens[1]),
+            output_weights: take(lens[2]),
+            output_biases: take(lens[3]),
+        })
+    }
+
+    /// `(ft_weights, ft_biases, output_weights, output_biases)` in file order.
+    pub fn values(&self) -> (&[i16], &[i16], &[i16], &[i16]) {
+        (&self.ft_weights, &self.ft_biases, &self.output_weights, &self.output_biases)
+    }
+
+    fn accumulator(&self, board: &ChessBoard, perspective: u8) -> Vec<i16> {
+        let hl = self.shape.hl_size;
+        // `opp_ksq` is already flipped to the opponent's side of the board.
+        let king = king_bucket(if perspective == 1 { board.opp_ksq } else { board.ksq });
+
+        let mut acc = self.ft_biases.clone();
+        for (colour, piece, square) in data::pieces(board) {
+            let feature = feature_index(perspective, colour, piece, square, king);
+            let weights = &self.ft_weights[feature * hl..][..hl];
+            acc.iter_mut().zip(weights).for_each(|(a, &w)| *a = a.wrapping_add(w));
+        }
+        acc
+    }
+
+    /// The side to move's hidden layer, SCReLU of its accumulator scaled back
+    /// to the float range training sees (`stm_hidden`, in [0, 1]).
+    pub fn stm_activations(&self, board: &ChessBoard) -> Vec<f32> {
+        let qa = f32::from(self.qa);
+        self.accumulator(board, 0)
+            .into_iter()
+            .map(|a| {
+                let clamped = f32::from(a.clamp(0, self.qa)) / qa;
+                clamped * clamped
+            })
+            .collect()
+    }
+
+    /// Side-to-move eval in centipawns, computed as the engine does.
+    pub fn eval(&self, board: &ChessBoard, eval_scale: i32) -> i32 {
+        let bucket = data::material_bucket(board, self.shape.output_buckets);
+        let weights = &self.output_weights[bucket * self.shape.l1_inputs()..][..self.shape.l1_inputs()];
+        let screlu_dot = |acc: &[i16], weights: &[i16]| -> i32 {
+            acc.iter()
+                .zip(weights)
+                .map(|(&a, &w)| {
+                    let clamped = i32::from(a.clamp(0, self.qa));
+                    clamped * clamped * i32::from(w)
+                })
+                .sum()
+        };
+
+        let hl = self.shape.hl_size;
+        let mut output = screlu_dot(&self.accumulator(board, 0), &weights[..hl]);
+        if !self.shape.single_perspective {
+            output += screlu_dot(&self.accumulator(board, 1), &weights[hl..]);
+        }
+        output /= i32::from(self.qa);
+        output += i32::from(self.output_biases[bucket]);
+        output * eval_scale / (i32::from(self.qa) * i32::from(QB))
+    }
+
+    /// Whether an accumulator of `board` leaves the i16 range, where the
+    /// engine's (and [`eval`](Self::eval)'s) wrapping adds go wrong.
+    pub fn overflows(&self, board: &ChessBoard) -> bool {
+        let hl = self.shape.hl_size;
+        let perspectives: &[u8] = if self.shape.single_perspective { &[0] } else { &[0, 1] };
+        perspectives.iter().any(|&perspective| {
+            let king = king_bucket(if perspective == 1 { board.opp_ksq } else { board.ksq });
+            let mut acc: Vec<i32> = self.ft_biases.iter().map(|&b| i32::from(b)).collect();
+            for (colour, piece, square) in data::pieces(board) {
+                let feature = feature_index(perspective, colour, piece, square, king);
+                let weights = &self.ft_weights[feature * hl..][..hl];
+                acc.iter_mut().zip(weights).for_each(|(a, &w)| *a += i32::from(w));
+            }
+            acc.iter().any(|&a| i16::try_from(a).is_err())
+        })
+    }
+}
+
+/// `--fen-list`: writes `<fen>,<cp>` for every FEN line of `input`, blank
+/// lines and `#` comments skipped. Malformed FENs go to `malformed` with their
+/// line number instead. Returns how many FENs were scored.
+pub fn score_fens(
+    net: &QuantisedNet,
+    eval_scale: i32,
+    input: impl BufRead,
+    out: &mut impl Write,
+    mut malformed: impl FnMut(usize, String),
+) -> io::Result<usize> {
+    let mut scored = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let fen = line.trim();
+        if fen.is_empty() || fen.starts_with('#') {
+            continue;
+        }
+        match data::parse_fen(fen) {
+            Ok(board) => {
+                writeln!(out, "{},{}", fen, net.eval(&board, eval_scale))?;
+                scored += 1;
+            }
+            Err(e) => malformed(i + 1, e),
+        }
+    }
+    Ok(scored)
+}
+
+/// `--sanity-startpos`: the start position's eval in cp, or `Err` with it
+/// when its magnitude is beyond `limit`.
+pub fn check_startpos(net: &QuantisedNet, eval_scale: i32, limit: i32) -> Result<i32, i32> {
+    let startpos = data::parse_fen(data::STARTPOS_FEN).expect("the start position parses");
+    let cp = net.eval(&startpos, eval_scale);
+    if cp.abs() > limit { Err(cp) } else { Ok(cp) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::{NUM_INPUT_BUCKETS, NUM_OUTPUT_BUCKETS};
+
+    /// A net whose eval is `cp * (bucket + 1)` at scale 400, whatever the
+    /// pieces; the start position is in the last of 8 buckets.
+    fn bucket_net(cp: i32) -> QuantisedNet {
+        let shape = NetShape {
+            hl_size: 2,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: NUM_OUTPUT_BUCKETS,
+            single_perspective: false,
+            output_factoriser: false,
+        };
+        let mut values = vec![0; 768 * NUM_INPUT_BUCKETS * 2 + 2 + NUM_OUTPUT_BUCKETS * 4];
+        let bias = |bucket: i32| (bucket * cp * i32::from(QA) * i32::from(QB) / 400) as i16;
+        values.extend((1..=NUM_OUTPUT_BUCKETS as i32).map(bias));
+        QuantisedNet::from_values(shape, &values, QA).unwrap()
+    }
+
+    #[test]
+    fn scores_each_fen_and_reports_malformed_lines() {
+        let input = "# puzzles\n\
+            rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\n\
+            \n\
+            4k3/4p3/8/8/8/8/4P3 w - - 0 1\n\
+            4k3/4p3/8/8/8/8/4P3/4K3 b - - 0 1\n";
+        let mut out = Vec::new();
+        let mut errors = Vec::new();
+        let scored =
+            score_fens(&bucket_net(100), 400, input.as_bytes(), &mut out, |line, e| errors.push((line, e))).unwrap();
+
+        assert_eq!(scored, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1,800\n4k3/4p3/8/8/8/8/4P3/4K3 b - - 0 1,100\n"
+        );
+        assert_eq!(errors, [(4, "7 ranks, expected 8".to_string())]);
+    }
+
+    #[test]
+    fn startpos_eval_must_be_within_the_limit() {
+        assert_eq!(check_startpos(&bucket_net(5), 400, 40), Ok(40));
+        assert_eq!(check_startpos(&bucket_net(5), 400, 39), Err(40));
+        assert_eq!(check_startpos(&bucket_net(-100), 400, 50), Err(-800));
+        assert_eq!(check_startpos(&bucket_net(0), 400, 0), Ok(0));
+    }
+}
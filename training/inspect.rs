@@ -0,0 +1,107 @@
+//! `training inspect`: what a data file holds, before training on it. The
+//! same summary as `--dataset-stats`, and optionally the feature coverage of
+//! the whole file rather than of one superbatch.
+
+use crate::{
+    commands::ToolError,
+    config::{ConfigError, value},
+    coverage::FeatureCoverage,
+    data::{self, RECORD_SIZE},
+    dataset_stats::DatasetStats,
+    net::{NUM_INPUT_BUCKETS, NUM_OUTPUT_BUCKETS},
+};
+
+pub const USAGE: &str = "\
+Summarise a bulletformat data file
+
+Usage: training inspect <PATH> [OPTIONS]
+
+Options:
+      --coverage           Also report which input features the file leaves untrained
+      --single-perspective Count coverage for the side-to-move accumulator only
+      --head <N>           Only read the first N records
+  -h, --help               Show this help";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InspectOptions {
+    pub path: String,
+    pub coverage: bool,
+    pub single_perspective: bool,
+    pub head: Option<u64>,
+}
+
+impl InspectOptions {
+    /// Parses `inspect`'s arguments, the program name at index 0.
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut path: Option<String> = None;
+        let mut coverage = false;
+        let mut single_perspective = false;
+        let mut head: Option<u64> = None;
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--coverage" => coverage = true,
+                "--single-perspective" => single_perspective = true,
+                "--head" => head = Some(value(args, &mut i)?),
+                "--help" | "-h" => return Err(ConfigError::HelpRequested),
+                flag if flag.starts_with('-') || path.is_some() => {
+                    return Err(ConfigError::UnknownFlag(flag.to_string()));
+                }
+                file => path = Some(file.to_string()),
+            }
+            i += 1;
+        }
+
+        let path = path.ok_or(ConfigError::Requires("inspect", "a data file <PATH>"))?;
+        if single_perspective && !coverage {
+            return Err(ConfigError::Requires("--single-perspective", "--coverage"));
+        }
+        Ok(Self { path, coverage, single_perspective, head })
+    }
+}
+
+/// Prints the summary of `options.path`.
+pub fn run(options: &InspectOptions) -> Result<(), ToolError> {
+    let records = data::count_records(&options.path, RECORD_SIZE)?;
+    let limit = options.head.unwrap_or(u64::MAX);
+    let mut stats = DatasetStats::new(NUM_OUTPUT_BUCKETS);
+    let mut coverage = options.coverage.then(|| FeatureCoverage::new(NUM_INPUT_BUCKETS, !options.single_perspective));
+    data::for_each_record_up_to(&options.path, limit, |board| {
+        stats.add(board);
+        if let Some(coverage) = &mut coverage {
+            coverage.add(board);
+        }
+    })?;
+    println!("{}: {} records of {} bytes", options.path, records, RECORD_SIZE);
+    print!("{}", stats);
+    if let Some(coverage) = coverage {
+        println!("{}", coverage);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::args;
+
+    #[test]
+    fn inspect_takes_one_file() {
+        let options =
+            InspectOptions::from_args(&args(&["a.data", "--coverage", "--single-perspective", "--head", "9"])).unwrap();
+        assert_eq!(
+            options,
+            InspectOptions { path: "a.data".to_string(), coverage: true, single_perspective: true, head: Some(9) }
+        );
+        assert_eq!(InspectOptions::from_args(&args(&[])), Err(ConfigError::Requires("inspect", "a data file <PATH>")));
+        assert_eq!(
+            InspectOptions::from_args(&args(&["a.data", "b.data"])),
+            Err(ConfigError::UnknownFlag("b.data".to_string()))
+        );
+        assert_eq!(
+            InspectOptions::from_args(&args(&["a.data", "--single-perspective"])),
+            Err(ConfigError::Requires("--single-perspective", "--coverage"))
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! `--load` of nets bullet's optimiser cannot read: float archives written
+//! before a tensor existed, and quantised engine nets. Both are upgraded to
+//! the current [`FloatNet`] with the missing factorisers zeroed, which is
+//! the same net, so training continues from exactly what was saved.
+
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    archive,
+    inference::QuantisedNet,
+    net::{DESCRIPTION_MAGIC, FloatNet, NetShape, OUTPUT_FACTORISER, QA, QB, TENSORS},
+};
+
+/// Tensors an older net may lack; zero is a no-op for each.
+const ZERO_WHEN_MISSING: [&str; 2] = ["l0f", OUTPUT_FACTORISER];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightsFormat {
+    /// bullet's `weights.bin`, loaded by the optimiser itself.
+    Optimiser,
+    /// A tensor [`archive`], e.g. `weights.fp32`.
+    FloatArchive,
+    /// A quantised engine net (save format 1 or 2), factorisers merged.
+    Quantised,
+}
+
+/// Picks the format from the archive magic, then from the file size a
+/// quantised net of `shape` would have, or a description right after it.
+pub fn detect(path: impl AsRef<Path>, shape: &NetShape) -> io::Result<WeightsFormat> {
+    let path = path.as_ref();
+    if archive::is_archive(path)? {
+        return Ok(WeightsFormat::FloatArchive);
+    }
+    let len = fs::metadata(path)?.len() as usize;
+    let net_bytes = shape.quantised_bytes();
+    let values = len / 2;
+    let expected = 768 * shape.input_buckets * shape.hl_size
+        + shape.hl_size
+        + shape.output_buckets * shape.l1_inputs()
+        + shape.output_buckets;
+    if (expected..=expected.next_multiple_of(32)).contains(&values) {
+        return Ok(WeightsFormat::Quantised);
+    }
+    if len >= net_bytes + 8 {
+        let mut magic = [0u8; 4];
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(net_bytes as u64))?;
+        file.read_exact(&mut magic)?;
+        if &magic == DESCRIPTION_MAGIC {
+            return Ok(WeightsFormat::Quantised);
+        }
+    }
+    Ok(WeightsFormat::Optimiser)
+}
+
+/// Fills in the tensors of `shape` an archive lacks with zeros and returns
+/// the net with the ids that were filled in.
+pub fn upgrade_archive(
+    tensors: Vec<(String, Vec<f32>)>,
+    shape: &NetShape,
+) -> Result<(FloatNet, Vec<&'static str>), String> {
+    let mut net = FloatNet::default();
+    for (id, values) in tensors {
+        match net.get_mut(&id) {
+            Some(tensor) if shape.tensor_len(&id).is_some() => *tensor = values,
+            _ => return Err(format!("tensor {} is not part of this architecture", id)),
+        }
+    }
+    let mut filled = Vec::new();
+    for id in ZERO_WHEN_MISSING {
+        let tensor = net.get_mut(id).unwrap();
+        if let (true, Some(len)) = (tensor.is_empty(), shape.tensor_len(id)) {
+            *tensor = vec![0.0; len];
+            filled.push(id);
+        }
+    }
+    if let Some(id) = TENSORS.into_iter().find(|id| net.get(id).unwrap().is_empty()) {
+        return Err(format!("tensor {} is missing", id));
+    }
+    net.check_shape(shape)?;
+    Ok((net, filled))
+}
+
+/// The float net a quantised one was made from, up to rounding: `l0w` keeps
+/// the merged factoriser, `l1` is unscaled by `l1_scale` and transposed back,
+/// and the factorisers of `shape` are zero.
+pub fn dequantise(net: &QuantisedNet, shape: &NetShape, l1_scale: f32) -> FloatNet {
+    let (ft_weights, ft_biases, output_weights, output_biases) = net.values();
+    let (inputs, buckets) = (shape.l1_inputs(), shape.output_buckets);
+    let (qa, qb) = (f32::from(QA), f32::from(QB));
+    FloatNet {
+        l0w: ft_weights.iter().map(|&w| f32::from(w) / qa).collect(),
+        l0f: vec![0.0; shape.tensor_len("l0f").unwrap()],
+        l0b: ft_biases.iter().map(|&b| f32::from(b) / qa).collect(),
+        l1w: (0..inputs * buckets)
+            .map(|i| f32::from(output_weights[(i % buckets) * inputs + i / buckets]) / (qb * l1_scale))
+            .collect(),
+        l1b: output_biases.iter().map(|&b| f32::from(b) / (qa * qb * l1_scale)).collect(),
+        l1f: vec![0.0; shape.tensor_len(OUTPUT_FACTORISER).unwrap_or(0)],
+    }
+}
+
+/// Reads a non-optimiser net at `path` as the current [`FloatNet`], with a
+/// note on what was upgraded.
+pub fn load(path: &str, format: WeightsFormat, shape: &NetShape, l1_scale: f32) -> Result<(FloatNet, String), String> {
+    match format {
+        WeightsFormat::Optimiser => Err(format!("{} is bullet's own format, load it through the optimiser", path)),
+        WeightsFormat::FloatArchive => {
+            let tensors = archive::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            let (net, filled) = upgrade_archive(tensors, shape).map_err(|e| format!("{}: {}", path, e))?;
+            let note = if filled.is_empty() {
+                "float archive".to_string()
+            } else {
+                format!("float archive, zeroed missing {}", filled.join(", "))
+            };
+            Ok((net, note))
+        }
+        WeightsFormat::Quantised => {
+            let quantised = QuantisedNet::read(path, *shape).map_err(|e| format!("{}: {}", path, e))?;
+            let note = match &quantised.description {
+                Some(description) => format!("quantised net \"{}\", factorisers zeroed", description),
+                None => "quantised net, factorisers zeroed".to_string(),
+            };
+            Ok((dequantise(&quantised, shape, l1_scale), note))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{net, test_util::temp_path};
+
+    fn tiny_shape() -> NetShape {
+        NetShape {
+            hl_size: 2,
+            input_buckets: 1,
+            output_buckets: 2,
+            single_perspective: false,
+            output_factoriser: false,
+        }
+    }
+
+    /// The tensors of `shape` but `skip`, with every value `value`.
+    fn old_archive(shape: &NetShape, skip: &str, value: f32) -> Vec<(String, Vec<f32>)> {
+        shape
+            .tensors()
+            .into_iter()
+            .filter(|&id| id != skip)
+            .map(|id| (id.to_string(), vec![value; shape.tensor_len(id).unwrap()]))
+            .collect()
+    }
+
+    #[test]
+    fn detects_each_format() {
+        let shape = tiny_shape();
+        let path = temp_path("legacy-detect");
+        archive::write(&path, &old_archive(&shape, "l0f", 0.1)).unwrap();
+        assert_eq!(detect(&path, &shape).unwrap(), WeightsFormat::FloatArchive);
+
+        let (net, _) = upgrade_archive(old_archive(&shape, "l0f", 0.1), &shape).unwrap();
+        net::write_quantised(&path, &net::quantise(&net, &shape, 1.0).unwrap()).unwrap();
+        assert_eq!(detect(&path, &shape).unwrap(), WeightsFormat::Quantised);
+        net::write_description(&path, shape.quantised_bytes(), "old net").unwrap();
+        assert_eq!(detect(&path, &shape).unwrap(), WeightsFormat::Quantised);
+
+        fs::write(&path, vec![0u8; 4 * 3084]).unwrap();
+        assert_eq!(detect(&path, &shape).unwrap(), WeightsFormat::Optimiser);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_factorisers_are_zeroed() {
+        let shape = NetShape { output_factoriser: true, ..tiny_shape() };
+        let (net, filled) = upgrade_archive(old_archive(&shape, "l0f", 0.1), &shape).unwrap();
+        assert_eq!(filled, ["l0f"]);
+        assert!(net.l0f.len() == 768 * 2 && net.l0f.iter().all(|&v| v == 0.0));
+        assert!(net.l1f.iter().all(|&v| v == 0.1));
+
+        // an archive from before the output factoriser
+        let old: Vec<_> = old_archive(&tiny_shape(), "", 0.1);
+        let (net, filled) = upgrade_archive(old, &shape).unwrap();
+        assert_eq!(filled, [OUTPUT_FACTORISER]);
+        assert_eq!(net.l1f, [0.0; 4]);
+    }
+
+    #[test]
+    fn rejects_an_archive_of_another_architecture() {
+        let shape = tiny_shape();
+        let error = upgrade_archive(old_archive(&NetShape { output_factoriser: true, ..shape }, "", 0.1), &shape);
+        assert_eq!(error, Err("tensor l1f is not part of this architecture".to_string()));
+        assert_eq!(upgrade_archive(old_archive(&shape, "l1b", 0.1), &shape), Err("tensor l1b is missing".to_string()));
+    }
+
+    #[test]
+    fn a_quantised_net_dequantises_to_the_merged_float_net() {
+        let shape = tiny_shape();
+        let (mut original, _) = upgrade_archive(old_archive(&shape, "", 0.1), &shape).unwrap();
+        original.l1w = (0..8).map(|i| i as f32 / 16.0).collect();
+        let values = net::quantise(&original, &shape, 2.0).unwrap();
+        let path = temp_path("legacy-dequantise");
+        net::write_quantised(&path, &values).unwrap();
+        let (net, note) = load(path.to_str().unwrap(), WeightsFormat::Quantised, &shape, 2.0).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(note, "quantised net, factorisers zeroed");
+        // l0f is merged into l0w, and quantising again gives the same net
+        assert!(net.l0w.iter().all(|&w| (w - 0.2).abs() < 1.0 / f32::from(QA)));
+        assert!(net.l0f.iter().all(|&f| f == 0.0));
+        for (got, want) in net.l1w.iter().zip(&original.l1w) {
+            assert!((got - want).abs() < 1.0 / f32::from(QB), "{} != {}", got, want);
+        }
+        assert_eq!(net::quantise(&net, &shape, 2.0).unwrap(), values);
+    }
+}
@@ -0,0 +1,52 @@
+//! SleepMind NNUE trainer, usable both from the `training` binary and from
+//! other tools that want to embed a training run.
+
+pub mod activations;
+pub mod affinity;
+pub mod archive;
+pub mod backend;
+pub mod checkpoint;
+pub mod chunks;
+pub mod commands;
+pub mod config;
+pub mod config_file;
+pub mod convert;
+pub mod coverage;
+pub mod data;
+pub mod datagen;
+pub mod dataset_stats;
+pub mod elo;
+pub mod ema;
+pub mod git;
+pub mod grow;
+pub mod inference;
+pub mod inspect;
+pub mod legacy;
+pub mod loader;
+pub mod logging;
+pub mod lr_find;
+pub mod lr_schedule;
+pub mod manifest;
+pub mod memory;
+pub mod metrics;
+pub mod net;
+pub mod piece_values;
+pub mod profile;
+pub mod quant_scales;
+pub mod recovery;
+pub mod replay;
+pub mod resume;
+pub mod schedule;
+pub mod shuffle;
+pub mod stopping;
+pub mod summary;
+pub mod symmetry;
+pub mod trainer;
+pub mod warm_cache;
+pub mod weights;
+
+#[cfg(test)]
+mod test_util;
+
+pub use config::{Config, ConfigError};
+pub use trainer::{TrainError, run};
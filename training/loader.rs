@@ -0,0 +1,1071 @@
+//! Data loader wrapper applying per-position target transforms on top of
+//! bullet's sequential or binpack loader.
+//!
+//! bullet blends `score` and `result` with a single per-batch WDL proportion.
+//! Transforms that need a per-position blend instead rewrite `score` so that
+//! `sigmoid(score / eval_scale)` equals the desired target, and the schedule
+//! is run with a WDL proportion of 0.
+
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bullet::{
+    game::formats::{bulletformat::ChessBoard, sfbinpack::TrainingDataEntry},
+    value::loader::{DataLoader, DirectSequentialDataLoader, SfBinpackLoader},
+};
+
+use crate::{
+    affinity,
+    config::{Config, DataFormat},
+    data, notice, warn,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetTransform {
+    pub eval_scale: f32,
+    /// `(opening, endgame)` WDL proportions, interpolated by game phase.
+    pub wdl_by_phase: Option<(f32, f32)>,
+    /// Constant WDL proportion when there is no phase-aware blend. Bullet
+    /// blends it in by itself unless `wdl_smooth` is set.
+    pub wdl: f32,
+    /// Label smoothing on the game result, see [`smooth_wdl`].
+    pub wdl_smooth: f32,
+}
+
+impl TargetTransform {
+    pub fn is_identity(&self) -> bool {
+        self.wdl_by_phase.is_none() && self.wdl_smooth == 0.0
+    }
+
+    /// Target in `[0, 1]` the net is trained towards for this record.
+    pub fn target(&self, board: &ChessBoard) -> f32 {
+        let eval = data::sigmoid(f32::from(board.score) / self.eval_scale);
+        let wdl = match self.wdl_by_phase {
+            Some((opening, endgame)) => wdl_for_phase(data::game_phase(board), opening, endgame),
+            None => self.wdl,
+        };
+        wdl * smooth_wdl(data::result(board), self.wdl_smooth) + (1.0 - wdl) * eval
+    }
+
+    pub fn apply(&self, board: &mut ChessBoard) {
+        board.score = score_for_target(self.target(board), self.eval_scale);
+    }
+}
+
+/// `--subsample`: keeps a record when a seeded hash of its contents falls
+/// below `threshold`, so the same records are kept on every pass and rerun
+/// wherever they sit in the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subsample {
+    threshold: u64,
+    pub seed: u64,
+}
+
+impl Subsample {
+    pub fn new(fraction: f32, seed: u64) -> Self {
+        let threshold = if fraction >= 1.0 { u64::MAX } else { (f64::from(fraction) * u64::MAX as f64) as u64 };
+        Self { threshold, seed }
+    }
+
+    pub fn keeps(&self, board: &ChessBoard) -> bool {
+        self.threshold == u64::MAX || record_hash(board, self.seed) < self.threshold
+    }
+}
+
+/// Cap on a `--reweight-buckets` weight, so a bucket seen a handful of times
+/// in the sample is not repeated hundreds of times per batch.
+pub const MAX_BUCKET_WEIGHT: f32 = 16.0;
+/// Hash seed for the fractional copy of a reweighted record, independent of
+/// `--subsample-seed`.
+pub const REWEIGHT_SEED: u64 = 0x5eed_b0c7;
+
+/// Inverse-frequency weights from per-bucket record counts, normalised so
+/// the mean weight over the data is 1 and capped at [`MAX_BUCKET_WEIGHT`]. A
+/// bucket absent from the counts gets the largest weight.
+pub fn inverse_frequency_weights(counts: &[u64]) -> Vec<f32> {
+    let total: u64 = counts.iter().sum();
+    let seen = counts.iter().filter(|&&c| c > 0).count();
+    if seen == 0 {
+        return vec![1.0; counts.len()];
+    }
+    let weights: Vec<f32> =
+        counts.iter().map(|&c| if c > 0 { total as f32 / (seen as f32 * c as f32) } else { 0.0 }).collect();
+    let largest = weights.iter().copied().fold(0.0, f32::max);
+    weights.iter().map(|&w| if w > 0.0 { w } else { largest }.min(MAX_BUCKET_WEIGHT)).collect()
+}
+
+/// `--reweight-buckets`: a record of bucket `b` is passed on `weight[b]`
+/// times on average, the whole part always and the fraction by a seeded hash.
+/// Repeating a record in the batches scales its share of the loss the way a
+/// per-position weight would, which the loss function has no input for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BucketWeights {
+    whole: [u8; 64],
+    fraction: [u64; 64],
+    num_buckets: usize,
+}
+
+impl BucketWeights {
+    pub fn new(weights: &[f32]) -> Self {
+        let mut whole = [0; 64];
+        let mut fraction = [0; 64];
+        for (b, &w) in weights.iter().enumerate().take(64) {
+            let w = w.clamp(0.0, MAX_BUCKET_WEIGHT);
+            whole[b] = w.trunc() as u8;
+            fraction[b] = (f64::from(w.fract()) * u64::MAX as f64) as u64;
+        }
+        Self { whole, fraction, num_buckets: weights.len().min(64) }
+    }
+
+    /// Copies of `board`, the fractional one decided by `seed`'s hash of it
+    /// ([`REWEIGHT_SEED`] unless `--resumeable-seed` derives one).
+    pub fn copies(&self, board: &ChessBoard, seed: u64) -> usize {
+        let b = data::material_bucket(board, self.num_buckets);
+        usize::from(self.whole[b]) + usize::from(record_hash(board, seed) < self.fraction[b])
+    }
+}
+
+/// One splitmix64 step mixing `value` into `hash`.
+pub fn mix(hash: u64, value: u64) -> u64 {
+    let mut z = (hash ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// splitmix64 over the fields of a record, starting from `seed`.
+pub fn record_hash(board: &ChessBoard, seed: u64) -> u64 {
+    let (low, high) = board.pcs.split_at(8);
+    let tail = u64::from(board.score as u16)
+        | u64::from(board.result) << 16
+        | u64::from(board.ksq) << 24
+        | u64::from(board.opp_ksq) << 32;
+    [board.occ, u64::from_le_bytes(low.try_into().unwrap()), u64::from_le_bytes(high.try_into().unwrap()), tail]
+        .into_iter()
+        .fold(seed, mix)
+}
+
+/// `--target-noise`: zero-mean Gaussian noise of `sigma` centipawns added to
+/// a record's score before it becomes a target, so it goes through the eval
+/// scale and sigmoid like the score itself. The noise is drawn from the seed
+/// and the record's position in the stream, so reruns see the same noise and
+/// each pass over the data a fresh draw. Unlike label smoothing it perturbs
+/// the eval, not the game result, and unlike gradient noise it leaves the
+/// optimiser alone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetNoise {
+    pub sigma: f32,
+    pub seed: u64,
+}
+
+impl TargetNoise {
+    /// Noise in centipawns for the `index`-th record streamed (Box-Muller).
+    pub fn offset(&self, index: u64) -> f32 {
+        let first = mix(self.seed, index);
+        let second = mix(first, index);
+        let uniform = |h: u64| ((h >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        let gaussian = (-2.0 * uniform(first).ln()).sqrt() * (std::f64::consts::TAU * uniform(second)).cos();
+        (gaussian * f64::from(self.sigma)) as f32
+    }
+
+    pub fn apply(&self, board: &mut ChessBoard, index: u64) {
+        let score = f32::from(board.score) + self.offset(index);
+        board.score = score.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+    }
+}
+
+/// `--resumeable-seed`: superbatch `n` draws from the seed `base ^ n`, so
+/// every superbatch gets its own draws and a run resumed at superbatch K
+/// repeats the original's from K on. A record's superbatch follows from its
+/// index in the stream, which bullet starts at the resumed superbatch's first
+/// batch; it is exact while records and trained positions are one to one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SuperbatchSeed {
+    pub base: u64,
+    pub positions_per_superbatch: u64,
+}
+
+impl SuperbatchSeed {
+    pub fn at(&self, superbatch: usize) -> u64 {
+        self.base ^ superbatch as u64
+    }
+
+    /// The 1-based superbatch of the `index`-th record streamed.
+    pub fn superbatch_of(&self, index: u64) -> usize {
+        (index / self.positions_per_superbatch.max(1)) as usize + 1
+    }
+
+    pub fn for_record(&self, index: u64) -> u64 {
+        self.at(self.superbatch_of(index))
+    }
+}
+
+/// Records dropped while loading (`--filter-eval-max`, `--filter-no-check`,
+/// `--holdout-buckets`, `--subsample`), or repeated (`--reweight-buckets`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecordFilter {
+    /// Drop records whose `|score|` exceeds this many centipawns.
+    pub eval_max: Option<i16>,
+    /// Drop records where the side to move is in check.
+    pub no_check: bool,
+    /// Bit `b` set: drop records in output bucket `b` of `num_buckets`.
+    pub holdout_buckets: u64,
+    pub num_buckets: usize,
+    pub subsample: Option<Subsample>,
+    pub reweight: Option<BucketWeights>,
+}
+
+impl RecordFilter {
+    pub fn is_active(&self) -> bool {
+        self.eval_max.is_some()
+            || self.no_check
+            || self.holdout_buckets != 0
+            || self.subsample.is_some()
+            || self.reweight.is_some()
+    }
+
+    /// The same filter over all records, for validation positions that are
+    /// already a separate sample.
+    pub fn without_subsample(self) -> Self {
+        Self { subsample: None, ..self }
+    }
+
+    /// How many times the loader passes `board` on: 0 if dropped, more than 1
+    /// for a record of an upweighted bucket; `reweight_seed` as in
+    /// [`BucketWeights::copies`].
+    pub fn copies(&self, board: &ChessBoard, reweight_seed: u64) -> usize {
+        if !self.keep(board) {
+            return 0;
+        }
+        self.reweight.map_or(1, |weights| weights.copies(board, reweight_seed))
+    }
+
+    pub fn keep(&self, board: &ChessBoard) -> bool {
+        if self.subsample.is_some_and(|subsample| !subsample.keeps(board)) {
+            return false;
+        }
+        if self.eval_max.is_some_and(|max| board.score.unsigned_abs() > max.unsigned_abs()) {
+            return false;
+        }
+        if self.holdout_buckets != 0
+            && self.holdout_buckets & (1 << data::material_bucket(board, self.num_buckets)) != 0
+        {
+            return false;
+        }
+        !(self.no_check && data::in_check(board))
+    }
+}
+
+/// `--holdout-buckets` as a bitmask for [`RecordFilter`].
+pub fn bucket_mask(buckets: &[usize]) -> u64 {
+    buckets.iter().fold(0, |mask, &b| mask | (1 << b))
+}
+
+/// WDL proportion for a position: `opening` at phase 1, `endgame` at phase 0,
+/// linear in between.
+pub fn wdl_for_phase(phase: f32, opening: f32, endgame: f32) -> f32 {
+    endgame + (opening - endgame) * phase
+}
+
+/// Pulls a game result towards 0.5: 1 becomes `1 - eps`, 0 becomes `eps`,
+/// a draw stays 0.5.
+pub fn smooth_wdl(result: f32, eps: f32) -> f32 {
+    result * (1.0 - 2.0 * eps) + eps
+}
+
+/// Targets closer than this to 0 or 1 are clamped before inverting the sigmoid.
+pub const TARGET_EPSILON: f32 = 1e-6;
+
+/// Inverse of `sigmoid(score / eval_scale)`, saturating at the i16 range.
+pub fn score_for_target(target: f32, eval_scale: f32) -> i16 {
+    let target = target.clamp(TARGET_EPSILON, 1.0 - TARGET_EPSILON);
+    let score = eval_scale * (target / (1.0 - target)).ln();
+    score.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+}
+
+/// `(low, high)` number of targets at or beyond the clamp bounds.
+pub fn count_clamped(targets: impl IntoIterator<Item = f32>, epsilon: f32) -> (u64, u64) {
+    targets
+        .into_iter()
+        .fold((0, 0), |(low, high), t| (low + u64::from(t <= epsilon), high + u64::from(t >= 1.0 - epsilon)))
+}
+
+/// Counters shared between the loader threads and the training callback.
+pub struct LoaderStats {
+    bucket_counts: Vec<AtomicU64>,
+    /// Time spent reading and transforming batches.
+    loading_nanos: AtomicU64,
+    /// Time spent handing batches over, i.e. blocked on a full batch queue.
+    handoff_nanos: AtomicU64,
+    /// `[seen, dropped]` records for the record filter.
+    filtered: [AtomicU64; 2],
+    /// `[low, high, total]` targets for `--target-clamp-report`, if enabled.
+    clamped: Option<[AtomicU64; 3]>,
+    /// Records dropped by `--skip-bad-records`.
+    bad_records: AtomicU64,
+}
+
+impl LoaderStats {
+    pub fn new(num_buckets: usize, count_clamped: bool) -> Self {
+        Self {
+            bucket_counts: (0..num_buckets).map(|_| AtomicU64::new(0)).collect(),
+            loading_nanos: AtomicU64::new(0),
+            handoff_nanos: AtomicU64::new(0),
+            filtered: [AtomicU64::new(0), AtomicU64::new(0)],
+            clamped: count_clamped.then(|| [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)]),
+            bad_records: AtomicU64::new(0),
+        }
+    }
+
+    fn record_targets(&self, batch: &[ChessBoard], transform: &TargetTransform) {
+        let Some([low, high, total]) = &self.clamped else { return };
+        let (l, h) = count_clamped(batch.iter().map(|board| transform.target(board)), TARGET_EPSILON);
+        low.fetch_add(l, Ordering::Relaxed);
+        high.fetch_add(h, Ordering::Relaxed);
+        total.fetch_add(batch.len() as u64, Ordering::Relaxed);
+    }
+
+    /// `(seen, dropped)` records since the last call.
+    pub fn take_filtered(&self) -> (u64, u64) {
+        let [seen, dropped] = &self.filtered;
+        (seen.swap(0, Ordering::Relaxed), dropped.swap(0, Ordering::Relaxed))
+    }
+
+    /// Records skipped as malformed since the last call.
+    pub fn take_bad_records(&self) -> u64 {
+        self.bad_records.swap(0, Ordering::Relaxed)
+    }
+
+    /// `(low, high, total)` target counts since the last call, if counting.
+    pub fn take_clamped(&self) -> Option<(u64, u64, u64)> {
+        let [low, high, total] = self.clamped.as_ref()?;
+        let take = |c: &AtomicU64| c.swap(0, Ordering::Relaxed);
+        Some((take(low), take(high), take(total)))
+    }
+
+    fn record(&self, batch: &[ChessBoard]) {
+        let num_buckets = self.bucket_counts.len();
+        let mut counts = vec![0u64; num_buckets];
+        for board in batch {
+            counts[data::material_bucket(board, num_buckets)] += 1;
+        }
+        for (total, count) in self.bucket_counts.iter().zip(counts) {
+            total.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Positions seen per output bucket since the last call.
+    pub fn take_bucket_counts(&self) -> Vec<u64> {
+        self.bucket_counts.iter().map(|c| c.swap(0, Ordering::Relaxed)).collect()
+    }
+
+    /// `(loading, handoff)` time since the last call.
+    pub fn take_times(&self) -> (Duration, Duration) {
+        let take = |nanos: &AtomicU64| Duration::from_nanos(nanos.swap(0, Ordering::Relaxed));
+        (take(&self.loading_nanos), take(&self.handoff_nanos))
+    }
+
+    fn add_time(nanos: &AtomicU64, time: Duration) {
+        nanos.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Runs `op`, retrying failed attempts up to `retries` times with exponential
+/// backoff starting at 100ms and capped at 10s.
+pub fn with_retries<T>(what: &str, retries: usize, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                let backoff = Duration::from_millis(100 << (attempt - 1).min(7)).min(Duration::from_secs(10));
+                notice!("{} failed ({}), retry {}/{} in {:?}", what, e, attempt, retries, backoff);
+                thread::sleep(backoff);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Sequential loader over a record range of one file, wrapping around at the
+/// end of the range. Used to keep a `--val-split` hold-out out of training and
+/// to retry transient read errors (`--io-retries`).
+#[derive(Clone)]
+pub struct RangeLoader {
+    paths: [String; 1],
+    records: Range<u64>,
+    retries: usize,
+}
+
+impl RangeLoader {
+    pub fn new(path: &str, records: Range<u64>, retries: usize) -> Self {
+        Self { paths: [path.to_string()], records, retries }
+    }
+}
+
+impl DataLoader<ChessBoard> for RangeLoader {
+    fn data_file_paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    fn count_positions(&self) -> Option<u64> {
+        Some(self.records.end - self.records.start)
+    }
+
+    fn map_batches<F: FnMut(&[ChessBoard]) -> bool>(&self, start_batch: usize, batch_size: usize, mut f: F) {
+        let len = self.records.end - self.records.start;
+        if len == 0 {
+            return;
+        }
+        let path = &self.paths[0];
+        let mut file = with_retries("opening training data", self.retries, || fs::File::open(path))
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", path, e));
+        let mut next = (start_batch as u64 * batch_size as u64) % len;
+        let mut batch = vec![ChessBoard::default(); batch_size];
+        loop {
+            let mut filled = 0;
+            while filled < batch_size {
+                let count = (batch_size - filled).min((len - next) as usize);
+                let offset = (self.records.start + next) * data::RECORD_SIZE as u64;
+                // SAFETY: ChessBoard is plain-old-data, valid for any bit pattern
+                let bytes = unsafe {
+                    std::slice::from_raw_parts_mut(
+                        batch[filled..filled + count].as_mut_ptr().cast::<u8>(),
+                        count * data::RECORD_SIZE,
+                    )
+                };
+                // a failed read may leave the handle in a bad state, so retries reopen it
+                let mut first = true;
+                with_retries("reading training data", self.retries, || {
+                    if !std::mem::take(&mut first) {
+                        file = fs::File::open(path)?;
+                    }
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.read_exact(bytes)
+                })
+                .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+                filled += count;
+                next = (next + count as u64) % len;
+            }
+            if !f(&batch) {
+                return;
+            }
+        }
+    }
+}
+
+/// Decode buffer of bullet's binpack loader.
+pub const BINPACK_BUFFER_MB: usize = 1024;
+
+/// Binpack entries are all passed on; [`RecordFilter`] works on the decoded
+/// boards like it does for the other sources.
+pub type BinpackFilter = fn(&TrainingDataEntry) -> bool;
+
+pub fn keep_entry(_: &TrainingDataEntry) -> bool {
+    true
+}
+
+/// The loader [`TargetLoader`] wraps: the whole file through bullet, a
+/// record range of it, or a binpack file through bullet.
+#[derive(Clone)]
+pub enum SourceLoader {
+    File(DirectSequentialDataLoader),
+    Range(RangeLoader),
+    Binpack(SfBinpackLoader<BinpackFilter>),
+}
+
+impl SourceLoader {
+    /// The loader for `config`'s `--data`. bullet's loader reads the whole
+    /// file and cannot retry, so a [`RangeLoader`] over `train_records` is used
+    /// whenever part of the file is `held_out` or reads need `--io-retries`.
+    pub fn for_config(config: &Config, train_records: Range<u64>, held_out: bool) -> Self {
+        match config.data_format {
+            DataFormat::Binpack => Self::binpack(&config.dataset_path, config.threads),
+            DataFormat::Direct if held_out || config.io_retries > 0 => {
+                Self::Range(RangeLoader::new(&config.dataset_path, train_records, config.io_retries))
+            }
+            DataFormat::Direct => Self::File(DirectSequentialDataLoader::new(&[&config.dataset_path])),
+        }
+    }
+
+    pub fn binpack(path: &str, threads: usize) -> Self {
+        Self::Binpack(SfBinpackLoader::new(path, BINPACK_BUFFER_MB, threads, keep_entry as BinpackFilter))
+    }
+
+    /// Bytes per record in the file, for byte offsets; binpack has none.
+    pub fn record_size(&self) -> Option<usize> {
+        match self {
+            Self::File(_) | Self::Range(_) => Some(data::RECORD_SIZE),
+            Self::Binpack(_) => None,
+        }
+    }
+}
+
+impl DataLoader<ChessBoard> for SourceLoader {
+    fn data_file_paths(&self) -> &[String] {
+        match self {
+            Self::File(l) => l.data_file_paths(),
+            Self::Range(l) => l.data_file_paths(),
+            Self::Binpack(l) => l.data_file_paths(),
+        }
+    }
+
+    fn count_positions(&self) -> Option<u64> {
+        match self {
+            Self::File(l) => l.count_positions(),
+            Self::Range(l) => l.count_positions(),
+            Self::Binpack(l) => l.count_positions(),
+        }
+    }
+
+    fn map_batches<F: FnMut(&[ChessBoard]) -> bool>(&self, start_batch: usize, batch_size: usize, f: F) {
+        match self {
+            Self::File(l) => l.map_batches(start_batch, batch_size, f),
+            Self::Range(l) => l.map_batches(start_batch, batch_size, f),
+            Self::Binpack(l) => l.map_batches(start_batch, batch_size, f),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TargetLoader<L> {
+    inner: L,
+    transform: TargetTransform,
+    filter: RecordFilter,
+    stats: Arc<LoaderStats>,
+    /// Core the loading thread pins itself to (`--pin-threads`).
+    pin_core: Option<usize>,
+    /// Drop records failing [`data::check_record`] instead of aborting.
+    skip_bad: bool,
+    noise: Option<TargetNoise>,
+    /// Replaces the noise and reweight seeds per superbatch (`--resumeable-seed`).
+    seeds: Option<SuperbatchSeed>,
+    /// For the byte offset of a bad record; `None` for binpack.
+    record_size: Option<usize>,
+    /// Once set, no more batches are handed over, which ends bullet's run:
+    /// its callback cannot.
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl<L> TargetLoader<L> {
+    pub fn new(inner: L, transform: TargetTransform, filter: RecordFilter, stats: Arc<LoaderStats>) -> Self {
+        Self {
+            inner,
+            transform,
+            filter,
+            stats,
+            pin_core: None,
+            skip_bad: false,
+            noise: None,
+            seeds: None,
+            record_size: Some(data::RECORD_SIZE),
+            stop: None,
+        }
+    }
+
+    pub fn pinned_to(self, core: Option<usize>) -> Self {
+        Self { pin_core: core, ..self }
+    }
+
+    pub fn skipping_bad_records(self, skip_bad: bool) -> Self {
+        Self { skip_bad, ..self }
+    }
+
+    pub fn with_target_noise(self, noise: Option<TargetNoise>) -> Self {
+        Self { noise, ..self }
+    }
+
+    pub fn with_superbatch_seeds(self, seeds: Option<SuperbatchSeed>) -> Self {
+        Self { seeds, ..self }
+    }
+
+    pub fn with_record_size(self, record_size: Option<usize>) -> Self {
+        Self { record_size, ..self }
+    }
+
+    pub fn stopping_on(self, stop: Arc<AtomicBool>) -> Self {
+        Self { stop: Some(stop), ..self }
+    }
+}
+
+/// Skipped records reported individually before only counting them.
+const BAD_RECORD_WARNINGS: u64 = 10;
+
+impl<L: DataLoader<ChessBoard>> DataLoader<ChessBoard> for TargetLoader<L> {
+    fn data_file_paths(&self) -> &[String] {
+        self.inner.data_file_paths()
+    }
+
+    fn count_positions(&self) -> Option<u64> {
+        self.inner.count_positions()
+    }
+
+    fn map_batches<F: FnMut(&[ChessBoard]) -> bool>(&self, start_batch: usize, batch_size: usize, mut f: F) {
+        if let Some(core) = self.pin_core {
+            if !affinity::pin_current(core) {
+                warn!("could not pin the data loader thread to core {}", core);
+            }
+        }
+
+        let stats = &self.stats;
+        let (transform, filter, skip_bad, noise, seeds) =
+            (self.transform, self.filter, self.skip_bad, self.noise, self.seeds);
+        let passthrough = transform.is_identity() && !filter.is_active() && !skip_bad && noise.is_none();
+        let path = self.inner.data_file_paths().first().cloned().unwrap_or_default();
+        // index of the next record in the data, for pointing at bad ones
+        let records = self.inner.count_positions().filter(|&n| n > 0);
+        let record_size = self.record_size;
+        let stop = self.stop.clone();
+        let mut next_record = start_batch as u64 * batch_size as u64;
+        let mut skipped = 0;
+        let mut check = move |board: &ChessBoard, index: u64| match data::check_record(board) {
+            Ok(()) => true,
+            Err(e) => {
+                let record = records.map_or(index, |n| index % n);
+                let at = match record_size {
+                    Some(size) => {
+                        format!("record {} (byte offset {}) of {}: {}", record, record * size as u64, path, e)
+                    }
+                    None => format!("record {} of {}: {}", record, path, e),
+                };
+                if !skip_bad {
+                    panic!("bad {}; pass --skip-bad-records to drop such records", at);
+                }
+                skipped += 1;
+                if skipped <= BAD_RECORD_WARNINGS {
+                    notice!("skipping bad {}", at);
+                }
+                false
+            }
+        };
+        // filtered records are topped up from the following batches, so
+        // bullet still only ever sees full batches
+        let mut pending = Vec::with_capacity(2 * batch_size);
+        let mut ready = Instant::now();
+        self.inner.map_batches(start_batch, batch_size, |batch| {
+            let first = next_record;
+            next_record += batch.len() as u64;
+            let mut hand_over = |batch: &[ChessBoard]| {
+                if stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                    return false;
+                }
+                let handoff = Instant::now();
+                LoaderStats::add_time(&stats.loading_nanos, handoff - ready);
+                let more = f(batch);
+                ready = Instant::now();
+                LoaderStats::add_time(&stats.handoff_nanos, ready - handoff);
+                more
+            };
+            if passthrough {
+                for (i, board) in batch.iter().enumerate() {
+                    check(board, first + i as u64);
+                }
+                stats.record(batch);
+                stats.record_targets(batch, &transform);
+                return hand_over(batch);
+            }
+
+            let (mut bad, mut dropped) = (0, 0);
+            for (i, board) in batch.iter().enumerate() {
+                if !check(board, first + i as u64) {
+                    bad += 1;
+                    continue;
+                }
+                let index = first + i as u64;
+                let seed = seeds.map(|seeds| seeds.for_record(index));
+                let mut board = *board;
+                match filter.copies(&board, seed.map_or(REWEIGHT_SEED, |seed| mix(REWEIGHT_SEED, seed))) {
+                    0 => dropped += 1,
+                    copies => {
+                        if let Some(noise) = &noise {
+                            let noise = TargetNoise { seed: seed.unwrap_or(noise.seed), ..*noise };
+                            noise.apply(&mut board, index);
+                        }
+                        pending.extend(std::iter::repeat_n(board, copies));
+                    }
+                }
+            }
+            stats.bad_records.fetch_add(bad as u64, Ordering::Relaxed);
+            stats.filtered[0].fetch_add((batch.len() - bad) as u64, Ordering::Relaxed);
+            stats.filtered[1].fetch_add(dropped as u64, Ordering::Relaxed);
+
+            // repeated records can fill more than one batch
+            while pending.len() >= batch_size {
+                let full = &mut pending[..batch_size];
+                stats.record(full);
+                stats.record_targets(full, &transform);
+                if !transform.is_identity() {
+                    full.iter_mut().for_each(|board| transform.apply(board));
+                }
+                if !hand_over(full) {
+                    return false;
+                }
+                pending.drain(..batch_size);
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::NUM_OUTPUT_BUCKETS,
+        test_util::{STARTPOS, board, write_records},
+    };
+
+    /// A data file of a good, an impossible and another good record.
+    fn with_a_bad_record(name: &str) -> String {
+        let mut bad = board(STARTPOS, 0, "0.5");
+        bad.occ = u64::MAX;
+        let boards = [board(STARTPOS, 10, "0.5"), bad, board(STARTPOS, 20, "0.5")];
+        write_records(name, &boards).display().to_string()
+    }
+
+    fn target_loader(path: &str) -> TargetLoader<RangeLoader> {
+        let transform = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: 0.0, wdl_smooth: 0.0 };
+        let stats = Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, false));
+        TargetLoader::new(RangeLoader::new(path, 0..3, 0), transform, RecordFilter::default(), stats)
+    }
+
+    #[test]
+    fn phase_interpolates_between_opening_and_endgame() {
+        for (phase, expected) in [(1.0, 0.2), (0.0, 0.8), (0.25, 0.65), (0.5, 0.5)] {
+            assert!((wdl_for_phase(phase, 0.2, 0.8) - expected).abs() < 1e-6, "phase {}", phase);
+        }
+    }
+
+    #[test]
+    fn phase_aware_target_uses_the_position_phase() {
+        let transform =
+            TargetTransform { eval_scale: 400.0, wdl_by_phase: Some((0.0, 1.0)), wdl: 0.5, wdl_smooth: 0.0 };
+        // all material on: pure eval, whatever the result
+        assert_eq!(transform.target(&board(STARTPOS, 0, "1.0")), 0.5);
+        // bare kings and pawns: pure result
+        assert_eq!(transform.target(&board("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1", 300, "0.0")), 0.0);
+    }
+
+    #[test]
+    fn counts_targets_at_either_clamp_bound() {
+        let targets = [0.0, 0.01, 0.02, 0.5, 0.98, 0.99, 1.0];
+        assert_eq!(count_clamped(targets, 0.01), (2, 2));
+        assert_eq!(count_clamped(targets, 0.0), (1, 1));
+        assert_eq!(count_clamped([], 0.01), (0, 0));
+    }
+
+    #[test]
+    fn clamp_counts_are_taken_per_report() {
+        let stats = LoaderStats::new(NUM_OUTPUT_BUCKETS, true);
+        let transform = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: 1.0, wdl_smooth: 0.0 };
+        let batch = [board(STARTPOS, 0, "1.0"), board(STARTPOS, 0, "0.0"), board(STARTPOS, 0, "0.5")];
+        stats.record_targets(&batch, &transform);
+        assert_eq!(stats.take_clamped(), Some((1, 1, 3)));
+        assert_eq!(stats.take_clamped(), Some((0, 0, 0)));
+        assert_eq!(LoaderStats::new(NUM_OUTPUT_BUCKETS, false).take_clamped(), None);
+    }
+
+    #[test]
+    fn eval_filter_drops_scores_beyond_the_bound_either_way() {
+        let filter = RecordFilter { eval_max: Some(300), ..RecordFilter::default() };
+        assert!(filter.is_active());
+        assert!(filter.keep(&board(STARTPOS, 300, "0.5")));
+        assert!(filter.keep(&board(STARTPOS, -300, "0.5")));
+        assert!(!filter.keep(&board(STARTPOS, 301, "0.5")));
+        assert!(!filter.keep(&board(STARTPOS, -500, "0.5")));
+    }
+
+    #[test]
+    fn check_filter_drops_the_side_to_move_in_check() {
+        let filter = RecordFilter { no_check: true, ..RecordFilter::default() };
+        let checked = board("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1", 0, "1.0");
+        assert!(!filter.keep(&checked));
+        assert_eq!(filter.copies(&checked, 0), 0);
+        assert!(filter.keep(&board(STARTPOS, 0, "0.5")));
+        assert!(RecordFilter::default().keep(&checked));
+        assert!(!RecordFilter::default().is_active());
+    }
+
+    /// Fails the first `failures` reads, then returns `value`.
+    fn flaky(failures: usize, value: u32) -> impl FnMut() -> io::Result<u32> {
+        let mut calls = 0;
+        move || {
+            calls += 1;
+            if calls <= failures { Err(io::Error::other("transient")) } else { Ok(value) }
+        }
+    }
+
+    #[test]
+    fn retries_until_a_read_succeeds() {
+        assert_eq!(with_retries("read", 2, flaky(2, 7)).unwrap(), 7);
+        assert_eq!(with_retries("read", 0, flaky(0, 7)).unwrap(), 7);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let error = with_retries("read", 1, flaky(2, 7)).unwrap_err();
+        assert_eq!(error.to_string(), "transient");
+    }
+
+    #[test]
+    fn smoothing_pulls_results_towards_a_draw() {
+        for (eps, win, loss) in [(0.0, 1.0, 0.0), (0.05, 0.95, 0.05), (0.1, 0.9, 0.1), (0.25, 0.75, 0.25)] {
+            assert!((smooth_wdl(1.0, eps) - win).abs() < 1e-6, "eps {}", eps);
+            assert!((smooth_wdl(0.0, eps) - loss).abs() < 1e-6, "eps {}", eps);
+            assert!((smooth_wdl(0.5, eps) - 0.5).abs() < 1e-6, "eps {}", eps);
+        }
+    }
+
+    #[test]
+    fn smoothing_leaves_the_eval_component_alone() {
+        let smoothed = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: 0.5, wdl_smooth: 0.1 };
+        let plain = TargetTransform { wdl_smooth: 0.0, ..smoothed };
+        let won = board(STARTPOS, 200, "1.0");
+        // only the result half moves, by wdl * (0.9 - 1.0)
+        assert!((plain.target(&won) - smoothed.target(&won) - 0.05).abs() < 1e-6);
+        assert!(!smoothed.is_identity() && plain.is_identity());
+    }
+
+    #[test]
+    fn holdout_masks_positions_of_the_listed_buckets() {
+        assert_eq!(bucket_mask(&[0, 1]), 0b11);
+        assert_eq!(bucket_mask(&[]), 0);
+        let filter = RecordFilter {
+            holdout_buckets: bucket_mask(&[0, 1]),
+            num_buckets: NUM_OUTPUT_BUCKETS,
+            ..RecordFilter::default()
+        };
+        // 4, 8 and 32 pieces: buckets 0, 1 and 7 of 8
+        let boards = [
+            board("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1", 0, "0.5"),
+            board("r3k3/4p3/8/8/8/8/4P3/R3K2R w - - 0 1", 0, "0.5"),
+            board(STARTPOS, 0, "0.5"),
+        ];
+        let buckets: Vec<usize> = boards.iter().map(|b| data::material_bucket(b, NUM_OUTPUT_BUCKETS)).collect();
+        assert_eq!(buckets, [0, 1, 7]);
+        let kept: Vec<bool> = boards.iter().map(|b| filter.keep(b)).collect();
+        assert_eq!(kept, [false, false, true]);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad record 1 (byte offset 32) of")]
+    fn aborts_on_a_record_with_an_impossible_feature_count() {
+        let path = with_a_bad_record("bad-record-abort.data");
+        target_loader(&path).map_batches(0, 3, |_| false);
+    }
+
+    #[test]
+    fn skips_bad_records_when_asked() {
+        let path = with_a_bad_record("bad-record-skip.data");
+        let loader = target_loader(&path).skipping_bad_records(true);
+        let mut scores = Vec::new();
+        loader.map_batches(0, 2, |batch| {
+            scores.extend(batch.iter().map(|board| board.score));
+            false
+        });
+        fs::remove_file(&path).unwrap();
+        assert_eq!(scores, [10, 20]);
+        assert_eq!(loader.stats.take_bad_records(), 1);
+    }
+
+    #[test]
+    fn hands_over_nothing_once_stopped() {
+        let boards: Vec<ChessBoard> = (0..8).map(|score| board(STARTPOS, score, "0.5")).collect();
+        let path = write_records("stop-flag.data", &boards).display().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let transform = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: 0.0, wdl_smooth: 0.0 };
+        let stats = Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, false));
+        let loader = TargetLoader::new(RangeLoader::new(&path, 0..8, 0), transform, RecordFilter::default(), stats)
+            .stopping_on(stop.clone());
+        let mut batches = 0;
+        loader.map_batches(0, 2, |_| {
+            batches += 1;
+            // the consumer asks to stop after the second batch, but keeps wanting more
+            if batches == 2 {
+                stop.store(true, Ordering::Relaxed);
+            }
+            true
+        });
+        fs::remove_file(&path).unwrap();
+        assert_eq!(batches, 2);
+    }
+
+    #[test]
+    fn subsample_keeps_about_the_requested_fraction() {
+        let boards: Vec<ChessBoard> = (0..10_000).map(|score| board(STARTPOS, score, "0.5")).collect();
+        let kept = |subsample: Subsample| boards.iter().filter(|b| subsample.keeps(b)).count();
+        for fraction in [0.1, 0.5, 0.9] {
+            let share = kept(Subsample::new(fraction, 7)) as f32 / boards.len() as f32;
+            assert!((share - fraction).abs() < 0.02, "kept {} of {}", share, fraction);
+        }
+        assert_eq!(kept(Subsample::new(1.0, 7)), boards.len());
+        assert_eq!(kept(Subsample::new(0.0, 7)), 0);
+    }
+
+    #[test]
+    fn subsample_is_reproducible_per_seed() {
+        let boards: Vec<ChessBoard> = (0..1000).map(|score| board(STARTPOS, score, "0.5")).collect();
+        let kept = |seed| boards.iter().map(|b| Subsample::new(0.5, seed).keeps(b)).collect::<Vec<_>>();
+        assert_eq!(kept(1), kept(1));
+        assert_ne!(kept(1), kept(2));
+    }
+
+    #[test]
+    fn rare_buckets_get_proportionally_more_weight() {
+        let weights = inverse_frequency_weights(&[100, 300, 0, 600]);
+        for (weight, expected) in weights.iter().zip([10.0 / 3.0, 10.0 / 9.0, 10.0 / 3.0, 5.0 / 9.0]) {
+            assert!((weight - expected).abs() < 1e-5, "{:?}", weights);
+        }
+        // a mean weight of 1 over the data, so the overall loss scale is kept
+        let mean =
+            [100.0, 300.0, 600.0].iter().zip([weights[0], weights[1], weights[3]]).map(|(c, w)| c * w).sum::<f32>()
+                / 1000.0;
+        assert!((mean - 1.0).abs() < 1e-5, "{}", mean);
+    }
+
+    #[test]
+    fn bucket_weights_are_capped_and_default_to_one() {
+        assert_eq!(inverse_frequency_weights(&[1, 1_000_000]), [MAX_BUCKET_WEIGHT, 0.500_000_5]);
+        assert_eq!(inverse_frequency_weights(&[0, 0, 0]), [1.0; 3]);
+        assert_eq!(inverse_frequency_weights(&[5, 5]), [1.0; 2]);
+    }
+
+    #[test]
+    fn a_weight_is_the_mean_number_of_copies() {
+        // the start position is in the last of 8 buckets
+        let boards: Vec<ChessBoard> = (0..10_000).map(|score| board(STARTPOS, score, "0.5")).collect();
+        let copies = |weight: f32| {
+            let weights = BucketWeights::new(&[[1.0; 7].as_slice(), &[weight]].concat());
+            boards.iter().map(|b| weights.copies(b, REWEIGHT_SEED)).collect::<Vec<_>>()
+        };
+        let upweighted = copies(2.5);
+        assert!(upweighted.iter().all(|&n| n == 2 || n == 3));
+        let mean = upweighted.iter().sum::<usize>() as f32 / boards.len() as f32;
+        assert!((mean - 2.5).abs() < 0.02, "{}", mean);
+        assert!(copies(0.0).iter().all(|&n| n == 0));
+        assert!(copies(100.0).iter().all(|&n| n == MAX_BUCKET_WEIGHT as usize));
+    }
+
+    /// The scores `--target-noise` leaves on 64 start positions scored 0..640.
+    fn noisy_scores(name: &str, noise: Option<TargetNoise>) -> Vec<i16> {
+        let boards: Vec<ChessBoard> = (0..64).map(|i| board(STARTPOS, i * 10, "0.5")).collect();
+        let path = write_records(name, &boards).display().to_string();
+        let transform = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: 0.0, wdl_smooth: 0.0 };
+        let stats = Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, false));
+        let loader = TargetLoader::new(RangeLoader::new(&path, 0..64, 0), transform, RecordFilter::default(), stats)
+            .with_target_noise(noise);
+        let mut scores = Vec::new();
+        loader.map_batches(0, 64, |batch| {
+            scores.extend(batch.iter().map(|board| board.score));
+            false
+        });
+        fs::remove_file(&path).unwrap();
+        scores
+    }
+
+    #[test]
+    fn zero_target_noise_changes_nothing() {
+        let clean = noisy_scores("noise-off.data", None);
+        assert_eq!(clean, (0..64).map(|i| i * 10).collect::<Vec<i16>>());
+        assert_eq!(noisy_scores("noise-zero.data", Some(TargetNoise { sigma: 0.0, seed: 3 })), clean);
+    }
+
+    #[test]
+    fn target_noise_is_reproducible_per_seed() {
+        let noisy = |seed| noisy_scores(&format!("noise-{}.data", seed), Some(TargetNoise { sigma: 25.0, seed }));
+        let (first, again, other) = (noisy(1), noisy(1), noisy(2));
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        let offsets: Vec<f32> =
+            first.iter().zip(0..).map(|(&score, i)| f32::from(score) - f32::from(i * 10i16)).collect();
+        assert!(offsets.iter().any(|&d| d != 0.0) && offsets.iter().all(|&d| d.abs() < 6.0 * 25.0), "{:?}", offsets);
+    }
+
+    #[test]
+    fn target_noise_has_the_requested_spread() {
+        let noise = TargetNoise { sigma: 50.0, seed: 9 };
+        let offsets: Vec<f64> = (0..20_000).map(|i| f64::from(noise.offset(i))).collect();
+        let mean = offsets.iter().sum::<f64>() / offsets.len() as f64;
+        let sd = (offsets.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / offsets.len() as f64).sqrt();
+        assert!(mean.abs() < 1.5 && (sd - 50.0).abs() < 1.5, "mean {} sd {}", mean, sd);
+    }
+
+    #[test]
+    fn superbatch_seeds_follow_the_stream_index() {
+        let seeds = SuperbatchSeed { base: 0b1010_0000, positions_per_superbatch: 16 };
+        assert_eq!((seeds.at(1), seeds.at(2), seeds.at(3)), (0b1010_0001, 0b1010_0010, 0b1010_0011));
+        let superbatches: Vec<usize> =
+            [0, 15, 16, 47, 48].into_iter().map(|index| seeds.superbatch_of(index)).collect();
+        assert_eq!(superbatches, [1, 1, 2, 3, 4]);
+        assert_eq!(seeds.for_record(40), seeds.at(3));
+    }
+
+    #[test]
+    fn a_resumed_stream_repeats_the_seeded_noise() {
+        let boards: Vec<ChessBoard> = (0..64).map(|i| board(STARTPOS, i * 10, "0.5")).collect();
+        let path = write_records("resumeable-seed.data", &boards).display().to_string();
+        // superbatches of 2 batches of 8 records, 4 superbatches per pass over the data
+        let seeds = SuperbatchSeed { base: 77, positions_per_superbatch: 16 };
+        let stream = |start_batch: usize, records: usize| {
+            let transform = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: 0.0, wdl_smooth: 0.0 };
+            let stats = Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, false));
+            let loader =
+                TargetLoader::new(RangeLoader::new(&path, 0..64, 0), transform, RecordFilter::default(), stats)
+                    .with_target_noise(Some(TargetNoise { sigma: 25.0, seed: 0 }))
+                    .with_superbatch_seeds(Some(seeds));
+            let mut scores = Vec::new();
+            loader.map_batches(start_batch, 8, |batch| {
+                scores.extend(batch.iter().map(|board| board.score));
+                scores.len() < records
+            });
+            scores
+        };
+        let full = stream(0, 128);
+        let resumed = stream(6, 80);
+        fs::remove_file(&path).unwrap();
+
+        // resuming at superbatch 4 draws what the full run drew from there on
+        assert_eq!(resumed, full[48..]);
+        // while the second pass over the same records draws afresh
+        assert_ne!(full[..64], full[64..]);
+    }
+
+    fn config(args: &[&str]) -> Config {
+        let args: Vec<String> = ["training"].iter().chain(args).map(|arg| arg.to_string()).collect();
+        Config::from_args(&args).unwrap()
+    }
+
+    #[test]
+    fn each_data_format_gets_its_loader_and_record_size() {
+        let path = write_records("source-loader.data", &[board(STARTPOS, 0, "0.5"); 4]).display().to_string();
+        let source = |args: &[&str], held_out| {
+            SourceLoader::for_config(&config(&[&["-d", &path], args].concat()), 0..3, held_out)
+        };
+
+        let direct = source(&[], false);
+        assert!(matches!(direct, SourceLoader::File(_)));
+        assert_eq!(direct.record_size(), Some(data::RECORD_SIZE));
+        assert_eq!(config(&["-d", &path]).data_record_size(), Some(data::RECORD_SIZE));
+        for (args, held_out) in [(&[][..], true), (&["--io-retries", "2"][..], false)] {
+            let range = source(args, held_out);
+            assert!(matches!(&range, SourceLoader::Range(l) if l.count_positions() == Some(3)), "{:?}", args);
+            assert_eq!(range.record_size(), Some(data::RECORD_SIZE));
+        }
+
+        let binpack = source(&["--data-format", "binpack"], false);
+        assert!(matches!(binpack, SourceLoader::Binpack(_)));
+        assert_eq!((binpack.record_size(), binpack.data_file_paths()), (None, &[path.clone()][..]));
+        assert_eq!(config(&["-d", &path, "--data-format", "binpack"]).data_record_size(), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
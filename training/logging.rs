@@ -0,0 +1,114 @@
+//! Minimal leveled console output. Errors always go to stderr; everything
+//! else goes through [`info!`](crate::info) or [`verbose!`](crate::verbose),
+//! and warnings through [`warn!`](crate::warn) so `--strict` can stop on them,
+//! or [`notice!`](crate::notice) for the ones it lets through.
+
+use std::{
+    fmt, process,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Level {
+    /// Only errors and the final summary.
+    Quiet = 0,
+    /// Startup summary, saves and per-report-interval summaries.
+    Normal = 1,
+    /// Additionally weight stats, bucket occupancy and device info.
+    Verbose = 2,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        _ => Level::Verbose,
+    }
+}
+
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Prints a warning, or under `--strict` prints it as an error and exits;
+/// warnings are raised from bullet's callback and the loader thread too,
+/// where there is no error to return.
+pub fn warning(message: fmt::Arguments<'_>) {
+    if STRICT.load(Ordering::Relaxed) {
+        eprintln!("Error: {} (a warning, fatal under --strict)", message);
+        process::exit(1);
+    }
+    eprintln!("WARNING: {}", message);
+}
+
+/// Prints a warning that `--strict` lets through: one confirming what a flag
+/// asked for, such as `--force` overwriting checkpoints, which would
+/// otherwise make that flag unusable in a strict run.
+pub fn notice(message: fmt::Arguments<'_>) {
+    eprintln!("WARNING: {}", message);
+}
+
+/// Whether a message logged at `level` should be printed.
+pub fn enabled(level: Level) -> bool {
+    level <= self::level()
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::logging::warning(format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        $crate::logging::notice(format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the level is process-wide, so every change to it stays in this one test
+    #[test]
+    fn level_gates_messages() {
+        let gates = |level| {
+            set_level(level);
+            [Level::Quiet, Level::Normal, Level::Verbose].map(enabled)
+        };
+        assert_eq!(gates(Level::Quiet), [true, false, false]);
+        assert_eq!(gates(Level::Normal), [true, true, false]);
+        assert_eq!(gates(Level::Verbose), [true, true, true]);
+        set_level(Level::Normal);
+        assert_eq!(level(), Level::Normal);
+    }
+}
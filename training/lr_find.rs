@@ -0,0 +1,106 @@
+//! LR range test for `--lr-find`: ramp the learning rate exponentially over a
+//! short run, record the loss at each step and pick the LR where the loss
+//! falls fastest.
+
+use bullet::trainer::schedule::lr::LrScheduler;
+
+pub const START_LR: f32 = 1e-7;
+pub const END_LR: f32 = 1.0;
+/// Loss is measured after each superbatch of the sweep.
+pub const POINTS: usize = 30;
+pub const BATCHES_PER_POINT: usize = 10;
+/// Positions the loss is measured on, spread evenly through the data file.
+pub const SAMPLE_POSITIONS: usize = 1024;
+
+/// `start * (end / start)^(step / total_batches)`, per batch.
+#[derive(Clone, Debug)]
+pub struct ExponentialRampLR {
+    pub start: f32,
+    pub end: f32,
+    pub batches_per_superbatch: usize,
+    pub total_batches: usize,
+}
+
+impl LrScheduler for ExponentialRampLR {
+    fn lr(&self, batch: usize, superbatch: usize) -> f32 {
+        let step = superbatch.saturating_sub(1) * self.batches_per_superbatch + batch;
+        let t = step as f32 / self.total_batches.max(1) as f32;
+        self.start * (self.end / self.start).powf(t.min(1.0))
+    }
+}
+
+/// Bias-corrected exponential smoothing, so the first points are not pulled
+/// towards zero.
+pub fn smooth(losses: &[f32], beta: f32) -> Vec<f32> {
+    let mut avg = 0.0;
+    losses
+        .iter()
+        .enumerate()
+        .map(|(i, &loss)| {
+            avg = beta * avg + (1.0 - beta) * loss;
+            avg / (1.0 - beta.powi(i as i32 + 1))
+        })
+        .collect()
+}
+
+/// Index of the point with the steepest loss decrease per unit of `ln(lr)`,
+/// over `(lr, smoothed loss)` points. Points after the loss has blown up to
+/// four times its minimum so far are ignored.
+pub fn steepest_descent(points: &[(f32, f32)]) -> Option<usize> {
+    let mut best: Option<(usize, f32)> = None;
+    let mut min_loss = f32::INFINITY;
+    for i in 1..points.len().saturating_sub(1) {
+        min_loss = min_loss.min(points[i - 1].1);
+        if !points[i + 1].1.is_finite() || points[i + 1].1 > 4.0 * min_loss {
+            break;
+        }
+        let (lr_prev, loss_prev) = points[i - 1];
+        let (lr_next, loss_next) = points[i + 1];
+        let slope = (loss_next - loss_prev) / (lr_next.ln() - lr_prev.ln());
+        if slope < 0.0 && best.is_none_or(|(_, s)| slope < s) {
+            best = Some((i, slope));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+pub fn table(lrs: &[f32], losses: &[f32], smoothed: &[f32]) -> String {
+    let mut out = format!("{:>12} {:>12} {:>12}", "lr", "loss", "smoothed");
+    for ((lr, loss), smooth) in lrs.iter().zip(losses).zip(smoothed) {
+        out.push_str(&format!("\n{:>12.3e} {:>12.6} {:>12.6}", lr, loss, smooth));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_steepest_fall_before_the_blow_up() {
+        let lrs = [1e-5, 1e-4, 1e-3, 1e-2, 1e-1, 1.0];
+        let losses = [1.0, 0.95, 0.6, 0.5, 0.45, 5.0];
+        let points: Vec<(f32, f32)> = lrs.into_iter().zip(losses).collect();
+        assert_eq!(steepest_descent(&points), Some(2));
+    }
+
+    #[test]
+    fn a_rising_curve_has_no_suggestion() {
+        let points = [(1e-4, 0.5), (1e-3, 0.6), (1e-2, 0.7), (1e-1, 0.8)];
+        assert_eq!(steepest_descent(&points), None);
+        assert_eq!(steepest_descent(&points[..2]), None);
+    }
+
+    #[test]
+    fn smoothing_keeps_a_constant_loss() {
+        assert!(smooth(&[0.3; 5], 0.9).iter().all(|loss| (loss - 0.3).abs() < 1e-6));
+    }
+
+    #[test]
+    fn ramp_spans_start_to_end() {
+        let ramp = ExponentialRampLR { start: START_LR, end: END_LR, batches_per_superbatch: 10, total_batches: 100 };
+        assert!((ramp.lr(0, 1) - START_LR).abs() < 1e-12);
+        assert!((ramp.lr(0, 11) - END_LR).abs() < 1e-5);
+        assert!((ramp.lr(0, 6) - (START_LR * END_LR).sqrt()).abs() < 1e-6);
+    }
+}
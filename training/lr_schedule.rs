@@ -0,0 +1,215 @@
+//! The training LR schedule: bullet's cosine decay, or the same curve pinned
+//! to explicit endpoints with `--schedule-anchor` so a renamed or split run
+//! keeps the LR it would have had. `--reduce-on-plateau` can take over from
+//! either once the loss stops improving.
+
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+use bullet::trainer::schedule::lr::{CosineDecayLR, LrScheduler};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Cosine decay from `initial_lr` at superbatch `first` to `final_lr` at
+/// `last`, flat outside that range.
+pub fn anchored_cosine(initial_lr: f32, final_lr: f32, first: usize, last: usize, superbatch: usize) -> f32 {
+    if superbatch <= first {
+        return initial_lr;
+    }
+    if superbatch >= last {
+        return final_lr;
+    }
+    let progress = (superbatch - first) as f32 / (last - first) as f32;
+    let lambda = 1.0 - 0.5 * (1.0 + (PI * progress).cos());
+    initial_lr + (final_lr - initial_lr) * lambda
+}
+
+#[derive(Clone, Debug)]
+pub struct AnchoredCosineLR {
+    pub initial_lr: f32,
+    pub final_lr: f32,
+    pub first_superbatch: usize,
+    pub final_superbatch: usize,
+}
+
+impl LrScheduler for AnchoredCosineLR {
+    fn lr(&self, _batch: usize, superbatch: usize) -> f32 {
+        anchored_cosine(self.initial_lr, self.final_lr, self.first_superbatch, self.final_superbatch, superbatch)
+    }
+}
+
+#[derive(Clone)]
+pub enum TrainingLR {
+    Cosine(CosineDecayLR),
+    Anchored(AnchoredCosineLR),
+}
+
+impl LrScheduler for TrainingLR {
+    fn lr(&self, batch: usize, superbatch: usize) -> f32 {
+        match self {
+            Self::Cosine(lr) => lr.lr(batch, superbatch),
+            Self::Anchored(lr) => lr.lr(batch, superbatch),
+        }
+    }
+}
+
+/// Weight of the newest measurement in the smoothed loss, as for `--stop-at-loss`.
+const PLATEAU_SMOOTHING: f32 = 0.5;
+
+/// `--reduce-on-plateau <FACTOR:PATIENCE:MIN_DELTA>`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlateauSettings {
+    pub factor: f32,
+    pub patience: usize,
+    pub min_delta: f32,
+}
+
+/// Counts superbatches since the smoothed loss last beat its best by
+/// `min_delta`; `patience` of them in a row is a plateau.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlateauDetector {
+    pub settings: PlateauSettings,
+    smoothed: Option<f32>,
+    best: Option<f32>,
+    stale: usize,
+}
+
+impl PlateauDetector {
+    pub fn new(settings: PlateauSettings) -> Self {
+        Self { settings, smoothed: None, best: None, stale: 0 }
+    }
+
+    pub fn smoothed(&self) -> Option<f32> {
+        self.smoothed
+    }
+
+    /// Folds in the loss after a superbatch trained at `lr` and returns the
+    /// reduced LR on a plateau. The count starts over after a reduction.
+    pub fn update(&mut self, loss: f32, lr: f32) -> Option<f32> {
+        let smoothed = self.smoothed.map_or(loss, |s| PLATEAU_SMOOTHING * loss + (1.0 - PLATEAU_SMOOTHING) * s);
+        self.smoothed = Some(smoothed);
+        match self.best {
+            Some(best) if smoothed > best - self.settings.min_delta => self.stale += 1,
+            _ => {
+                self.best = Some(smoothed);
+                self.stale = 0;
+            }
+        }
+        if self.stale < self.settings.patience {
+            return None;
+        }
+        self.stale = 0;
+        Some(lr * self.settings.factor)
+    }
+}
+
+/// A schedule whose LR can be pinned from the training callback, which only
+/// sees the schedule by shared reference. Clones share the pinned value.
+#[derive(Clone)]
+pub struct PlateauLR<LR: LrScheduler> {
+    pub inner: LR,
+    pinned: Arc<Mutex<Option<f32>>>,
+}
+
+impl<LR: LrScheduler> PlateauLR<LR> {
+    pub fn new(inner: LR) -> Self {
+        Self { inner, pinned: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Replaces the inner schedule with a constant `lr` from now on.
+    pub fn pin(&self, lr: f32) {
+        *self.pinned.lock().unwrap() = Some(lr);
+    }
+}
+
+impl<LR: LrScheduler> LrScheduler for PlateauLR<LR> {
+    fn lr(&self, batch: usize, superbatch: usize) -> f32 {
+        self.pinned.lock().unwrap().unwrap_or_else(|| self.inner.lr(batch, superbatch))
+    }
+}
+
+pub fn from_config(config: &Config) -> TrainingLR {
+    match config.schedule_anchor {
+        Some((first, last)) => TrainingLR::Anchored(AnchoredCosineLR {
+            initial_lr: config.initial_lr,
+            final_lr: config.final_lr,
+            first_superbatch: first,
+            final_superbatch: last,
+        }),
+        None => TrainingLR::Cosine(CosineDecayLR {
+            initial_lr: config.initial_lr,
+            final_lr: config.final_lr,
+            final_superbatch: config.superbatches,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Config {
+        let args: Vec<String> = std::iter::once("training").chain(args.iter().copied()).map(String::from).collect();
+        Config::from_args(&args).unwrap()
+    }
+
+    #[test]
+    fn cosine_runs_between_the_anchors_and_is_flat_outside() {
+        assert_eq!(anchored_cosine(0.001, 0.0001, 10, 20, 5), 0.001);
+        assert_eq!(anchored_cosine(0.001, 0.0001, 10, 20, 25), 0.0001);
+        assert!((anchored_cosine(0.001, 0.0001, 10, 20, 15) - 0.00055).abs() < 1e-9);
+        let fifth = 0.001 + (0.0001 - 0.001) * (1.0 - 0.5 * (1.0 + (PI * 0.2).cos()));
+        assert!((anchored_cosine(0.001, 0.0001, 10, 20, 12) - fifth).abs() < 1e-9);
+    }
+
+    #[test]
+    fn anchored_lr_does_not_depend_on_the_start() {
+        let from_scratch = from_config(&config(&["--schedule-anchor", "1:100", "-s", "40"]));
+        let renamed =
+            from_config(&config(&["--schedule-anchor", "1:100", "--start", "41", "-s", "100", "-n", "renamed"]));
+        let expected = anchored_cosine(0.001, 0.001 * 0.3f32.powi(5), 1, 100, 60);
+        for lr in [&from_scratch, &renamed] {
+            assert!(matches!(lr, TrainingLR::Anchored(_)));
+            assert!((lr.lr(0, 60) - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn plateau_reduces_the_lr_after_patience_stale_superbatches() {
+        let mut detector = PlateauDetector::new(PlateauSettings { factor: 0.5, patience: 2, min_delta: 0.01 });
+        // smoothed: 0.1, then 0.1 (stale), 0.1 (stale: plateau)
+        assert_eq!(detector.update(0.1, 0.001), None);
+        assert_eq!(detector.update(0.1, 0.001), None);
+        assert_eq!(detector.update(0.1, 0.001), Some(0.0005));
+        // the count starts over after a reduction
+        assert_eq!(detector.update(0.1, 0.0005), None);
+        assert_eq!(detector.update(0.1, 0.0005), Some(0.00025));
+    }
+
+    #[test]
+    fn improving_by_min_delta_resets_the_count() {
+        let mut detector = PlateauDetector::new(PlateauSettings { factor: 0.5, patience: 2, min_delta: 0.01 });
+        detector.update(0.2, 0.001);
+        // smoothed 0.195 is not 0.01 below the best 0.2
+        assert_eq!(detector.update(0.19, 0.001), None);
+        // smoothed 0.1475 is, so the count starts over
+        assert_eq!(detector.update(0.1, 0.001), None);
+        assert!((detector.smoothed().unwrap() - 0.1475).abs() < 1e-6);
+        assert_eq!(detector.update(0.15, 0.001), None);
+        assert_eq!(detector.update(0.15, 0.001), Some(0.0005));
+    }
+
+    #[test]
+    fn pinned_lr_overrides_the_schedule_for_every_clone() {
+        let lr = PlateauLR::new(from_config(&config(&["--schedule-anchor", "1:10", "-s", "10"])));
+        let clone = lr.clone();
+        let scheduled = lr.lr(0, 5);
+        assert!(scheduled < 0.001 && scheduled > 0.001 * 0.3f32.powi(5));
+        lr.pin(0.0002);
+        assert_eq!((lr.lr(0, 5), clone.lr(0, 9)), (0.0002, 0.0002));
+    }
+}
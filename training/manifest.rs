@@ -0,0 +1,164 @@
+//! Dataset manifests for `--dataset-manifest`: one `<sha256>  <path>` line per
+//! data file, as written by `sha256sum`. Relative paths are resolved against
+//! the manifest's directory. Blank lines and `#` comments are ignored.
+
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// Lowercase hex digest.
+    pub sha256: String,
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io { path: PathBuf, error: io::Error },
+    Parse { line: usize, text: String },
+    Mismatch { path: PathBuf, expected: String, actual: String },
+    NotListed(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Self::Parse { line, text } => {
+                write!(f, "manifest line {}: expected `<sha256>  <path>`, got {:?}", line, text)
+            }
+            Self::Mismatch { path, expected, actual } => {
+                write!(f, "checksum mismatch for {}: manifest has {}, file has {}", path.display(), expected, actual)
+            }
+            Self::NotListed(path) => write!(f, "{} is not listed in the dataset manifest", path),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+pub fn parse(text: &str, base: &Path) -> Result<Vec<Entry>, ManifestError> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_error = || ManifestError::Parse { line: i + 1, text: line.to_string() };
+        let (hash, path) = line.split_once(char::is_whitespace).ok_or_else(parse_error)?;
+        // `sha256sum -b` marks binary mode with a leading '*'
+        let path = path.trim_start().trim_start_matches('*');
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) || path.is_empty() {
+            return Err(parse_error());
+        }
+        entries.push(Entry { path: base.join(path), sha256: hash.to_ascii_lowercase() });
+    }
+    Ok(entries)
+}
+
+/// Streams the file through SHA-256 and returns the lowercase hex digest.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Verifies every listed file and that `dataset` is among them. Returns the
+/// digest of the manifest itself for the run metadata.
+pub fn verify(manifest: &str, dataset: &str) -> Result<String, ManifestError> {
+    let manifest_path = Path::new(manifest);
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| ManifestError::Io { path, error }
+    };
+    let text = fs::read_to_string(manifest_path).map_err(io_error(manifest_path))?;
+    let entries = parse(&text, manifest_path.parent().unwrap_or(Path::new(".")))?;
+
+    let dataset_path = fs::canonicalize(dataset).map_err(io_error(Path::new(dataset)))?;
+    let mut listed = false;
+    for entry in &entries {
+        let actual = sha256_file(&entry.path).map_err(io_error(&entry.path))?;
+        if actual != entry.sha256 {
+            return Err(ManifestError::Mismatch { path: entry.path.clone(), expected: entry.sha256.clone(), actual });
+        }
+        listed |= fs::canonicalize(&entry.path).is_ok_and(|p| p == dataset_path);
+    }
+    if !listed {
+        return Err(ManifestError::NotListed(dataset.to_string()));
+    }
+
+    Ok(hex(&Sha256::digest(text.as_bytes())))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    /// A data file holding `abc` and a manifest listing it with `sha256`.
+    fn write_manifest(name: &str, sha256: &str) -> (PathBuf, PathBuf) {
+        let dir = temp_path(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("train.data"), b"abc").unwrap();
+        let manifest = dir.join("data.sha256");
+        fs::write(&manifest, format!("# provenance\n{}  train.data\n", sha256)).unwrap();
+        (dir, manifest)
+    }
+
+    #[test]
+    fn streams_the_file_digest() {
+        let (dir, _) = write_manifest("manifest-digest", ABC_SHA256);
+        assert_eq!(sha256_file(&dir.join("train.data")).unwrap(), ABC_SHA256);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn accepts_a_matching_checksum() {
+        let (dir, manifest) = write_manifest("manifest-good", &ABC_SHA256.to_ascii_uppercase());
+        let dataset = dir.join("train.data");
+        let digest = verify(manifest.to_str().unwrap(), dataset.to_str().unwrap()).unwrap();
+        assert_eq!(digest, hex(&Sha256::digest(fs::read(&manifest).unwrap())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_wrong_checksum() {
+        let wrong = "0".repeat(64);
+        let (dir, manifest) = write_manifest("manifest-bad", &wrong);
+        let dataset = dir.join("train.data");
+        match verify(manifest.to_str().unwrap(), dataset.to_str().unwrap()) {
+            Err(ManifestError::Mismatch { expected, actual, .. }) => {
+                assert_eq!((expected, actual.as_str()), (wrong, ABC_SHA256))
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(matches!(parse("abc  train.data", Path::new(".")), Err(ManifestError::Parse { line: 1, .. })));
+        let entries = parse(&format!("\n{} *train.data\n", ABC_SHA256), Path::new("data")).unwrap();
+        assert_eq!(entries[0].path, Path::new("data/train.data"));
+    }
+}
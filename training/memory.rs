@@ -0,0 +1,228 @@
+//! RAM estimate for `--max-ram-mb`.
+//!
+//! The data is read sequentially, so the host memory that grows with the
+//! settings is the batch queue (bullet's prepared batches), the loader's raw
+//! record buffers and, on the CPU backend, the weights with their gradients
+//! and AdamW moments. The figures are upper bounds, not measurements.
+
+use std::fmt;
+
+use crate::{checkpoint, net::NetShape};
+
+/// Most pieces on the board, i.e. active features per perspective.
+pub const MAX_ACTIVE_FEATURES: u64 = 32;
+/// Bytes of one position in a prepared batch: i32 feature indices for both
+/// perspectives, plus the target and the output bucket.
+pub const PREPARED_BYTES_PER_POSITION: u64 = 2 * MAX_ACTIVE_FEATURES * 4 + 4 + 4;
+/// Raw batches the loader holds at once: the one being read plus the
+/// top-up buffer for filtered records.
+pub const LOADER_BATCHES: u64 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    pub queue: u64,
+    pub loader: u64,
+    pub weights: u64,
+}
+
+impl MemoryEstimate {
+    /// `on_host` counts the weights, which only live in RAM on the CPU backend.
+    pub fn new(batch_size: usize, record_size: usize, batch_queue: usize, shape: &NetShape, on_host: bool) -> Self {
+        let batch_size = batch_size as u64;
+        let params: usize = shape.tensors().iter().filter_map(|id| shape.tensor_len(id)).sum();
+        Self {
+            // the queued batches plus the one being trained on
+            queue: (batch_queue as u64 + 1) * batch_size * PREPARED_BYTES_PER_POSITION,
+            loader: LOADER_BATCHES * batch_size * record_size as u64,
+            weights: if on_host { params as u64 * 4 * 4 } else { 0 },
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.queue + self.loader + self.weights
+    }
+}
+
+impl fmt::Display for MemoryEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} MB (batch queue {:.0} MB, loader {:.0} MB, weights {:.0} MB)",
+            checkpoint::mb(self.total()),
+            checkpoint::mb(self.queue),
+            checkpoint::mb(self.loader),
+            checkpoint::mb(self.weights)
+        )
+    }
+}
+
+/// Largest batch queue up to `max_queue` that fits in `budget` bytes, or
+/// `None` if not even a queue of one does.
+pub fn fit_batch_queue(
+    budget: u64,
+    max_queue: usize,
+    batch_size: usize,
+    record_size: usize,
+    shape: &NetShape,
+    on_host: bool,
+) -> Option<usize> {
+    let fixed = MemoryEstimate::new(batch_size, record_size, 0, shape, on_host).total();
+    let per_batch = batch_size as u64 * PREPARED_BYTES_PER_POSITION;
+    let fits = budget.checked_sub(fixed)? / per_batch.max(1);
+    (fits >= 1).then(|| max_queue.min(fits as usize))
+}
+
+/// `--print-memory-plan`: memory by purpose for a run, i.e. the device-side
+/// training state on top of the host-side [`MemoryEstimate`]. Everything is
+/// f32 while training; bullet's workspace and allocator slack are not counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryPlan {
+    pub params: u64,
+    pub weights: u64,
+    pub gradients: u64,
+    /// AdamW's first and second moments.
+    pub moments: u64,
+    /// One batch's activations and their gradients, plus its sparse inputs.
+    pub activations: u64,
+    /// The host-side `--ema` shadow weights, if enabled.
+    pub ema: u64,
+    pub batch_size: usize,
+    pub batch_queue: usize,
+    pub host: MemoryEstimate,
+    /// Whether the training state lives on a GPU rather than in RAM.
+    pub on_device: bool,
+}
+
+impl MemoryPlan {
+    pub fn new(
+        batch_size: usize,
+        record_size: usize,
+        batch_queue: usize,
+        shape: &NetShape,
+        on_device: bool,
+        ema: bool,
+    ) -> Self {
+        let params = shape.tensors().iter().filter_map(|id| shape.tensor_len(id)).sum::<usize>() as u64;
+        // per position: the l1 inputs before and after SCReLU, the bucket
+        // outputs and the selected one, each with a gradient, and the target
+        let floats = 2 * (2 * shape.l1_inputs() as u64 + shape.output_buckets as u64 + 1) + 1;
+        Self {
+            params,
+            weights: params * 4,
+            gradients: params * 4,
+            moments: params * 4 * 2,
+            activations: batch_size as u64 * (floats * 4 + PREPARED_BYTES_PER_POSITION),
+            ema: if ema { params * 4 } else { 0 },
+            batch_size,
+            batch_queue,
+            // the weights are counted here, not in the host estimate
+            host: MemoryEstimate::new(batch_size, record_size, batch_queue, shape, false),
+            on_device,
+        }
+    }
+
+    pub fn device_total(&self) -> u64 {
+        self.weights + self.gradients + self.moments + self.activations
+    }
+
+    pub fn host_total(&self) -> u64 {
+        self.host.queue + self.host.loader + self.ema
+    }
+
+    pub fn total(&self) -> u64 {
+        self.device_total() + self.host_total()
+    }
+}
+
+impl fmt::Display for MemoryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter<'_>, what: &str, bytes: u64, note: String| {
+            let mb = checkpoint::mb(bytes);
+            if note.is_empty() {
+                writeln!(f, "  {:<16} {:>9.1} MB", what, mb)
+            } else {
+                writeln!(f, "  {:<16} {:>9.1} MB  {}", what, mb, note)
+            }
+        };
+        let state = if self.on_device { "on the GPU" } else { "in RAM, CPU backend" };
+        writeln!(f, "Memory plan (estimate, f32 training):")?;
+        row(f, "weights", self.weights, format!("{} parameters", self.params))?;
+        row(f, "gradients", self.gradients, String::new())?;
+        row(f, "AdamW moments", self.moments, String::new())?;
+        row(f, "activations", self.activations, format!("batch of {}", self.batch_size))?;
+        row(f, "training state", self.device_total(), state.to_string())?;
+        row(f, "batch queue", self.host.queue, format!("{} batches + 1", self.batch_queue))?;
+        row(f, "loader buffers", self.host.loader, String::new())?;
+        if self.ema > 0 {
+            row(f, "EMA weights", self.ema, String::new())?;
+        }
+        row(f, "host buffers", self.host_total(), "in RAM".to_string())?;
+        row(f, "total", self.total(), String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::DEFAULT_BATCH_QUEUE,
+        data,
+        net::{HL_SIZE, NUM_INPUT_BUCKETS, NUM_OUTPUT_BUCKETS},
+        schedule::DEFAULT_BATCH_SIZE,
+    };
+
+    /// 3084 parameters: l0w and l0f 1536 each, l0b 2, l1w 8, l1b 2.
+    fn tiny_shape() -> NetShape {
+        NetShape {
+            hl_size: 2,
+            input_buckets: 1,
+            output_buckets: 2,
+            single_perspective: false,
+            output_factoriser: false,
+        }
+    }
+
+    #[test]
+    fn estimates_the_queue_loader_and_host_weights() {
+        let estimate = MemoryEstimate::new(100, 32, 4, &tiny_shape(), true);
+        assert_eq!(estimate, MemoryEstimate { queue: 5 * 100 * 264, loader: 3 * 100 * 32, weights: 3084 * 16 });
+        assert_eq!(estimate.total(), 190_944);
+        assert_eq!(MemoryEstimate::new(100, 32, 4, &tiny_shape(), false).weights, 0);
+    }
+
+    #[test]
+    fn fits_the_largest_queue_in_the_budget() {
+        let fit = |budget, max_queue| fit_batch_queue(budget, max_queue, 100, 32, &tiny_shape(), true);
+        // the estimate above is exactly a queue of 4
+        assert_eq!(fit(190_944, 64), Some(4));
+        assert_eq!(fit(190_943, 64), Some(3));
+        assert_eq!(fit(190_944, 2), Some(2));
+        // the fixed 85344 bytes plus less than one batch
+        assert_eq!(fit(85_344 + 26_399, 64), None);
+        assert_eq!(fit(1000, 64), None);
+    }
+
+    #[test]
+    fn plans_the_default_run() {
+        let shape = NetShape {
+            hl_size: HL_SIZE,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: NUM_OUTPUT_BUCKETS,
+            single_perspective: false,
+            output_factoriser: false,
+        };
+        let plan = MemoryPlan::new(DEFAULT_BATCH_SIZE, data::RECORD_SIZE, DEFAULT_BATCH_QUEUE, &shape, true, false);
+        // l0w 5898240, l0f 589824, l0b 768, l1w 12288, l1b 8
+        assert_eq!(plan.params, 6_501_128);
+        assert_eq!((plan.weights, plan.gradients, plan.moments), (26_004_512, 26_004_512, 52_009_024));
+        // 6163 floats and 264 prepared bytes per position
+        assert_eq!(plan.activations, 16_384 * (6163 * 4 + 264));
+        assert_eq!(plan.device_total(), 512_241_792);
+        assert_eq!((plan.host.queue, plan.host.loader, plan.host.weights), (142_737_408, 1_572_864, 0));
+        assert_eq!(plan.total(), 656_552_064);
+        assert!(plan.to_string().ends_with("  total                626.1 MB\n"), "{}", plan);
+
+        let with_ema = MemoryPlan::new(DEFAULT_BATCH_SIZE, data::RECORD_SIZE, DEFAULT_BATCH_QUEUE, &shape, true, true);
+        assert_eq!((with_ema.ema, with_ema.host_total()), (plan.weights, plan.host_total() + plan.weights));
+    }
+}
@@ -0,0 +1,141 @@
+//! Aggregates over logged losses: windowed averages for
+//! `--accumulate-metrics`, so losses read as a curve instead of per-superbatch
+//! noise, and per-bucket means for `--loss-by-bucket`.
+
+use std::collections::VecDeque;
+
+use crate::data;
+
+/// Mean of the last `size` values pushed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricWindow {
+    size: usize,
+    values: VecDeque<f32>,
+}
+
+impl MetricWindow {
+    pub fn new(size: usize) -> Self {
+        Self { size: size.max(1), values: VecDeque::with_capacity(size) }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        if self.values.len() == self.size {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// Number of values the mean is currently taken over.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        (!self.values.is_empty()).then(|| self.values.iter().sum::<f32>() / self.values.len() as f32)
+    }
+}
+
+/// Squared error of one position: `output` is the net's eval in units of the
+/// loss scale (see `--loss-target-scale`) and `target` is in `[0, 1]`.
+pub fn position_loss(output: f32, target: f32) -> f32 {
+    (data::sigmoid(output) - target).powi(2)
+}
+
+/// Fraction of `losses` above `clip`, i.e. how often `--loss-clip` caps a
+/// position; 0 for no losses.
+pub fn clipped_fraction(losses: impl IntoIterator<Item = f32>, clip: f32) -> f32 {
+    let (clipped, total) = losses
+        .into_iter()
+        .fold((0usize, 0usize), |(clipped, total), loss| (clipped + usize::from(loss > clip), total + 1));
+    if total == 0 { 0.0 } else { clipped as f32 / total as f32 }
+}
+
+/// `(mean, count)` of the losses in each of `num_buckets` buckets, from
+/// `(bucket, loss)` pairs; `None` for a bucket without positions.
+pub fn mean_by_bucket(losses: &[(usize, f32)], num_buckets: usize) -> Vec<Option<(f32, usize)>> {
+    let mut sums = vec![(0.0f64, 0usize); num_buckets];
+    for &(bucket, loss) in losses {
+        if let Some((sum, count)) = sums.get_mut(bucket) {
+            *sum += f64::from(loss);
+            *count += 1;
+        }
+    }
+    sums.into_iter().map(|(sum, count)| (count > 0).then(|| ((sum / count as f64) as f32, count))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        loader::TargetTransform,
+        test_util::{STARTPOS, board},
+    };
+
+    #[test]
+    fn window_averages_the_last_values() {
+        let mut window = MetricWindow::new(3);
+        let means: Vec<f32> = [0.6, 0.3, 0.3, 0.9, 0.0]
+            .into_iter()
+            .map(|loss| {
+                window.push(loss);
+                window.mean().unwrap()
+            })
+            .collect();
+        for (mean, expected) in means.into_iter().zip([0.6, 0.45, 0.4, 0.5, 0.4]) {
+            assert!((mean - expected).abs() < 1e-6, "{} != {}", mean, expected);
+        }
+        assert_eq!(window.len(), 3);
+    }
+
+    #[test]
+    fn an_empty_window_has_no_mean() {
+        let mut window = MetricWindow::new(0);
+        assert!(window.is_empty() && window.mean().is_none());
+        window.push(0.2);
+        window.push(0.4);
+        assert_eq!((window.len(), window.mean()), (1, Some(0.4)));
+    }
+
+    #[test]
+    fn groups_losses_by_bucket() {
+        let losses = [(0, 0.1), (2, 0.4), (0, 0.3), (2, 0.2), (2, 0.3), (9, 1.0)];
+        let means = mean_by_bucket(&losses, 3);
+        assert_eq!(means.len(), 3);
+        let (mean, count) = means[0].unwrap();
+        assert!((mean - 0.2).abs() < 1e-6 && count == 2);
+        assert_eq!(means[1], None);
+        let (mean, count) = means[2].unwrap();
+        assert!((mean - 0.3).abs() < 1e-6 && count == 3);
+    }
+
+    #[test]
+    fn a_smaller_loss_scale_weighs_large_evals_less() {
+        // the net's output is in eval-scale units, so 1.0 is 400 cp
+        let record = board(STARTPOS, 600, "0.5");
+        let loss = |output: f32, loss_scale: f32| {
+            let transform = TargetTransform { eval_scale: loss_scale, wdl_by_phase: None, wdl: 0.0, wdl_smooth: 0.0 };
+            position_loss(output * 400.0 / loss_scale, transform.target(&record))
+        };
+
+        // an exact eval has no loss at either scale
+        assert!(loss(1.5, 400.0) < 1e-9 && loss(1.5, 200.0) < 1e-9);
+        // 200 cp off at 600 cp: sigmoid(1) vs sigmoid(1.5), then sigmoid(2) vs sigmoid(3)
+        assert!((loss(1.0, 400.0) - 0.007_48).abs() < 1e-4, "{}", loss(1.0, 400.0));
+        assert!((loss(1.0, 200.0) - 0.005_15).abs() < 1e-4, "{}", loss(1.0, 200.0));
+        // an eval of the wrong sign costs more once the sigmoid is steeper
+        assert!(loss(-0.25, 200.0) > loss(-0.25, 400.0));
+    }
+
+    #[test]
+    fn clip_rate_counts_losses_strictly_above_the_clip() {
+        let losses = [0.01, 0.2, 0.05, 0.2, 0.6, 0.9, 0.02, 0.1];
+        assert_eq!(clipped_fraction(losses, 0.2), 0.25);
+        assert_eq!(clipped_fraction(losses, 0.95), 0.0);
+        assert_eq!(clipped_fraction(losses, 0.0), 1.0);
+        assert_eq!(clipped_fraction([], 0.2), 0.0);
+    }
+}
@@ -0,0 +1,607 @@
+//! Network architecture constants and a pure-Rust mirror of the save format,
+//! for writing engine nets from float weights outside of bullet's own saves.
+
+use std::{fmt, fs, io, path::Path};
+
+use bullet::game::inputs::get_num_buckets;
+
+pub const HL_SIZE: usize = 768;
+pub const NUM_OUTPUT_BUCKETS: usize = 8;
+#[rustfmt::skip]
+pub const BUCKET_LAYOUT: [usize; 32] = [
+    0, 1, 2, 3,
+    4, 4, 5, 5,
+    6, 6, 6, 6,
+    7, 7, 7, 7,
+    8, 8, 8, 8,
+    8, 8, 8, 8,
+    9, 9, 9, 9,
+    9, 9, 9, 9,
+];
+pub const NUM_INPUT_BUCKETS: usize = get_num_buckets(&BUCKET_LAYOUT);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// `bucket` at layout index `square` is not below the number of entries.
+    OutOfRange { square: usize, bucket: usize },
+    /// No square maps to `bucket`, although a higher bucket is used.
+    Missing(usize),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange { square, bucket } => {
+                write!(f, "bucket layout entry {} is {}, which is out of range", square, bucket)
+            }
+            Self::Missing(bucket) => write!(f, "bucket layout never uses bucket {}", bucket),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// Checks that a king bucket layout uses exactly the buckets `0..n` and
+/// returns `n`. The engine indexes `ft_weights` by bucket, so a hole would
+/// leave a block of weights that no position ever trains.
+pub fn validate_bucket_layout(layout: &[usize]) -> Result<usize, LayoutError> {
+    let mut used = vec![false; layout.len()];
+    for (square, &bucket) in layout.iter().enumerate() {
+        if bucket >= layout.len() {
+            return Err(LayoutError::OutOfRange { square, bucket });
+        }
+        used[bucket] = true;
+    }
+
+    let num_buckets = used.iter().rposition(|&u| u).map_or(0, |b| b + 1);
+    match used[..num_buckets].iter().position(|&u| !u) {
+        Some(missing) => Err(LayoutError::Missing(missing)),
+        None => Ok(num_buckets),
+    }
+}
+
+/// Feature transformer quantisation (engine `NNUE_QA`).
+pub const QA: i16 = 255;
+/// Output layer quantisation (engine `NNUE_QB`).
+pub const QB: i16 = 64;
+
+/// Trainable tensors in the order they are saved.
+pub const TENSORS: [&str; 5] = ["l0w", "l0f", "l0b", "l1w", "l1b"];
+/// Output bucket factoriser, only trained with `--output-factoriser`.
+pub const OUTPUT_FACTORISER: &str = "l1f";
+
+/// On-disk layouts `--save-format-version` can select, oldest first, with
+/// the engine build that reads each. Only add a version when the layout
+/// changes; the old entries stay so nets can be made for older engines.
+pub const SAVE_FORMATS: [(u32, &str); 2] = [
+    (
+        1,
+        "l0w (factoriser merged) [bucket][768][hl], l0b [hl], l1w [bucket][l1 inputs], l1b [bucket]; \
+         i16 LE, zero-padded to 64 bytes; no header. Read by src/nnue.c",
+    ),
+    (
+        2,
+        "version 1, then a description: magic SMND, u32 LE byte length, UTF-8 text. \
+         Read by src/nnue.c, which reports the description on load and on uci",
+    ),
+];
+/// First save format with a description after the weights.
+pub const DESCRIPTION_FORMAT: u32 = 2;
+pub const DESCRIPTION_MAGIC: &[u8; 4] = b"SMND";
+/// Longest description the engine keeps (its buffer holds one more byte).
+pub const MAX_DESCRIPTION_LEN: usize = 255;
+/// Written unless `--save-format-version` or `--net-description` asks for
+/// more: the layout every engine build reads.
+pub const DEFAULT_SAVE_FORMAT: u32 = 1;
+
+pub fn is_supported_save_format(version: u32) -> bool {
+    SAVE_FORMATS.iter().any(|&(v, _)| v == version)
+}
+
+/// Multiplier on the scheduled LR each tensor effectively trains at. AdamW
+/// steps are scale-invariant, so the `--l1-lr` reparameterisation
+/// `w = l1_scale * v` moves the output layer at `l1_scale` times the base rate.
+pub fn lr_multipliers(l1_scale: f32) -> [(&'static str, f32); 5] {
+    TENSORS.map(|id| (id, if id.starts_with("l1") { l1_scale } else { 1.0 }))
+}
+
+/// `--print-layer-lr`: the effective LR of each tensor at scheduled LR `lr`.
+pub fn layer_lrs(lr: f32, l1_scale: f32) -> String {
+    let lrs: Vec<String> = lr_multipliers(l1_scale).iter().map(|(id, m)| format!("{} {:.6}", id, lr * m)).collect();
+    lrs.join(" | ")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NetShape {
+    pub hl_size: usize,
+    pub input_buckets: usize,
+    pub output_buckets: usize,
+    pub single_perspective: bool,
+    /// Whether `l1f` exists; it is merged into `l1w` on save like `l0f`.
+    pub output_factoriser: bool,
+}
+
+impl NetShape {
+    pub fn l1_inputs(&self) -> usize {
+        if self.single_perspective { self.hl_size } else { 2 * self.hl_size }
+    }
+
+    /// Bytes of a version 1 engine net of this shape, padding included.
+    pub fn quantised_bytes(&self) -> usize {
+        let values = 768 * self.input_buckets * self.hl_size
+            + self.hl_size
+            + self.output_buckets * self.l1_inputs()
+            + self.output_buckets;
+        (2 * values).next_multiple_of(64)
+    }
+
+    /// Number of f32 values bullet stores for a tensor.
+    pub fn tensor_len(&self, id: &str) -> Option<usize> {
+        Some(match id {
+            "l0w" => 768 * self.input_buckets * self.hl_size,
+            "l0f" => 768 * self.hl_size,
+            "l0b" => self.hl_size,
+            "l1w" => self.l1_inputs() * self.output_buckets,
+            "l1b" => self.output_buckets,
+            "l1f" if self.output_factoriser => self.l1_inputs(),
+            _ => return None,
+        })
+    }
+
+    /// Tensors the graph of this shape has, in save order.
+    pub fn tensors(&self) -> Vec<&'static str> {
+        let mut tensors = TENSORS.to_vec();
+        if self.output_factoriser {
+            tensors.push(OUTPUT_FACTORISER);
+        }
+        tensors
+    }
+}
+
+/// Float weights as stored by bullet (column-major, `l1w` untransposed).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FloatNet {
+    pub l0w: Vec<f32>,
+    pub l0f: Vec<f32>,
+    pub l0b: Vec<f32>,
+    pub l1w: Vec<f32>,
+    pub l1b: Vec<f32>,
+    /// Empty unless the net has an output factoriser.
+    pub l1f: Vec<f32>,
+}
+
+impl FloatNet {
+    /// Collects the tensors of `shape` through `read`, e.g. from the training graph.
+    pub fn from_fn(shape: &NetShape, mut read: impl FnMut(&str) -> Option<Vec<f32>>) -> Option<FloatNet> {
+        let mut net = FloatNet::default();
+        for id in shape.tensors() {
+            *net.get_mut(id).unwrap() = read(id)?;
+        }
+        Some(net)
+    }
+
+    /// Ids of the tensors this net holds, in save order.
+    pub fn tensor_ids(&self) -> Vec<&'static str> {
+        let mut ids = TENSORS.to_vec();
+        if !self.l1f.is_empty() {
+            ids.push(OUTPUT_FACTORISER);
+        }
+        ids
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Vec<f32>> {
+        Some(match id {
+            "l0w" => &self.l0w,
+            "l0f" => &self.l0f,
+            "l0b" => &self.l0b,
+            "l1w" => &self.l1w,
+            "l1b" => &self.l1b,
+            "l1f" => &self.l1f,
+            _ => return None,
+        })
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Vec<f32>> {
+        Some(match id {
+            "l0w" => &mut self.l0w,
+            "l0f" => &mut self.l0f,
+            "l0b" => &mut self.l0b,
+            "l1w" => &mut self.l1w,
+            "l1b" => &mut self.l1b,
+            "l1f" => &mut self.l1f,
+            _ => return None,
+        })
+    }
+
+    /// `(id, values)` pairs in save order, as written by [`archive`](crate::archive).
+    pub fn named_tensors(&self) -> Vec<(String, Vec<f32>)> {
+        self.tensor_ids().iter().map(|id| (id.to_string(), self.get(id).unwrap().clone())).collect()
+    }
+
+    /// Checks every tensor has the length `shape` implies.
+    pub fn check_shape(&self, shape: &NetShape) -> Result<(), String> {
+        for id in TENSORS.into_iter().chain([OUTPUT_FACTORISER]) {
+            let expected = shape.tensor_len(id).unwrap_or(0);
+            let got = self.get(id).unwrap().len();
+            if got != expected {
+                return Err(format!("{} has {} values, expected {}", id, got, expected));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Element-wise mean of nets of the same shape.
+pub fn average(nets: &[FloatNet]) -> Option<FloatNet> {
+    let (first, rest) = nets.split_first()?;
+    let mut sum = first.clone();
+    for net in rest {
+        for id in TENSORS.into_iter().chain([OUTPUT_FACTORISER]) {
+            let total = sum.get_mut(id).unwrap();
+            let values = net.get(id).unwrap();
+            if total.len() != values.len() {
+                return None;
+            }
+            total.iter_mut().zip(values).for_each(|(t, v)| *t += v);
+        }
+    }
+    let scale = 1.0 / nets.len() as f32;
+    for id in sum.tensor_ids() {
+        sum.get_mut(id).unwrap().iter_mut().for_each(|v| *v *= scale);
+    }
+    Some(sum)
+}
+
+/// Re-expresses the stored l1 tensors `v` of a net trained with `w = from * v`
+/// (see `--l1-lr`) for a run training `w = to * v`; the effective weights,
+/// and so the quantised net, are unchanged.
+pub fn rescale_l1(net: &mut FloatNet, from: f32, to: f32) {
+    let ratio = from / to;
+    for id in ["l1w", "l1b", OUTPUT_FACTORISER] {
+        net.get_mut(id).unwrap().iter_mut().for_each(|v| *v *= ratio);
+    }
+}
+
+/// Applies the save format: merge the factorisers into `l0w` and `l1w`, scale `l1` by
+/// `l1_scale` (see `--l1-lr`), quantise and transpose `l1w` to bucket-major.
+/// Values outside the i16 range are an error rather than silently wrapped.
+pub fn quantise(net: &FloatNet, shape: &NetShape, l1_scale: f32) -> Result<Vec<i16>, String> {
+    quantise_at(net, shape, l1_scale, QA)
+}
+
+/// [`quantise`] with `qa` in place of the engine's [`QA`], for comparing scales.
+pub fn quantise_at(net: &FloatNet, shape: &NetShape, l1_scale: f32, qa: i16) -> Result<Vec<i16>, String> {
+    net.check_shape(shape)?;
+    let quant = |id: &str, values: &mut dyn Iterator<Item = f32>, q: f32, out: &mut Vec<i16>| {
+        for v in values {
+            let scaled = (v * q).round();
+            if !(f32::from(i16::MIN)..=f32::from(i16::MAX)).contains(&scaled) {
+                return Err(format!("{} value {} overflows i16 at scale {}", id, v, q));
+            }
+            out.push(scaled as i16);
+        }
+        Ok(())
+    };
+
+    let mut out = Vec::new();
+    let factoriser_len = net.l0f.len();
+    let mut l0w = net.l0w.iter().enumerate().map(|(i, &w)| w + net.l0f[i % factoriser_len]);
+    quant("l0w", &mut l0w, f32::from(qa), &mut out)?;
+    quant("l0b", &mut net.l0b.iter().copied(), f32::from(qa), &mut out)?;
+
+    let (inputs, buckets) = (shape.l1_inputs(), shape.output_buckets);
+    let factoriser = |i: usize| net.l1f.get(i).copied().unwrap_or(0.0);
+    let mut l1w =
+        (0..buckets).flat_map(|b| (0..inputs).map(move |i| (net.l1w[i * buckets + b] + factoriser(i)) * l1_scale));
+    quant("l1w", &mut l1w, f32::from(QB), &mut out)?;
+    let mut l1b = net.l1b.iter().map(|&b| b * l1_scale);
+    quant("l1b", &mut l1b, f32::from(qa) * f32::from(QB), &mut out)?;
+    Ok(out)
+}
+
+/// C header with the constants the engine needs to read a net of `shape`,
+/// so they are not copied over by hand. `eval_scale` is the engine's
+/// centipawn scale and `format_version` the `--save-format-version` in use.
+pub fn c_header(shape: &NetShape, layout: &[usize], eval_scale: f32, format_version: u32) -> String {
+    let rows: Vec<String> =
+        layout.chunks(4).map(|row| row.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(", ")).collect();
+    let mut out = String::new();
+    out.push_str("/* Generated by the SleepMind trainer (--export-c-header). Do not edit. */\n");
+    out.push_str("#ifndef SLEEPMIND_NNUE_CONSTANTS_H\n#define SLEEPMIND_NNUE_CONSTANTS_H\n\n");
+    out.push_str(&format!("#define NNUE_SAVE_FORMAT_VERSION {}\n", format_version));
+    out.push_str(&format!("#define NNUE_HL_SIZE {}\n", shape.hl_size));
+    out.push_str(&format!("#define NNUE_NUM_INPUT_BUCKETS {}\n", shape.input_buckets));
+    out.push_str(&format!("#define NNUE_NUM_OUTPUT_BUCKETS {}\n", shape.output_buckets));
+    out.push_str(&format!("#define NNUE_SINGLE_PERSPECTIVE {}\n", u8::from(shape.single_perspective)));
+    out.push_str(&format!("#define NNUE_QA {}\n", QA));
+    out.push_str(&format!("#define NNUE_QB {}\n", QB));
+    out.push_str(&format!("#define NNUE_EVAL_SCALE {}\n\n", eval_scale));
+    out.push_str("/* king bucket per square, a1..h8 with files e-h mirrored onto d-a */\n");
+    out.push_str(&format!("#define NNUE_BUCKET_LAYOUT_LEN {}\n", layout.len()));
+    out.push_str(&format!("#define NNUE_BUCKET_LAYOUT {{ \\\n    {} \\\n}}\n\n", rows.join(", \\\n    ")));
+    out.push_str("#endif\n");
+    out
+}
+
+/// Default save format 2 description: what the net is, then the UTC date.
+pub fn auto_description(what: &str, unix_secs: u64) -> String {
+    let (year, month, day) = utc_date(unix_secs);
+    format!("{}, trained {:04}-{:02}-{:02}", what, year, month, day)
+}
+
+/// `(year, month, day)` in UTC of a Unix timestamp.
+pub fn utc_date(unix_secs: u64) -> (i64, i64, i64) {
+    // days since 1970-01-01 to a civil date (Hinnant's algorithm)
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Splits an engine net into its weight bytes (`net_bytes` long) and the
+/// save format 2 description after them, if there is one.
+pub fn split_description(bytes: &[u8], net_bytes: usize) -> Result<(&[u8], Option<String>), String> {
+    if bytes.len() <= net_bytes {
+        return Ok((bytes, None));
+    }
+    let (weights, trailer) = bytes.split_at(net_bytes);
+    if trailer.len() < 8 || &trailer[..4] != DESCRIPTION_MAGIC {
+        return Err(format!("{} bytes after the weights are not a net description", trailer.len()));
+    }
+    let len = u32::from_le_bytes(trailer[4..8].try_into().unwrap()) as usize;
+    if trailer.len() != 8 + len {
+        return Err(format!("net description claims {} bytes but {} follow", len, trailer.len() - 8));
+    }
+    let text = String::from_utf8(trailer[8..].to_vec()).map_err(|_| "net description is not UTF-8".to_string())?;
+    Ok((weights, Some(text)))
+}
+
+/// Sets the description of the engine net at `path`, replacing any earlier
+/// one, so a file bullet has just written becomes save format 2.
+pub fn write_description(path: impl AsRef<Path>, net_bytes: usize, text: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut bytes = fs::read(path)?;
+    bytes.truncate(net_bytes);
+    bytes.extend_from_slice(DESCRIPTION_MAGIC);
+    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+/// Writes an engine net, zero-padded to a multiple of 64 bytes like bullet does.
+pub fn write_quantised(path: impl AsRef<Path>, values: &[i16]) -> io::Result<()> {
+    let mut bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    bytes.resize(bytes.len().next_multiple_of(64), 0);
+    fs::write(path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    /// Two hidden neurons, one input bucket and two output buckets.
+    fn tiny_shape() -> NetShape {
+        NetShape {
+            hl_size: 2,
+            input_buckets: 1,
+            output_buckets: 2,
+            single_perspective: false,
+            output_factoriser: false,
+        }
+    }
+
+    /// A net of `shape` with every value `value`.
+    fn filled(shape: &NetShape, value: f32) -> FloatNet {
+        FloatNet::from_fn(shape, |id| Some(vec![value; shape.tensor_len(id)?])).unwrap()
+    }
+
+    #[test]
+    fn accepts_contiguous_layouts() {
+        assert_eq!(validate_bucket_layout(&BUCKET_LAYOUT), Ok(NUM_INPUT_BUCKETS));
+        assert_eq!(validate_bucket_layout(&[0; 32]), Ok(1));
+        assert_eq!(validate_bucket_layout(&[1, 0, 2, 2]), Ok(3));
+    }
+
+    #[test]
+    fn rejects_a_gap_in_the_buckets() {
+        assert_eq!(validate_bucket_layout(&[0, 0, 2, 2]), Err(LayoutError::Missing(1)));
+        assert_eq!(validate_bucket_layout(&[3, 3, 3, 3]), Err(LayoutError::Missing(0)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_buckets() {
+        assert_eq!(validate_bucket_layout(&[0, 1, 4, 2]), Err(LayoutError::OutOfRange { square: 2, bucket: 4 }));
+        let mut layout = BUCKET_LAYOUT;
+        layout[31] = 32;
+        assert_eq!(validate_bucket_layout(&layout), Err(LayoutError::OutOfRange { square: 31, bucket: 32 }));
+    }
+
+    #[test]
+    fn l1_lr_scales_only_the_output_layer() {
+        let multipliers = lr_multipliers(4.0);
+        for (id, multiplier) in multipliers {
+            let expected = if id == "l1w" || id == "l1b" { 4.0 } else { 1.0 };
+            assert_eq!(multiplier, expected, "{}", id);
+        }
+        assert!(lr_multipliers(1.0).iter().all(|&(_, multiplier)| multiplier == 1.0));
+    }
+
+    #[test]
+    fn layer_lrs_report_the_l1_override() {
+        assert_eq!(layer_lrs(0.001, 4.0), "l0w 0.001000 | l0f 0.001000 | l0b 0.001000 | l1w 0.004000 | l1b 0.004000");
+        assert!(!layer_lrs(0.001, 1.0).contains("0.004"));
+    }
+
+    #[test]
+    fn rescaled_l1_quantises_to_the_same_net() {
+        let shape = NetShape { output_factoriser: true, ..tiny_shape() };
+        let net = filled(&shape, 0.1);
+        let mut rescaled = net.clone();
+        rescale_l1(&mut rescaled, 2.0, 1.0);
+        assert_eq!(rescaled.l1w, vec![0.2; rescaled.l1w.len()]);
+        assert_eq!(rescaled.l1f, vec![0.2; rescaled.l1f.len()]);
+        assert_eq!(rescaled.l0w, net.l0w);
+        assert_eq!(quantise(&rescaled, &shape, 1.0), quantise(&net, &shape, 2.0));
+    }
+
+    #[test]
+    fn c_header_records_the_engine_scale() {
+        let shape = NetShape { hl_size: HL_SIZE, ..tiny_shape() };
+        let header = c_header(&shape, &BUCKET_LAYOUT, 250.0, 1);
+        assert!(header.contains("#define NNUE_EVAL_SCALE 250\n"), "{}", header);
+        assert!(header.contains(&format!("#define NNUE_HL_SIZE {}\n", HL_SIZE)));
+        assert!(header.contains("#define NNUE_SAVE_FORMAT_VERSION 1\n"));
+    }
+
+    #[test]
+    fn quantised_net_has_the_engine_layout_of_the_shape() {
+        let shape = tiny_shape();
+        let mut net = filled(&shape, 0.1);
+        // l1w[input * buckets + bucket], quantised by QB to its own index
+        net.l1w = (0..8).map(|i| i as f32 / f32::from(QB)).collect();
+        let quantised = quantise(&net, &shape, 1.0).unwrap();
+
+        assert_eq!(quantised.len(), 768 * 2 + 2 + 2 * 4 + 2);
+        // factoriser merged: (0.1 + 0.1) * QA
+        assert!(quantised[..768 * 2].iter().all(|&v| v == 51));
+        assert_eq!(&quantised[768 * 2..768 * 2 + 2], &[26, 26]);
+        // bucket-major: every input of bucket 0, then of bucket 1
+        assert_eq!(&quantised[768 * 2 + 2..768 * 2 + 10], &[0, 2, 4, 6, 1, 3, 5, 7]);
+
+        let path = temp_path("tiny.nnue");
+        write_quantised(&path, &quantised).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize, shape.quantised_bytes());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn each_save_format_has_its_documented_layout() {
+        assert_eq!(SAVE_FORMATS.map(|(version, _)| version), [1, 2]);
+        assert!(!is_supported_save_format(0) && !is_supported_save_format(3));
+
+        let shape = tiny_shape();
+        let quantised = quantise(&filled(&shape, 0.1), &shape, 1.0).unwrap();
+        let path = temp_path("formats.nnue");
+        // 1: the padded weights alone
+        write_quantised(&path, &quantised).unwrap();
+        let v1 = fs::read(&path).unwrap();
+        assert_eq!(v1.len(), shape.quantised_bytes());
+        assert_eq!(v1[..2 * quantised.len()], *quantised.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>());
+        assert!(v1[2 * quantised.len()..].iter().all(|&b| b == 0));
+        assert_eq!(split_description(&v1, shape.quantised_bytes()), Ok((&v1[..], None)));
+
+        // 2: version 1, then magic, length and text
+        write_description(&path, shape.quantised_bytes(), "tiny").unwrap();
+        let v2 = fs::read(&path).unwrap();
+        assert_eq!(v2[..v1.len()], v1[..]);
+        assert_eq!(v2[v1.len()..], *b"SMND\x04\0\0\0tiny");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn description_round_trips_through_the_net() {
+        let shape = tiny_shape();
+        let path = temp_path("described.nnue");
+        write_quantised(&path, &quantise(&filled(&shape, 0.1), &shape, 1.0).unwrap()).unwrap();
+        write_description(&path, shape.quantised_bytes(), "first").unwrap();
+        // a second description replaces the first
+        let text = auto_description("tiny superbatch 40", 1_791_936_000);
+        write_description(&path, shape.quantised_bytes(), &text).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (weights, description) = split_description(&bytes, shape.quantised_bytes()).unwrap();
+        assert_eq!(weights.len(), shape.quantised_bytes());
+        assert_eq!(description.as_deref(), Some("tiny superbatch 40, trained 2026-10-14"));
+
+        let mut truncated = bytes.clone();
+        truncated.pop();
+        assert_eq!(
+            split_description(&truncated, shape.quantised_bytes()),
+            Err("net description claims 38 bytes but 37 follow".to_string())
+        );
+        let mut garbage = bytes[..shape.quantised_bytes()].to_vec();
+        garbage.extend_from_slice(b"junk after the net");
+        assert!(
+            split_description(&garbage, shape.quantised_bytes()).unwrap_err().ends_with("are not a net description")
+        );
+    }
+
+    #[test]
+    fn utc_dates_of_unix_timestamps() {
+        assert_eq!(utc_date(0), (1970, 1, 1));
+        assert_eq!(utc_date(951_868_799), (2000, 2, 29));
+        assert_eq!(utc_date(951_868_800), (2000, 3, 1));
+    }
+
+    #[test]
+    fn quantise_rejects_another_shape_and_overflow() {
+        let shape = tiny_shape();
+        let wider = NetShape { hl_size: 4, ..shape };
+        assert_eq!(quantise(&filled(&wider, 0.1), &shape, 1.0), Err("l0w has 3072 values, expected 1536".to_string()));
+        let error = quantise(&filled(&shape, 200.0), &shape, 1.0).unwrap_err();
+        assert!(error.starts_with("l0w value"), "{}", error);
+    }
+
+    #[test]
+    fn c_header_defines_the_default_config() {
+        let shape = NetShape {
+            hl_size: HL_SIZE,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: NUM_OUTPUT_BUCKETS,
+            single_perspective: false,
+            output_factoriser: false,
+        };
+        let header = c_header(&shape, &BUCKET_LAYOUT, 400.0, 1);
+        for define in [
+            format!("#define NNUE_HL_SIZE {}", HL_SIZE),
+            format!("#define NNUE_NUM_INPUT_BUCKETS {}", NUM_INPUT_BUCKETS),
+            format!("#define NNUE_NUM_OUTPUT_BUCKETS {}", NUM_OUTPUT_BUCKETS),
+            "#define NNUE_SINGLE_PERSPECTIVE 0".to_string(),
+            format!("#define NNUE_QA {}", QA),
+            format!("#define NNUE_QB {}", QB),
+            "#define NNUE_EVAL_SCALE 400".to_string(),
+            format!("#define NNUE_BUCKET_LAYOUT_LEN {}", BUCKET_LAYOUT.len()),
+        ] {
+            assert!(header.lines().any(|line| line == define), "missing {:?} in\n{}", define, header);
+        }
+        let layout: Vec<usize> = header
+            .split_once("#define NNUE_BUCKET_LAYOUT {")
+            .and_then(|(_, rest)| rest.split_once('}'))
+            .map(|(values, _)| {
+                values.split(',').map(|v| v.trim_matches(|c: char| !c.is_ascii_digit()).parse().unwrap()).collect()
+            })
+            .unwrap();
+        assert_eq!(layout, BUCKET_LAYOUT);
+        assert!(header.trim_end().ends_with("#endif"));
+    }
+
+    #[test]
+    fn average_is_the_element_wise_mean() {
+        let shape = tiny_shape();
+        let mut a = filled(&shape, 0.1);
+        a.l1b = vec![1.0, -1.0];
+        let b = filled(&shape, 0.3);
+        let average = average(&[a, b]).unwrap();
+        assert!(average.l0w.iter().all(|v| (v - 0.2).abs() < 1e-6));
+        assert!((average.l1b[0] - 0.65).abs() < 1e-6 && (average.l1b[1] + 0.35).abs() < 1e-6);
+        average.check_shape(&shape).unwrap();
+    }
+
+    #[test]
+    fn average_needs_nets_of_one_shape() {
+        let shape = tiny_shape();
+        assert_eq!(average(&[]), None);
+        assert_eq!(average(&[filled(&shape, 0.1), filled(&NetShape { hl_size: 4, ..shape }, 0.1)]), None);
+        assert_eq!(average(&[filled(&shape, 0.5)]), Some(filled(&shape, 0.5)));
+    }
+}
@@ -0,0 +1,205 @@
+//! `--export-piece-values`: centipawn piece values implied by a quantised
+//! net, as the mean change in eval when one piece of the side to move is
+//! added to a kings-only board. A pawn far from ~100cp, or a queen below a
+//! rook, usually means a scale or data bug rather than a strong net.
+
+use std::fmt;
+
+use bullet::game::formats::bulletformat::ChessBoard;
+
+use crate::{
+    data::{self, BISHOP, KNIGHT, PAWN, QUEEN, ROOK},
+    inference,
+};
+
+/// Pieces valued, with their FEN letters for the side to move.
+pub const VALUED_PIECES: [(u8, char, &str); 5] =
+    [(PAWN, 'P', "pawn"), (KNIGHT, 'N', "knight"), (BISHOP, 'B', "bishop"), (ROOK, 'R', "rook"), (QUEEN, 'Q', "queen")];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceValue {
+    pub name: &'static str,
+    /// Mean eval delta in centipawns over every square and king placement.
+    pub mean: f64,
+    /// Lowest and highest per-input-bucket mean.
+    pub bucket_range: (f64, f64),
+    pub samples: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceValues {
+    pub values: Vec<PieceValue>,
+}
+
+/// Kings-only board with the side to move's king on `ours` and the other
+/// king on `theirs`, plus `extra` pieces of the side to move.
+fn board(ours: u8, theirs: u8, extra: &[(char, u8)]) -> ChessBoard {
+    let mut squares = [None; 64];
+    squares[usize::from(ours)] = Some('K');
+    squares[usize::from(theirs)] = Some('k');
+    for &(c, square) in extra {
+        squares[usize::from(square)] = Some(c);
+    }
+    let ranks: Vec<String> = (0..8)
+        .rev()
+        .map(|rank| {
+            let mut fen = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match squares[rank * 8 + file] {
+                    Some(c) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(c);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            fen
+        })
+        .collect();
+    data::parse_fen(&format!("{} w - - 0 1", ranks.join("/"))).expect("constructed FEN is valid")
+}
+
+/// One king placement per input bucket: the own king on the first square of
+/// the bucket, the other king on the same square of the far rank.
+pub fn king_placements(input_buckets: usize) -> Vec<(usize, u8, u8)> {
+    (0..input_buckets)
+        .filter_map(|bucket| {
+            let ours = (0..64u8).find(|&sq| inference::king_bucket(sq).0 == bucket)?;
+            let mut theirs = ours ^ 56;
+            // kings on the same file two ranks apart never touch, but the
+            // middle ranks map onto each other; move the other king away
+            if (i16::from(ours / 8) - i16::from(theirs / 8)).abs() <= 1 {
+                theirs = if ours / 8 < 4 { 56 + ours % 8 } else { ours % 8 };
+            }
+            Some((bucket, ours, theirs))
+        })
+        .collect()
+}
+
+impl PieceValues {
+    /// Values from `eval` (centipawns, side to move) over every empty square
+    /// a piece can stand on, for each placement in `king_placements`.
+    pub fn compute(input_buckets: usize, eval: impl Fn(&ChessBoard) -> i32) -> Self {
+        let placements = king_placements(input_buckets);
+        let values = VALUED_PIECES
+            .iter()
+            .map(|&(piece, c, name)| {
+                let mut bucket_means = Vec::new();
+                let (mut sum, mut samples) = (0.0, 0);
+                for &(_, ours, theirs) in &placements {
+                    let base = eval(&board(ours, theirs, &[]));
+                    let deltas: Vec<i32> = (0..64u8)
+                        .filter(|&sq| sq != ours && sq != theirs && (piece != PAWN || (8..56).contains(&sq)))
+                        .map(|sq| eval(&board(ours, theirs, &[(c, sq)])) - base)
+                        .collect();
+                    let total: f64 = deltas.iter().map(|&d| f64::from(d)).sum();
+                    bucket_means.push(total / deltas.len().max(1) as f64);
+                    sum += total;
+                    samples += deltas.len();
+                }
+                let low = bucket_means.iter().copied().fold(f64::INFINITY, f64::min);
+                let high = bucket_means.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                PieceValue { name, mean: sum / samples.max(1) as f64, bucket_range: (low, high), samples }
+            })
+            .collect();
+        Self { values }
+    }
+
+    pub fn pawn(&self) -> Option<f64> {
+        self.values.first().map(|v| v.mean)
+    }
+}
+
+impl fmt::Display for PieceValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Piece values (eval delta vs. kings only, side to move):")?;
+        writeln!(f, "  {:<7} {:>8} {:>10} {:>9}", "piece", "cp", "pawn=100", "bucket range")?;
+        let pawn = self.pawn().filter(|&p| p.abs() > f64::EPSILON);
+        for v in &self.values {
+            let relative = pawn.map_or("-".to_string(), |p| format!("{:.0}", 100.0 * v.mean / p));
+            writeln!(
+                f,
+                "  {:<7} {:>8.1} {:>10} {:>9.1}..{:.1}",
+                v.name, v.mean, relative, v.bucket_range.0, v.bucket_range.1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inference::QuantisedNet,
+        net::{NUM_INPUT_BUCKETS, NetShape, QA, QB},
+    };
+
+    /// One hidden neuron that only the side to move's non-king pieces feed,
+    /// with the same weight on every square and in every bucket, so adding a
+    /// piece always adds `(w^2 * QB / QA) * 1000 / (QA * QB)` cp at eval
+    /// scale 1000: 100 for a pawn.
+    fn handcrafted_net() -> QuantisedNet {
+        let shape = NetShape {
+            hl_size: 1,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: 1,
+            single_perspective: true,
+            output_factoriser: false,
+        };
+        let weights = [81, 140, 145, 181, 242];
+        let mut values: Vec<i16> = (0..768 * NUM_INPUT_BUCKETS)
+            .map(|feature| {
+                let (colour, piece) = ((feature % 768) / 384, (feature % 384) / 64);
+                if colour == 0 { weights.get(piece).copied().unwrap_or(0) } else { 0 }
+            })
+            .collect();
+        values.extend([0, QB, 0]);
+        QuantisedNet::from_values(shape, &values, QA).unwrap()
+    }
+
+    #[test]
+    fn values_are_the_eval_delta_of_each_piece() {
+        let net = handcrafted_net();
+        let values = PieceValues::compute(NUM_INPUT_BUCKETS, |board| net.eval(board, 1000));
+        let means: Vec<(&str, f64)> = values.values.iter().map(|v| (v.name, v.mean)).collect();
+        assert_eq!(means, [("pawn", 100.0), ("knight", 301.0), ("bishop", 323.0), ("rook", 503.0), ("queen", 900.0)]);
+        assert!(values.values.iter().all(|v| v.bucket_range == (v.mean, v.mean)));
+        // every square but the kings', once per input bucket
+        assert_eq!(values.values[1].samples, 62 * NUM_INPUT_BUCKETS);
+        assert_eq!(values.pawn(), Some(100.0));
+    }
+
+    #[test]
+    fn report_scales_to_a_pawn_of_100() {
+        let net = handcrafted_net();
+        let report = PieceValues::compute(NUM_INPUT_BUCKETS, |board| net.eval(board, 1000)).to_string();
+        assert!(report.contains("  queen      900.0        900     900.0..900.0\n"), "{}", report);
+    }
+
+    #[test]
+    fn kings_never_share_a_square_or_touch() {
+        let placements = king_placements(NUM_INPUT_BUCKETS);
+        assert_eq!(
+            placements.iter().map(|&(bucket, _, _)| bucket).collect::<Vec<_>>(),
+            (0..NUM_INPUT_BUCKETS).collect::<Vec<_>>()
+        );
+        for (bucket, ours, theirs) in placements {
+            assert_eq!(inference::king_bucket(ours).0, bucket);
+            assert!(
+                (i16::from(ours / 8) - i16::from(theirs / 8)).abs() > 1,
+                "bucket {}: {} and {}",
+                bucket,
+                ours,
+                theirs
+            );
+        }
+    }
+}
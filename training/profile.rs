@@ -0,0 +1,202 @@
+//! Time breakdown for `--profile`.
+//!
+//! bullet does not expose per-stage timers, so this measures the loader side
+//! of the batch queue instead: time spent reading and transforming batches
+//! versus time blocked handing them over because the queue is full. A mostly
+//! full queue means the device is the bottleneck; mostly loading means the
+//! trainer is starved for data.
+//!
+//! [`StallDetector`] watches the reports for a lasting throughput drop while
+//! the loader is busy and suggests a loader setting to change.
+
+use std::time::Duration;
+
+pub const LOADING: &str = "loading";
+pub const QUEUE_FULL: &str = "queue full";
+pub const IDLE: &str = "idle";
+
+/// Accumulated time per named stage, in insertion order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    pub fn add(&mut self, stage: &'static str, time: Duration) {
+        match self.stages.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += time,
+            None => self.stages.push((stage, time)),
+        }
+    }
+
+    pub fn merge(&mut self, other: &Profile) {
+        for &(stage, time) in &other.stages {
+            self.add(stage, time);
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, time)| *time).sum()
+    }
+
+    /// Share of the total per stage, in percent; all zero if nothing was timed.
+    pub fn percentages(&self) -> Vec<(&'static str, f64)> {
+        let total = self.total().as_secs_f64();
+        self.stages
+            .iter()
+            .map(|&(stage, time)| (stage, if total > 0.0 { 100.0 * time.as_secs_f64() / total } else { 0.0 }))
+            .collect()
+    }
+
+    /// Share of the total spent in `stage`, in percent.
+    pub fn share(&self, stage: &str) -> f64 {
+        self.percentages().iter().find(|(s, _)| *s == stage).map_or(0.0, |(_, p)| *p)
+    }
+
+    /// One-line summary for the periodic report.
+    pub fn line(&self) -> String {
+        let parts: Vec<String> =
+            self.percentages().iter().map(|(stage, pct)| format!("{} {:.1}%", stage, pct)).collect();
+        parts.join(" | ")
+    }
+
+    /// Summary table for the end of the run.
+    pub fn table(&self) -> String {
+        let mut out = format!("{:<12} {:>10} {:>7}\n", "stage", "time", "share");
+        for (&(stage, time), (_, pct)) in self.stages.iter().zip(self.percentages()) {
+            out.push_str(&format!("{:<12} {:>9.1}s {:>6.1}%\n", stage, time.as_secs_f64(), pct));
+        }
+        out.push_str(&format!("{:<12} {:>9.1}s", "total", self.total().as_secs_f64()));
+        out
+    }
+
+    /// Rough reading of the loader breakdown for the report.
+    pub fn hint(&self) -> Option<&'static str> {
+        if self.total().is_zero() {
+            None
+        } else if self.share(QUEUE_FULL) >= 50.0 {
+            Some("compute-bound: the loader keeps the queue full")
+        } else {
+            Some("data-bound: the trainer is waiting on the loader, try more --threads")
+        }
+    }
+}
+
+/// Throughput this far below the best report counts as a drop.
+pub const STALL_DROP: f64 = 0.25;
+/// Loading share, in percent, above which a drop is blamed on the loader.
+pub const STALL_DATA_WAIT: f64 = 30.0;
+/// Consecutive slow reports before suggesting anything.
+pub const STALL_REPORTS: usize = 3;
+
+/// Spots a sustained throughput drop during which the loader spends most of
+/// its time producing batches, i.e. the trainer is waiting on data. It only
+/// suggests changes; settings are never adjusted mid-run.
+#[derive(Clone, Debug, Default)]
+pub struct StallDetector {
+    best: f64,
+    slow_reports: usize,
+}
+
+impl StallDetector {
+    /// Feeds one report's positions/sec and loading share (percent). Returns
+    /// a suggestion once per stall, when it has lasted [`STALL_REPORTS`].
+    pub fn observe(&mut self, throughput: f64, data_wait: f64, threads: usize, batch_queue: usize) -> Option<String> {
+        self.best = self.best.max(throughput);
+        let slow = throughput < self.best * (1.0 - STALL_DROP);
+        if !slow || data_wait < STALL_DATA_WAIT {
+            self.slow_reports = 0;
+            return None;
+        }
+
+        self.slow_reports += 1;
+        (self.slow_reports == STALL_REPORTS).then(|| {
+            format!(
+                "throughput {:.0} pos/s is {:.0}% below the best {:.0} and data-wait is {:.0}%; try --threads {} or --batch-queue {}",
+                throughput,
+                100.0 * (1.0 - throughput / self.best),
+                self.best,
+                data_wait,
+                threads * 2,
+                batch_queue * 2
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn percentages_of_the_total_per_stage() {
+        let mut profile = Profile::default();
+        profile.add(LOADING, ms(300));
+        profile.add(QUEUE_FULL, ms(500));
+        profile.add(LOADING, ms(100));
+        profile.add(IDLE, ms(100));
+        assert_eq!(profile.total(), ms(1000));
+        let percentages = profile.percentages();
+        let expected = [(LOADING, 40.0), (QUEUE_FULL, 50.0), (IDLE, 10.0)];
+        assert_eq!(percentages.len(), expected.len());
+        for ((stage, pct), (expected_stage, expected_pct)) in percentages.into_iter().zip(expected) {
+            assert_eq!(stage, expected_stage);
+            assert!((pct - expected_pct).abs() < 1e-9, "{} {}", stage, pct);
+        }
+        assert_eq!(profile.hint(), Some("compute-bound: the loader keeps the queue full"));
+    }
+
+    #[test]
+    fn merge_adds_up_stages() {
+        let mut first = Profile::default();
+        first.add(LOADING, ms(100));
+        let mut second = Profile::default();
+        second.add(QUEUE_FULL, ms(100));
+        second.add(LOADING, ms(200));
+        first.merge(&second);
+        assert!((first.share(LOADING) - 75.0).abs() < 1e-9);
+        assert_eq!(first.line(), "loading 75.0% | queue full 25.0%");
+    }
+
+    #[test]
+    fn nothing_timed_is_all_zero() {
+        let mut profile = Profile::default();
+        profile.add(LOADING, Duration::ZERO);
+        assert_eq!(profile.percentages(), vec![(LOADING, 0.0)]);
+        assert_eq!(profile.hint(), None);
+    }
+
+    #[test]
+    fn suggests_once_after_a_sustained_data_bound_drop() {
+        let mut detector = StallDetector::default();
+        let reports = [(1000.0, 10.0), (1000.0, 10.0), (600.0, 40.0), (600.0, 40.0), (600.0, 40.0), (600.0, 40.0)];
+        let suggestions: Vec<Option<String>> =
+            reports.iter().map(|&(throughput, wait)| detector.observe(throughput, wait, 2, 32)).collect();
+        assert!(suggestions[..4].iter().all(Option::is_none));
+        assert_eq!(
+            suggestions[4].as_deref(),
+            Some(
+                "throughput 600 pos/s is 40% below the best 1000 and data-wait is 40%; try --threads 4 or --batch-queue 64"
+            )
+        );
+        assert_eq!(suggestions[5], None);
+    }
+
+    #[test]
+    fn compute_bound_or_short_drops_are_not_stalls() {
+        let mut detector = StallDetector::default();
+        assert_eq!(detector.observe(1000.0, 10.0, 2, 32), None);
+        // slow, but the loader keeps up
+        for _ in 0..STALL_REPORTS {
+            assert_eq!(detector.observe(500.0, 10.0, 2, 32), None);
+        }
+        // data-bound, but interrupted by a normal report
+        for throughput in [500.0, 500.0, 950.0, 500.0, 500.0] {
+            assert_eq!(detector.observe(throughput, 50.0, 2, 32), None);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! `--compare-quant-scales`: quantises the trained float net at several
+//! feature transformer scales (the engine's `NNUE_QA`) and compares each
+//! against the float eval on a sample, to pick a scale.
+//!
+//! A larger scale rounds less but leaves less headroom: weights can overflow
+//! i16 when quantised, and sums of them overflow the engine's i16
+//! accumulators. Positions that overflow are counted rather than scored, as
+//! their integer eval is garbage.
+
+use std::fmt;
+
+/// Largest scale offered: above it `qa * qa` times an i16 weight no longer
+/// fits the engine's i32 SCReLU products.
+pub const MAX_SCALE: i16 = 255;
+
+/// Quantisation error over a sample, in centipawns.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuantError {
+    /// Positions scored, without the ones that overflowed.
+    pub positions: usize,
+    pub mean_abs: f64,
+    pub max_abs: f64,
+    /// Positions whose accumulators left the i16 range.
+    pub overflows: usize,
+}
+
+impl QuantError {
+    /// Aggregates `(float cp, quantised cp)` pairs, with `None` for a position
+    /// whose quantised eval overflowed.
+    pub fn measure(pairs: impl IntoIterator<Item = (f32, Option<i32>)>) -> Self {
+        let mut error = Self::default();
+        let mut sum = 0.0;
+        for (float_cp, quantised_cp) in pairs {
+            let Some(quantised_cp) = quantised_cp else {
+                error.overflows += 1;
+                continue;
+            };
+            let abs = (f64::from(float_cp) - f64::from(quantised_cp)).abs();
+            sum += abs;
+            error.max_abs = error.max_abs.max(abs);
+            error.positions += 1;
+        }
+        error.mean_abs = sum / error.positions.max(1) as f64;
+        error
+    }
+}
+
+/// One scale's row: its error, or why the weights could not be quantised.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScaleResult {
+    pub qa: i16,
+    pub error: Result<QuantError, String>,
+}
+
+/// The scale with the lowest mean error among those without any overflow.
+pub fn recommend(results: &[ScaleResult]) -> Option<i16> {
+    results
+        .iter()
+        .filter_map(|result| Some((result.qa, result.error.as_ref().ok().filter(|e| e.overflows == 0)?.mean_abs)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(qa, _)| qa)
+}
+
+/// The error-vs-scale table and the recommendation.
+pub struct ScaleTable<'a>(pub &'a [ScaleResult]);
+
+impl fmt::Display for ScaleTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>5}  {:>12}  {:>11}  {:>9}", "qa", "mean |err|", "max |err|", "overflow")?;
+        for result in self.0 {
+            match &result.error {
+                Ok(e) => {
+                    writeln!(f, "{:>5}  {:>9.2} cp  {:>8.1} cp  {:>9}", result.qa, e.mean_abs, e.max_abs, e.overflows)?
+                }
+                Err(reason) => writeln!(f, "{:>5}  {}", result.qa, reason)?,
+            }
+        }
+        match recommend(self.0) {
+            Some(qa) => writeln!(f, "Recommended qa: {} (lowest mean error without overflow)", qa),
+            None => writeln!(f, "No scale quantises without overflow"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_the_error_of_the_scored_pairs() {
+        let error =
+            QuantError::measure([(100.0, Some(98)), (-50.5, Some(-50)), (0.0, None), (10.0, Some(13)), (20.0, None)]);
+        assert_eq!((error.positions, error.overflows), (3, 2));
+        assert!((error.mean_abs - 5.5 / 3.0).abs() < 1e-9, "{:?}", error);
+        assert_eq!(error.max_abs, 3.0);
+        assert_eq!(QuantError::measure([]), QuantError::default());
+    }
+
+    fn result(qa: i16, mean_abs: f64, overflows: usize) -> ScaleResult {
+        ScaleResult { qa, error: Ok(QuantError { positions: 10, mean_abs, max_abs: mean_abs * 3.0, overflows }) }
+    }
+
+    #[test]
+    fn recommends_the_lowest_error_without_overflow() {
+        let results = [
+            result(128, 2.5, 0),
+            result(181, 1.25, 0),
+            result(255, 0.5, 3),
+            ScaleResult { qa: 300, error: Err("l0w overflows i16".to_string()) },
+        ];
+        assert_eq!(recommend(&results), Some(181));
+        let table = ScaleTable(&results).to_string();
+        assert!(table.contains("\n  181       1.25 cp       3.8 cp          0\n"), "{}", table);
+        assert!(table.contains("\n  300  l0w overflows i16\n"), "{}", table);
+        assert!(table.ends_with("Recommended qa: 181 (lowest mean error without overflow)\n"), "{}", table);
+
+        let overflowing = [result(255, 0.5, 1)];
+        assert_eq!(recommend(&overflowing), None);
+        assert!(ScaleTable(&overflowing).to_string().ends_with("No scale quantises without overflow\n"));
+    }
+}
@@ -0,0 +1,226 @@
+//! `--recover-on-divergence`: restart from the last good checkpoint with a
+//! lower LR instead of training on after the loss blows up.
+//!
+//! bullet's training loop cannot be rewound from its callback, so a recovery
+//! re-runs the trainer binary with its own command line, rewritten to load the
+//! restore point, start after it and train at a reduced LR. The retry budget
+//! is passed on as a decremented `--recover-on-divergence`.
+
+use std::fmt;
+
+/// A sampled loss this many times the best one so far counts as a spike.
+pub const SPIKE_FACTOR: f32 = 2.0;
+/// Multiplier on all LRs for each recovery.
+pub const LR_FACTOR: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Divergence {
+    NonFinite(f32),
+    Spike { loss: f32, best: f32 },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonFinite(loss) => write!(f, "sampled loss is {}", loss),
+            Self::Spike { loss, best } => {
+                write!(f, "sampled loss {:.6} is over {}x the best {:.6}", loss, SPIKE_FACTOR, best)
+            }
+        }
+    }
+}
+
+/// Watches the per-superbatch sampled loss for NaN/Inf or a spike.
+#[derive(Clone, Debug, Default)]
+pub struct DivergenceGuard {
+    best: Option<f32>,
+}
+
+impl DivergenceGuard {
+    pub fn check(&mut self, loss: f32) -> Option<Divergence> {
+        if !loss.is_finite() {
+            return Some(Divergence::NonFinite(loss));
+        }
+        if let Some(best) = self.best {
+            if loss > SPIKE_FACTOR * best {
+                return Some(Divergence::Spike { loss, best });
+            }
+        }
+        self.best = Some(self.best.map_or(loss, |best| best.min(loss)));
+        None
+    }
+}
+
+/// Weights to go back to and the superbatch to continue with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RestorePoint {
+    pub weights: String,
+    pub start_superbatch: usize,
+}
+
+/// The LRs of an attempt, `(initial, final, l1)`.
+pub type Lrs = (f32, f32, Option<f32>);
+
+/// The LRs of the next attempt.
+pub fn reduced_lrs(initial: f32, final_lr: f32, l1: Option<f32>) -> Lrs {
+    (initial * LR_FACTOR, final_lr * LR_FACTOR, l1.map(|lr| lr * LR_FACTOR))
+}
+
+/// The LRs and command line of the attempt after a divergence, or `None`
+/// once `retries` is used up and the run should abort.
+pub fn next_attempt(
+    args: &[String],
+    restore: Option<&RestorePoint>,
+    lrs: Lrs,
+    retries: usize,
+) -> Option<(Lrs, Vec<String>)> {
+    let retries_left = retries.checked_sub(1)?;
+    let (initial, final_lr, l1) = lrs;
+    let lrs = reduced_lrs(initial, final_lr, l1);
+    Some((lrs, recovery_args(args, restore, lrs, retries_left)))
+}
+
+/// Flags every attempt sets itself, with whether each takes a value.
+const ATTEMPT_FLAGS: [(&str, bool); 5] =
+    [("--lr", true), ("--final-lr", true), ("--l1-lr", true), ("--recover-on-divergence", true), ("--force", false)];
+/// Flags that pick the starting weights, replaced when there is a restore point.
+const START_FLAGS: [(&str, bool); 4] =
+    [("--load", true), ("-l", true), ("--init-from-average", true), ("--start", true)];
+
+/// The command line of the next attempt: `args` (including the program name)
+/// with the reduced LRs and the remaining retries, and loading `restore` if
+/// there is one. Without a restore point the run starts over as it began.
+pub fn recovery_args(args: &[String], restore: Option<&RestorePoint>, lrs: Lrs, retries_left: usize) -> Vec<String> {
+    let start_flags = if restore.is_some() { &START_FLAGS[..] } else { &[] };
+    let mut out = without_flags(args, ATTEMPT_FLAGS.iter().chain(start_flags));
+
+    let (initial, final_lr, l1) = lrs;
+    if let Some(restore) = restore {
+        out.extend(["--load".to_string(), restore.weights.clone()]);
+        out.extend(["--start".to_string(), restore.start_superbatch.to_string()]);
+    }
+    out.extend(["--lr".to_string(), initial.to_string(), "--final-lr".to_string(), final_lr.to_string()]);
+    if let Some(l1) = l1 {
+        out.extend(["--l1-lr".to_string(), l1.to_string()]);
+    }
+    out.extend(["--recover-on-divergence".to_string(), retries_left.to_string(), "--force".to_string()]);
+    out
+}
+
+/// The command line that continues the same run from `restore`, as
+/// `--progressive-hl` does after widening: `args` loading `restore` and
+/// starting after it, with `--force` and every other flag unchanged.
+pub fn restart_args(args: &[String], restore: &RestorePoint) -> Vec<String> {
+    let mut out = without_flags(args, START_FLAGS.iter().chain(&[("--force", false)]));
+    out.extend(["--load".to_string(), restore.weights.clone()]);
+    out.extend(["--start".to_string(), restore.start_superbatch.to_string(), "--force".to_string()]);
+    out
+}
+
+/// `args` without any of `flags`, and the values of those that take one.
+fn without_flags<'a>(args: &[String], flags: impl Iterator<Item = &'a (&'a str, bool)> + Clone) -> Vec<String> {
+    let replaced = |arg: &str| flags.clone().find(|(flag, _)| *flag == arg).map(|&(_, takes_value)| takes_value);
+    let mut out = Vec::with_capacity(args.len() + 12);
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match replaced(arg) {
+            Some(takes_value) => {
+                if takes_value {
+                    rest.next();
+                }
+            }
+            None => out.push(arg.clone()),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("training").chain(args.iter().copied()).map(String::from).collect()
+    }
+
+    #[test]
+    fn guard_flags_non_finite_losses_and_spikes() {
+        let mut guard = DivergenceGuard::default();
+        assert_eq!(guard.check(0.2), None);
+        assert_eq!(guard.check(0.1), None);
+        assert_eq!(guard.check(0.19), None);
+        assert_eq!(guard.check(0.25), Some(Divergence::Spike { loss: 0.25, best: 0.1 }));
+        assert!(matches!(guard.check(f32::NAN), Some(Divergence::NonFinite(loss)) if loss.is_nan()));
+        assert_eq!(guard.check(f32::INFINITY), Some(Divergence::NonFinite(f32::INFINITY)));
+    }
+
+    #[test]
+    fn recovers_from_the_restore_point_until_the_retries_run_out() {
+        let mut command = args(&[
+            "-d",
+            "a.data",
+            "--load",
+            "start.fp32",
+            "--start",
+            "1",
+            "--lr",
+            "0.004",
+            "--final-lr",
+            "0.001",
+            "--recover-on-divergence",
+            "2",
+        ]);
+        let restore =
+            RestorePoint { weights: "out/net-6/optimiser_state/weights.bin".to_string(), start_superbatch: 7 };
+        let mut guard = DivergenceGuard::default();
+        guard.check(0.1);
+
+        for (lr, retries_left) in [(0.002, 1), (0.001, 0)] {
+            // detect
+            assert!(guard.check(0.5).is_some());
+            // reload at a reduced LR
+            let config = Config::from_args(&command).unwrap();
+            let lrs = (config.initial_lr, config.final_lr, config.l1_lr);
+            let retries = config.recover_on_divergence.unwrap();
+            let ((initial, _, _), next) = next_attempt(&command, Some(&restore), lrs, retries).unwrap();
+            assert_eq!(initial, lr);
+            // resume
+            let resumed = Config::from_args(&next).unwrap();
+            assert_eq!(resumed.load_weights.as_deref(), Some(restore.weights.as_str()));
+            assert_eq!(resumed.start_superbatch, 7);
+            assert_eq!((resumed.initial_lr, resumed.final_lr), (lr, lr / 4.0));
+            assert_eq!(resumed.recover_on_divergence, Some(retries_left));
+            assert!(resumed.force);
+            command = next;
+        }
+
+        // give up
+        let config = Config::from_args(&command).unwrap();
+        assert_eq!(next_attempt(&command, Some(&restore), (config.initial_lr, config.final_lr, None), 0), None);
+    }
+
+    #[test]
+    fn without_a_restore_point_the_run_starts_over() {
+        let command = args(&["-d", "a.data", "--start", "3", "--l1-lr", "0.01", "--recover-on-divergence", "1"]);
+        let (_, next) = next_attempt(&command, None, (0.001, 0.0001, Some(0.01)), 1).unwrap();
+        assert_eq!(
+            next,
+            args(&[
+                "-d",
+                "a.data",
+                "--start",
+                "3",
+                "--lr",
+                "0.0005",
+                "--final-lr",
+                "0.00005",
+                "--l1-lr",
+                "0.005",
+                "--recover-on-divergence",
+                "0",
+                "--force",
+            ])
+        );
+    }
+}
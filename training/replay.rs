@@ -0,0 +1,150 @@
+//! `--replay-log`: runs the per-superbatch decisions of the training callback
+//! (LR, saves, reports, `--stop-at-loss`, `--reduce-on-plateau`) against a recorded loss log instead
+//! of a trainer, to check schedule changes without a GPU.
+
+use std::{fmt, fs, io};
+
+use crate::{config::Config, lr_schedule::PlateauDetector, schedule, stopping::LossTarget};
+
+/// Mean loss per superbatch from bullet's `log.txt` (`superbatch,batch,loss`
+/// lines, as read by `log_viewer.html`), in superbatch order. Lines that do
+/// not parse, such as a header, are skipped.
+pub fn read_log(path: &str) -> io::Result<Vec<(usize, f32)>> {
+    let text = fs::read_to_string(path)?;
+    let mut per_superbatch: Vec<(usize, f64, usize)> = Vec::new();
+    for line in text.lines() {
+        let mut parts = line.split(',').map(str::trim);
+        let (Some(superbatch), Some(_batch), Some(loss)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let (Ok(superbatch), Ok(loss)) = (superbatch.parse::<usize>(), loss.parse::<f64>()) else { continue };
+        match per_superbatch.iter_mut().find(|(sb, ..)| *sb == superbatch) {
+            Some((_, sum, count)) => {
+                *sum += loss;
+                *count += 1;
+            }
+            None => per_superbatch.push((superbatch, loss, 1)),
+        }
+    }
+    per_superbatch.sort_by_key(|&(sb, ..)| sb);
+    Ok(per_superbatch.into_iter().map(|(sb, sum, count)| (sb, (sum / count as f64) as f32)).collect())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decision {
+    pub superbatch: usize,
+    pub loss: f32,
+    pub lr: f32,
+    pub save: bool,
+    pub report: bool,
+    pub stop: Option<String>,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "superbatch {:>5} | loss {:.6} | lr {:.6}", self.superbatch, self.loss, self.lr)?;
+        if self.save {
+            write!(f, " | save")?;
+        }
+        if self.report {
+            write!(f, " | report")?;
+        }
+        if let Some(reason) = &self.stop {
+            write!(f, " | STOP: {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decisions for each logged superbatch in `config`'s range, up to and
+/// including the one the loss target would stop at. `lr` is the schedule's
+/// rate at a superbatch. The recorded training loss stands in for the
+/// sampled loss the callback measures.
+pub fn replay(config: &Config, log: &[(usize, f32)], lr: impl Fn(usize) -> f32) -> Vec<Decision> {
+    let end = config.superbatches;
+    let mut target = config.stop_at_loss.map(LossTarget::new);
+    let mut plateau = config.reduce_on_plateau.map(PlateauDetector::new);
+    let mut pinned_lr = None;
+    let mut decisions = Vec::new();
+    for &(superbatch, loss) in log.iter().filter(|&&(sb, _)| (config.start_superbatch..=end).contains(&sb)) {
+        let stop = target.as_mut().and_then(|t| t.check(loss, superbatch, end));
+        let superbatch_lr = pinned_lr.unwrap_or_else(|| lr(superbatch));
+        // as in training, a reduction applies from the next superbatch on
+        if let Some(reduced) = plateau.as_mut().and_then(|p| p.update(loss, superbatch_lr)) {
+            pinned_lr = Some(reduced);
+        }
+        let interval_save = schedule::is_interval_save(superbatch, end, config.save_rate, config.final_only_save);
+        let stopping = stop.is_some();
+        decisions.push(Decision {
+            superbatch,
+            loss,
+            lr: superbatch_lr,
+            save: interval_save || stopping || superbatch == end,
+            report: schedule::should_report(superbatch, config.report_interval),
+            stop,
+        });
+        if stopping {
+            break;
+        }
+    }
+    decisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn config(args: &[&str]) -> Config {
+        let args: Vec<String> = std::iter::once("training").chain(args.iter().copied()).map(String::from).collect();
+        Config::from_args(&args).unwrap()
+    }
+
+    #[test]
+    fn reads_the_mean_loss_per_superbatch() {
+        let path = temp_path("replay-log.txt");
+        fs::write(&path, "superbatch,batch,loss\n2,1,0.4\n1,1,0.5\n1,2,0.7\nnot a line\n").unwrap();
+        let log = read_log(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].0, 1);
+        assert!((log[0].1 - 0.6).abs() < 1e-6 && (log[1].1 - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn replays_saves_reports_and_the_stop() {
+        let config = config(&["-s", "6", "--save-rate", "2", "--report-interval", "3", "--stop-at-loss", "0.2"]);
+        let log = [(1, 0.5), (2, 0.4), (3, 0.3), (4, 0.1), (5, 0.1), (6, 0.1)];
+        let decisions = replay(&config, &log, |superbatch| 0.1 / superbatch as f32);
+
+        // smoothed: 0.5, 0.45, 0.375, 0.2375, 0.16875 -> stop after 5
+        let summary: Vec<(usize, bool, bool, bool)> =
+            decisions.iter().map(|d| (d.superbatch, d.save, d.report, d.stop.is_some())).collect();
+        assert_eq!(
+            summary,
+            [
+                (1, false, false, false),
+                (2, true, false, false),
+                (3, false, true, false),
+                (4, true, false, false),
+                (5, true, false, true)
+            ]
+        );
+        assert!((decisions[1].lr - 0.05).abs() < 1e-9);
+        assert_eq!(
+            decisions[4].to_string(),
+            format!(
+                "superbatch     5 | loss 0.100000 | lr 0.020000 | save | STOP: {}",
+                decisions[4].stop.as_ref().unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn replay_skips_superbatches_outside_the_run() {
+        let config = config(&["--start", "2", "-s", "3"]);
+        let decisions = replay(&config, &[(1, 0.5), (2, 0.4), (3, 0.3), (4, 0.2)], |_| 0.001);
+        assert_eq!(decisions.iter().map(|d| d.superbatch).collect::<Vec<_>>(), [2, 3]);
+        assert!(decisions[1].save);
+    }
+}
@@ -0,0 +1,319 @@
+//! `--resume-safe`: checks that resuming from `--load` continues the previous
+//! run rather than quietly starting a different one. The run metadata also
+//! serves as the header `--validate-shapes-against-header` checks weights by.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::net::NetShape;
+
+/// Files bullet writes into `<checkpoint>/optimiser_state`.
+pub const OPTIMISER_FILES: [&str; 3] = ["weights.bin", "momentum.bin", "velocity.bin"];
+
+/// Run metadata that decides what the saved weights mean.
+pub const ARCHITECTURE_KEYS: [&str; 4] = ["hl_size", "perspective", "l1_lr_scale", "output_factoriser"];
+/// Run metadata the LR schedule is computed from.
+pub const SCHEDULE_KEYS: [&str; 3] = ["superbatches", "initial_lr", "final_lr"];
+/// Run metadata bullet's data position is computed from: the first batch of
+/// superbatch `n` is `(n - 1) * batches_per_superbatch` into the file.
+pub const DATA_KEYS: [&str; 5] =
+    ["dataset", "data_format", "dataset_manifest_sha256", "batch_size", "batches_per_superbatch"];
+
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<String, String>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn add(&mut self, name: &'static str, result: Result<String, String>) {
+        self.checks.push(Check { name, result });
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.result {
+                Ok(detail) => writeln!(f, "PASS  {:<16} {}", check.name, detail)?,
+                Err(detail) => writeln!(f, "FAIL  {:<16} {}", check.name, detail)?,
+            }
+        }
+        write!(f, "Resume check: {}", if self.passed() { "PASS" } else { "FAIL" })
+    }
+}
+
+/// The checkpoint directory a `--load` path belongs to, which may be the
+/// directory itself or a file in it or in its `optimiser_state`.
+pub fn checkpoint_dir(load_path: &str) -> PathBuf {
+    let path = Path::new(load_path);
+    let mut dir = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new(".")) };
+    if dir.file_name().is_some_and(|name| name == "optimiser_state") {
+        dir = dir.parent().unwrap_or(Path::new("."));
+    }
+    dir.to_path_buf()
+}
+
+pub fn format_metadata(entries: &[(&str, String)]) -> String {
+    entries.iter().map(|(key, value)| format!("{}={}\n", key, value)).collect()
+}
+
+pub fn parse_metadata(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Compares `keys` between the previous run's metadata and this run's. A key
+/// missing from both is fine; missing from one side is a mismatch.
+pub fn compare(
+    previous: &HashMap<String, String>,
+    current: &[(&str, String)],
+    keys: &[&str],
+) -> Result<String, String> {
+    let mut mismatches = Vec::new();
+    for &key in keys {
+        let now = current.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str());
+        let before = previous.get(key).map(String::as_str);
+        if before != now {
+            mismatches.push(format!("{} {} -> {}", key, before.unwrap_or("(unset)"), now.unwrap_or("(unset)")));
+        }
+    }
+    if mismatches.is_empty() { Ok(format!("{} unchanged", keys.join(", "))) } else { Err(mismatches.join("; ")) }
+}
+
+/// Reads `<parent of checkpoint>/<run_name>.meta`, written by the previous run.
+pub fn previous_metadata(checkpoint: &Path, run_name: &str) -> Result<HashMap<String, String>, String> {
+    let path = checkpoint.parent().unwrap_or(Path::new(".")).join(format!("{}.meta", run_name));
+    fs::read_to_string(&path)
+        .map(|text| parse_metadata(&text))
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+}
+
+pub fn optimiser_state(checkpoint: &Path) -> Result<String, String> {
+    let dir = checkpoint.join("optimiser_state");
+    let missing: Vec<&str> = OPTIMISER_FILES.into_iter().filter(|file| !dir.join(file).is_file()).collect();
+    if missing.is_empty() {
+        Ok(format!("{} has {}", dir.display(), OPTIMISER_FILES.join(", ")))
+    } else {
+        Err(format!("{} is missing {}", dir.display(), missing.join(", ")))
+    }
+}
+
+/// Checkpoints are saved as `<net_id>-<superbatch>`, so the run must pick up
+/// at the superbatch after the one in the directory name.
+pub fn schedule_anchor(checkpoint: &Path, net_id: &str, start_superbatch: usize) -> Result<String, String> {
+    let name = checkpoint.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let saved = name
+        .strip_prefix(net_id)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|n| n.parse::<usize>().ok())
+        .ok_or_else(|| format!("checkpoint {:?} is not named {}-<superbatch>", name, net_id))?;
+    if start_superbatch == saved + 1 {
+        Ok(format!("saved after superbatch {}, --start {}", saved, start_superbatch))
+    } else {
+        Err(format!("saved after superbatch {} but --start is {} (expected {})", saved, start_superbatch, saved + 1))
+    }
+}
+
+/// The architecture a run's metadata declares. Bucket counts were not always
+/// recorded; older metadata is taken to use the compiled ones.
+pub fn declared_shape(metadata: &HashMap<String, String>, compiled: &NetShape) -> Result<NetShape, String> {
+    let get = |key: &str| metadata.get(key).map(String::as_str);
+    let number = |key: &str, default: Option<usize>| match get(key) {
+        Some(raw) => raw.parse().map_err(|_| format!("metadata {} = {:?} is not a number", key, raw)),
+        None => default.ok_or_else(|| format!("metadata has no {}", key)),
+    };
+    Ok(NetShape {
+        hl_size: number("hl_size", None)?,
+        input_buckets: number("input_buckets", Some(compiled.input_buckets))?,
+        output_buckets: number("output_buckets", Some(compiled.output_buckets))?,
+        single_perspective: match get("perspective") {
+            Some("single") => true,
+            Some("dual") | None => false,
+            Some(other) => return Err(format!("metadata perspective = {:?} is neither single nor dual", other)),
+        },
+        output_factoriser: get("output_factoriser") == Some("true"),
+    })
+}
+
+/// Every way the declared architecture differs from the configured one.
+pub fn compare_shapes(declared: &NetShape, configured: &NetShape) -> Result<(), String> {
+    let perspective = |shape: &NetShape| if shape.single_perspective { "single" } else { "dual" };
+    let fields = [
+        ("hl_size", declared.hl_size.to_string(), configured.hl_size.to_string()),
+        ("input_buckets", declared.input_buckets.to_string(), configured.input_buckets.to_string()),
+        ("output_buckets", declared.output_buckets.to_string(), configured.output_buckets.to_string()),
+        ("perspective", perspective(declared).to_string(), perspective(configured).to_string()),
+        ("output_factoriser", declared.output_factoriser.to_string(), configured.output_factoriser.to_string()),
+    ];
+    let mismatches: Vec<String> = fields
+        .iter()
+        .filter(|(_, header, config)| header != config)
+        .map(|(key, header, config)| format!("{} is {} in the metadata but {} here", key, header, config))
+        .collect();
+    if mismatches.is_empty() { Ok(()) } else { Err(mismatches.join("; ")) }
+}
+
+/// Checks tensor lengths against the declared architecture, naming the width
+/// the payload actually has when it disagrees on `hl_size`.
+pub fn check_payload<'a>(
+    tensors: impl IntoIterator<Item = (&'a str, usize)>,
+    declared: &NetShape,
+) -> Result<(), String> {
+    let tensors: Vec<(&str, usize)> = tensors.into_iter().collect();
+    if let Some(&(_, width)) = tensors.iter().find(|(id, _)| *id == "l0b") {
+        if width != declared.hl_size {
+            return Err(format!(
+                "the metadata declares hl_size {} but the tensors are {} wide (l0b has {} values)",
+                declared.hl_size, width, width
+            ));
+        }
+    }
+    for (id, len) in tensors {
+        match declared.tensor_len(id) {
+            Some(expected) if expected == len => {}
+            Some(expected) => {
+                return Err(format!("{} has {} values where the declared architecture needs {}", id, len, expected));
+            }
+            None => return Err(format!("{} is not a tensor of the declared architecture", id)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn metadata(hl_size: usize) -> Vec<(&'static str, String)> {
+        vec![
+            ("hl_size", hl_size.to_string()),
+            ("perspective", "dual".to_string()),
+            ("l1_lr_scale", "1".to_string()),
+            ("superbatches", "640".to_string()),
+        ]
+    }
+
+    #[test]
+    fn metadata_round_trips() {
+        let parsed = parse_metadata(&format_metadata(&metadata(1024)));
+        assert_eq!(parsed.len(), 4);
+        assert_eq!(parsed["hl_size"], "1024");
+        assert_eq!(
+            compare(&parsed, &metadata(1024), &ARCHITECTURE_KEYS),
+            Ok(format!("{} unchanged", ARCHITECTURE_KEYS.join(", ")))
+        );
+    }
+
+    #[test]
+    fn a_different_architecture_fails_the_report() {
+        let previous = parse_metadata(&format_metadata(&metadata(1024)));
+        let mut report = Report::default();
+        report.add("architecture", compare(&previous, &metadata(768), &ARCHITECTURE_KEYS));
+        report.add("schedule", compare(&previous, &metadata(768), &SCHEDULE_KEYS));
+        assert!(!report.passed());
+        let text = report.to_string();
+        assert!(text.contains("FAIL  architecture     hl_size 1024 -> 768"), "{}", text);
+        assert!(text.ends_with("Resume check: FAIL"), "{}", text);
+    }
+
+    #[test]
+    fn schedule_must_continue_after_the_saved_superbatch() {
+        let checkpoint = Path::new("checkpoints/net/net-40");
+        assert!(schedule_anchor(checkpoint, "net", 41).is_ok());
+        assert_eq!(
+            schedule_anchor(checkpoint, "net", 1),
+            Err("saved after superbatch 40 but --start is 1 (expected 41)".to_string())
+        );
+        assert!(schedule_anchor(Path::new("checkpoints/net/other-40"), "net", 41).is_err());
+    }
+
+    #[test]
+    fn optimiser_state_needs_every_file() {
+        let checkpoint = temp_path("resume-optimiser");
+        let state = checkpoint.join("optimiser_state");
+        fs::create_dir_all(&state).unwrap();
+        fs::write(state.join("weights.bin"), b"").unwrap();
+        let missing = optimiser_state(&checkpoint).unwrap_err();
+        assert!(missing.ends_with("is missing momentum.bin, velocity.bin"), "{}", missing);
+        for file in OPTIMISER_FILES {
+            fs::write(state.join(file), b"").unwrap();
+        }
+        assert!(optimiser_state(&checkpoint).is_ok());
+        fs::remove_dir_all(&checkpoint).unwrap();
+    }
+
+    #[test]
+    fn load_paths_resolve_to_their_checkpoint() {
+        assert_eq!(checkpoint_dir("out/net-40/optimiser_state/weights.bin"), Path::new("out/net-40"));
+        assert_eq!(checkpoint_dir("out/net-40/quantised.bin"), Path::new("out/net-40"));
+    }
+
+    fn shape(hl_size: usize) -> NetShape {
+        NetShape { hl_size, input_buckets: 10, output_buckets: 8, single_perspective: false, output_factoriser: false }
+    }
+
+    /// `(id, len)` of every tensor of `shape`.
+    fn payload(shape: &NetShape) -> Vec<(&'static str, usize)> {
+        shape.tensors().into_iter().map(|id| (id, shape.tensor_len(id).unwrap())).collect()
+    }
+
+    #[test]
+    fn declared_shape_reads_the_metadata() {
+        let mut metadata = parse_metadata(&format_metadata(&metadata(768)));
+        assert_eq!(declared_shape(&metadata, &shape(1024)), Ok(shape(768)));
+        metadata.insert("perspective".to_string(), "both".to_string());
+        assert_eq!(
+            declared_shape(&metadata, &shape(1024)),
+            Err("metadata perspective = \"both\" is neither single nor dual".to_string())
+        );
+        metadata.remove("hl_size");
+        assert_eq!(declared_shape(&metadata, &shape(1024)), Err("metadata has no hl_size".to_string()));
+    }
+
+    #[test]
+    fn every_shape_mismatch_is_named() {
+        assert_eq!(compare_shapes(&shape(768), &shape(768)), Ok(()));
+        let configured = NetShape { single_perspective: true, ..shape(512) };
+        assert_eq!(
+            compare_shapes(&shape(768), &configured),
+            Err("hl_size is 768 in the metadata but 512 here; perspective is dual in the metadata but single here"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn a_payload_narrower_than_its_header_is_rejected() {
+        assert_eq!(check_payload(payload(&shape(768)), &shape(768)), Ok(()));
+        assert_eq!(
+            check_payload(payload(&shape(512)), &shape(768)),
+            Err("the metadata declares hl_size 768 but the tensors are 512 wide (l0b has 512 values)".to_string())
+        );
+        // the right width, but one tensor of another bucket count
+        let mut tensors = payload(&shape(768));
+        tensors[3].1 = 2 * 768 * 4;
+        assert_eq!(
+            check_payload(tensors, &shape(768)),
+            Err("l1w has 6144 values where the declared architecture needs 12288".to_string())
+        );
+        let factorised = payload(&NetShape { output_factoriser: true, ..shape(768) });
+        assert_eq!(
+            check_payload(factorised, &shape(768)),
+            Err("l1f is not a tensor of the declared architecture".to_string())
+        );
+    }
+}
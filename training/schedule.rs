@@ -0,0 +1,175 @@
+//! Superbatch sizing and per-superbatch decisions, kept free of bullet types
+//! so `--replay-log` can run them without a trainer.
+
+pub const DEFAULT_BATCH_SIZE: usize = 16_384;
+pub const DEFAULT_BATCHES_PER_SUPERBATCH: usize = 6104;
+
+/// Why the superbatch sizing flags cannot be resolved together.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SizingConflict {
+    /// `--batches-per-superbatch` fixes a size that one epoch would replace.
+    BatchesWithEpoch,
+    /// `--batches-per-superbatch` and `--positions-per-superbatch` disagree.
+    BatchesWithPositions { batches: usize, batch_size: usize, positions: usize },
+}
+
+impl std::fmt::Display for SizingConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BatchesWithEpoch => write!(
+                f,
+                "--batches-per-superbatch fixes the superbatch size, --superbatch-equals-epoch sizes it to the data; pick one"
+            ),
+            Self::BatchesWithPositions { batches, batch_size, positions } => write!(
+                f,
+                "--batches-per-superbatch {} x --batch-size {} = {} positions, but --positions-per-superbatch {} rounds to {} batches; drop one of them",
+                batches,
+                batch_size,
+                batches * batch_size,
+                positions,
+                batches_for_positions(*positions, *batch_size)
+            ),
+        }
+    }
+}
+
+/// Batches per superbatch from the sizing flags. An explicit batch count wins
+/// over the default, and agrees with `positions` when both are given if the
+/// positions round to it. With `--superbatch-equals-epoch` the result is a
+/// placeholder until the data is counted.
+pub fn resolve_batches_per_superbatch(
+    batch_size: usize,
+    batches: Option<usize>,
+    positions: Option<usize>,
+    superbatch_equals_epoch: bool,
+) -> Result<usize, SizingConflict> {
+    match (batches, positions) {
+        (Some(_), _) if superbatch_equals_epoch => Err(SizingConflict::BatchesWithEpoch),
+        (Some(batches), Some(positions)) if batches_for_positions(positions, batch_size) != batches => {
+            Err(SizingConflict::BatchesWithPositions { batches, batch_size, positions })
+        }
+        (Some(batches), _) => Ok(batches),
+        (None, Some(positions)) => Ok(batches_for_positions(positions, batch_size)),
+        (None, None) => Ok(DEFAULT_BATCHES_PER_SUPERBATCH),
+    }
+}
+
+/// Number of batches of `batch_size` closest to `positions`, never less than one.
+pub fn batches_for_positions(positions: usize, batch_size: usize) -> usize {
+    ((positions + batch_size / 2) / batch_size).max(1)
+}
+
+/// How many times each position is seen over `superbatches` superbatches.
+pub fn repetition_factor(positions: u64, positions_per_superbatch: usize, superbatches: usize) -> f64 {
+    (positions_per_superbatch as f64 * superbatches as f64) / positions.max(1) as f64
+}
+
+/// `(batches, leftover positions)` for one superbatch covering the data once.
+/// Leftover positions roll over into the next superbatch; at least one batch.
+pub fn batches_per_epoch(positions: u64, batch_size: usize) -> (usize, u64) {
+    let batch_size = batch_size as u64;
+    ((positions / batch_size).max(1) as usize, positions % batch_size)
+}
+
+/// Whether the callback prints its report after `superbatch`. Reporting is
+/// independent of `save_rate`; an interval of 0 disables it.
+pub fn should_report(superbatch: usize, interval: usize) -> bool {
+    interval > 0 && superbatch.is_multiple_of(interval)
+}
+
+/// Whether `superbatch` gets an interval checkpoint. The final superbatch is
+/// not one: it is always saved, by bullet.
+pub fn is_interval_save(superbatch: usize, end_superbatch: usize, save_rate: usize, final_only: bool) -> bool {
+    !final_only && superbatch < end_superbatch && superbatch.is_multiple_of(save_rate)
+}
+
+/// Whether the run ends after `superbatch`, at `end` or at an early stop; the
+/// `--holdout-file` is scored then and only then.
+pub fn is_last_superbatch(superbatch: usize, end: usize, stopping: bool) -> bool {
+    superbatch == end || stopping
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_round_to_the_nearest_batch_count() {
+        assert_eq!(batches_for_positions(100_000_000, 16_384), 6104);
+        assert_eq!(batches_for_positions(16_384 * 3 + 8_191, 16_384), 3);
+        assert_eq!(batches_for_positions(16_384 * 3 + 8_192, 16_384), 4);
+        assert_eq!(batches_for_positions(1, 16_384), 1);
+    }
+
+    #[test]
+    fn positions_set_the_batches_unless_given() {
+        assert_eq!(resolve_batches_per_superbatch(1000, None, Some(50_000), false), Ok(50));
+        assert_eq!(resolve_batches_per_superbatch(1000, None, None, false), Ok(DEFAULT_BATCHES_PER_SUPERBATCH));
+    }
+
+    #[test]
+    fn a_batch_count_must_agree_with_the_positions() {
+        assert_eq!(resolve_batches_per_superbatch(1000, Some(50), Some(50_400), false), Ok(50));
+        let conflict = resolve_batches_per_superbatch(1000, Some(50), Some(80_000), false).unwrap_err();
+        assert_eq!(conflict, SizingConflict::BatchesWithPositions { batches: 50, batch_size: 1000, positions: 80_000 });
+        assert_eq!(
+            conflict.to_string(),
+            "--batches-per-superbatch 50 x --batch-size 1000 = 50000 positions, but --positions-per-superbatch 80000 rounds to 80 batches; drop one of them"
+        );
+    }
+
+    #[test]
+    fn reports_every_interval_independent_of_saves() {
+        let reported: Vec<usize> = (1..=10).filter(|&superbatch| should_report(superbatch, 3)).collect();
+        assert_eq!(reported, vec![3, 6, 9]);
+        assert!((1..=10).all(|superbatch| should_report(superbatch, 1)));
+    }
+
+    #[test]
+    fn interval_zero_never_reports() {
+        assert!((0..=10).all(|superbatch| !should_report(superbatch, 0)));
+    }
+
+    #[test]
+    fn final_only_skips_every_interval_save() {
+        let saved: Vec<usize> = (1..=10).filter(|&superbatch| is_interval_save(superbatch, 10, 3, false)).collect();
+        assert_eq!(saved, vec![3, 6, 9]);
+        assert!((1..=10).all(|superbatch| !is_interval_save(superbatch, 10, 1, true)));
+    }
+
+    #[test]
+    fn the_last_superbatch_is_not_an_interval_save() {
+        assert!(!is_interval_save(10, 10, 5, false));
+        assert!(is_interval_save(5, 10, 5, false));
+    }
+
+    #[test]
+    fn the_holdout_superbatch_comes_once_at_the_end() {
+        let scored: Vec<usize> = (1..=10).filter(|&superbatch| is_last_superbatch(superbatch, 10, false)).collect();
+        assert_eq!(scored, vec![10]);
+        // an early stop at 4 is the end, and the run goes no further
+        let scored: Vec<usize> =
+            (1..=4).filter(|&superbatch| is_last_superbatch(superbatch, 10, superbatch == 4)).collect();
+        assert_eq!(scored, vec![4]);
+    }
+
+    #[test]
+    fn one_epoch_is_the_whole_batches_in_the_data() {
+        assert_eq!(batches_per_epoch(100_000_000, 16_384), (6103, 8448));
+        assert_eq!(batches_per_epoch(16_384 * 4, 16_384), (4, 0));
+        assert_eq!(batches_per_epoch(1000, 16_384), (1, 1000));
+    }
+
+    #[test]
+    fn epoch_sizing_conflicts_with_a_batch_count() {
+        assert_eq!(resolve_batches_per_superbatch(16_384, Some(10), None, true), Err(SizingConflict::BatchesWithEpoch));
+        assert!(resolve_batches_per_superbatch(16_384, None, None, true).is_ok());
+    }
+
+    #[test]
+    fn repetition_is_positions_consumed_over_positions_held() {
+        assert!((repetition_factor(1_000_000, 100_000, 20) - 2.0).abs() < 1e-9);
+        assert!((repetition_factor(1_000_000, 100_000, 5) - 0.5).abs() < 1e-9);
+        assert!((repetition_factor(0, 10, 1) - 10.0).abs() < 1e-9);
+    }
+}
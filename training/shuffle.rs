@@ -0,0 +1,312 @@
+//! `training shuffle`: a uniform shuffle of a data file's records, so a
+//! sequential loader does not see a game's positions one after another.
+//! Replaces `bullet-utils interleave` and `bullet-utils shuffle`.
+//!
+//! A file that fits in `--mem-mb` is shuffled in memory. A larger one is cut
+//! into runs that do, each shuffled and written next to the output, which
+//! are then merged by drawing every record from a run with probability
+//! proportional to the records the run has left: that keeps every order of
+//! the whole file equally likely.
+
+use std::{
+    fs,
+    io::{BufReader, BufWriter, Read, Write},
+};
+
+use crate::{
+    commands::ToolError,
+    config::{ConfigError, value},
+    data::{self, DataError, RECORD_SIZE},
+    loader,
+};
+
+pub const USAGE: &str = "\
+Shuffle the records of bulletformat data files into one file
+
+Usage: training shuffle --input <PATH> --output <PATH> [OPTIONS]
+
+Options:
+  -i, --input <PATH>       Data file; repeat to shuffle several into one
+  -o, --output <PATH>      Shuffled data file to write
+      --mem-mb <MB>        Records held in memory at once; larger inputs are shuffled in runs
+                           of this size written next to the output (default: 1024)
+      --seed <N>           Seed of the shuffle (default: 0)
+  -h, --help               Show this help";
+
+const DEFAULT_MEM_MB: usize = 1024;
+
+type Record = [u8; RECORD_SIZE];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShuffleOptions {
+    pub inputs: Vec<String>,
+    pub output: String,
+    pub mem_mb: usize,
+    pub seed: u64,
+}
+
+impl ShuffleOptions {
+    /// Parses `shuffle`'s arguments, the program name at index 0.
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut inputs = Vec::new();
+        let mut output: Option<String> = None;
+        let mut mem_mb = DEFAULT_MEM_MB;
+        let mut seed = 0;
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--input" | "-i" => inputs.push(value(args, &mut i)?),
+                "--output" | "-o" => output = Some(value(args, &mut i)?),
+                "--mem-mb" => {
+                    mem_mb = value(args, &mut i)?;
+                    if mem_mb == 0 {
+                        return Err(ConfigError::InvalidValue { flag: "--mem-mb".to_string(), value: "0".to_string() });
+                    }
+                }
+                "--seed" => seed = value(args, &mut i)?,
+                "--help" | "-h" => return Err(ConfigError::HelpRequested),
+                flag => return Err(ConfigError::UnknownFlag(flag.to_string())),
+            }
+            i += 1;
+        }
+
+        if inputs.is_empty() {
+            return Err(ConfigError::MissingValue("--input".to_string()));
+        }
+        let output = output.ok_or_else(|| ConfigError::MissingValue("--output".to_string()))?;
+        if inputs.contains(&output) {
+            return Err(ConfigError::InvalidValue {
+                flag: "--output".to_string(),
+                value: format!("{} is an input", output),
+            });
+        }
+        Ok(Self { inputs, output, mem_mb, seed })
+    }
+}
+
+/// splitmix64 over a counter.
+struct Rng {
+    seed: u64,
+    counter: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self { seed, counter: 0 }
+    }
+
+    /// Uniform in `0..bound`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.counter += 1;
+        ((u128::from(loader::mix(self.seed, self.counter)) * u128::from(bound)) >> 64) as u64
+    }
+}
+
+/// Fisher-Yates.
+fn shuffle_in_place(records: &mut [Record], rng: &mut Rng) {
+    for i in (1..records.len()).rev() {
+        records.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+}
+
+/// Reads the inputs back to back, `count` records per call.
+struct Records {
+    files: Vec<(String, BufReader<fs::File>, u64)>,
+}
+
+impl Records {
+    fn open(paths: &[String]) -> Result<(Self, u64), ToolError> {
+        let mut files = Vec::with_capacity(paths.len());
+        let mut total = 0;
+        for path in paths {
+            let records = data::count_records(path, RECORD_SIZE)?;
+            let file = fs::File::open(path).map_err(ToolError::io(path))?;
+            files.push((path.clone(), BufReader::with_capacity(1 << 20, file), records));
+            total += records;
+        }
+        files.reverse();
+        Ok((Self { files }, total))
+    }
+
+    fn read(&mut self, count: usize, out: &mut Vec<Record>) -> Result<(), ToolError> {
+        out.clear();
+        while out.len() < count {
+            let Some((path, reader, left)) = self.files.last_mut() else { break };
+            if *left == 0 {
+                self.files.pop();
+                continue;
+            }
+            let mut record = [0; RECORD_SIZE];
+            reader.read_exact(&mut record).map_err(|error| DataError::Io { path: path.clone(), error })?;
+            out.push(record);
+            *left -= 1;
+        }
+        Ok(())
+    }
+}
+
+fn write_records(path: &str, records: &[Record]) -> Result<(), ToolError> {
+    let mut writer = BufWriter::with_capacity(1 << 20, fs::File::create(path).map_err(ToolError::io(path))?);
+    writer.write_all(records.as_flattened()).map_err(ToolError::io(path))?;
+    writer.flush().map_err(ToolError::io(path))
+}
+
+/// Shuffles the inputs into `options.output` and returns the record count.
+pub fn run(options: &ShuffleOptions) -> Result<u64, ToolError> {
+    let (mut input, total) = Records::open(&options.inputs)?;
+    let run_records = (options.mem_mb * 1024 * 1024 / RECORD_SIZE).max(1);
+    let mut rng = Rng::new(options.seed);
+    let partial = format!("{}.partial", options.output);
+    let mut buffer = Vec::with_capacity(run_records.min(total as usize));
+
+    if total <= run_records as u64 {
+        input.read(run_records, &mut buffer)?;
+        shuffle_in_place(&mut buffer, &mut rng);
+        write_records(&partial, &buffer)?;
+        return fs::rename(&partial, &options.output).map(|()| total).map_err(ToolError::io(&options.output));
+    }
+
+    let mut runs = Vec::new();
+    loop {
+        input.read(run_records, &mut buffer)?;
+        if buffer.is_empty() {
+            break;
+        }
+        shuffle_in_place(&mut buffer, &mut rng);
+        let path = format!("{}.run{}", options.output, runs.len());
+        write_records(&path, &buffer)?;
+        runs.push((path, buffer.len() as u64));
+    }
+    drop(buffer);
+    let merged = merge(&runs, &partial, total, &mut rng);
+    for (path, _) in &runs {
+        let _ = fs::remove_file(path);
+    }
+    merged?;
+    fs::rename(&partial, &options.output).map(|()| total).map_err(ToolError::io(&options.output))
+}
+
+/// Merges shuffled runs `(path, records)` into `output`, drawing each next
+/// record from a run chosen in proportion to what it has left.
+fn merge(runs: &[(String, u64)], output: &str, total: u64, rng: &mut Rng) -> Result<(), ToolError> {
+    let mut readers = Vec::with_capacity(runs.len());
+    let mut left = Vec::with_capacity(runs.len());
+    for (path, records) in runs {
+        // small buffers: with many runs they would add up to another run
+        readers.push(BufReader::with_capacity(1 << 16, fs::File::open(path).map_err(ToolError::io(path))?));
+        left.push(*records);
+    }
+    let mut writer = BufWriter::with_capacity(1 << 20, fs::File::create(output).map_err(ToolError::io(output))?);
+    let mut record = [0; RECORD_SIZE];
+    for remaining in (1..=total).rev() {
+        let mut draw = rng.below(remaining);
+        let run = left
+            .iter()
+            .position(|&n| {
+                if draw < n {
+                    return true;
+                }
+                draw -= n;
+                false
+            })
+            .expect("draws stay below the records left");
+        readers[run].read_exact(&mut record).map_err(ToolError::io(&runs[run].0))?;
+        left[run] -= 1;
+        writer.write_all(&record).map_err(ToolError::io(output))?;
+    }
+    writer.flush().map_err(ToolError::io(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::Range, path::Path};
+
+    use super::*;
+    use crate::test_util::{args, temp_path};
+
+    /// One record per index in `range`, each holding its index, as a data file.
+    fn numbered(name: &str, range: Range<u64>) -> String {
+        let path = temp_path(name);
+        let records: Vec<Record> = range
+            .map(|i| {
+                let mut record = [0; RECORD_SIZE];
+                record[..8].copy_from_slice(&i.to_le_bytes());
+                record
+            })
+            .collect();
+        fs::write(&path, records.as_flattened()).unwrap();
+        path.display().to_string()
+    }
+
+    /// The record indices of a data file, in file order.
+    fn indices(path: &str) -> Vec<u64> {
+        fs::read(path).unwrap().chunks(RECORD_SIZE).map(|r| u64::from_le_bytes(r[..8].try_into().unwrap())).collect()
+    }
+
+    fn shuffled(inputs: &[String], name: &str, mem_mb: usize, seed: u64) -> Vec<u64> {
+        let output = temp_path(name).display().to_string();
+        let options = ShuffleOptions { inputs: inputs.to_vec(), output: output.clone(), mem_mb, seed };
+        let records = run(&options).unwrap();
+        let order = indices(&output);
+        assert_eq!(records, order.len() as u64);
+        fs::remove_file(&output).unwrap();
+        order
+    }
+
+    #[test]
+    fn shuffle_needs_inputs_other_than_its_output() {
+        let options = ShuffleOptions::from_args(&args(&["-i", "a", "-i", "b", "-o", "c", "--seed", "7"])).unwrap();
+        assert_eq!(
+            options,
+            ShuffleOptions {
+                inputs: vec!["a".to_string(), "b".to_string()],
+                output: "c".to_string(),
+                mem_mb: DEFAULT_MEM_MB,
+                seed: 7
+            }
+        );
+        assert_eq!(
+            ShuffleOptions::from_args(&args(&["-o", "c"])),
+            Err(ConfigError::MissingValue("--input".to_string()))
+        );
+        assert!(matches!(
+            ShuffleOptions::from_args(&args(&["-i", "a", "-o", "a"])),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            ShuffleOptions::from_args(&args(&["-i", "a", "-o", "c", "--mem-mb", "0"])),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn every_record_comes_out_once_in_a_seeded_order() {
+        let inputs = [numbered("shuffle-a", 0..600), numbered("shuffle-b", 600..1000)];
+        let order = shuffled(&inputs, "shuffle-out", 1, 3);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..1000).collect::<Vec<_>>());
+        assert_ne!(order, sorted);
+        assert_eq!(shuffled(&inputs, "shuffle-out", 1, 3), order);
+        assert_ne!(shuffled(&inputs, "shuffle-out", 1, 4), order);
+        inputs.iter().for_each(|path| fs::remove_file(path).unwrap());
+    }
+
+    #[test]
+    fn a_file_larger_than_memory_is_merged_from_runs() {
+        // 1 MB holds 32768 records, so this is shuffled in three runs
+        let total = 80_000;
+        let input = [numbered("shuffle-runs", 0..total)];
+        let order = shuffled(&input, "shuffle-runs-out", 1, 5);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..total).collect::<Vec<_>>());
+        // drawn in proportion to what each run has left, the first run is not read out first
+        let first_run = order[..32768].iter().filter(|&&i| i < 32768).count();
+        assert!((12_000..15_000).contains(&first_run), "{}", first_run);
+        let output = temp_path("shuffle-runs-out").display().to_string();
+        assert!((0..3).all(|run| !Path::new(&format!("{}.run{}", output, run)).exists()));
+        fs::remove_file(&input[0]).unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+//! Conditions that end a run before its last superbatch.
+
+/// Weight of the newest measurement in the smoothed loss.
+const SMOOTHING: f32 = 0.5;
+
+/// `--stop-at-loss`: stop once the smoothed loss is at or below `target`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LossTarget {
+    pub target: f32,
+    smoothed: Option<f32>,
+}
+
+impl LossTarget {
+    pub fn new(target: f32) -> Self {
+        Self { target, smoothed: None }
+    }
+
+    pub fn smoothed(&self) -> Option<f32> {
+        self.smoothed
+    }
+
+    /// Folds in a new measurement; returns whether the target is reached.
+    pub fn update(&mut self, loss: f32) -> bool {
+        let smoothed = self.smoothed.map_or(loss, |s| SMOOTHING * loss + (1.0 - SMOOTHING) * s);
+        self.smoothed = Some(smoothed);
+        smoothed <= self.target
+    }
+
+    /// Folds in the loss after `superbatch` and returns why the run should
+    /// stop there. Reaching the target on the last superbatch is no reason.
+    pub fn check(&mut self, loss: f32, superbatch: usize, end_superbatch: usize) -> Option<String> {
+        let reached = self.update(loss);
+        (reached && superbatch < end_superbatch).then(|| {
+            format!(
+                "smoothed loss {:.6} reached --stop-at-loss {} at superbatch {}",
+                self.smoothed.unwrap_or_default(),
+                self.target,
+                superbatch
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_once_the_smoothed_loss_reaches_the_target() {
+        let mut target = LossTarget::new(0.3);
+        let stops: Vec<bool> = [0.5, 0.2, 0.3, 0.25].into_iter().map(|loss| target.update(loss)).collect();
+        // smoothed: 0.5, 0.35, 0.325, 0.2875
+        assert_eq!(stops, [false, false, false, true]);
+        assert!((target.smoothed().unwrap() - 0.2875).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reaching_the_target_on_the_last_superbatch_is_no_reason() {
+        let mut target = LossTarget::new(0.3);
+        assert_eq!(target.check(0.1, 10, 10), None);
+        let reason = target.check(0.1, 4, 10).unwrap();
+        assert_eq!(reason, "smoothed loss 0.100000 reached --stop-at-loss 0.3 at superbatch 4");
+    }
+}
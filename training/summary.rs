@@ -0,0 +1,88 @@
+//! Machine-readable per-run summary for `--summary-json`.
+
+use std::{fs, io, path::Path, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub net_id: String,
+    /// `false` for the snapshots written while the run is still going, so a
+    /// run that was cut short still leaves what was known at its last report.
+    pub completed: bool,
+    pub start_superbatch: usize,
+    pub last_superbatch: usize,
+    pub positions_seen: u64,
+    pub wall_time_secs: f64,
+    pub positions_per_sec: f64,
+    pub final_net: Option<String>,
+    pub last_val_loss: Option<f32>,
+    pub best_val_loss: Option<f32>,
+    /// `--holdout-file` loss of the final net, measured once.
+    #[serde(default)]
+    pub holdout_loss: Option<f32>,
+    pub config: Config,
+}
+
+impl RunSummary {
+    /// Fills in positions, wall time and throughput up to `last_superbatch`.
+    pub fn update_timing(&mut self, start: Instant, positions_per_superbatch: usize) {
+        let superbatches = (self.last_superbatch + 1).saturating_sub(self.start_superbatch);
+        self.positions_seen = (superbatches * positions_per_superbatch) as u64;
+        self.wall_time_secs = start.elapsed().as_secs_f64();
+        self.positions_per_sec = self.positions_seen as f64 / self.wall_time_secs.max(1e-9);
+    }
+
+    /// Writes through a temp file so readers never see a half-written summary.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn summary() -> RunSummary {
+        let config =
+            Config::from_args(&["training".to_string(), "--name".to_string(), "round-trip".to_string()]).unwrap();
+        RunSummary {
+            net_id: config.net_id.clone(),
+            completed: true,
+            start_superbatch: 1,
+            last_superbatch: 40,
+            positions_seen: 40 * 16_384 * 6104,
+            wall_time_secs: 3600.5,
+            positions_per_sec: 1.1e6,
+            final_net: Some("checkpoints/round-trip/round-trip-40/quantised.bin".to_string()),
+            last_val_loss: Some(0.0031),
+            best_val_loss: Some(0.0029),
+            holdout_loss: None,
+            config,
+        }
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let summary = summary();
+        let json = serde_json::to_string(&summary).unwrap();
+        assert_eq!(serde_json::from_str::<RunSummary>(&json).unwrap(), summary);
+    }
+
+    #[test]
+    fn written_summary_reads_back() {
+        let path = temp_path("summary.json");
+        summary().write(&path).unwrap();
+        let read: RunSummary = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(read, summary());
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! `--eval-symmetry-check`: compares each sampled position's eval with the
+//! eval of the same position with the other side to move.
+//!
+//! Records are stored from the side to move's point of view, so flipping the
+//! colours and swapping the side to move gives back the same record. The
+//! informative swap keeps the pieces and only changes who moves: the stm and
+//! ntm accumulators trade places, and a correctly wired net scores the result
+//! close to the negated eval. What remains is the tempo bonus, which shows up
+//! as a mean shift rather than as scattered large values.
+
+use std::fmt;
+
+use bullet::game::formats::bulletformat::ChessBoard;
+
+use crate::data;
+
+/// Positions sampled from the data file.
+pub const POSITIONS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asymmetry {
+    pub positions: usize,
+    /// Records that do not parse back from their FEN, e.g. without both kings.
+    pub skipped: usize,
+    /// Mean of `|eval(pos) + eval(swapped)|` in centipawns.
+    pub mean_abs: f64,
+    /// Mean of `eval(pos) + eval(swapped)`, i.e. the net's tempo bias.
+    pub mean_signed: f64,
+    /// Largest `|eval(pos) + eval(swapped)|` and the FEN it came from.
+    pub max_abs: Option<(i32, String)>,
+}
+
+/// `board` with the other side to move.
+pub fn side_swapped(board: &ChessBoard) -> Result<ChessBoard, String> {
+    data::parse_fen(&data::to_fen(board).replacen(" w ", " b ", 1))
+}
+
+impl Asymmetry {
+    /// Scores every board and its [`side_swapped`] copy with `eval`, which
+    /// returns side-to-move centipawns.
+    pub fn measure(boards: &[ChessBoard], eval: impl Fn(&ChessBoard) -> i32) -> Self {
+        let (mut sum_abs, mut sum_signed) = (0i64, 0i64);
+        let mut max_abs: Option<(i32, &ChessBoard)> = None;
+        let mut skipped = 0;
+        for board in boards {
+            let Ok(swapped) = side_swapped(board) else {
+                skipped += 1;
+                continue;
+            };
+            let asymmetry = eval(board) + eval(&swapped);
+            sum_abs += i64::from(asymmetry.abs());
+            sum_signed += i64::from(asymmetry);
+            if max_abs.is_none_or(|(max, _)| asymmetry.abs() > max) {
+                max_abs = Some((asymmetry.abs(), board));
+            }
+        }
+        let positions = boards.len() - skipped;
+        let n = positions.max(1) as f64;
+        Self {
+            positions,
+            skipped,
+            mean_abs: sum_abs as f64 / n,
+            mean_signed: sum_signed as f64 / n,
+            max_abs: max_abs.map(|(max, board)| (max, data::to_fen(board))),
+        }
+    }
+}
+
+impl fmt::Display for Asymmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Symmetry check on {} positions: eval(pos) + eval(pos, other side to move)", self.positions)?;
+        if self.skipped > 0 {
+            writeln!(f, "  skipped {} records that are not valid positions", self.skipped)?;
+        }
+        writeln!(f, "  mean |asymmetry|  {:.1} cp", self.mean_abs)?;
+        writeln!(f, "  mean asymmetry    {:+.1} cp (tempo bias)", self.mean_signed)?;
+        match &self.max_abs {
+            Some((max, fen)) => writeln!(f, "  max |asymmetry|   {} cp ({})", max, fen),
+            None => writeln!(f, "  max |asymmetry|   -"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inference::QuantisedNet,
+        net::{NUM_INPUT_BUCKETS, NetShape, QA, QB},
+        test_util::{STARTPOS, board},
+    };
+
+    /// One hidden neuron summing the material of the perspective's own side,
+    /// added for the side to move and `ntm_weight` times for the other side.
+    fn material_net(ntm_weight: i16) -> QuantisedNet {
+        let shape = NetShape {
+            hl_size: 1,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: 1,
+            single_perspective: false,
+            output_factoriser: false,
+        };
+        let material = [10, 30, 32, 50, 90, 0];
+        let mut values: Vec<i16> = (0..768 * NUM_INPUT_BUCKETS)
+            .map(|feature| {
+                let (colour, piece) = ((feature % 768) / 384, (feature % 384) / 64);
+                if colour == 0 { material[piece] } else { 0 }
+            })
+            .collect();
+        values.extend([0, QB, ntm_weight, 0]);
+        QuantisedNet::from_values(shape, &values, QA).unwrap()
+    }
+
+    fn boards() -> Vec<ChessBoard> {
+        ["4k3/8/8/3q4/8/8/PPP5/4K3 w - - 0 1", "k7/8/8/8/8/8/8/K6R w - - 0 1", STARTPOS]
+            .into_iter()
+            .map(|fen| board(fen, 0, "0.5"))
+            .collect()
+    }
+
+    #[test]
+    fn a_symmetric_net_has_no_asymmetry() {
+        let net = material_net(-QB);
+        let asymmetry = Asymmetry::measure(&boards(), |board| net.eval(board, 400));
+        assert_eq!((asymmetry.positions, asymmetry.skipped), (3, 0));
+        assert_eq!((asymmetry.mean_abs, asymmetry.mean_signed), (0.0, 0.0));
+        assert_eq!(asymmetry.max_abs.map(|(max, _)| max), Some(0));
+        // the net is not trivially zero: the queen side is ahead
+        assert!(net.eval(&boards()[0], 400) < 0);
+    }
+
+    #[test]
+    fn a_net_that_ignores_the_other_side_is_asymmetric() {
+        let net = material_net(0);
+        let asymmetry = Asymmetry::measure(&boards(), |board| net.eval(board, 400));
+        let (max, fen) = asymmetry.max_abs.clone().unwrap();
+        assert!(max > 0 && asymmetry.mean_abs > 0.0, "{:?}", asymmetry);
+        assert_eq!(fen, data::to_fen(&boards()[2]));
+        assert_eq!(asymmetry.mean_signed, asymmetry.mean_abs);
+    }
+
+    #[test]
+    fn the_swap_keeps_the_pieces_and_changes_the_side_to_move() {
+        let original = &boards()[0];
+        let swapped = side_swapped(original).unwrap();
+        assert_eq!(data::pieces(&swapped).count(), data::pieces(original).count());
+        assert_ne!(data::to_fen(&swapped), data::to_fen(original));
+        assert_eq!(data::to_fen(&side_swapped(&swapped).unwrap()), data::to_fen(original));
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the unit tests.
+
+use std::{env, fs, path::PathBuf, process};
+
+use bullet::game::formats::bulletformat::{BulletFormat, ChessBoard};
+
+pub const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// The record of `fen` with `score` and `result` from white's point of view,
+/// as a `<fen> | <eval> | <wdl>` line of the datagen output.
+pub fn board(fen: &str, score: i16, result: &str) -> ChessBoard {
+    format!("{} | {} | {}", fen, score, result).parse().unwrap()
+}
+
+/// A path in the temp dir unique to this process and `name`.
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("sleepmind-test-{}-{}", process::id(), name))
+}
+
+/// `boards` as a bulletformat data file at [`temp_path`]`(name)`.
+pub fn write_records(name: &str, boards: &[ChessBoard]) -> PathBuf {
+    let path = temp_path(name);
+    fs::write(&path, ChessBoard::as_bytes_slice(boards)).unwrap();
+    path
+}
+
+/// `args` as a subcommand's arguments, after the program name.
+pub fn args(args: &[&str]) -> Vec<String> {
+    std::iter::once("training").chain(args.iter().copied()).map(String::from).collect()
+}
@@ -0,0 +1,28 @@
+//! `--also-save-fp32` puts a float archive next to every quantised net.
+
+mod common;
+
+use std::path::Path;
+
+use common::Scratch;
+use training::archive;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn every_save_has_both_nets() {
+    let scratch = Scratch::new("also-save-fp32");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+    let config =
+        common::config(&scratch, &data, &["--load", &start, "-s", "2", "--save-rate", "1", "--also-save-fp32"]);
+
+    training::run(&config).unwrap();
+
+    for superbatch in [1, 2] {
+        let quantised = common::quantised_net(&config, superbatch);
+        assert!(Path::new(&quantised).is_file(), "{}", quantised);
+        let fp32 = format!("{}/{}-{}/weights.fp32", config.output_directory, config.net_id, superbatch);
+        let tensors = archive::read(&fp32).unwrap();
+        assert_eq!(tensors.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), ["l0w", "l0f", "l0b", "l1w", "l1b"]);
+    }
+}
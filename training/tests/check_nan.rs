@@ -0,0 +1,29 @@
+//! `--check-nan` refuses to train on a loaded net holding a NaN.
+
+mod common;
+
+use common::Scratch;
+use training::{TrainError, archive};
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn rejects_a_loaded_nan() {
+    let scratch = Scratch::new("check-nan");
+    let data = common::dataset(&scratch);
+    let shape = common::shape(false);
+    let start = common::starting_net(&scratch, &shape, 0);
+    let mut tensors = archive::read(&start).unwrap();
+    let (id, values) = &mut tensors[1];
+    values[3] = f32::NAN;
+    let poisoned = id.clone();
+    archive::write(&start, &tensors).unwrap();
+
+    let config = common::config(&scratch, &data, &["--load", &start, "--check-nan", "-s", "1"]);
+    match training::run(&config) {
+        Err(TrainError::NonFinite { tensor, index, value }) => {
+            assert_eq!((tensor, index), (poisoned.as_str(), 3));
+            assert!(value.is_nan());
+        }
+        other => panic!("expected a NaN to be rejected, got {:?}", other),
+    }
+}
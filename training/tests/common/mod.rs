@@ -0,0 +1,129 @@
+//! Shared setup of the tests that train: a tiny dataset converted from the
+//! text fixture, a fixed starting net so no run depends on bullet's unseeded
+//! init, and configs that write into a scratch directory.
+//!
+//! Training needs bullet's CPU backend and a few minutes, so those tests are
+//! `#[ignore]`d: `cargo test --no-default-features -- --ignored`.
+
+#![allow(dead_code)]
+
+use std::{fs, path::PathBuf, process};
+
+use training::{
+    Config, archive,
+    convert::{self, ConvertOptions},
+    data,
+    inference::QuantisedNet,
+    loader::{self, TargetTransform},
+    net::{HL_SIZE, NUM_INPUT_BUCKETS, NUM_OUTPUT_BUCKETS, NetShape},
+};
+
+/// 512 `<fen> | <eval> | <wdl>` lines: two superbatches of [`BASE_ARGS`].
+pub const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tiny.txt");
+
+pub const NET_ID: &str = "tiny";
+
+/// Flags every training test runs with, on top of `--data` and `--load`.
+pub const BASE_ARGS: [&str; 9] =
+    ["--name", NET_ID, "--cpu", "--deterministic", "--quiet", "--batch-size", "64", "--batches-per-superbatch", "4"];
+
+/// A directory under the system temp dir, removed again on drop.
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("sleepmind-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    pub fn path(&self, file: &str) -> String {
+        self.dir.join(file).display().to_string()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn shape(single_perspective: bool) -> NetShape {
+    NetShape {
+        hl_size: HL_SIZE,
+        input_buckets: NUM_INPUT_BUCKETS,
+        output_buckets: NUM_OUTPUT_BUCKETS,
+        single_perspective,
+        output_factoriser: false,
+    }
+}
+
+/// The fixture as bulletformat records in `scratch`.
+pub fn dataset(scratch: &Scratch) -> String {
+    let output = scratch.path("tiny.data");
+    let options =
+        ConvertOptions { inputs: vec![FIXTURE.to_string()], output: output.clone(), min_ply: 0, skip_bad_lines: false };
+    convert::run(&options).unwrap();
+    output
+}
+
+/// A float archive of `shape` with small values hashed from `seed`, loaded
+/// instead of bullet's random init.
+pub fn starting_net(scratch: &Scratch, shape: &NetShape, seed: u64) -> String {
+    let path = scratch.path("start.fp32");
+    let tensors: Vec<(String, Vec<f32>)> = shape
+        .tensors()
+        .into_iter()
+        .map(|id| {
+            let len = shape.tensor_len(id).unwrap();
+            let salt = loader::mix(seed, id.bytes().fold(0, |h, b| h * 31 + u64::from(b)));
+            let values = (0..len as u64)
+                .map(|i| (loader::mix(salt, i) >> 40) as f32 / (1u64 << 24) as f32 * 0.2 - 0.1)
+                .collect();
+            (id.to_string(), values)
+        })
+        .collect();
+    archive::write(&path, &tensors).unwrap();
+    path
+}
+
+/// The config of `training <BASE_ARGS> --data <data> <extra>`, writing into `scratch`.
+pub fn config(scratch: &Scratch, data: &str, extra: &[&str]) -> Config {
+    let args: Vec<String> = ["training", "--data", data]
+        .into_iter()
+        .chain(BASE_ARGS)
+        .chain(extra.iter().copied())
+        .map(String::from)
+        .collect();
+    let mut config = Config::from_args(&args).unwrap();
+    config.output_directory = scratch.path("out");
+    config
+}
+
+/// Where the run of `config` writes the quantised net of `superbatch`.
+pub fn quantised_net(config: &Config, superbatch: usize) -> String {
+    format!("{}/{}-{}/quantised.bin", config.output_directory, config.net_id, superbatch)
+}
+
+/// Bullet's weights of `superbatch`, which `--load` continues from.
+pub fn optimiser_weights(config: &Config, superbatch: usize) -> String {
+    format!("{}/{}-{}/optimiser_state/weights.bin", config.output_directory, config.net_id, superbatch)
+}
+
+/// Mean squared error of the integer eval of the net at `path` against the
+/// training target, over every record of `data`.
+pub fn quantised_loss(path: &str, shape: NetShape, data: &str, config: &Config) -> f64 {
+    let net = QuantisedNet::read(path, shape).unwrap();
+    let transform = TargetTransform { eval_scale: 400.0, wdl_by_phase: None, wdl: config.wdl, wdl_smooth: 0.0 };
+    let (mut total, mut count) = (0.0, 0);
+    data::for_each_record(data, |board| {
+        let eval = net.eval(board, 400) as f32 / 400.0;
+        total += f64::from((data::sigmoid(eval) - transform.target(board)).powi(2));
+        count += 1;
+    })
+    .unwrap();
+    total / f64::from(count)
+}
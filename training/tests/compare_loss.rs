@@ -0,0 +1,51 @@
+//! `--compare-loss` reports the loaded net's loss without training it.
+
+mod common;
+
+use std::{fs, process::Command};
+
+use common::Scratch;
+
+/// Runs `--load` of a starting net with `flags`, checks that no step was
+/// taken, and returns the reported loss line.
+fn compare(name: &str, flags: &[&str]) -> String {
+    let scratch = Scratch::new(name);
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+    let before = fs::read(&start).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_training"))
+        .current_dir(&scratch.dir)
+        .args(["--data", &data])
+        .args(common::BASE_ARGS)
+        .args(["--load", &start])
+        .args(flags)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.lines().find(|line| line.starts_with("Loss of the loaded net on ")).unwrap().to_string();
+    let loss: f64 = line.rsplit(": ").next().unwrap().parse().unwrap();
+    assert!(loss.is_finite() && loss > 0.0, "{}", line);
+
+    // no step was taken: nothing was saved and the loaded net is untouched
+    assert!(!scratch.dir.join("checkpoints").exists());
+    assert_eq!(fs::read(&start).unwrap(), before);
+    line
+}
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn prints_a_loss_without_optimizer_steps() {
+    let line = compare("compare-loss", &["--compare-loss", "128"]);
+    assert!(line.starts_with("Loss of the loaded net on 128 positions"), "{}", line);
+}
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn count_defaults_and_positions_is_an_alias() {
+    compare("compare-loss-default", &["--compare-loss"]);
+    let line = compare("compare-loss-alias", &["--compare-loss-positions", "128"]);
+    assert!(line.starts_with("Loss of the loaded net on 128 positions"), "{}", line);
+}
@@ -0,0 +1,26 @@
+//! `--cpu` trains on the CPU backend of a CPU build and says so.
+
+mod common;
+
+use std::process::Command;
+
+use common::Scratch;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn cpu_run_prints_its_device() {
+    let scratch = Scratch::new("cpu-backend");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_training"))
+        .current_dir(&scratch.dir)
+        .args(["--data", &data])
+        .args(common::BASE_ARGS.iter().filter(|&&arg| arg != "--quiet"))
+        .args(["--load", &start, "-s", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().any(|line| line == "Device:        cpu"), "{}", stdout);
+}
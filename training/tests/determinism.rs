@@ -0,0 +1,25 @@
+//! `--deterministic` runs from the same net and data train bit-identical weights.
+
+mod common;
+
+use std::fs;
+
+use common::Scratch;
+
+/// Bullet's weights after the first superbatch of a fresh run in `scratch`.
+fn first_superbatch(scratch: &Scratch) -> Vec<u8> {
+    let data = common::dataset(scratch);
+    let start = common::starting_net(scratch, &common::shape(false), 0);
+    let config = common::config(scratch, &data, &["--load", &start, "-s", "1"]);
+    training::run(&config).unwrap();
+    fs::read(common::optimiser_weights(&config, 1)).unwrap()
+}
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn two_runs_give_identical_weights() {
+    let first = first_superbatch(&Scratch::new("determinism-a"));
+    let second = first_superbatch(&Scratch::new("determinism-b"));
+    assert!(!first.is_empty());
+    assert!(first == second, "the first superbatch trained different weights");
+}
@@ -0,0 +1,25 @@
+//! `--engine-scale` is what the run metadata tells the engine, whatever the
+//! training eval scale.
+
+mod common;
+
+use std::fs;
+
+use common::Scratch;
+use training::resume;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn metadata_records_the_overridden_scale() {
+    let scratch = Scratch::new("engine-scale");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+
+    let config = common::config(&scratch, &data, &["--load", &start, "-s", "1", "--engine-scale", "250"]);
+    training::run(&config).unwrap();
+
+    let text = fs::read_to_string(format!("{}/{}.meta", config.output_directory, config.run_name)).unwrap();
+    let metadata = resume::parse_metadata(&text);
+    assert_eq!(metadata["engine_scale"], "250");
+    assert_eq!(metadata["eval_scale"], "400");
+}
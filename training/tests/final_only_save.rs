@@ -0,0 +1,21 @@
+//! `--final-only-save` writes the final checkpoint and nothing before it.
+
+mod common;
+
+use common::Scratch;
+use training::checkpoint;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn saves_exactly_one_checkpoint() {
+    let scratch = Scratch::new("final-only-save");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+
+    let config =
+        common::config(&scratch, &data, &["--load", &start, "-s", "2", "--save-rate", "1", "--final-only-save"]);
+    training::run(&config).unwrap();
+
+    let saved = checkpoint::list_checkpoints(&config.output_directory, &config.net_id).unwrap();
+    assert_eq!(saved.iter().map(|info| info.superbatch).collect::<Vec<_>>(), vec![2]);
+}
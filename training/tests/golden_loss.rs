@@ -0,0 +1,52 @@
+//! The whole pipeline on the tiny fixture (build, train, save, reload, train
+//! on) against a recorded loss, so a change to what training computes shows
+//! up as a failing test rather than a weaker net weeks later.
+
+mod common;
+
+use std::fs;
+
+use common::Scratch;
+
+const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/golden_loss.txt");
+/// Loose enough for summation order on another CPU, tight against any real change.
+const TOLERANCE: f64 = 1e-4;
+
+fn recorded() -> Option<f64> {
+    let text = fs::read_to_string(GOLDEN).unwrap();
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.parse().unwrap())
+}
+
+fn record(loss: f64) {
+    let text = fs::read_to_string(GOLDEN).unwrap();
+    let comments: String =
+        text.lines().filter(|line| line.starts_with('#')).map(|line| format!("{}\n", line)).collect();
+    fs::write(GOLDEN, format!("{}{:.8}\n", comments, loss)).unwrap();
+}
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn loss_after_reload_matches_golden() {
+    let scratch = Scratch::new("golden-loss");
+    let data = common::dataset(&scratch);
+    let shape = common::shape(false);
+    let start = common::starting_net(&scratch, &shape, 0);
+
+    let first = common::config(&scratch, &data, &["--load", &start, "-s", "2", "--save-rate", "1"]);
+    training::run(&first).unwrap();
+
+    let saved = common::optimiser_weights(&first, 2);
+    let resumed = common::config(&scratch, &data, &["--load", &saved, "--start", "3", "-s", "3"]);
+    training::run(&resumed).unwrap();
+
+    let loss = common::quantised_loss(&common::quantised_net(&resumed, 3), shape, &data, &resumed);
+    if std::env::var_os("SLEEPMIND_BLESS").is_some() {
+        record(loss);
+        return;
+    }
+    let golden = recorded().unwrap_or_else(|| panic!("no golden loss in {}; record it with SLEEPMIND_BLESS=1", GOLDEN));
+    assert!((loss - golden).abs() <= TOLERANCE, "loss {:.8} after reload, golden {:.8}", loss, golden);
+}
@@ -0,0 +1,25 @@
+//! `--hidden-dropout` adds a training-only op, so the graph it builds gets a
+//! run of its own; the saved net must not depend on it.
+
+mod common;
+
+use std::fs;
+
+use common::Scratch;
+use training::{inference::QuantisedNet, resume};
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn dropout_graph_builds_and_saves_a_plain_net() {
+    let scratch = Scratch::new("hidden-dropout");
+    let data = common::dataset(&scratch);
+    let shape = common::shape(false);
+    let start = common::starting_net(&scratch, &shape, 2);
+    let config = common::config(&scratch, &data, &["--hidden-dropout", "0.1", "--load", &start, "-s", "1"]);
+
+    training::run(&config).unwrap();
+
+    assert_eq!(QuantisedNet::read(common::quantised_net(&config, 1), shape).unwrap().shape, shape);
+    let text = fs::read_to_string(format!("{}/{}.meta", config.output_directory, config.run_name)).unwrap();
+    assert_eq!(resume::parse_metadata(&text)["hidden_dropout"], "0.1");
+}
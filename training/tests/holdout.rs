@@ -0,0 +1,36 @@
+//! `--holdout-file` is scored once, on the net the run ends with.
+
+mod common;
+
+use std::{fs, process::Command};
+
+use common::Scratch;
+use training::summary::RunSummary;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn the_holdout_is_scored_once_after_the_last_superbatch() {
+    let scratch = Scratch::new("holdout");
+    let data = common::dataset(&scratch);
+    let holdout = scratch.path("holdout.data");
+    fs::copy(&data, &holdout).unwrap();
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+    let summary = scratch.path("summary.json");
+
+    // not --quiet, the holdout line is a normal-level message
+    let output = Command::new(env!("CARGO_BIN_EXE_training"))
+        .current_dir(&scratch.dir)
+        .args(["--data", &data])
+        .args(common::BASE_ARGS.iter().filter(|&&arg| arg != "--quiet"))
+        .args(["--load", &start, "-s", "2", "--holdout-file", &holdout, "--summary-json", &summary])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().filter(|line| line.starts_with("[holdout]")).collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    let summary: RunSummary = serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    let loss = summary.holdout_loss.unwrap();
+    assert!(loss.is_finite() && lines[0].contains(&format!("{:.6}", loss)), "{}", lines[0]);
+}
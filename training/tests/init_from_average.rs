@@ -0,0 +1,46 @@
+//! `--init-from-average` starts training from the mean of the given nets.
+
+mod common;
+
+use common::Scratch;
+use training::{Config, archive};
+
+/// The float weights `config` saved after `superbatch` with `--also-save-fp32`.
+fn fp32(config: &Config, superbatch: usize) -> Vec<(String, Vec<f32>)> {
+    archive::read(format!("{}/{}-{}/weights.fp32", config.output_directory, config.net_id, superbatch)).unwrap()
+}
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn averaged_weights_are_the_starting_state() {
+    let scratch = Scratch::new("init-from-average");
+    let data = common::dataset(&scratch);
+    let shape = common::shape(false);
+
+    // two nets in bullet's format, trained from different starts
+    let mut parents = Vec::new();
+    for seed in [0, 1] {
+        let start = common::starting_net(&scratch, &shape, seed);
+        let mut config = common::config(&scratch, &data, &["--load", &start, "-s", "1", "--also-save-fp32"]);
+        config.output_directory = scratch.path(&format!("parent-{}", seed));
+        training::run(&config).unwrap();
+        parents.push(config);
+    }
+
+    // at a learning rate of 0 the first superbatch leaves the start as it is
+    let (a, b) = (common::optimiser_weights(&parents[0], 1), common::optimiser_weights(&parents[1], 1));
+    let averaged = format!("{},{}", a, b);
+    let config = common::config(
+        &scratch,
+        &data,
+        &["--init-from-average", &averaged, "-s", "1", "--lr", "0", "--also-save-fp32"],
+    );
+    training::run(&config).unwrap();
+
+    let (first, second) = (fp32(&parents[0], 1), fp32(&parents[1], 1));
+    for (((id, got), (_, x)), (_, y)) in fp32(&config, 1).iter().zip(&first).zip(&second) {
+        for ((got, x), y) in got.iter().zip(x).zip(y) {
+            assert!((got - (x + y) / 2.0).abs() < 1e-6, "{}: {} is not the mean of {} and {}", id, got, x, y);
+        }
+    }
+}
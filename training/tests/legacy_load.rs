@@ -0,0 +1,33 @@
+//! `--load` upgrades nets saved before the current layout: a float archive
+//! without `l0f`, and a quantised engine net.
+
+mod common;
+
+use std::path::Path;
+
+use common::Scratch;
+use training::archive;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn continues_from_an_old_float_archive_and_a_quantised_net() {
+    let scratch = Scratch::new("legacy-load");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+
+    // an archive from before the input factoriser
+    let old = scratch.path("old.fp32");
+    let tensors: Vec<_> = archive::read(&start).unwrap().into_iter().filter(|(id, _)| id != "l0f").collect();
+    archive::write(&old, &tensors).unwrap();
+    let mut config = common::config(&scratch, &data, &["--load", &old, "-s", "1"]);
+    config.output_directory = scratch.path("from-archive");
+    training::run(&config).unwrap();
+    let quantised = common::quantised_net(&config, 1);
+    assert!(Path::new(&quantised).is_file(), "{}", quantised);
+
+    // and one more superbatch from the engine net that run saved
+    let mut config = common::config(&scratch, &data, &["--load", &quantised, "--start", "2", "-s", "2"]);
+    config.output_directory = scratch.path("from-quantised");
+    training::run(&config).unwrap();
+    assert!(Path::new(&common::quantised_net(&config, 2)).is_file());
+}
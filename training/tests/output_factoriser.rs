@@ -0,0 +1,44 @@
+//! `--output-factoriser` trains a shared `l1f` column and merges it into
+//! every bucket of `l1w` on save, as `net::quantise` does.
+
+mod common;
+
+use std::fs;
+
+use common::Scratch;
+use training::{
+    archive,
+    inference::QuantisedNet,
+    net::{self, FloatNet, NetShape},
+    resume,
+};
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn builds_and_saves_the_merged_factoriser() {
+    let scratch = Scratch::new("output-factoriser");
+    let data = common::dataset(&scratch);
+    let shape = NetShape { output_factoriser: true, ..common::shape(false) };
+    let start = common::starting_net(&scratch, &shape, 0);
+    let config =
+        common::config(&scratch, &data, &["--load", &start, "-s", "1", "--output-factoriser", "--also-save-fp32"]);
+
+    training::run(&config).unwrap();
+
+    let fp32 = format!("{}/{}-1/weights.fp32", config.output_directory, config.net_id);
+    let tensors = archive::read(&fp32).unwrap();
+    assert!(tensors.iter().any(|(id, _)| id == net::OUTPUT_FACTORISER));
+    let float = FloatNet::from_fn(&shape, |id| tensors.iter().find(|(t, _)| t == id).map(|(_, v)| v.clone())).unwrap();
+
+    let saved = QuantisedNet::read(common::quantised_net(&config, 1), shape).unwrap();
+    let (l0w, l0b, l1w, l1b) = saved.values();
+    let saved: Vec<i16> = [l0w, l0b, l1w, l1b].concat();
+    let expected = net::quantise(&float, &shape, 1.0).unwrap();
+    assert_eq!(saved.len(), expected.len());
+    for (i, (got, want)) in saved.iter().zip(&expected).enumerate() {
+        assert!((got - want).abs() <= 1, "value {}: saved {}, merged {}", i, got, want);
+    }
+
+    let text = fs::read_to_string(format!("{}/{}.meta", config.output_directory, config.run_name)).unwrap();
+    assert_eq!(resume::parse_metadata(&text)["output_factoriser"], "true");
+}
@@ -0,0 +1,50 @@
+//! Each run writes into `checkpoints/<run name>/` unless `--flat-output`.
+
+mod common;
+
+use std::{path::Path, process::Command};
+
+use common::Scratch;
+use training::Config;
+
+/// The trainer's arguments for one superbatch from `start`.
+fn args(data: &str, start: &str, extra: &[&str]) -> Vec<String> {
+    let fixed = ["--data", data].into_iter().chain(common::BASE_ARGS).chain(["--load", start, "-s", "1"]);
+    fixed.chain(extra.iter().copied()).map(String::from).collect()
+}
+
+fn train(scratch: &Scratch, data: &str, start: &str, extra: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_training"))
+        .current_dir(&scratch.dir)
+        .args(args(data, start, extra))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn run_files_go_under_the_run_name() {
+    let scratch = Scratch::new("output-subdirectory");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+
+    train(&scratch, &data, &start, &["--run-name", "sweep-1"]);
+    let run = scratch.dir.join("checkpoints/sweep-1");
+    for file in ["tiny-1/quantised.bin", "sweep-1.meta", "sweep-1.toml"] {
+        assert!(run.join(file).is_file(), "{} is missing", run.join(file).display());
+    }
+    assert!(!scratch.dir.join("checkpoints/tiny-1").exists());
+
+    // the run's settings load back as the run they came from
+    let program = || "training".to_string();
+    let from_file = [program(), "--config".to_string(), run.join("sweep-1.toml").display().to_string()];
+    let from_flags: Vec<String> =
+        [program()].into_iter().chain(args(&data, &start, &["--run-name", "sweep-1"])).collect();
+    assert_eq!(Config::from_args(&from_file).unwrap(), Config::from_args(&from_flags).unwrap());
+
+    train(&scratch, &data, &start, &["--run-name", "sweep-2", "--flat-output"]);
+    let flat = scratch.dir.join("checkpoints");
+    assert!(flat.join("tiny-1/quantised.bin").is_file() && flat.join("sweep-2.meta").is_file());
+    assert!(!Path::new(&flat.join("sweep-2")).exists());
+}
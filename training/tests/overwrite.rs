@@ -0,0 +1,31 @@
+//! A second run under the same `--name` refuses to replace the first one's
+//! checkpoints unless it is continuing after them or given `--force`.
+
+mod common;
+
+use common::Scratch;
+use training::TrainError;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn existing_checkpoints_abort_without_force() {
+    let scratch = Scratch::new("overwrite");
+    let data = common::dataset(&scratch);
+    let start = common::starting_net(&scratch, &common::shape(false), 0);
+    let first = common::config(&scratch, &data, &["--load", &start, "-s", "1"]);
+    training::run(&first).unwrap();
+
+    match training::run(&first) {
+        Err(TrainError::WouldOverwrite { net_id, superbatches }) => {
+            assert_eq!((net_id.as_str(), superbatches), ("tiny", vec![1]))
+        }
+        other => panic!("expected the rerun to abort, got {:?}", other),
+    }
+
+    let resumed =
+        common::config(&scratch, &data, &["--load", &common::optimiser_weights(&first, 1), "--start", "2", "-s", "2"]);
+    training::run(&resumed).unwrap();
+
+    let forced = common::config(&scratch, &data, &["--load", &start, "-s", "1", "--force"]);
+    training::run(&forced).unwrap();
+}
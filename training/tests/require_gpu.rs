@@ -0,0 +1,21 @@
+//! `--require-gpu` aborts before any data is read when there is no GPU.
+
+use std::process::Command;
+
+use training::backend;
+
+#[test]
+fn aborts_before_reading_the_data() {
+    if backend::gpu_missing().is_none() {
+        // a GPU build on a machine with a device trains, nothing to check
+        return;
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_training"))
+        .current_dir(std::env::temp_dir())
+        .args(["--require-gpu", "--quiet", "--data", "does-not-exist.data", "-s", "1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--require-gpu: ") && !stderr.contains("does-not-exist.data"), "{}", stderr);
+}
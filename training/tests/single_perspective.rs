@@ -0,0 +1,24 @@
+//! `--single-perspective` builds its own graph, so it gets a run of its own.
+
+mod common;
+
+use common::Scratch;
+use training::inference::QuantisedNet;
+
+#[test]
+#[ignore = "trains on bullet's CPU backend"]
+fn single_perspective_builds_and_saves() {
+    let scratch = Scratch::new("single-perspective");
+    let data = common::dataset(&scratch);
+    let shape = common::shape(true);
+    let start = common::starting_net(&scratch, &shape, 1);
+    let config = common::config(&scratch, &data, &["--single-perspective", "--load", &start, "-s", "1"]);
+
+    training::run(&config).unwrap();
+
+    let net = common::quantised_net(&config, 1);
+    let read = QuantisedNet::read(&net, shape).unwrap();
+    assert_eq!(read.shape, shape);
+    // the l1 input of a dual net is twice as wide, so its layout does not fit
+    assert!(QuantisedNet::read(&net, common::shape(false)).is_err());
+}
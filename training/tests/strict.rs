@@ -0,0 +1,40 @@
+//! `--strict` stops on a warning, but lets through the ones confirming what a
+//! flag asked for.
+
+use std::process::{Command, Output};
+
+fn memory_plan(extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_training"))
+        .current_dir(std::env::temp_dir())
+        .args(["--print-memory-plan", "--deterministic"])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn a_warning_aborts_under_strict() {
+    // --deterministic without --load warns that the init is unseeded
+    let output = memory_plan(&[]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("WARNING: random weight init is not seeded"));
+
+    let output = memory_plan(&["--strict"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Error: random weight init is not seeded") && stderr.contains("fatal under --strict"),
+        "{}",
+        stderr
+    );
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Memory plan"));
+}
+
+#[test]
+fn strict_lets_the_deterministic_notice_through() {
+    let output = memory_plan(&["--strict", "--load", "start.fp32"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("WARNING: --deterministic: 1 thread"), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Memory plan"));
+}
@@ -0,0 +1,2511 @@
+use bullet::{
+    game::{formats::bulletformat::ChessBoard, inputs::ChessBucketsMirrored, outputs::MaterialCount},
+    nn::{
+        InitSettings, Shape,
+        optimiser::{AdamW, AdamWParams},
+    },
+    trainer::{
+        save::SavedFormat,
+        schedule::{TrainingSchedule, TrainingSteps, lr::LrScheduler, wdl},
+        settings::LocalSettings,
+    },
+    value::ValueTrainerBuilder,
+};
+use std::{
+    collections::HashMap,
+    env, fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    ops::Range,
+    path::Path,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    activations, affinity, archive, backend,
+    checkpoint::{self, CheckpointTable},
+    chunks::{self, ChunkState, Startup},
+    config::{Config, DEFAULT_BATCH_QUEUE, DataFormat},
+    coverage::FeatureCoverage,
+    data::{self, DataError},
+    dataset_stats::DatasetStats,
+    ema::Ema,
+    git,
+    grow::{self, ProgressiveHl},
+    inference::{self, QuantisedNet},
+    info,
+    legacy::{self, WeightsFormat},
+    loader::{
+        self, BucketWeights, LoaderStats, RecordFilter, SourceLoader, Subsample, SuperbatchSeed, TargetLoader,
+        TargetNoise, TargetTransform,
+    },
+    logging,
+    lr_find::{self, ExponentialRampLR},
+    lr_schedule::{self, PlateauDetector, PlateauLR},
+    manifest::{self, ManifestError},
+    memory::{self, MemoryEstimate, MemoryPlan},
+    metrics::{self, MetricWindow},
+    net::{self, BUCKET_LAYOUT, FloatNet, LayoutError, NUM_INPUT_BUCKETS, NUM_OUTPUT_BUCKETS, NetShape},
+    notice,
+    piece_values::PieceValues,
+    profile::{self, Profile, StallDetector},
+    quant_scales::{QuantError, ScaleResult, ScaleTable},
+    recovery::{self, Divergence, DivergenceGuard, RestorePoint},
+    replay, resume,
+    stopping::LossTarget,
+    summary::RunSummary,
+    symmetry::{self, Asymmetry},
+    verbose, warm_cache, warn, weights,
+};
+
+const EVAL_SCALE: f32 = 400.0;
+
+/// Validation loss is measured on this many held-out positions per report.
+const VAL_POSITIONS: usize = 2048;
+/// Records sampled to estimate the bucket frequencies for `--reweight-buckets`.
+const REWEIGHT_SAMPLE: usize = 100_000;
+
+const HISTOGRAM_BINS: usize = 20;
+
+#[derive(Debug)]
+pub enum TrainError {
+    Io(io::Error),
+    Download(String),
+    LoadWeights(String),
+    Data(DataError),
+    Manifest(ManifestError),
+    Layout(LayoutError),
+    /// The weights do not fit the configured architecture or the i16 range.
+    Quantise(String),
+    /// A loaded tensor contains NaN or Inf (`--check-nan`).
+    NonFinite {
+        tensor: &'static str,
+        index: usize,
+        value: f32,
+    },
+    /// The compiled backend cannot be used as asked or on this machine.
+    Backend(String),
+    /// The settings are estimated to exceed `--max-ram-mb`.
+    MemoryBudget(String),
+    /// The final net's startpos eval is beyond `--sanity-startpos`.
+    StartposEval {
+        net: String,
+        cp: i32,
+        limit: i32,
+    },
+    /// `--resume-safe` found at least one failing check.
+    ResumeUnsafe,
+    /// Checkpoints at these superbatches exist and would be overwritten.
+    WouldOverwrite {
+        net_id: String,
+        superbatches: Vec<usize>,
+    },
+}
+
+impl fmt::Display for TrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Download(e) => write!(f, "failed to download weights: {}", e),
+            Self::LoadWeights(e) => write!(f, "failed to load weights: {}", e),
+            Self::Data(e) => write!(f, "bad training data: {}", e),
+            Self::Manifest(e) => write!(f, "dataset manifest check failed: {}", e),
+            Self::Layout(e) => write!(f, "invalid bucket layout: {}", e),
+            Self::Quantise(e) => write!(f, "cannot quantise: {}", e),
+            Self::NonFinite { tensor, index, value } => {
+                write!(f, "loaded weights are corrupt: {}[{}] is {}", tensor, index, value)
+            }
+            Self::WouldOverwrite { net_id, superbatches } => {
+                let first = superbatches.first().copied().unwrap_or_default();
+                let last = superbatches.last().copied().unwrap_or_default();
+                write!(
+                    f,
+                    "this run would overwrite {} existing checkpoint(s) {}-{}..{}-{}; pass --force, or --load and \
+                     --start to continue after the last one",
+                    superbatches.len(),
+                    net_id,
+                    first,
+                    net_id,
+                    last
+                )
+            }
+            Self::Backend(e) => write!(f, "{}", e),
+            Self::MemoryBudget(e) => write!(f, "over the memory budget: {}", e),
+            Self::StartposEval { net, cp, limit } => write!(
+                f,
+                "{} scores the start position {} cp, beyond --sanity-startpos {}; check the eval scale and the \
+                 sign of the targets",
+                net, cp, limit
+            ),
+            Self::ResumeUnsafe => write!(f, "resume would not continue the saved run, see the FAIL lines above"),
+        }
+    }
+}
+
+impl std::error::Error for TrainError {}
+
+impl From<DataError> for TrainError {
+    fn from(e: DataError) -> Self {
+        Self::Data(e)
+    }
+}
+
+impl From<ManifestError> for TrainError {
+    fn from(e: ManifestError) -> Self {
+        Self::Manifest(e)
+    }
+}
+
+impl From<LayoutError> for TrainError {
+    fn from(e: LayoutError) -> Self {
+        Self::Layout(e)
+    }
+}
+
+impl From<io::Error> for TrainError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// What the graph is built from besides the compiled architecture.
+#[derive(Clone, Copy, Debug)]
+struct GraphSettings {
+    hl_size: usize,
+    /// Accumulators concatenated into l1's input: 1 for single, 2 for dual perspective.
+    perspectives: usize,
+    l1_scale: f32,
+    dropout: f32,
+    output_factoriser: bool,
+    output_scale: f32,
+    loss_clip: Option<f32>,
+}
+
+/// Builds the trainer for either perspective: `$perspective` is the builder
+/// method choosing it, and the idents after it name the accumulator inputs
+/// its graph gets, side to move first. Everything else is the same for both;
+/// a macro because bullet's builder changes type with the perspective.
+macro_rules! value_trainer {
+    ($perspective:ident, $graph:expr, $save_format:expr, $($inputs:ident),+) => {{
+        let graph: GraphSettings = $graph;
+        ValueTrainerBuilder::default()
+            .$perspective()
+            .optimiser(AdamW)
+            .inputs(ChessBucketsMirrored::new(BUCKET_LAYOUT))
+            .output_buckets(MaterialCount::<NUM_OUTPUT_BUCKETS>)
+            .save_format($save_format)
+            .loss_fn(move |output, target| {
+                let output = if graph.output_scale != 1.0 { output * graph.output_scale } else { output };
+                let loss = output.sigmoid().squared_error(target);
+                // min(loss, clip), written with the elementwise ops the graph has
+                match graph.loss_clip {
+                    Some(clip) => loss - (loss + -clip).relu(),
+                    None => loss,
+                }
+            })
+            .build(move |builder, $($inputs,)+ output_buckets| {
+                // input layer factoriser
+                let l0f = builder.new_weights("l0f", Shape::new(graph.hl_size, 768), InitSettings::Zeroed);
+                let expanded_factoriser = l0f.repeat(NUM_INPUT_BUCKETS);
+
+                // input layer weights
+                let mut l0 = builder.new_affine("l0", 768 * NUM_INPUT_BUCKETS, graph.hl_size);
+                l0.weights = l0.weights + expanded_factoriser;
+
+                // output layer weights
+                let l1_inputs = graph.perspectives * graph.hl_size;
+                let mut l1 = builder.new_affine("l1", l1_inputs, NUM_OUTPUT_BUCKETS);
+                if graph.l1_scale != 1.0 {
+                    l1.weights = l1.weights * graph.l1_scale;
+                    l1.bias = l1.bias * graph.l1_scale;
+                }
+
+                // inference: each accumulator, then them side by side
+                let accumulators = [$({
+                    let hidden = l0.forward($inputs).screlu();
+                    if graph.dropout > 0.0 { hidden.dropout(graph.dropout) } else { hidden }
+                }),+];
+                let hidden_layer =
+                    accumulators.into_iter().reduce(|stm, ntm| stm.concat(ntm)).expect("at least one accumulator");
+                let output = l1.forward(hidden_layer).select(output_buckets);
+                if graph.output_factoriser {
+                    let l1f =
+                        builder.new_weights(net::OUTPUT_FACTORISER, Shape::new(1, l1_inputs), InitSettings::Zeroed);
+                    output + (l1f * graph.l1_scale).matmul(hidden_layer)
+                } else {
+                    output
+                }
+            })
+    }};
+}
+
+/// Builds the network, optionally loads weights and trains for the configured
+/// schedule.
+pub fn run(config: &Config) -> Result<(), TrainError> {
+    // needs neither a device nor data, so it runs before either is touched
+    if run_tool(config)? {
+        return Ok(());
+    }
+
+    let device = backend::select(config.cpu, config.require_gpu).map_err(TrainError::Backend)?;
+    info!("Device:        {}", device);
+    if !backend::IS_GPU {
+        warn!("CPU backend, training will be {}", backend::CPU_SLOWDOWN);
+    }
+
+    let resumed = resume_preempted(config)?;
+    train(resumed.as_ref().unwrap_or(config))
+}
+
+/// Runs the mode asked for that needs no trainer, if any; `true` if one ran.
+fn run_tool(config: &Config) -> Result<bool, TrainError> {
+    if let Some(path) = &config.dump_config {
+        fs::write(path, config.to_toml().map_err(io::Error::other)?)?;
+        info!("Wrote the resolved config to {}", path);
+    } else if let Some(path) = &config.replay_log {
+        replay_log(config, path)?;
+    } else if let Some(path) = &config.dataset_stats {
+        print_dataset_stats(path)?;
+    } else if config.print_feature_coverage {
+        print_feature_coverage(config)?;
+    } else if let Some(path) = &config.fen_list {
+        eval_fen_list(config, path)?;
+    } else if config.export_piece_values {
+        export_piece_values(config)?;
+    } else if config.list_checkpoints {
+        list_checkpoints(config)?;
+    } else if config.print_memory_plan {
+        print_memory_plan(config);
+    } else if config.eval_symmetry_check {
+        eval_symmetry_check(config)?;
+    } else if let Some(path) = &config.dump_activations {
+        dump_activations(config, path)?;
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+fn print_dataset_stats(path: &str) -> Result<(), TrainError> {
+    let mut stats = DatasetStats::new(NUM_OUTPUT_BUCKETS);
+    data::for_each_record(path, |board| stats.add(board))?;
+    print!("{}", stats);
+    Ok(())
+}
+
+/// `--print-feature-coverage` on the first superbatch, as training would see it.
+fn print_feature_coverage(config: &Config) -> Result<(), TrainError> {
+    let positions = if config.superbatch_equals_epoch {
+        u64::MAX
+    } else {
+        (config.batch_size * config.batches_per_superbatch) as u64
+    };
+    let mut coverage = FeatureCoverage::new(NUM_INPUT_BUCKETS, !config.single_perspective);
+    data::for_each_record_up_to(&config.dataset_path, positions, |board| coverage.add(board))?;
+    println!("{}", coverage);
+    Ok(())
+}
+
+fn export_piece_values(config: &Config) -> Result<(), TrainError> {
+    let (net, eval_scale) = load_eval_net(config)?;
+    print!("{}", PieceValues::compute(NUM_INPUT_BUCKETS, |board| net.eval(board, eval_scale)));
+    Ok(())
+}
+
+fn list_checkpoints(config: &Config) -> Result<(), TrainError> {
+    let checkpoints = checkpoint::list_checkpoints(&config.output_directory, &config.net_id)?;
+    if checkpoints.is_empty() {
+        println!("No checkpoints of {} in {}", config.net_id, config.output_directory);
+    } else {
+        println!("Checkpoints of {} in {}:", config.net_id, config.output_directory);
+        print!("{}", CheckpointTable(&checkpoints));
+    }
+    Ok(())
+}
+
+fn print_memory_plan(config: &Config) {
+    let batch_queue = config.batch_queue.unwrap_or(DEFAULT_BATCH_QUEUE);
+    let shape = configured_shape(config);
+    let plan = MemoryPlan::new(
+        config.batch_size,
+        data::RECORD_SIZE,
+        batch_queue,
+        &shape,
+        backend::IS_GPU,
+        config.ema.is_some(),
+    );
+    print!("{}", plan);
+}
+
+fn eval_symmetry_check(config: &Config) -> Result<(), TrainError> {
+    let (net, eval_scale) = load_eval_net(config)?;
+    let boards = data::sample_records(&config.dataset_path, symmetry::POSITIONS)?;
+    print!("{}", Asymmetry::measure(&boards, |board| net.eval(board, eval_scale)));
+    Ok(())
+}
+
+fn dump_activations(config: &Config, path: &str) -> Result<(), TrainError> {
+    let (net, _) = load_eval_net(config)?;
+    let boards = data::sample_records(&config.dataset_path, config.dump_count)?;
+    let rows: Vec<Vec<f32>> = boards.iter().map(|board| net.stm_activations(board)).collect();
+    activations::write(path, net.shape.hl_size, &rows)?;
+    println!("Wrote {} x {} stm activations to {}", rows.len(), net.shape.hl_size, path);
+    Ok(())
+}
+
+/// A preempted chunked run continues from its last verified chunk, whatever
+/// it was started with; its own later checkpoints are retrained and replaced.
+/// `None` to run `config` as given.
+fn resume_preempted(config: &Config) -> Result<Option<Config>, TrainError> {
+    let startup = match config.chunk_superbatches {
+        Some(_) => chunks::detect(&config.output_directory, &config.run_name)?,
+        None => Startup::Fresh,
+    };
+    Ok(match startup {
+        Startup::Preempted(state) => {
+            info!(
+                "Resuming:      preempted run, from the chunk at superbatch {} ({})",
+                state.superbatch,
+                state.checkpoint.display()
+            );
+            Some(Config {
+                load_weights: Some(state.weights().display().to_string()),
+                start_superbatch: state.superbatch + 1,
+                force: true,
+                ..config.clone()
+            })
+        }
+        Startup::PreemptedBeforeChunk => {
+            notice!("the previous run was preempted before its first chunk, starting over");
+            Some(Config { force: true, ..config.clone() })
+        }
+        Startup::Fresh | Startup::Restarted => None,
+    })
+}
+
+/// The weights in `$trainer`'s graph as a [`FloatNet`] of `$shape`, `None`
+/// if a tensor can't be read back.
+macro_rules! graph_weights {
+    ($trainer:expr, $shape:expr) => {
+        FloatNet::from_fn($shape, |id| $trainer.optimiser.graph.get_weights(id).get_dense_vals())
+    };
+}
+
+/// Loads bullet's checkpoint weights at `$path`, which only load through the
+/// optimiser, into `$trainer`'s graph and reads them back.
+macro_rules! load_through_graph {
+    ($trainer:expr, $shape:expr, $path:expr) => {{
+        let path: &str = $path;
+        $trainer.optimiser.load_weights_from_file(path).map_err(|e| format!("{:?}", e)).and_then(|()| {
+            graph_weights!($trainer, $shape).ok_or_else(|| "could not read the weights back".to_string())
+        })
+    }};
+}
+
+/// [`run`] on the chosen device: builds the trainer, then runs the mode that
+/// needs one or trains.
+fn train(config: &Config) -> Result<(), TrainError> {
+    let input_buckets = net::validate_bucket_layout(&BUCKET_LAYOUT)?;
+    debug_assert_eq!(input_buckets, NUM_INPUT_BUCKETS);
+
+    // bullet's dropout is only active in training steps, so neither the saved
+    // net nor `trainer.eval` see it. Survivors are scaled by 1 / (1 - p),
+    // which pushes them past the [0, 1] range the output layer is quantised for.
+    let dropout = config.hidden_dropout;
+    if dropout > 0.25 {
+        warn!(
+            "--hidden-dropout {} scales kept activations by {:.2}x; expect a larger train/export gap",
+            dropout,
+            1.0 / (1.0 - dropout)
+        );
+    }
+
+    let graph = graph_settings(config);
+    let l1_scale = graph.l1_scale;
+    let save_format = save_format(graph);
+    // single perspective only feeds the stm accumulator into l1, so the saved
+    // l1w is [NUM_OUTPUT_BUCKETS][hl_size] instead of [NUM_OUTPUT_BUCKETS][2 * hl_size]
+    let mut trainer = if config.single_perspective {
+        value_trainer!(single_perspective, graph, &save_format, stm_inputs)
+    } else {
+        value_trainer!(dual_perspective, graph, &save_format, stm_inputs, ntm_inputs)
+    };
+    let shape = graph.shape();
+
+    let batch_queue = fit_batch_queue(config, &shape)?;
+
+    if let Some(path) = &config.export_c_header {
+        let header = net::c_header(
+            &shape,
+            &BUCKET_LAYOUT,
+            config.engine_scale.unwrap_or(EVAL_SCALE),
+            config.save_format_version,
+        );
+        fs::write(path, header)?;
+        info!("Wrote C header {}", path);
+    }
+
+    if let (Some(input), Some(output)) = (&config.quantize_only, &config.export_net) {
+        return quantize_only(config, &shape, l1_scale, (input, output), |path| {
+            load_through_graph!(trainer, &shape, path)
+        });
+    }
+
+    let data = DataPlan::for_config(config)?;
+    let metadata = run_metadata(config, graph, &data);
+
+    if config.resume_safe {
+        return resume_check(config, &shape, &metadata, |path| load_through_graph!(trainer, &shape, path));
+    }
+    if let Some((a, b)) = &config.diff {
+        return diff_weights(config, &shape, l1_scale, (a, b), |path| load_through_graph!(trainer, &shape, path));
+    }
+
+    if let Some(path) = &config.load_weights {
+        let upgraded = load_weights(config, path, &shape, l1_scale, |path| load_through_graph!(trainer, &shape, path))?;
+        if let Some(net) = upgraded {
+            write_weights(&net, |id, values| {
+                trainer.optimiser.graph.get_weights_mut(id).load_dense_from_slice(None, values)
+            })?;
+        }
+    }
+
+    if !config.init_from_average.is_empty() {
+        let average = average_nets(config, &shape, l1_scale, |path| load_through_graph!(trainer, &shape, path))?;
+        write_weights(&average, |id, values| {
+            trainer.optimiser.graph.get_weights_mut(id).load_dense_from_slice(None, values)
+        })?;
+        info!("Initialised from the average of {} nets", config.init_from_average.len());
+    }
+
+    if let Some(threshold) = config.sparsity_report {
+        return sparsity_report(config, &shape, threshold, |id| {
+            trainer.optimiser.graph.get_weights(id).get_dense_vals().unwrap_or_default()
+        });
+    }
+
+    let ema = restore_ema(config)?;
+
+    for (id, params) in clipping_params(l1_scale, graph.output_factoriser) {
+        trainer.optimiser.set_params_for_weight(id, params);
+    }
+
+    let filter = record_filter(config, &data.train_records)?;
+    let loss_scale = loss_scale(config);
+    let transform = TargetTransform {
+        eval_scale: loss_scale,
+        wdl_by_phase: config.wdl_by_phase,
+        wdl: config.target_wdl_proportion(),
+        wdl_smooth: config.wdl_smooth,
+    };
+
+    // training records only; the val and loss samples keep their scores
+    let target_noise = config.target_noise.map(|sigma| TargetNoise { sigma, seed: config.target_noise_seed });
+
+    let source = SourceLoader::for_config(config, data.train_records.clone(), data.val_records.is_some());
+
+    if let Some(count) = config.compare_loss_positions {
+        let sample = loss_sample(&config.dataset_path, 0..data.positions, count, &transform, &filter)?;
+        compare_loss(config, &sample, |fen| trainer.eval(fen) * graph.output_scale);
+        return Ok(());
+    }
+
+    if config.lr_find {
+        let sample = loss_sample(
+            &config.dataset_path,
+            data.train_records.clone(),
+            lr_find::SAMPLE_POSITIONS,
+            &transform,
+            &filter,
+        )?;
+        let dataloader =
+            TargetLoader::new(source, transform, filter, Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, false)))
+                .skipping_bad_records(config.skip_bad_records)
+                .with_target_noise(target_noise);
+        lr_range_test(config, &sample, loss_scale, batch_queue, |schedule, settings, on_point| {
+            trainer.run_with_callback(schedule, settings, &dataloader, |superbatch, trainer, _, _| {
+                on_point(superbatch, &|fen| trainer.eval(fen) * graph.output_scale)
+            })
+        });
+        return Ok(());
+    }
+
+    // the engine needs to know which accumulator layout and output scale the net expects
+    let engine_scale = config.engine_scale.unwrap_or(EVAL_SCALE);
+    if engine_scale != EVAL_SCALE {
+        warn!("--engine-scale {} differs from the training eval scale {}", engine_scale, EVAL_SCALE);
+    }
+    start_output(config, &metadata)?;
+
+    // 317690799
+
+    let schedule = TrainingSchedule {
+        net_id: config.net_id.clone(),
+        eval_scale: loss_scale,
+        steps: TrainingSteps {
+            batch_size: config.batch_size,
+            batches_per_superbatch: data.batches_per_superbatch,
+            start_superbatch: config.start_superbatch,
+            end_superbatch: config.superbatches,
+        },
+        wdl_scheduler: wdl::ConstantWDL { value: config.wdl_proportion() },
+        lr_scheduler: PlateauLR::new(lr_schedule::from_config(config)),
+        // interval saves are done in the callback so they can be guarded by
+        // the free-space check; bullet itself only writes the final net
+        save_rate: usize::MAX,
+    };
+    if let Some((first, last)) = config.schedule_anchor {
+        info!(
+            "LR anchor:     cosine over superbatches {}..{}, lr {:.6} at superbatch {}",
+            first,
+            last,
+            schedule.lr_scheduler.lr(1, config.start_superbatch),
+            config.start_superbatch
+        );
+    }
+
+    let settings = LocalSettings {
+        threads: config.threads,
+        test_set: None,
+        output_directory: &config.output_directory,
+        batch_queue_size: batch_queue,
+    };
+
+    let loader_stats = Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, config.target_clamp_report));
+    let positions_per_superbatch = schedule.steps.batch_size * schedule.steps.batches_per_superbatch;
+    // subsample keeps its fixed hash: it picks the data, not a per-pass draw
+    let seeds = config
+        .resumeable_seed
+        .map(|base| SuperbatchSeed { base, positions_per_superbatch: positions_per_superbatch as u64 });
+    if seeds.is_some() && target_noise.is_none() && filter.reweight.is_none() {
+        warn!("--resumeable-seed only seeds --target-noise and --reweight-buckets, neither is on");
+    }
+    let samples = Samples::for_config(config, &data, &transform, &filter)?;
+    let mut progress =
+        Progress::new(config, graph, positions_per_superbatch, samples, ema, loader_stats.clone(), filter.is_active());
+
+    let record_size = source.record_size();
+    let dataloader = TargetLoader::new(source, transform, filter, loader_stats)
+        .with_record_size(record_size)
+        .pinned_to(loader_core(config))
+        .skipping_bad_records(config.skip_bad_records)
+        .with_target_noise(target_noise)
+        .with_superbatch_seeds(seeds)
+        .stopping_on(progress.stop.clone());
+    trainer.run_with_callback(&schedule, &settings, &dataloader, |superbatch, trainer, schedule, settings| {
+        let eval = |fen: &str| trainer.eval(fen);
+        let weights = |id: &str| trainer.optimiser.graph.get_weights(id).get_dense_vals();
+        let save = |dir: &str| trainer.save_to_checkpoint(dir);
+        let view = TrainerView { eval: &eval, weights: &weights, save: &save };
+        progress.after_superbatch(superbatch, &view, &schedule.lr_scheduler, settings.batch_queue_size);
+    });
+    progress.finish()
+}
+
+/// The output layer's learning rate as a multiple of the base rate.
+///
+/// AdamW steps are invariant to gradient scale, so a separate output layer
+/// rate is realised by training l1 as `w = l1_scale * v`: the stored tensor
+/// v moves at the base rate and the effective weights at l1_scale times it.
+/// The cosine schedule still scales both layers proportionally.
+fn l1_scale(config: &Config) -> f32 {
+    config.l1_lr.map_or(1.0, |l1_lr| l1_lr / config.initial_lr)
+}
+
+/// The eval scale of the loss's sigmoid.
+///
+/// The net's output stays in eval-scale units (output * EVAL_SCALE is the
+/// centipawn eval the engine computes), while the loss compares sigmoid(cp /
+/// loss_scale) on both sides: bullet's target uses the schedule's scale, and
+/// the output is rescaled by EVAL_SCALE / loss_scale in the loss only.
+fn loss_scale(config: &Config) -> f32 {
+    config.loss_target_scale.unwrap_or(EVAL_SCALE)
+}
+
+fn graph_settings(config: &Config) -> GraphSettings {
+    GraphSettings {
+        hl_size: config.hl_size(),
+        perspectives: if config.single_perspective { 1 } else { 2 },
+        l1_scale: l1_scale(config),
+        dropout: config.hidden_dropout,
+        // shares one l1 column across the output buckets, like l0f does across input buckets
+        output_factoriser: config.output_factoriser,
+        output_scale: EVAL_SCALE / loss_scale(config),
+        // caps each position's squared error; the graph cannot report how often,
+        // so the clip rate is estimated on the loss sample at each report
+        loss_clip: config.loss_clip,
+    }
+}
+
+/// How bullet writes the engine net: factorisers merged in and the effective
+/// l1 weights quantised.
+fn save_format(graph: GraphSettings) -> [SavedFormat; 4] {
+    let GraphSettings { l1_scale, output_factoriser, .. } = graph;
+    [
+        // merge in the factoriser weights
+        SavedFormat::id("l0w")
+            .transform(|store, weights| {
+                let factoriser = store.get("l0f").values.repeat(NUM_INPUT_BUCKETS);
+                weights.into_iter().zip(factoriser).map(|(a, b)| a + b).collect()
+            })
+            .round()
+            .quantise::<i16>(255),
+        SavedFormat::id("l0b").round().quantise::<i16>(255),
+        SavedFormat::id("l1w")
+            .transform(move |store, weights| {
+                // l1w is [input][bucket] here, so input i's factoriser value is shared by a run of buckets
+                let factoriser =
+                    if output_factoriser { store.get(net::OUTPUT_FACTORISER).values.clone() } else { Vec::new() };
+                weights
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, w)| (w + factoriser.get(i / NUM_OUTPUT_BUCKETS).copied().unwrap_or(0.0)) * l1_scale)
+                    .collect()
+            })
+            .round()
+            .quantise::<i16>(64)
+            .transpose(),
+        SavedFormat::id("l1b")
+            .transform(move |_, weights| weights.into_iter().map(|w| w * l1_scale).collect())
+            .round()
+            .quantise::<i16>(255 * 64),
+    ]
+}
+
+impl GraphSettings {
+    fn shape(&self) -> NetShape {
+        NetShape {
+            hl_size: self.hl_size,
+            input_buckets: NUM_INPUT_BUCKETS,
+            output_buckets: NUM_OUTPUT_BUCKETS,
+            single_perspective: self.perspectives == 1,
+            output_factoriser: self.output_factoriser,
+        }
+    }
+}
+
+/// `--quantize-only`: the checkpoint weights `input` quantised into the
+/// engine net `output`.
+fn quantize_only(
+    config: &Config,
+    shape: &NetShape,
+    l1_scale: f32,
+    (input, output): (&str, &str),
+    load: impl FnOnce(&str) -> Result<FloatNet, String>,
+) -> Result<(), TrainError> {
+    info!("Quantising {} -> {}", input, output);
+    let mut weights = load(input).map_err(|e| TrainError::LoadWeights(format!("{}: {}", input, e)))?;
+    match_l1_scale(config, input, &mut weights, l1_scale)?;
+    let quantised = net::quantise(&weights, shape, l1_scale).map_err(TrainError::Quantise)?;
+    net::write_quantised(output, &quantised)?;
+    describe_net(config, shape, output, &format!("{} from {}", config.net_id, input));
+    println!("Wrote {} ({} values)", output, quantised.len());
+    Ok(())
+}
+
+/// `--load`: the weights at `path`, downloaded first if it is a URL, checked
+/// against `--validate-shapes-against-header` and `--check-nan`. bullet's
+/// checkpoint weights are loaded into the graph by `load`; weights of any
+/// other format, or checkpoint weights trained at another l1 scale, are
+/// returned to be written into it.
+fn load_weights(
+    config: &Config,
+    path: &str,
+    shape: &NetShape,
+    l1_scale: f32,
+    load: impl FnOnce(&str) -> Result<FloatNet, String>,
+) -> Result<Option<FloatNet>, TrainError> {
+    // a URL is downloaded first, then loaded exactly like a local file
+    let downloaded = if checkpoint::is_url(path) {
+        info!("Downloading weights from: {}", path);
+        Some(checkpoint::download_weights(path).map_err(TrainError::Download)?)
+    } else {
+        info!("Loading weights from: {}", path);
+        None
+    };
+    let local = downloaded.as_ref().map_or_else(|| path.to_string(), |file| file.display().to_string());
+    let read = || -> Result<(FloatNet, bool), TrainError> {
+        let format = legacy::detect(&local, shape)?;
+        let declared = if config.validate_shapes_against_header {
+            let declared = header_shape(config, path, shape)?;
+            // the graph only takes tensors of the configured shape, so
+            // check an archive's own lengths before it is upgraded
+            if let (WeightsFormat::FloatArchive, Ok(tensors)) = (format, archive::read(&local)) {
+                resume::check_payload(tensors.iter().map(|(id, values)| (id.as_str(), values.len())), &declared)
+                    .map_err(|e| TrainError::LoadWeights(format!("{}: {}", path, e)))?;
+            }
+            Some(declared)
+        } else {
+            None
+        };
+        let (net, in_graph) = match format {
+            WeightsFormat::Optimiser => {
+                let mut net = load(&local).map_err(|e| TrainError::LoadWeights(format!("{}: {}", path, e)))?;
+                let rescaled = match_l1_scale(config, path, &mut net, l1_scale)?;
+                (net, !rescaled)
+            }
+            format => {
+                let (net, note) = legacy::load(&local, format, shape, l1_scale)
+                    .map_err(|e| TrainError::LoadWeights(e.replace(&local, path)))?;
+                info!("Upgrading:     {} ({})", path, note);
+                (net, false)
+            }
+        };
+        if let Some(declared) = &declared {
+            let loaded = shape.tensors().into_iter().map(|id| (id, net.get(id).map_or(0, Vec::len)));
+            resume::check_payload(loaded, declared).map_err(|e| TrainError::LoadWeights(format!("{}: {}", path, e)))?;
+            info!("Header check:  {} matches its run metadata", path);
+        }
+        Ok((net, in_graph))
+    };
+    let loaded = read();
+    if let Some(file) = &downloaded {
+        let _ = fs::remove_file(file);
+    }
+    let (net, in_graph) = loaded?;
+
+    if config.check_nan {
+        for tensor in shape.tensors() {
+            let values = net.get(tensor).map(Vec::as_slice).unwrap_or_default();
+            if let Some((index, value)) = weights::find_non_finite(values) {
+                return Err(TrainError::NonFinite { tensor, index, value });
+            }
+        }
+        info!("Checked loaded weights: all finite");
+    }
+    Ok((!in_graph).then_some(net))
+}
+
+/// What training reads of `--data`, worked out before any weights are loaded.
+struct DataPlan {
+    manifest_hash: Option<String>,
+    /// Records in the file; 0 for a binpack, which is not counted.
+    positions: u64,
+    train_records: Range<u64>,
+    val_records: Option<Range<u64>>,
+    batches_per_superbatch: usize,
+}
+
+impl DataPlan {
+    fn for_config(config: &Config) -> Result<Self, TrainError> {
+        let manifest_hash = match &config.dataset_manifest {
+            Some(path) => {
+                info!("Verifying dataset against {}", path);
+                Some(manifest::verify(path, &config.dataset_path)?)
+            }
+            None => None,
+        };
+
+        // catch a wrong data format before loading weights or training; a binpack
+        // is not counted, and Config refuses everything that reads it by record
+        if config.record_size != data::RECORD_SIZE {
+            data::count_records(&config.dataset_path, config.record_size)?;
+            warn!(
+                "--record-size {} only checks the file size; the loaders read {}-byte records",
+                config.record_size,
+                data::RECORD_SIZE
+            );
+        }
+        let counted =
+            config.data_record_size().map(|size| data::count_records(&config.dataset_path, size)).transpose()?;
+        match counted {
+            Some(positions) => info!("Positions:     {}", positions),
+            None => info!("Positions:     not counted ({} is a binpack)", config.dataset_path),
+        }
+        let positions = counted.unwrap_or(0);
+        if config.warm_cache {
+            let mut next_percent = 10;
+            let warmed = warm_cache::warm(&config.dataset_path, |read, total| {
+                let percent = read * 100 / total.max(1);
+                if percent >= next_percent {
+                    info!("Warming cache: {}% ({:.0} MB)", percent, checkpoint::mb(read));
+                    next_percent = percent / 10 * 10 + 10;
+                }
+            })?;
+            if warmed.already_cached {
+                info!("Warm cache:    already cached, skipped ({:.2?} probe)", warmed.elapsed);
+            } else {
+                info!("Warm cache:    read {:.0} MB in {:.1?}", checkpoint::mb(warmed.bytes_read), warmed.elapsed);
+            }
+        }
+        let (train_records, val_records) = match config.val_split {
+            Some(fraction) => {
+                let (train, val) = data::split_records(positions, fraction);
+                info!("Split:         {} train, {} validation", train.end - train.start, val.end - val.start);
+                (train, Some(val))
+            }
+            None => (0..positions, None),
+        };
+
+        let mut train_positions = train_records.end - train_records.start;
+        if let (Some(fraction), true) = (config.subsample, counted.is_some()) {
+            let kept = (train_positions as f64 * f64::from(fraction)).round() as u64;
+            info!(
+                "Subsample:     keeping about {} of {} training positions ({}%, seed {})",
+                kept,
+                train_positions,
+                100.0 * fraction,
+                config.subsample_seed
+            );
+            train_positions = kept;
+        }
+        let batches_per_superbatch = if config.superbatch_equals_epoch {
+            let (batches, leftover) = crate::schedule::batches_per_epoch(train_positions, config.batch_size);
+            info!("Superbatch:    one epoch = {} batches x {} ({} left over)", batches, config.batch_size, leftover);
+            batches
+        } else {
+            config.batches_per_superbatch
+        };
+
+        // a loader wrapping around a small file silently overfits
+        let superbatch_positions = batches_per_superbatch * config.batch_size;
+        if counted.is_some() && train_positions < superbatch_positions as u64 {
+            let superbatches = (config.superbatches + 1).saturating_sub(config.start_superbatch);
+            warn!(
+                "only {} training positions but {} per superbatch; each position would be seen {:.1}x over the run",
+                train_positions,
+                superbatch_positions,
+                crate::schedule::repetition_factor(train_positions, superbatch_positions, superbatches)
+            );
+        }
+
+        Ok(Self { manifest_hash, positions, train_records, val_records, batches_per_superbatch })
+    }
+}
+
+/// What the engine and a later --resume-safe need to know about this run.
+fn run_metadata(config: &Config, graph: GraphSettings, data: &DataPlan) -> Vec<(&'static str, String)> {
+    let mut metadata = vec![
+        ("net_id", config.net_id.clone()),
+        ("hl_size", graph.hl_size.to_string()),
+        ("input_buckets", NUM_INPUT_BUCKETS.to_string()),
+        ("output_buckets", NUM_OUTPUT_BUCKETS.to_string()),
+        ("perspective", (if config.single_perspective { "single" } else { "dual" }).to_string()),
+        ("l1_lr_scale", graph.l1_scale.to_string()),
+        ("eval_scale", EVAL_SCALE.to_string()),
+        ("engine_scale", config.engine_scale.unwrap_or(EVAL_SCALE).to_string()),
+        ("save_format_version", config.save_format_version.to_string()),
+        ("superbatches", config.superbatches.to_string()),
+        ("initial_lr", config.initial_lr.to_string()),
+        ("final_lr", config.final_lr.to_string()),
+        ("dataset", config.dataset_path.clone()),
+        ("batch_size", config.batch_size.to_string()),
+        ("batches_per_superbatch", data.batches_per_superbatch.to_string()),
+    ];
+    // only recorded for binpack, so metadata of earlier direct runs still matches
+    if config.data_format != DataFormat::Direct {
+        metadata.push(("data_format", config.data_format.to_string()));
+    }
+    if let Some(growth) = config.progressive_hl {
+        metadata.push(("progressive_hl_start", growth.start.to_string()));
+        metadata.push(("progressive_hl_end", growth.end.to_string()));
+        metadata.push(("progressive_hl_at", growth.at.to_string()));
+    }
+    if let Some(decay) = config.ema {
+        metadata.push(("ema_decay", decay.to_string()));
+    }
+    if graph.dropout > 0.0 {
+        metadata.push(("hidden_dropout", graph.dropout.to_string()));
+    }
+    if graph.output_factoriser {
+        metadata.push(("output_factoriser", "true".to_string()));
+    }
+    if let Some(scale) = config.loss_target_scale {
+        metadata.push(("loss_target_scale", scale.to_string()));
+    }
+    if let Some(hash) = &data.manifest_hash {
+        metadata.push(("dataset_manifest_sha256", hash.clone()));
+    }
+    if !config.holdout_buckets.is_empty() {
+        let list: Vec<String> = config.holdout_buckets.iter().map(|b| b.to_string()).collect();
+        metadata.push(("holdout_buckets", list.join(",")));
+    }
+    if let Some(fraction) = config.subsample {
+        metadata.push(("subsample", format!("{}:{}", fraction, config.subsample_seed)));
+    }
+    if let Some(sigma) = config.target_noise {
+        metadata.push(("target_noise", format!("{}:{}", sigma, config.target_noise_seed)));
+    }
+    if let Some(base) = config.resumeable_seed {
+        metadata.push(("resumeable_seed", base.to_string()));
+    }
+    if config.record_git_state {
+        match git::source_state() {
+            Some(state) => {
+                if state.dirty {
+                    warn!(
+                        "trainer source tree has uncommitted changes; {} alone won't reproduce this run",
+                        state.commit
+                    );
+                }
+                metadata.extend(state.metadata());
+            }
+            None => warn!("--record-git-state: no git state for {}", env!("CARGO_MANIFEST_DIR")),
+        }
+    }
+    metadata
+}
+
+/// `--resume-safe`: checks that resuming from `--load` would continue the run
+/// `metadata` describes, without training.
+fn resume_check(
+    config: &Config,
+    shape: &NetShape,
+    metadata: &[(&str, String)],
+    load: impl FnOnce(&str) -> Result<FloatNet, String>,
+) -> Result<(), TrainError> {
+    let path = config.load_weights.as_deref().unwrap_or_default();
+    let mut report = resume::Report::default();
+    let loaded = if checkpoint::is_url(path) {
+        Err("resume checks need a local checkpoint, not a URL".to_string())
+    } else {
+        load(path)
+            .map_err(|e| format!("cannot load {}: {}", path, e))
+            .and_then(|weights| weights.check_shape(shape))
+            .map(|()| format!("{} loads into the hl_size {} graph", path, shape.hl_size))
+    };
+    report.add("weights", loaded);
+
+    let dir = resume::checkpoint_dir(path);
+    let previous = resume::previous_metadata(&dir, &config.run_name);
+    let against =
+        |keys: &[&str]| previous.as_ref().map_err(Clone::clone).and_then(|m| resume::compare(m, metadata, keys));
+    report.add("architecture", against(&resume::ARCHITECTURE_KEYS));
+    report.add("optimiser state", resume::optimiser_state(&dir));
+    report.add("schedule anchor", resume::schedule_anchor(&dir, &config.net_id, config.start_superbatch));
+    report.add("schedule", against(&resume::SCHEDULE_KEYS));
+    report.add("data offset", against(&resume::DATA_KEYS));
+
+    println!("{}", report);
+    if report.passed() { Ok(()) } else { Err(TrainError::ResumeUnsafe) }
+}
+
+/// `--diff`: per-tensor changes from the weights at `a` to those at `b`,
+/// both at this run's l1 scale. `load` reads bullet's checkpoint weights,
+/// which need the trainer.
+fn diff_weights(
+    config: &Config,
+    shape: &NetShape,
+    l1_scale: f32,
+    (a, b): (&str, &str),
+    mut load: impl FnMut(&str) -> Result<FloatNet, String>,
+) -> Result<(), TrainError> {
+    let mut read = |path: &str| -> Result<FloatNet, TrainError> {
+        match legacy::detect(path, shape)? {
+            WeightsFormat::Optimiser => {
+                let mut net = load(path).map_err(|e| TrainError::LoadWeights(format!("{}: {}", path, e)))?;
+                match_l1_scale(config, path, &mut net, l1_scale)?;
+                Ok(net)
+            }
+            format => legacy::load(path, format, shape, l1_scale).map(|(net, _)| net).map_err(TrainError::LoadWeights),
+        }
+    };
+    let (first, second) = (read(a)?, read(b)?);
+    println!("Weight diff {} -> {}", a, b);
+    print!("{}", weights::diff_table(&weights::diff(&first, &second).map_err(TrainError::LoadWeights)?));
+    Ok(())
+}
+
+/// `--init-from-average`: the mean of the checkpoint weights it names, each
+/// at this run's l1 scale.
+fn average_nets(
+    config: &Config,
+    shape: &NetShape,
+    l1_scale: f32,
+    mut load: impl FnMut(&str) -> Result<FloatNet, String>,
+) -> Result<FloatNet, TrainError> {
+    let paths = &config.init_from_average;
+    let mut nets = Vec::with_capacity(paths.len());
+    for path in paths {
+        info!("Loading weights for the average: {}", path);
+        let mut net = load(path)
+            .and_then(|net| net.check_shape(shape).map(|()| net))
+            .map_err(|e| TrainError::LoadWeights(format!("{}: {}", path, e)))?;
+        match_l1_scale(config, path, &mut net, l1_scale)?;
+        nets.push(net);
+    }
+    net::average(&nets).ok_or_else(|| TrainError::LoadWeights("nets differ in shape".to_string()))
+}
+
+/// `--sparsity-report`: the input features whose merged l0 weights are all
+/// below `threshold`, read from the graph through `read`.
+fn sparsity_report(
+    config: &Config,
+    shape: &NetShape,
+    threshold: f32,
+    read: impl Fn(&str) -> Vec<f32>,
+) -> Result<(), TrainError> {
+    let (l0w, l0f) = (read("l0w"), read("l0f"));
+    if Some(l0w.len()) != shape.tensor_len("l0w") || Some(l0f.len()) != shape.tensor_len("l0f") {
+        return Err(TrainError::LoadWeights("l0w/l0f do not have the configured shape".to_string()));
+    }
+    let features = l0w.len() / shape.hl_size;
+    let zero = weights::near_zero_features(&l0w, &l0f, shape.hl_size, threshold);
+    println!(
+        "{} of {} input features ({:.2}%) have all merged weights below {}",
+        zero.len(),
+        features,
+        100.0 * zero.len() as f64 / features.max(1) as f64,
+        threshold
+    );
+    for bucket in 0..NUM_INPUT_BUCKETS {
+        let count = zero.iter().filter(|&&f| f / 768 == bucket).count();
+        println!("  bucket {}: {}/768", bucket, count);
+    }
+    if let Some(path) = &config.sparsity_list {
+        let list: String = zero.iter().map(|f| format!("{}\n", f)).collect();
+        fs::write(path, list)?;
+        println!("Wrote near-zero feature indices to {}", path);
+    }
+    Ok(())
+}
+
+/// The `--ema` average, restored from the checkpoint being loaded when it was
+/// saved next to it, so resuming continues the average instead of restarting it.
+fn restore_ema(config: &Config) -> Result<Option<Ema>, TrainError> {
+    let mut ema = config.ema.map(Ema::new);
+    if let (Some(ema), Some(path)) = (&mut ema, &config.load_weights) {
+        let ema_path = ema_path_for(path);
+        if ema_path.exists() {
+            ema.load(&ema_path)?;
+            info!("Restored EMA from: {}", ema_path.display());
+        } else {
+            warn!("no {} found, EMA starts from the loaded weights", ema_path.display());
+        }
+    }
+    Ok(ema)
+}
+
+/// AdamW clipping that differs from the default, by tensor.
+fn clipping_params(l1_scale: f32, output_factoriser: bool) -> Vec<(&'static str, AdamWParams)> {
+    // need to account for factoriser weight magnitudes
+    let stricter_clipping = AdamWParams { max_weight: 0.99, min_weight: -0.99, ..Default::default() };
+    let mut params = vec![("l0w", stricter_clipping), ("l0f", stricter_clipping)];
+
+    // keep the effective output weights inside the default clipping range; with
+    // an output factoriser l1w and l1f split it, as l0w and l0f do above
+    if l1_scale != 1.0 || output_factoriser {
+        let default = AdamWParams::default();
+        let l1_clipping = |share: f32| AdamWParams {
+            max_weight: default.max_weight * share / l1_scale,
+            min_weight: default.min_weight * share / l1_scale,
+            ..default
+        };
+        let weight_share = if output_factoriser { 0.5 } else { 1.0 };
+        params.push(("l1w", l1_clipping(weight_share)));
+        params.push(("l1b", l1_clipping(1.0)));
+        if output_factoriser {
+            params.push((net::OUTPUT_FACTORISER, l1_clipping(weight_share)));
+        }
+    }
+    params
+}
+
+/// The records training skips or repeats, with `--reweight-buckets`' weights
+/// estimated on a sample of `train_records`.
+fn record_filter(config: &Config, train_records: &Range<u64>) -> Result<RecordFilter, TrainError> {
+    let mut filter = RecordFilter {
+        eval_max: config.filter_eval_max,
+        no_check: config.filter_no_check,
+        holdout_buckets: loader::bucket_mask(&config.holdout_buckets),
+        num_buckets: NUM_OUTPUT_BUCKETS,
+        subsample: config.subsample.map(|fraction| Subsample::new(fraction, config.subsample_seed)),
+        reweight: None,
+    };
+    if config.reweight_buckets {
+        let mut counts = vec![0u64; NUM_OUTPUT_BUCKETS];
+        for board in data::sample_records_in(&config.dataset_path, train_records.clone(), REWEIGHT_SAMPLE)? {
+            if filter.keep(&board) {
+                counts[data::material_bucket(&board, NUM_OUTPUT_BUCKETS)] += 1;
+            }
+        }
+        let weights = loader::inverse_frequency_weights(&counts);
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        let buckets: Vec<String> = counts
+            .iter()
+            .zip(&weights)
+            .enumerate()
+            .map(|(b, (&count, weight))| format!("{} {:.1}% x{:.2}", b, 100.0 * count as f64 / total, weight))
+            .collect();
+        info!("Reweight:      {} (bucket, sampled share, weight)", buckets.join(" | "));
+        filter.reweight = Some(BucketWeights::new(&weights));
+    }
+    Ok(filter)
+}
+
+/// `--compare-loss`: the loaded net's loss on `sample`, scored through
+/// `eval` without a training step.
+fn compare_loss(config: &Config, sample: &[(String, f32)], eval: impl Fn(&str) -> f32) -> f32 {
+    let loss = mean_loss(sample, eval);
+    println!("Loss of the loaded net on {} positions of {}: {:.6}", sample.len(), config.dataset_path, loss);
+    loss
+}
+
+/// `--lr-find`: trains through an exponential LR ramp and suggests the rate
+/// where the loss on `sample` falls fastest. `train` runs bullet on the
+/// schedule and settings given, calling back after each point with the
+/// superbatch and the net's scaled eval.
+fn lr_range_test(
+    config: &Config,
+    sample: &[(String, f32)],
+    loss_scale: f32,
+    batch_queue: usize,
+    train: impl FnOnce(
+        &TrainingSchedule<ExponentialRampLR, wdl::ConstantWDL>,
+        &LocalSettings,
+        &mut dyn FnMut(usize, &dyn Fn(&str) -> f32),
+    ),
+) {
+    let batches = lr_find::POINTS * lr_find::BATCHES_PER_POINT;
+    let schedule = TrainingSchedule {
+        net_id: format!("{}-lr-find", config.net_id),
+        eval_scale: loss_scale,
+        steps: TrainingSteps {
+            batch_size: config.batch_size,
+            batches_per_superbatch: lr_find::BATCHES_PER_POINT,
+            start_superbatch: 1,
+            end_superbatch: lr_find::POINTS,
+        },
+        wdl_scheduler: wdl::ConstantWDL { value: config.wdl_proportion() },
+        lr_scheduler: ExponentialRampLR {
+            start: lr_find::START_LR,
+            end: lr_find::END_LR,
+            batches_per_superbatch: lr_find::BATCHES_PER_POINT,
+            total_batches: batches,
+        },
+        save_rate: usize::MAX,
+    };
+    // bullet always writes the final net, so point it somewhere disposable
+    let scratch = std::env::temp_dir().join(format!("sleepmind-lr-find-{}", std::process::id()));
+    let scratch_dir = scratch.to_string_lossy().into_owned();
+    let settings = LocalSettings {
+        threads: config.threads,
+        test_set: None,
+        output_directory: &scratch_dir,
+        batch_queue_size: batch_queue,
+    };
+
+    info!("LR range test: {} -> {} over {} batches", lr_find::START_LR, lr_find::END_LR, batches);
+    let (mut lrs, mut losses) = (Vec::new(), Vec::new());
+    train(&schedule, &settings, &mut |superbatch, eval| {
+        lrs.push(schedule.lr_scheduler.lr(schedule.steps.batches_per_superbatch, superbatch));
+        losses.push(mean_loss(sample, eval));
+    });
+    let _ = fs::remove_dir_all(&scratch);
+
+    let smoothed = lr_find::smooth(&losses, 0.7);
+    println!("{}", lr_find::table(&lrs, &losses, &smoothed));
+    let points: Vec<(f32, f32)> = lrs.iter().copied().zip(smoothed.iter().copied()).collect();
+    match lr_find::steepest_descent(&points) {
+        Some(i) => println!("Suggested --lr {:.3e} (steepest loss decrease)", lrs[i]),
+        None => println!("No decreasing stretch in the loss curve; try a different range or more data"),
+    }
+}
+
+/// Refuses to overwrite an earlier run's checkpoints without `--force`, then
+/// writes the run metadata, the resolved config and, for a chunked run, its
+/// sentinel.
+fn start_output(config: &Config, metadata: &[(&str, String)]) -> Result<(), TrainError> {
+    // a reused --name would otherwise silently replace an earlier run's nets
+    let clobbered: Vec<usize> = checkpoint::existing_checkpoints(&config.output_directory, &config.net_id)?
+        .into_iter()
+        .filter(|&sb| sb >= config.start_superbatch)
+        .collect();
+    if !clobbered.is_empty() {
+        if !config.force {
+            return Err(TrainError::WouldOverwrite { net_id: config.net_id.clone(), superbatches: clobbered });
+        }
+        notice!("--force: overwriting {} existing checkpoint(s) of {}", clobbered.len(), config.net_id);
+    }
+    fs::create_dir_all(&config.output_directory)?;
+    fs::write(format!("{}/{}.meta", config.output_directory, config.run_name), resume::format_metadata(metadata))?;
+    // the settings in effect, --config file and command line merged
+    fs::write(
+        format!("{}/{}.toml", config.output_directory, config.run_name),
+        config.to_toml().map_err(io::Error::other)?,
+    )?;
+    if config.chunk_superbatches.is_some() {
+        chunks::write_sentinel(&config.output_directory, &config.run_name)?;
+    }
+    Ok(())
+}
+
+/// `--pin-threads`: the core for the data loader thread. bullet's compute
+/// threads are its own; the loader thread is the one we control.
+fn loader_core(config: &Config) -> Option<usize> {
+    if !config.pin_threads {
+        return None;
+    }
+    match affinity::available_cores() {
+        Some(cores) => {
+            let core = affinity::assign_cores(cores, 1)[0];
+            info!("Pinning:       data loader thread -> core {} of {}", core, cores);
+            Some(core)
+        }
+        None => {
+            warn!("thread affinity is not supported here, --pin-threads has no effect");
+            None
+        }
+    }
+}
+
+/// Positions scored through the float net during training, each drawn
+/// once before it starts.
+#[derive(Default)]
+struct Samples {
+    /// The `--val-split` positions.
+    val: Vec<(String, f32)>,
+    /// Training positions for the loss checks when there is no val split.
+    stop: Vec<(String, f32)>,
+    /// Training positions for `--loss-clip`'s rate when there is no val split.
+    clip: Vec<(String, f32)>,
+    /// `--loss-by-bucket`, with each position's output bucket.
+    bucket: Vec<(String, f32, usize)>,
+    /// `--holdout-file`, scored once at the end.
+    holdout: Vec<(String, f32)>,
+    /// `--compare-quant-scales`, scored by the float and the integer net.
+    quant: Vec<(String, ChessBoard)>,
+}
+
+impl Samples {
+    fn for_config(
+        config: &Config,
+        data: &DataPlan,
+        transform: &TargetTransform,
+        filter: &RecordFilter,
+    ) -> Result<Self, TrainError> {
+        let path = &config.dataset_path;
+        let train = || data.train_records.clone();
+        // on the held-out positions when there are some, like the val loss
+        let held_out = || data.val_records.clone().unwrap_or_else(train);
+        let unsampled = filter.without_subsample();
+
+        let val = match &data.val_records {
+            Some(range) => loss_sample(path, range.clone(), VAL_POSITIONS, transform, &unsampled)?,
+            None => Vec::new(),
+        };
+        let checks_loss = config.stop_at_loss.is_some()
+            || config.recover_on_divergence.is_some()
+            || config.reduce_on_plateau.is_some();
+        let stop = if checks_loss && val.is_empty() {
+            loss_sample(path, train(), VAL_POSITIONS, transform, filter)?
+        } else {
+            Vec::new()
+        };
+        let clip = if config.loss_clip.is_some() && val.is_empty() {
+            loss_sample(path, train(), VAL_POSITIONS, transform, filter)?
+        } else {
+            Vec::new()
+        };
+        let bucket = if config.loss_by_bucket {
+            bucket_loss_sample(path, held_out(), VAL_POSITIONS, transform, &unsampled)?
+        } else {
+            Vec::new()
+        };
+        // loaded up front so a bad path fails before training, scored only at the end
+        let holdout = match &config.holdout_file {
+            Some(holdout) => {
+                let records = data::count_records(holdout, data::RECORD_SIZE)?;
+                loss_sample(holdout, 0..records, VAL_POSITIONS, transform, &unsampled)?
+            }
+            None => Vec::new(),
+        };
+        let quant = if config.compare_quant_scales.is_empty() {
+            Vec::new()
+        } else {
+            data::sample_records_in(path, held_out(), VAL_POSITIONS)?
+                .into_iter()
+                .map(|b| (data::to_fen(&b), b))
+                .collect()
+        };
+        Ok(Self { val, stop, clip, bucket, holdout, quant })
+    }
+}
+
+/// What [`Progress`] uses of bullet's trainer, as closures over it: the
+/// bookkeeping then neither names bullet's trainer type nor needs one to be
+/// tested.
+struct TrainerView<'a> {
+    /// The float net's raw output for a FEN.
+    eval: &'a dyn Fn(&str) -> f32,
+    /// A tensor of the graph by id.
+    weights: &'a dyn Fn(&str) -> Option<Vec<f32>>,
+    /// bullet's checkpoint save into a directory.
+    save: &'a dyn Fn(&str),
+}
+
+impl TrainerView<'_> {
+    fn net(&self, shape: &NetShape) -> Option<FloatNet> {
+        FloatNet::from_fn(shape, |id| (self.weights)(id))
+    }
+}
+
+/// The bookkeeping between bullet's superbatches: what the training callback
+/// does after each one, and what it keeps from one to the next.
+struct Progress<'a> {
+    config: &'a Config,
+    graph: GraphSettings,
+    shape: NetShape,
+    positions_per_superbatch: usize,
+    samples: Samples,
+    ema: Option<Ema>,
+    loader_stats: Arc<LoaderStats>,
+    filter_active: bool,
+    /// `--stop-at-loss`, on the val positions when there are some.
+    loss_target: Option<LossTarget>,
+    val_window: Option<MetricWindow>,
+    guard: Option<DivergenceGuard>,
+    /// Where `--recover-on-divergence` restarts from.
+    restore: Option<RestorePoint>,
+    plateau: Option<PlateauDetector>,
+    best_snapshot: Option<f32>,
+    best_saved: Option<f32>,
+    checkpoint_bytes: u64,
+    min_free_bytes: u64,
+    start_time: Instant,
+    last_report: (usize, Instant),
+    last_superbatch_end: Instant,
+    interval_profile: Profile,
+    total_profile: Profile,
+    stall: StallDetector,
+    summary: RunSummary,
+    /// The engine net of the last checkpoint written.
+    last_saved: Option<String>,
+    /// Set by an early stop; the loader then ends bullet's run.
+    stop: Arc<AtomicBool>,
+}
+
+impl<'a> Progress<'a> {
+    fn new(
+        config: &'a Config,
+        graph: GraphSettings,
+        positions_per_superbatch: usize,
+        samples: Samples,
+        ema: Option<Ema>,
+        loader_stats: Arc<LoaderStats>,
+        filter_active: bool,
+    ) -> Self {
+        let val_window = config.metric_window.map(MetricWindow::new);
+        if val_window.is_some() && samples.val.is_empty() {
+            warn!("--accumulate-metrics averages the val loss, which needs --val-split");
+        }
+        let checkpoint_bytes = checkpoint::estimate_checkpoint_bytes(
+            graph.hl_size,
+            NUM_INPUT_BUCKETS,
+            NUM_OUTPUT_BUCKETS,
+            config.single_perspective,
+        );
+        Self {
+            config,
+            graph,
+            shape: graph.shape(),
+            positions_per_superbatch,
+            samples,
+            ema,
+            loader_stats,
+            filter_active,
+            loss_target: config.stop_at_loss.map(LossTarget::new),
+            val_window,
+            guard: config.recover_on_divergence.map(|_| DivergenceGuard::default()),
+            restore: config
+                .load_weights
+                .as_ref()
+                .map(|path| RestorePoint { weights: path.clone(), start_superbatch: config.start_superbatch }),
+            plateau: config.reduce_on_plateau.map(PlateauDetector::new),
+            best_snapshot: None,
+            best_saved: None,
+            checkpoint_bytes,
+            min_free_bytes: config.min_free_mb * 1024 * 1024,
+            start_time: Instant::now(),
+            last_report: (config.start_superbatch - 1, Instant::now()),
+            last_superbatch_end: Instant::now(),
+            interval_profile: Profile::default(),
+            total_profile: Profile::default(),
+            stall: StallDetector::default(),
+            summary: RunSummary {
+                net_id: config.net_id.clone(),
+                completed: false,
+                start_superbatch: config.start_superbatch,
+                last_superbatch: config.start_superbatch - 1,
+                positions_seen: 0,
+                wall_time_secs: 0.0,
+                positions_per_sec: 0.0,
+                final_net: None,
+                last_val_loss: None,
+                best_val_loss: None,
+                holdout_loss: None,
+                config: config.clone(),
+            },
+            last_saved: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Everything done after `superbatch`: the EMA, the loss checks, saves
+    /// and reports. Nothing once the run has stopped early, for the batches
+    /// bullet still had queued.
+    fn after_superbatch<LR: LrScheduler>(
+        &mut self,
+        superbatch: usize,
+        trainer: &TrainerView,
+        lr: &PlateauLR<LR>,
+        batch_queue: usize,
+    ) {
+        if self.stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let config = self.config;
+        let end = config.superbatches;
+        self.summary.last_superbatch = superbatch;
+        self.time_superbatch();
+        self.update_ema(superbatch, trainer);
+
+        let interval_save =
+            crate::schedule::is_interval_save(superbatch, end, config.save_rate, config.final_only_save);
+        let chunk_save =
+            config.chunk_superbatches.is_some_and(|chunk| chunks::is_chunk_boundary(superbatch, chunk, end));
+        // the window and the loss target need a value every superbatch, the
+        // log only on reports and the -best pointer on saves
+        let reporting = crate::schedule::should_report(superbatch, config.report_interval);
+        let needs_loss = self.loss_target.is_some() || self.guard.is_some() || self.plateau.is_some();
+        let tracks_best = config.snapshot_on_best.is_some() || interval_save || chunk_save || superbatch == end;
+        let val_loss = (!self.samples.val.is_empty()
+            && (self.val_window.is_some() || needs_loss || tracks_best || reporting))
+            .then(|| self.loss(&self.samples.val, trainer));
+        if let (Some(window), Some(loss)) = (&mut self.val_window, val_loss) {
+            window.push(loss);
+        }
+
+        let sampled_loss = needs_loss.then(|| val_loss.unwrap_or_else(|| self.loss(&self.samples.stop, trainer)));
+        // checked before any save, so a restore point never holds diverged weights
+        if let (Some(guard), Some(loss)) = (&mut self.guard, sampled_loss) {
+            if let Some(divergence) = guard.check(loss) {
+                recover(config, superbatch, divergence, self.restore.as_ref());
+            }
+        }
+        self.snapshot_if_best(superbatch, val_loss, trainer);
+        let stop_reason =
+            self.loss_target.as_mut().zip(sampled_loss).and_then(|(target, loss)| target.check(loss, superbatch, end));
+        if let Some(loss) = sampled_loss {
+            self.update_plateau(superbatch, loss, lr);
+        }
+
+        if interval_save || chunk_save || (superbatch < end && stop_reason.is_some()) {
+            self.save(superbatch, val_loss, chunk_save, trainer);
+        }
+        if superbatch == end {
+            self.finish_final(superbatch, val_loss, trainer);
+        }
+        if crate::schedule::is_last_superbatch(superbatch, end, stop_reason.is_some()) {
+            self.score_holdout(trainer);
+            self.report_quant_scales(trainer);
+        }
+        if let Some(reason) = stop_reason {
+            println!("Stopping early: {}", reason);
+            self.stop.store(true, Ordering::Relaxed);
+            return;
+        }
+
+        // the graph cannot change width in place, so the wide run is a new process
+        let growing =
+            config.progressive_hl.filter(|growth| growth.at == superbatch && growth.start == self.shape.hl_size);
+        if let Some(growth) = growing {
+            grow(&self.shape, growth, trainer.net(&self.shape), &self.checkpoint_dir(superbatch));
+        }
+
+        // the final net is never skipped; hold the run until there is room for it
+        if superbatch + 1 == end {
+            self.wait_for_room();
+        }
+
+        if reporting {
+            self.report(superbatch, val_loss, lr, trainer, batch_queue);
+        }
+    }
+
+    fn checkpoint_dir(&self, superbatch: usize) -> String {
+        format!("{}/{}-{}", self.config.output_directory, self.config.net_id, superbatch)
+    }
+
+    fn describe(&self, superbatch: usize) -> String {
+        format!("{} superbatch {}", self.config.net_id, superbatch)
+    }
+
+    /// Mean loss of the float net on `sample`.
+    fn loss(&self, sample: &[(String, f32)], trainer: &TrainerView) -> f32 {
+        mean_loss(sample, |fen| (trainer.eval)(fen) * self.graph.output_scale)
+    }
+
+    /// Splits the time since the last superbatch into loading, handing over
+    /// and the rest; always timed, the stall check reads it even without
+    /// --profile.
+    fn time_superbatch(&mut self) {
+        let (loading, handoff) = self.loader_stats.take_times();
+        let wall = self.last_superbatch_end.elapsed();
+        self.interval_profile.add(profile::LOADING, loading);
+        self.interval_profile.add(profile::QUEUE_FULL, handoff);
+        self.interval_profile.add(profile::IDLE, wall.saturating_sub(loading + handoff));
+        self.last_superbatch_end = Instant::now();
+    }
+
+    fn update_ema(&mut self, superbatch: usize, trainer: &TrainerView) {
+        let Some(ema) = &mut self.ema else { return };
+        match trainer.net(&self.shape) {
+            Some(current) => ema.update(&current),
+            None => warn!("could not read weights for the EMA at superbatch {}", superbatch),
+        }
+    }
+
+    fn snapshot_if_best(&mut self, superbatch: usize, val_loss: Option<f32>, trainer: &TrainerView) {
+        let config = self.config;
+        let Some(path) = &config.snapshot_on_best else { return };
+        let (shape, l1_scale) = (&self.shape, self.graph.l1_scale);
+        let snapshot = checkpoint::snapshot_if_best(&mut self.best_snapshot, val_loss, |loss| {
+            let what = format!("{} superbatch {}, val loss {:.6}", config.net_id, superbatch, loss);
+            trainer
+                .net(shape)
+                .ok_or_else(|| "could not read the weights".to_string())
+                .and_then(|current| snapshot_net(&current, shape, l1_scale, config, path, &what))
+        });
+        match snapshot {
+            Some((loss, Ok(()))) => info!("[snapshot] val loss {:.6} is a new best, wrote {}", loss, path),
+            Some((_, Err(e))) => warn!("could not write the best-net snapshot {}: {}", path, e),
+            None => {}
+        }
+    }
+
+    fn update_plateau<LR: LrScheduler>(&mut self, superbatch: usize, loss: f32, lr: &PlateauLR<LR>) {
+        let Some(plateau) = &mut self.plateau else { return };
+        let current = lr.lr(1, superbatch);
+        if let Some(reduced) = plateau.update(loss, current) {
+            lr.pin(reduced);
+            info!(
+                "[plateau] smoothed loss {:.6} not {} better in {} superbatches; lr {:.6} -> {:.6}",
+                plateau.smoothed().unwrap_or_default(),
+                plateau.settings.min_delta,
+                plateau.settings.patience,
+                current,
+                reduced
+            );
+        }
+    }
+
+    /// An interval, chunk or early-stop checkpoint, skipped with a warning
+    /// when the disk is too full for it.
+    fn save(&mut self, superbatch: usize, val_loss: Option<f32>, chunk_save: bool, trainer: &TrainerView) {
+        let config = self.config;
+        let checkpoint_dir = self.checkpoint_dir(superbatch);
+        let available = fs2::available_space(&config.output_directory).unwrap_or(u64::MAX);
+        if !checkpoint::has_room_for_save(available, self.checkpoint_bytes, self.min_free_bytes) {
+            warn!(
+                "skipping save at superbatch {}: {:.1} MB free, need {:.1} MB",
+                superbatch,
+                checkpoint::mb(available),
+                checkpoint::mb(self.checkpoint_bytes + self.min_free_bytes)
+            );
+            return;
+        }
+        if let Err(e) = fs::create_dir_all(&checkpoint_dir) {
+            warn!("could not create {}: {}", checkpoint_dir, e);
+            return;
+        }
+        (trainer.save)(&checkpoint_dir);
+        let net_path = format!("{}/quantised.bin", checkpoint_dir);
+        describe_net(config, &self.shape, &net_path, &self.describe(superbatch));
+        self.last_saved = Some(net_path);
+        write_checkpoint_metadata(&checkpoint_dir, superbatch, val_loss);
+        info!("Saved [{}-{}] to {}", config.net_id, superbatch, checkpoint_dir);
+        self.restore = Some(RestorePoint {
+            weights: format!("{}/optimiser_state/weights.bin", checkpoint_dir),
+            start_superbatch: superbatch + 1,
+        });
+        if config.also_save_fp32 {
+            save_fp32(trainer.net(&self.shape), &checkpoint_dir);
+        }
+        self.update_pointers(superbatch, val_loss);
+        if let Some(ema) = &self.ema {
+            save_ema(ema, &checkpoint_dir, &self.shape, self.graph.l1_scale, config, superbatch);
+        }
+        if chunk_save {
+            record_chunk(config, &checkpoint_dir, superbatch);
+        }
+    }
+
+    /// The rest of the final checkpoint, which bullet has just written.
+    fn finish_final(&mut self, superbatch: usize, val_loss: Option<f32>, trainer: &TrainerView) {
+        let config = self.config;
+        let checkpoint_dir = self.checkpoint_dir(superbatch);
+        let net_path = format!("{}/quantised.bin", checkpoint_dir);
+        describe_net(config, &self.shape, &net_path, &self.describe(superbatch));
+        self.last_saved = Some(net_path);
+        write_checkpoint_metadata(&checkpoint_dir, superbatch, val_loss);
+        if let Some(path) = &config.weights_histogram {
+            let histograms: Vec<_> = ["l0w", "l0f", "l1w"]
+                .into_iter()
+                .filter_map(|id| Some((id, weights::histogram(&(trainer.weights)(id)?, HISTOGRAM_BINS))))
+                .collect();
+            match fs::write(path, weights::histogram_csv(&histograms)) {
+                Ok(()) => info!("Wrote weight histograms to {}", path),
+                Err(e) => warn!("could not write {}: {}", path, e),
+            }
+        }
+        if config.also_save_fp32 {
+            save_fp32(trainer.net(&self.shape), &checkpoint_dir);
+        }
+        self.update_pointers(superbatch, val_loss);
+        if let Some(ema) = &self.ema {
+            save_ema(ema, &checkpoint_dir, &self.shape, self.graph.l1_scale, config, superbatch);
+        }
+    }
+
+    /// Points `-latest` at the checkpoint of `superbatch`, and `-best` when
+    /// its val loss is a new best.
+    fn update_pointers(&mut self, superbatch: usize, val_loss: Option<f32>) {
+        let config = self.config;
+        update_pointer(&config.output_directory, &config.net_id, checkpoint::LATEST, superbatch);
+        if checkpoint::is_new_best(&mut self.best_saved, val_loss) {
+            update_pointer(&config.output_directory, &config.net_id, checkpoint::BEST, superbatch);
+        }
+    }
+
+    /// `--holdout-file`: scores the net the run ends with.
+    fn score_holdout(&mut self, trainer: &TrainerView) {
+        let config = self.config;
+        let Some(path) = &config.holdout_file else { return };
+        let loss = self.loss(&self.samples.holdout, trainer);
+        info!("[holdout] loss {:.6} on {} positions of {}", loss, self.samples.holdout.len(), path);
+        self.summary.holdout_loss = Some(loss);
+    }
+
+    fn report_quant_scales(&self, trainer: &TrainerView) {
+        if self.samples.quant.is_empty() {
+            return;
+        }
+        match trainer.net(&self.shape) {
+            Some(current) => {
+                let float_eval = |fen: &str| (trainer.eval)(fen) * EVAL_SCALE;
+                let results = compare_quant_scales(
+                    &current,
+                    &self.shape,
+                    self.graph.l1_scale,
+                    self.config,
+                    &self.samples.quant,
+                    float_eval,
+                );
+                println!("Quantisation error against the float net on {} positions:", self.samples.quant.len());
+                print!("{}", ScaleTable(&results));
+            }
+            None => warn!("could not read the weights for --compare-quant-scales"),
+        }
+    }
+
+    /// Holds the run until the disk has room for the final net.
+    fn wait_for_room(&self) {
+        loop {
+            let available = fs2::available_space(&self.config.output_directory).unwrap_or(u64::MAX);
+            if checkpoint::has_room_for_save(available, self.checkpoint_bytes, self.min_free_bytes) {
+                break;
+            }
+            warn!(
+                "not enough disk space for the final net ({:.1} MB free, need {:.1} MB), retrying in 60s",
+                checkpoint::mb(available),
+                checkpoint::mb(self.checkpoint_bytes + self.min_free_bytes)
+            );
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        }
+    }
+
+    /// The `--report-interval` lines.
+    fn report<LR: LrScheduler>(
+        &mut self,
+        superbatch: usize,
+        val_loss: Option<f32>,
+        lr: &PlateauLR<LR>,
+        trainer: &TrainerView,
+        batch_queue: usize,
+    ) {
+        let config = self.config;
+        let (last_superbatch, last_time) = self.last_report;
+        let done = superbatch - last_superbatch;
+        let secs = last_time.elapsed().as_secs_f64();
+        let throughput = (done * self.positions_per_superbatch) as f64 / secs.max(1e-9);
+        let remaining = config.superbatches.saturating_sub(superbatch);
+        let eta = secs / done.max(1) as f64 * remaining as f64;
+
+        let lr = lr.lr(1, superbatch);
+        info!(
+            "[report] superbatch {}/{} | lr {:.6} (l1 {:.6}) | {:.0} pos/s | elapsed {:.0}s | eta {:.0}s",
+            superbatch,
+            config.superbatches,
+            lr,
+            lr * self.graph.l1_scale,
+            throughput,
+            self.start_time.elapsed().as_secs_f64(),
+            eta,
+        );
+        self.last_report = (superbatch, Instant::now());
+
+        if config.print_layer_lr {
+            info!("[layer lr] {}", net::layer_lrs(lr, self.graph.l1_scale));
+        }
+
+        if let Some(loss) = val_loss {
+            self.report_val_loss(loss);
+        }
+        self.report_sample_losses(trainer);
+
+        if let Some(path) = &config.summary_json {
+            self.summary.update_timing(self.start_time, self.positions_per_superbatch);
+            if let Err(e) = self.summary.write(path) {
+                warn!("could not write {}: {}", path, e);
+            }
+        }
+
+        self.report_loader();
+        self.report_profile(throughput, batch_queue);
+        if logging::enabled(logging::Level::Verbose) {
+            self.report_weights(trainer);
+        }
+    }
+
+    fn report_val_loss(&mut self, loss: f32) {
+        let val_positions = self.samples.val.len();
+        match self.val_window.as_ref().and_then(|w| Some((w.mean()?, w.len()))) {
+            Some((mean, n)) => {
+                info!(
+                    "[val] loss {:.6} (mean of last {}: {:.6}) on {} held-out positions",
+                    loss, n, mean, val_positions
+                )
+            }
+            None => info!("[val] loss {:.6} on {} held-out positions", loss, val_positions),
+        }
+        self.summary.last_val_loss = Some(loss);
+        self.summary.best_val_loss = Some(self.summary.best_val_loss.map_or(loss, |best| best.min(loss)));
+    }
+
+    /// `--loss-clip`'s rate and `--loss-by-bucket`.
+    fn report_sample_losses(&self, trainer: &TrainerView) {
+        let position_loss =
+            |fen: &str, target: f32| metrics::position_loss((trainer.eval)(fen) * self.graph.output_scale, target);
+        if let Some(clip) = self.graph.loss_clip {
+            let sample = if self.samples.val.is_empty() { &self.samples.clip } else { &self.samples.val };
+            let losses = sample.iter().map(|(fen, target)| position_loss(fen, *target));
+            info!(
+                "[loss clip] {:.2}% of {} sampled positions above {}",
+                100.0 * metrics::clipped_fraction(losses, clip),
+                sample.len(),
+                clip
+            );
+        }
+
+        if !self.samples.bucket.is_empty() {
+            let losses: Vec<(usize, f32)> = self
+                .samples
+                .bucket
+                .iter()
+                .map(|(fen, target, bucket)| (*bucket, position_loss(fen, *target)))
+                .collect();
+            let buckets: Vec<String> = metrics::mean_by_bucket(&losses, NUM_OUTPUT_BUCKETS)
+                .iter()
+                .enumerate()
+                .map(|(bucket, mean)| match mean {
+                    Some((loss, count)) => format!("{} {:.6} ({})", bucket, loss, count),
+                    None => format!("{} -", bucket),
+                })
+                .collect();
+            info!("[bucket loss] {}", buckets.join(" | "));
+        }
+    }
+
+    /// What the data loader counted since the last report.
+    fn report_loader(&self) {
+        let stats = &self.loader_stats;
+        if self.filter_active {
+            let (seen, dropped) = stats.take_filtered();
+            info!("[filter] dropped {:.2}% of {} positions", 100.0 * dropped as f64 / seen.max(1) as f64, seen);
+        }
+
+        let bad = stats.take_bad_records();
+        if bad > 0 {
+            info!("[data] skipped {} bad records", bad);
+        }
+
+        if let Some((low, high, total)) = stats.take_clamped() {
+            let pct = |n: u64| 100.0 * n as f64 / total.max(1) as f64;
+            info!("[targets] clamped low {:.3}% | high {:.3}% | of {} positions", pct(low), pct(high), total);
+        }
+    }
+
+    fn report_profile(&mut self, throughput: f64, batch_queue: usize) {
+        let config = self.config;
+        if config.profile {
+            info!("[profile] {}", self.interval_profile.line());
+            if let Some(hint) = self.interval_profile.hint() {
+                info!("[profile] {}", hint);
+            }
+        }
+        if !config.deterministic {
+            let data_wait = self.interval_profile.share(profile::LOADING);
+            if let Some(suggestion) = self.stall.observe(throughput, data_wait, config.threads, batch_queue) {
+                warn!("{}", suggestion);
+            }
+        }
+        let interval = std::mem::take(&mut self.interval_profile);
+        self.total_profile.merge(&interval);
+    }
+
+    /// `--verbose`: weight statistics and bucket occupancy.
+    fn report_weights(&self, trainer: &TrainerView) {
+        for id in ["l0w", "l0f", "l1w"] {
+            if let Some(values) = (trainer.weights)(id) {
+                let s = weights::stats(&values);
+                verbose!(
+                    "[weights] {:<3} min {:+.4} max {:+.4} mean {:+.5} mean|w| {:.5}",
+                    id,
+                    s.min,
+                    s.max,
+                    s.mean,
+                    s.mean_abs
+                );
+            }
+        }
+        let counts = self.loader_stats.take_bucket_counts();
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        let occupancy: Vec<String> =
+            counts.iter().enumerate().map(|(b, &c)| format!("{}:{:.1}%", b, 100.0 * c as f64 / total)).collect();
+        verbose!("[buckets] {}", occupancy.join(" "));
+    }
+
+    /// The end-of-run output, at the end or after an early stop; the final
+    /// net is the last one actually written.
+    fn finish(&mut self) -> Result<(), TrainError> {
+        let interval = std::mem::take(&mut self.interval_profile);
+        self.total_profile.merge(&interval);
+        self.summary.final_net = self.last_saved.clone();
+        finish_run(
+            self.config,
+            self.summary.last_superbatch,
+            self.start_time,
+            self.positions_per_superbatch,
+            &self.total_profile,
+            &mut self.summary,
+        )
+    }
+}
+
+fn replay_log(config: &Config, path: &str) -> Result<(), TrainError> {
+    let log = replay::read_log(path)?;
+    let lr = lr_schedule::from_config(config);
+    if config.loader_blends_wdl() {
+        println!("WDL: blended per position by the loader, bullet proportion 0");
+    } else {
+        println!("WDL: constant proportion {}", config.wdl_proportion());
+    }
+
+    let decisions = replay::replay(config, &log, |superbatch| lr.lr(1, superbatch));
+    for decision in &decisions {
+        println!("{}", decision);
+    }
+    let saves = decisions.iter().filter(|d| d.save).count();
+    match decisions.last() {
+        Some(last) if last.stop.is_some() => {
+            println!("Replayed {} superbatches, {} saves; the run would stop early", decisions.len(), saves)
+        }
+        Some(last) if last.superbatch < config.superbatches => println!(
+            "Replayed {} superbatches, {} saves; the log ends at superbatch {} of {}",
+            decisions.len(),
+            saves,
+            last.superbatch,
+            config.superbatches
+        ),
+        Some(_) => println!("Replayed {} superbatches, {} saves; the run would finish", decisions.len(), saves),
+        None => println!("No superbatches of {}..={} in {}", config.start_superbatch, config.superbatches, path),
+    }
+    Ok(())
+}
+
+/// The batch queue to run with: the requested or default size, checked
+/// against `--max-ram-mb`. Only the default is lowered to fit; explicit
+/// settings that don't fit are refused with a suggestion.
+fn fit_batch_queue(config: &Config, shape: &NetShape) -> Result<usize, TrainError> {
+    let requested = config.batch_queue.unwrap_or(DEFAULT_BATCH_QUEUE);
+    let Some(budget_mb) = config.max_ram_mb else { return Ok(requested) };
+    let budget = budget_mb * 1024 * 1024;
+    let on_host = !backend::IS_GPU;
+    let estimate = |queue| MemoryEstimate::new(config.batch_size, data::RECORD_SIZE, queue, shape, on_host);
+
+    if estimate(requested).total() <= budget {
+        info!("Memory:        about {} of --max-ram-mb {}", estimate(requested), budget_mb);
+        return Ok(requested);
+    }
+    match memory::fit_batch_queue(budget, requested, config.batch_size, data::RECORD_SIZE, shape, on_host) {
+        Some(queue) if config.batch_queue.is_none() => {
+            info!(
+                "Memory:        batch queue lowered to {} to fit --max-ram-mb {}: {}",
+                queue,
+                budget_mb,
+                estimate(queue)
+            );
+            Ok(queue)
+        }
+        Some(queue) => Err(TrainError::MemoryBudget(format!(
+            "--batch-queue {} needs about {}, more than --max-ram-mb {}; try --batch-queue {}",
+            requested,
+            estimate(requested),
+            budget_mb,
+            queue
+        ))),
+        None => Err(TrainError::MemoryBudget(format!(
+            "even a batch queue of 1 needs about {} with batches of {} positions, more than --max-ram-mb {}",
+            estimate(1),
+            config.batch_size,
+            budget_mb
+        ))),
+    }
+}
+
+/// The `--eval-net` quantised net and the integer eval scale to score with.
+fn load_eval_net(config: &Config) -> Result<(QuantisedNet, i32), TrainError> {
+    read_quantised(config, config.eval_net.as_deref().expect("checked by Config::from_args"))
+}
+
+/// The net architecture the compiled constants and `config` describe.
+fn configured_shape(config: &Config) -> NetShape {
+    NetShape {
+        hl_size: config.full_hl_size,
+        input_buckets: NUM_INPUT_BUCKETS,
+        output_buckets: NUM_OUTPUT_BUCKETS,
+        single_perspective: config.single_perspective,
+        output_factoriser: config.output_factoriser,
+    }
+}
+
+/// `--compare-quant-scales`: `net` quantised at each scale and scored with the
+/// integer inference against `float_eval` (centipawns) on `sample`.
+fn compare_quant_scales(
+    net: &FloatNet,
+    shape: &NetShape,
+    l1_scale: f32,
+    config: &Config,
+    sample: &[(String, ChessBoard)],
+    float_eval: impl Fn(&str) -> f32,
+) -> Vec<ScaleResult> {
+    let float_cps: Vec<f32> = sample.iter().map(|(fen, _)| float_eval(fen)).collect();
+    config
+        .compare_quant_scales
+        .iter()
+        .map(|&qa| {
+            let error = net::quantise_at(net, shape, l1_scale, qa)
+                .and_then(|values| QuantisedNet::from_values(*shape, &values, qa).map_err(|e| e.to_string()))
+                .map(|quantised| {
+                    QuantError::measure(sample.iter().zip(&float_cps).map(|((_, board), &cp)| {
+                        let quantised_cp =
+                            (!quantised.overflows(board)).then(|| quantised.eval(board, EVAL_SCALE as i32));
+                        (cp, quantised_cp)
+                    }))
+                });
+            ScaleResult { qa, error }
+        })
+        .collect()
+}
+
+/// A quantised net of the configured shape and the engine's eval scale.
+fn read_quantised(config: &Config, net_path: &str) -> Result<(QuantisedNet, i32), TrainError> {
+    let net = QuantisedNet::read(net_path, configured_shape(config))
+        .map_err(|e| TrainError::LoadWeights(format!("{}: {}", net_path, e)))?;
+    if let Some(description) = &net.description {
+        info!("Net:           {}", description);
+    }
+    Ok((net, config.engine_scale.unwrap_or(EVAL_SCALE) as i32))
+}
+
+/// `--fen-list`: scores every FEN with the engine's integer inference. Bad
+/// lines are reported by number and skipped, so one typo doesn't end the run.
+fn eval_fen_list(config: &Config, path: &str) -> Result<(), TrainError> {
+    let (net, eval_scale) = load_eval_net(config)?;
+
+    let mut out: Box<dyn Write> = match &config.fen_output {
+        Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut malformed = 0;
+    let scored = inference::score_fens(&net, eval_scale, BufReader::new(File::open(path)?), &mut out, |line, e| {
+        warn!("{} line {}: {}", path, line, e);
+        malformed += 1;
+    })?;
+    out.flush()?;
+    info!("Scored {} FENs, skipped {} malformed", scored, malformed);
+    Ok(())
+}
+
+/// `--recover-on-divergence`: replaces this process with a rerun from
+/// `restore` at a reduced LR, or aborts once the retries are used up.
+fn recover(config: &Config, superbatch: usize, divergence: Divergence, restore: Option<&RestorePoint>) -> ! {
+    let retries = config.recover_on_divergence.unwrap_or(0);
+    let args: Vec<String> = env::args().collect();
+    let lrs = (config.initial_lr, config.final_lr, config.l1_lr);
+    let Some(((initial, final_lr, _), next)) = recovery::next_attempt(&args, restore, lrs, retries) else {
+        eprintln!("Error: training diverged at superbatch {}: {}; no recovery retries left", superbatch, divergence);
+        process::exit(1);
+    };
+    let from = match restore {
+        Some(point) => format!("{} at superbatch {}", point.weights, point.start_superbatch),
+        None => "the start, no checkpoint saved yet".to_string(),
+    };
+    notice!(
+        "divergence at superbatch {}: {}; restarting from {} with LR {} -> {} ({} retries left)",
+        superbatch,
+        divergence,
+        from,
+        initial,
+        final_lr,
+        retries - 1
+    );
+
+    restart(&next);
+}
+
+/// `--progressive-hl`: widens the net after superbatch `growth.at` and
+/// replaces this process with a rerun that trains the wide net from there.
+fn grow(shape: &NetShape, growth: ProgressiveHl, weights: Option<FloatNet>, checkpoint_dir: &str) -> ! {
+    let path = format!("{}/widened.fp32", checkpoint_dir);
+    let written = weights
+        .ok_or_else(|| "could not read the weights back".to_string())
+        .and_then(|narrow| grow::widen(&narrow, shape, growth.end))
+        .and_then(|wide| {
+            fs::create_dir_all(checkpoint_dir)
+                .and_then(|()| archive::write(&path, &wide.named_tensors()))
+                .map_err(|e| format!("{}: {}", path, e))
+        });
+    if let Err(e) = written {
+        eprintln!("Error: --progressive-hl: cannot widen the net after superbatch {}: {}", growth.at, e);
+        process::exit(1);
+    }
+    info!("[grow] hl_size {} -> {} after superbatch {}, continuing from {}", growth.start, growth.end, growth.at, path);
+
+    let args: Vec<String> = env::args().collect();
+    let restore = RestorePoint { weights: path, start_superbatch: growth.at + 1 };
+    restart(&recovery::restart_args(&args, &restore));
+}
+
+/// Replaces this process with the trainer binary run with `args`, the
+/// program name included.
+fn restart(args: &[String]) -> ! {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Error: cannot find the trainer binary to restart: {}", e);
+            process::exit(1);
+        }
+    };
+    let mut command = process::Command::new(exe);
+    command.args(&args[1..]);
+    // exec releases this process's device memory before the rerun allocates its own
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let e = command.exec();
+        eprintln!("Error: could not restart the trainer: {}", e);
+        process::exit(1);
+    }
+    #[cfg(not(unix))]
+    match command.status() {
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!("Error: could not restart the trainer: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// End-of-run output, shared by the normal end and early stops.
+fn finish_run(
+    config: &Config,
+    last_superbatch: usize,
+    start_time: Instant,
+    positions_per_superbatch: usize,
+    profile: &Profile,
+    summary: &mut RunSummary,
+) -> Result<(), TrainError> {
+    println!(
+        "Training finished: superbatches {}-{} in {:.0}s",
+        config.start_superbatch,
+        last_superbatch,
+        start_time.elapsed().as_secs_f64()
+    );
+    if config.chunk_superbatches.is_some() {
+        if let Err(e) = chunks::finish(&config.output_directory, &config.run_name) {
+            warn!("could not remove the chunk sentinel of {}: {}", config.run_name, e);
+        }
+    }
+    if config.profile {
+        println!("Loader time breakdown:\n{}", profile.table());
+    }
+    if let Some(path) = &config.summary_json {
+        summary.completed = true;
+        summary.last_superbatch = last_superbatch;
+        summary.update_timing(start_time, positions_per_superbatch);
+        summary.write(path)?;
+        info!("Wrote run summary to {}", path);
+    }
+    if let (Some(limit), Some(net_path)) = (config.sanity_startpos, &summary.final_net) {
+        let (net, eval_scale) = read_quantised(config, net_path)?;
+        let cp = inference::check_startpos(&net, eval_scale, limit).map_err(|cp| TrainError::StartposEval {
+            net: net_path.clone(),
+            cp,
+            limit,
+        })?;
+        info!("Startpos eval: {} cp (within --sanity-startpos {})", cp, limit);
+    }
+    Ok(())
+}
+
+/// `(fen, target)` pairs spread through the data file, for measuring the loss
+/// through `trainer.eval` outside of training.
+fn loss_sample(
+    path: &str,
+    range: Range<u64>,
+    positions: usize,
+    transform: &TargetTransform,
+    filter: &RecordFilter,
+) -> Result<Vec<(String, f32)>, DataError> {
+    let records = data::sample_records_in(path, range, positions)?;
+    Ok(records.iter().filter(|b| filter.keep(b)).map(|b| (data::to_fen(b), transform.target(b))).collect())
+}
+
+/// Like [`loss_sample`], with each position's output bucket.
+fn bucket_loss_sample(
+    path: &str,
+    range: Range<u64>,
+    positions: usize,
+    transform: &TargetTransform,
+    filter: &RecordFilter,
+) -> Result<Vec<(String, f32, usize)>, DataError> {
+    let records = data::sample_records_in(path, range, positions)?;
+    Ok(records
+        .iter()
+        .filter(|b| filter.keep(b))
+        .map(|b| (data::to_fen(b), transform.target(b), data::material_bucket(b, NUM_OUTPUT_BUCKETS)))
+        .collect())
+}
+
+/// Squared error between `sigmoid(eval)` and the target, as trained on.
+fn mean_loss(sample: &[(String, f32)], eval: impl Fn(&str) -> f32) -> f32 {
+    let total: f32 = sample.iter().map(|(fen, target)| metrics::position_loss(eval(fen), *target)).sum();
+    total / sample.len().max(1) as f32
+}
+
+/// The run metadata next to the checkpoint at `path`, fetched for a URL.
+fn checkpoint_metadata(config: &Config, path: &str) -> Result<HashMap<String, String>, String> {
+    if checkpoint::is_url(path) {
+        checkpoint::fetch_metadata(path, &config.run_name)
+    } else {
+        resume::previous_metadata(&resume::checkpoint_dir(path), &config.run_name)
+    }
+}
+
+/// Re-expresses bullet's checkpoint weights `net`, loaded from `path`, for
+/// this run's `l1_scale` when their run metadata records another one; a run
+/// from before `--l1-lr` trained at 1. Weights without metadata are taken as
+/// trained at this run's scale. Returns whether `net` changed.
+fn match_l1_scale(config: &Config, path: &str, net: &mut FloatNet, l1_scale: f32) -> Result<bool, TrainError> {
+    let trained = match checkpoint_metadata(config, path) {
+        Ok(metadata) => match metadata.get("l1_lr_scale") {
+            Some(value) => {
+                value.parse::<f32>().ok().filter(|scale| scale.is_finite() && *scale > 0.0).ok_or_else(|| {
+                    TrainError::LoadWeights(format!("{}: l1_lr_scale {} is not a positive number", path, value))
+                })?
+            }
+            None => 1.0,
+        },
+        Err(e) => {
+            if l1_scale != 1.0 {
+                notice!("{}; taking {} as trained at l1 scale {}", e, path, l1_scale);
+            }
+            return Ok(false);
+        }
+    };
+    if trained == l1_scale {
+        return Ok(false);
+    }
+    info!("Rescaling:     {} (trained at l1 scale {}, this run trains at {})", path, trained, l1_scale);
+    net::rescale_l1(net, trained, l1_scale);
+    Ok(true)
+}
+
+/// `--validate-shapes-against-header`: the architecture the run metadata next
+/// to `path` declares, which must be the configured one.
+fn header_shape(config: &Config, path: &str, configured: &NetShape) -> Result<NetShape, TrainError> {
+    let fail = |e: String| TrainError::LoadWeights(format!("{}: {}", path, e));
+    let metadata = checkpoint_metadata(config, path).map_err(fail)?;
+    let declared = resume::declared_shape(&metadata, configured).map_err(fail)?;
+    resume::compare_shapes(&declared, configured).map_err(fail)?;
+    Ok(declared)
+}
+
+/// Writes every tensor of `net` through `write`, i.e. into the graph.
+fn write_weights<E: fmt::Debug>(
+    net: &FloatNet,
+    mut write: impl FnMut(&str, &[f32]) -> Result<(), E>,
+) -> Result<(), TrainError> {
+    for (id, values) in net.named_tensors() {
+        write(&id, &values).map_err(|e| TrainError::LoadWeights(format!("writing {}: {:?}", id, e)))?;
+    }
+    Ok(())
+}
+
+fn save_fp32(weights: Option<FloatNet>, checkpoint_dir: &str) {
+    let path = format!("{}/weights.fp32", checkpoint_dir);
+    let result = match weights {
+        Some(weights) => archive::write(&path, &weights.named_tensors()),
+        None => Err(io::Error::other("could not read weights back")),
+    };
+    if let Err(e) = result {
+        warn!("could not write {}: {}", path, e);
+    }
+}
+
+/// Save format 2: writes `--net-description`, or one made from `what` and
+/// today's date, after the weights of the engine net at `path`.
+fn describe_net(config: &Config, shape: &NetShape, path: &str, what: &str) {
+    if config.save_format_version < net::DESCRIPTION_FORMAT {
+        return;
+    }
+    let mut text = config.net_description.clone().unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        net::auto_description(what, now)
+    });
+    while text.len() > net::MAX_DESCRIPTION_LEN {
+        text.pop();
+    }
+    if let Err(e) = net::write_description(path, shape.quantised_bytes(), &text) {
+        warn!("could not write the net description to {}: {}", path, e);
+    }
+}
+
+/// `--snapshot-on-best`: writes next to `path` and renames over it, so a
+/// tester polling `path` never reads a half-written net.
+fn snapshot_net(
+    net: &FloatNet,
+    shape: &NetShape,
+    l1_scale: f32,
+    config: &Config,
+    path: &str,
+    what: &str,
+) -> Result<(), String> {
+    let partial = format!("{}.partial", path);
+    let quantised = net::quantise(net, shape, l1_scale)?;
+    net::write_quantised(&partial, &quantised).map_err(|e| e.to_string())?;
+    describe_net(config, shape, &partial, what);
+    fs::rename(&partial, path).map_err(|e| e.to_string())
+}
+
+/// What `--list-checkpoints` shows besides the files: the val loss, when it
+/// was measured at the superbatch being saved.
+fn write_checkpoint_metadata(checkpoint_dir: &str, superbatch: usize, val_loss: Option<f32>) {
+    let mut entries = vec![("superbatch", superbatch.to_string())];
+    if let Some(loss) = val_loss {
+        entries.push(("val_loss", loss.to_string()));
+    }
+    let path = Path::new(checkpoint_dir).join(checkpoint::CHECKPOINT_METADATA);
+    if let Err(e) = fs::write(&path, resume::format_metadata(&entries)) {
+        warn!("could not write {}: {}", path.display(), e);
+    }
+}
+
+/// Records a verified `--chunk-superbatches` checkpoint as the resume point;
+/// one that fails verification leaves the previous chunk in place.
+fn record_chunk(config: &Config, checkpoint_dir: &str, superbatch: usize) {
+    let dir = Path::new(checkpoint_dir);
+    if let Err(e) = chunks::verify(dir, superbatch) {
+        warn!("chunk at superbatch {} failed verification, keeping the previous one: {}", superbatch, e);
+        return;
+    }
+    let state = ChunkState { superbatch, checkpoint: fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()) };
+    match chunks::write_state(&config.output_directory, &config.run_name, &state) {
+        Ok(()) => info!("[chunk] verified {}, a restart resumes at superbatch {}", checkpoint_dir, superbatch + 1),
+        Err(e) => warn!("could not record the chunk at superbatch {}: {}", superbatch, e),
+    }
+}
+
+fn update_pointer(output_dir: &str, net_id: &str, pointer: &str, superbatch: usize) {
+    if let Err(e) = checkpoint::update_pointer(output_dir, net_id, pointer, &format!("{}-{}", net_id, superbatch)) {
+        warn!("could not update {}-{}: {}", net_id, pointer, e);
+    }
+}
+
+/// `ema.bin` lives in the checkpoint directory the weights were loaded from.
+fn ema_path_for(load_path: &str) -> std::path::PathBuf {
+    let path = Path::new(load_path);
+    let dir = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new(".")) };
+    dir.join("ema.bin")
+}
+
+fn save_ema(ema: &Ema, checkpoint_dir: &str, shape: &NetShape, l1_scale: f32, config: &Config, superbatch: usize) {
+    if let Err(e) = ema.save(format!("{}/ema.bin", checkpoint_dir)) {
+        warn!("could not save EMA to {}: {}", checkpoint_dir, e);
+        return;
+    }
+    let Some(shadow) = &ema.shadow else { return };
+    if config.export_ema {
+        let path = format!("{}/quantised-ema.bin", checkpoint_dir);
+        let result = net::quantise(shadow, shape, l1_scale)
+            .and_then(|q| net::write_quantised(&path, &q).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                describe_net(config, shape, &path, &format!("{} superbatch {} EMA", config.net_id, superbatch));
+                info!("Saved EMA net to {}", path);
+            }
+            Err(e) => warn!("could not export EMA net: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{STARTPOS, args, temp_path};
+    use std::cell::{Cell, RefCell};
+
+    /// A run of `flags` writing into a fresh directory named `name`.
+    fn config(name: &str, flags: &[&str]) -> Config {
+        let dir = temp_path(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Config { output_directory: dir.display().to_string(), ..Config::from_args(&args(flags)).unwrap() }
+    }
+
+    fn progress(config: &Config, samples: Samples) -> Progress<'_> {
+        let stats = Arc::new(LoaderStats::new(NUM_OUTPUT_BUCKETS, false));
+        Progress::new(config, graph_settings(config), 1, samples, None, stats, false)
+    }
+
+    /// A net scoring positions with `eval`, recording the directories bullet
+    /// would save checkpoints into.
+    fn after(progress: &mut Progress, superbatch: usize, eval: &dyn Fn(&str) -> f32, saved: &RefCell<Vec<String>>) {
+        let weights = |_: &str| None;
+        let save = |dir: &str| saved.borrow_mut().push(dir.to_string());
+        let lr = PlateauLR::new(lr_schedule::from_config(progress.config));
+        progress.after_superbatch(superbatch, &TrainerView { eval, weights: &weights, save: &save }, &lr, 1);
+    }
+
+    #[test]
+    fn an_early_stop_ends_on_the_last_checkpoint_written() {
+        let config = config("early-stop", &["-n", "net", "-s", "10", "--save-rate", "2", "--stop-at-loss", "0.6"]);
+        let samples = Samples { stop: vec![(STARTPOS.to_string(), 0.0)], ..Samples::default() };
+        let mut progress = progress(&config, samples);
+        let saved = RefCell::new(Vec::new());
+
+        // a loss near 1, with the interval save at 2
+        after(&mut progress, 1, &|_| 10.0, &saved);
+        after(&mut progress, 2, &|_| 10.0, &saved);
+        assert!(!progress.stop.load(Ordering::Relaxed));
+        // the smoothed loss falls to 0.5 and stops the run, with no room for its save
+        progress.min_free_bytes = u64::MAX / 2;
+        after(&mut progress, 3, &|_| -10.0, &saved);
+        assert!(progress.stop.load(Ordering::Relaxed));
+        // what bullet had queued still trains, but is not bookkept
+        after(&mut progress, 4, &|_| -10.0, &saved);
+
+        progress.finish().unwrap();
+        let second = format!("{}/net-2", config.output_directory);
+        assert_eq!(saved.into_inner(), [second.as_str()]);
+        assert_eq!(progress.summary.last_superbatch, 3);
+        assert_eq!(progress.summary.final_net, Some(format!("{}/quantised.bin", second)));
+        #[cfg(unix)]
+        assert_eq!(fs::read_link(Path::new(&config.output_directory).join("net-latest")).unwrap(), Path::new("net-2"));
+        fs::remove_dir_all(&config.output_directory).unwrap();
+    }
+
+    #[test]
+    fn an_early_stop_without_a_checkpoint_has_no_final_net() {
+        let config = config("early-stop-unsaved", &["-n", "net", "-s", "10", "--stop-at-loss", "0.6"]);
+        let samples = Samples { stop: vec![(STARTPOS.to_string(), 0.0)], ..Samples::default() };
+        let mut progress = progress(&config, samples);
+        progress.min_free_bytes = u64::MAX / 2;
+        let saved = RefCell::new(Vec::new());
+
+        after(&mut progress, 1, &|_| -10.0, &saved);
+        progress.finish().unwrap();
+        assert!(saved.borrow().is_empty());
+        assert_eq!(progress.summary.final_net, None);
+        fs::remove_dir_all(&config.output_directory).unwrap();
+    }
+
+    #[test]
+    fn the_holdout_is_scored_once_on_the_final_net() {
+        let summary = temp_path("holdout-summary.json");
+        let flags = ["-n", "net", "-s", "3", "--report-interval", "1", "--holdout-file", "holdout.data"];
+        let config = config("holdout", &[&flags[..], &["--summary-json", summary.to_str().unwrap()]].concat());
+        let holdout = vec![(STARTPOS.to_string(), 1.0), (STARTPOS.to_string(), 0.0)];
+        let mut progress = progress(&config, Samples { holdout, ..Samples::default() });
+        let evals = Cell::new(0);
+        let eval = |_: &str| {
+            evals.set(evals.get() + 1);
+            0.0
+        };
+        let saved = RefCell::new(Vec::new());
+
+        for superbatch in 1..=3 {
+            after(&mut progress, superbatch, &eval, &saved);
+        }
+        progress.finish().unwrap();
+        // each holdout position once, after the last superbatch
+        assert_eq!(evals.get(), 2);
+        // sigmoid(0) is 0.5 away from either target
+        let json = fs::read_to_string(&summary).unwrap();
+        assert!(json.contains("\"holdout_loss\": 0.25"), "{}", json);
+        assert!(serde_json::from_str::<RunSummary>(&json).unwrap().completed);
+        fs::remove_file(&summary).unwrap();
+        fs::remove_dir_all(&config.output_directory).unwrap();
+    }
+
+    #[test]
+    fn compare_loss_is_the_mean_loss_of_one_eval_per_position() {
+        let config = config("compare-loss", &["-n", "net", "--load", "net.bin", "--compare-loss"]);
+        let sample = vec![(STARTPOS.to_string(), 1.0), (STARTPOS.to_string(), 0.0)];
+        let evals = Cell::new(0);
+        let loss = compare_loss(&config, &sample, |_| {
+            evals.set(evals.get() + 1);
+            0.0
+        });
+        // sigmoid(0) is 0.5 away from either target
+        assert_eq!(loss, 0.25);
+        assert_eq!(evals.get(), 2);
+        fs::remove_dir_all(&config.output_directory).unwrap();
+    }
+
+    #[test]
+    fn a_checkpoint_trained_at_another_l1_scale_loads_rescaled() {
+        let config = config("l1-rescale", &["-n", "net"]);
+        let dir = Path::new(&config.output_directory);
+        fs::write(dir.join(format!("{}.meta", config.run_name)), "l1_lr_scale=2\n").unwrap();
+        let weights = dir.join("net-10/optimiser_state/weights.bin");
+        fs::create_dir_all(weights.parent().unwrap()).unwrap();
+        fs::write(&weights, [0; 8]).unwrap();
+        let shape = NetShape {
+            hl_size: 2,
+            input_buckets: 1,
+            output_buckets: 2,
+            single_perspective: false,
+            output_factoriser: true,
+        };
+        let saved = FloatNet::from_fn(&shape, |id| Some(vec![0.1; shape.tensor_len(id)?])).unwrap();
+        let path = weights.to_str().unwrap();
+
+        // this run trains l1 at scale 1, so the graph gets the same effective weights
+        let loaded = load_weights(&config, path, &shape, l1_scale(&config), |_| Ok(saved.clone())).unwrap();
+        let loaded = loaded.expect("rescaled weights to write into the graph");
+        assert_eq!(loaded.l1w, vec![0.2; saved.l1w.len()]);
+        assert_eq!(loaded.l0w, saved.l0w);
+        assert_eq!(net::quantise(&loaded, &shape, 1.0), net::quantise(&saved, &shape, 2.0));
+        // at the scale it was trained at, the graph keeps what bullet loaded
+        assert!(load_weights(&config, path, &shape, 2.0, |_| Ok(saved.clone())).unwrap().is_none());
+        fs::remove_dir_all(&config.output_directory).unwrap();
+    }
+}
@@ -1,206 +1,204 @@
-use bullet::{
-    game::{
-        inputs::{ChessBucketsMirrored, get_num_buckets},
-        outputs::MaterialCount,
-    },
-    nn::{
-        InitSettings, Shape,
-        optimiser::{AdamW, AdamWParams},
-    },
-    trainer::{
-        save::SavedFormat,
-        schedule::{TrainingSchedule, TrainingSteps, lr, wdl},
-        settings::LocalSettings,
-    },
-    value::{ValueTrainerBuilder, loader::DirectSequentialDataLoader},
+use std::{env, process};
+
+use training::{
+    Config, ConfigError,
+    commands::Command,
+    config::{DataFormat, USAGE},
+    convert::{self, ConvertOptions},
+    datagen::{self, DatagenOptions},
+    info,
+    inspect::{self, InspectOptions},
+    logging, notice,
+    shuffle::{self, ShuffleOptions},
+    warn,
 };
-use std::env;
 
 fn main() {
-    // Parse command line arguments
     let args: Vec<String> = env::args().collect();
-    
-    // Defaults
-    let mut dataset_path = "data/baseline.data".to_string();
-    let mut superbatches: usize = 640;
-    let mut start_superbatch: usize = 1;
-    let mut load_weights: Option<String> = None;
-    let mut net_id = "sleepmind".to_string();
-    let mut threads: usize = 2;
-    let mut save_rate: usize = 10;
-    
-    // Parse arguments
-    let mut i = 1;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--data" | "-d" => {
-                i += 1;
-                if i < args.len() {
-                    dataset_path = args[i].clone();
-                }
-            }
-            "--superbatches" | "-s" => {
-                i += 1;
-                if i < args.len() {
-                    superbatches = args[i].parse().unwrap_or(640);
-                }
-            }
-            "--start" => {
-                i += 1;
-                if i < args.len() {
-                    start_superbatch = args[i].parse().unwrap_or(1);
-                }
-            }
-            "--load" | "-l" => {
-                i += 1;
-                if i < args.len() {
-                    load_weights = Some(args[i].clone());
-                }
-            }
-            "--name" | "-n" => {
-                i += 1;
-                if i < args.len() {
-                    net_id = args[i].clone();
-                }
-            }
-            "--threads" | "-t" => {
-                i += 1;
-                if i < args.len() {
-                    threads = args[i].parse().unwrap_or(2);
-                }
-            }
-            "--save-rate" => {
-                i += 1;
-                if i < args.len() {
-                    save_rate = args[i].parse().unwrap_or(10);
-                }
-            }
-            "--help" | "-h" => {
-                println!("SleepMind NNUE Trainer");
-                println!();
-                println!("Usage: training [OPTIONS]");
-                println!();
-                println!("Options:");
-                println!("  -d, --data <PATH>        Training data file (default: data/baseline.data)");
-                println!("  -s, --superbatches <N>   Number of superbatches (default: 640)");
-                println!("      --start <N>          Start superbatch (default: 1, use for resuming)");
-                println!("  -l, --load <PATH>        Load weights from file (.wgts)");
-                println!("  -n, --name <NAME>        Network ID for output (default: sleepmind)");
-                println!("  -t, --threads <N>        Number of threads (default: 2)");
-                println!("      --save-rate <N>      Save checkpoint every N superbatches (default: 10)");
-                println!("  -h, --help               Show this help");
-                println!();
-                println!("Examples:");
-                println!("  # First training run with 10 superbatches");
-                println!("  training -d data/hce_games.data -s 10 -n sleepmind_v1");
-                println!();
-                println!("  # Continue training from checkpoint");
-                println!("  training -d data/more_games.data -s 50 --start 11 -l checkpoints/sleepmind_v1-10.wgts -n sleepmind_v1");
-                return;
-            }
-            _ => {}
+    let (command, args) = Command::split(&args);
+    let result = match command {
+        Command::Train => return train(&args),
+        Command::Convert => {
+            let options = options(&args, convert::USAGE, ConvertOptions::from_args);
+            convert::run(&options).map(|summary| println!("{} into {}", summary, options.output))
         }
-        i += 1;
-    }
-    
-    println!("=== SleepMind NNUE Trainer ===");
-    println!("Dataset:       {}", dataset_path);
-    println!("Superbatches:  {} (starting from {})", superbatches, start_superbatch);
-    println!("Network ID:    {}", net_id);
-    println!("Threads:       {}", threads);
-    if let Some(ref path) = load_weights {
-        println!("Loading weights: {}", path);
-    }
-    println!();
-
-    // hyperparams
-    let hl_size = 768;
-    let initial_lr = 0.001;
-    let final_lr = 0.001 * 0.3f32.powi(5);
-    let wdl_proportion = 0.00;
-    const NUM_OUTPUT_BUCKETS: usize = 8;
-    #[rustfmt::skip]
-    const BUCKET_LAYOUT: [usize; 32] = [
-        0, 1, 2, 3,
-        4, 4, 5, 5,
-        6, 6, 6, 6,
-        7, 7, 7, 7,
-        8, 8, 8, 8,
-        8, 8, 8, 8,
-        9, 9, 9, 9,
-        9, 9, 9, 9,
-    ];
-
-    const NUM_INPUT_BUCKETS: usize = get_num_buckets(&BUCKET_LAYOUT);
-
-    let mut trainer = ValueTrainerBuilder::default()
-        .dual_perspective()
-        .optimiser(AdamW)
-        .inputs(ChessBucketsMirrored::new(BUCKET_LAYOUT))
-        .output_buckets(MaterialCount::<NUM_OUTPUT_BUCKETS>)
-        .save_format(&[
-            // merge in the factoriser weights
-            SavedFormat::id("l0w")
-                .transform(|store, weights| {
-                    let factoriser = store.get("l0f").values.repeat(NUM_INPUT_BUCKETS);
-                    weights.into_iter().zip(factoriser).map(|(a, b)| a + b).collect()
-                })
-                .round()
-                .quantise::<i16>(255),
-            SavedFormat::id("l0b").round().quantise::<i16>(255),
-            SavedFormat::id("l1w").round().quantise::<i16>(64).transpose(),
-            SavedFormat::id("l1b").round().quantise::<i16>(255 * 64),
-        ])
-        .loss_fn(|output, target| output.sigmoid().squared_error(target))
-        .build(|builder, stm_inputs, ntm_inputs, output_buckets| {
-            // input layer factoriser
-            let l0f = builder.new_weights("l0f", Shape::new(hl_size, 768), InitSettings::Zeroed);
-            let expanded_factoriser = l0f.repeat(NUM_INPUT_BUCKETS);
-
-            // input layer weights
-            let mut l0 = builder.new_affine("l0", 768 * NUM_INPUT_BUCKETS, hl_size);
-            l0.weights = l0.weights + expanded_factoriser;
-
-            // output layer weights
-            let l1 = builder.new_affine("l1", 2 * hl_size, NUM_OUTPUT_BUCKETS);
-
-            // inference
-            let stm_hidden = l0.forward(stm_inputs).screlu();
-            let ntm_hidden = l0.forward(ntm_inputs).screlu();
-            let hidden_layer = stm_hidden.concat(ntm_hidden);
-            l1.forward(hidden_layer).select(output_buckets)
-        });
-
-    // Load weights if specified
-    if let Some(ref path) = load_weights {
-        println!("Loading weights from: {}", path);
-        trainer.optimiser.load_weights_from_file(path).expect("Failed to load weights");
+        Command::Shuffle => {
+            let options = options(&args, shuffle::USAGE, ShuffleOptions::from_args);
+            shuffle::run(&options).map(|records| println!("Shuffled {} records into {}", records, options.output))
+        }
+        Command::Datagen => {
+            let options = options(&args, datagen::USAGE, DatagenOptions::from_args);
+            datagen::run(&options).map(|positions| println!("Appended {} positions to {}", positions, options.output))
+        }
+        Command::Inspect => inspect::run(&options(&args, inspect::USAGE, InspectOptions::from_args)),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
     }
+}
 
-    // need to account for factoriser weight magnitudes
-    let stricter_clipping = AdamWParams { max_weight: 0.99, min_weight: -0.99, ..Default::default() };
-    trainer.optimiser.set_params_for_weight("l0w", stricter_clipping);
-    trainer.optimiser.set_params_for_weight("l0f", stricter_clipping);
-
-    // 317690799
-
-    let schedule = TrainingSchedule {
-        net_id,
-        eval_scale: 400.0,
-        steps: TrainingSteps {
-            batch_size: 16_384,
-            batches_per_superbatch: 6104,
-            start_superbatch,
-            end_superbatch: superbatches,
-        },
-        wdl_scheduler: wdl::ConstantWDL { value: wdl_proportion },
-        lr_scheduler: lr::CosineDecayLR { initial_lr, final_lr, final_superbatch: superbatches },
-        save_rate,
+fn train(args: &[String]) {
+    let config = match Config::from_args(args) {
+        Ok(config) => config,
+        Err(ConfigError::HelpRequested) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Run with --help for usage.");
+            process::exit(1);
+        }
     };
 
-    let settings = LocalSettings { threads, test_set: None, output_directory: "checkpoints", batch_queue_size: 32 };
+    logging::set_level(config.log_level);
+    logging::set_strict(config.strict);
+    print_config(&config);
 
-    let dataloader = DirectSequentialDataLoader::new(&[&dataset_path]);
+    if let Err(e) = training::run(&config) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
 
-    trainer.run(&schedule, &settings, &dataloader);
+/// A data tool's options, or its usage and an exit on `--help` or a bad flag.
+fn options<T>(args: &[String], usage: &str, parse: fn(&[String]) -> Result<T, ConfigError>) -> T {
+    match parse(args) {
+        Ok(options) => options,
+        Err(ConfigError::HelpRequested) => {
+            println!("{}", usage);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Run with --help for usage.");
+            process::exit(1);
+        }
+    }
+}
+
+fn print_config(config: &Config) {
+    info!("=== SleepMind NNUE Trainer ===");
+    if config.deterministic {
+        notice!("--deterministic: 1 thread and no batch prefetching, this run will be SLOW (debugging only)");
+        if config.load_weights.is_none() {
+            warn!("random weight init is not seeded; --load a fixed starting net for identical reruns");
+        }
+    }
+    match config.data_format {
+        DataFormat::Direct => info!("Dataset:       {}", config.dataset_path),
+        DataFormat::Binpack => info!("Dataset:       {} (binpack)", config.dataset_path),
+    }
+    if let Some(ref path) = config.holdout_file {
+        info!("Holdout:       {} (scored once, on the final net)", path);
+    }
+    if !config.compare_quant_scales.is_empty() {
+        let scales: Vec<String> = config.compare_quant_scales.iter().map(|qa| qa.to_string()).collect();
+        info!("Quant scales:  qa {} (compared on the final net)", scales.join(", "));
+    }
+    info!("Superbatches:  {} (starting from {})", config.superbatches, config.start_superbatch);
+    let positions = config.batch_size * config.batches_per_superbatch;
+    if config.superbatch_equals_epoch {
+        info!("Superbatch:    one epoch, sized once the data is counted");
+    } else {
+        info!(
+            "Superbatch:    {} batches x {} = {} positions",
+            config.batches_per_superbatch, config.batch_size, positions
+        );
+    }
+    if let Some(requested) = config.requested_positions_per_superbatch {
+        if requested != positions {
+            info!("               (requested {}, rounded to a whole number of batches)", requested);
+        }
+    }
+    info!("Network ID:    {}", config.net_id);
+    if config.run_name != config.net_id {
+        info!("Run name:      {}", config.run_name);
+    }
+    info!("Output:        {}", config.output_directory);
+    info!("Threads:       {}", config.threads);
+    if config.final_only_save {
+        info!("Saves:         final net only");
+    } else {
+        info!("Saves:         every {} superbatches", config.save_rate);
+    }
+    if let Some(chunk) = config.chunk_superbatches {
+        info!("Chunks:        verified every {} superbatches, resumed after a preemption", chunk);
+    }
+    info!("Perspective:   {}", if config.single_perspective { "single (stm only)" } else { "dual" });
+    if config.output_factoriser {
+        info!("Factorisers:   input (l0f) and output (l1f)");
+    }
+    if let Some(growth) = config.progressive_hl {
+        info!(
+            "Hidden layer:  {} growing to {} after superbatch {} (this run trains at {})",
+            growth.start,
+            growth.end,
+            growth.at,
+            config.hl_size()
+        );
+    }
+    info!("LR:            {} -> {}", config.initial_lr, config.final_lr);
+    if let Some(l1_lr) = config.l1_lr {
+        info!("L1 LR:         {} -> {}", l1_lr, config.final_lr * l1_lr / config.initial_lr);
+    }
+    if let Some((opening, endgame)) = config.wdl_by_phase {
+        info!("WDL by phase:  {} (opening) -> {} (endgame)", opening, endgame);
+    } else {
+        info!("Target:        {} (WDL proportion {})", config.target_from, config.target_wdl_proportion());
+    }
+    if let Some(scale) = config.loss_target_scale {
+        info!("Loss scale:    sigmoid(cp / {}), the net output keeps the eval scale", scale);
+    }
+    if let Some(clip) = config.loss_clip {
+        info!("Loss clip:     {} per position", clip);
+    }
+    if config.wdl_smooth > 0.0 {
+        info!("WDL smoothing: {} (game result only)", config.wdl_smooth);
+    }
+    if let Some(sigma) = config.target_noise {
+        match config.resumeable_seed {
+            Some(base) => info!("Target noise:  N(0, {} cp) on training scores, seed {} xor superbatch", sigma, base),
+            None => info!("Target noise:  N(0, {} cp) on training scores, seed {}", sigma, config.target_noise_seed),
+        }
+    }
+    if let Some(max) = config.filter_eval_max {
+        info!("Filter:        |eval| <= {} cp", max);
+    }
+    if config.filter_no_check {
+        info!("Filter:        side to move not in check");
+    }
+    if !config.holdout_buckets.is_empty() {
+        info!("Filter:        holding out output buckets {:?}", config.holdout_buckets);
+    }
+    if let Some(decay) = config.ema {
+        info!("EMA:           decay {}{}", decay, if config.export_ema { ", exporting quantised-ema.bin" } else { "" });
+    }
+    if let Some(target) = config.stop_at_loss {
+        info!("Stop at loss:  {} (smoothed, sampled)", target);
+    }
+    if let Some(plateau) = config.reduce_on_plateau {
+        info!(
+            "On plateau:    lr x {} after {} superbatches without a {} improvement",
+            plateau.factor, plateau.patience, plateau.min_delta
+        );
+    }
+    if config.profile {
+        info!("Profile:       loader time breakdown every report interval");
+    }
+    if let Some(ref path) = config.load_weights {
+        info!("Loading weights: {}", path);
+    }
+    if !config.init_from_average.is_empty() {
+        info!("Init from:     mean of {}", config.init_from_average.join(", "));
+    }
+    if config.finetune {
+        if config.finetune_defaults.is_empty() {
+            info!("Finetune:      on (all defaults overridden)");
+        } else {
+            info!("Finetune:      on, defaults in effect: {}", config.finetune_defaults.join(", "));
+        }
+    }
+    info!("");
 }
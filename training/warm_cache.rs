@@ -0,0 +1,100 @@
+//! `--warm-cache`: read the data file once before training so the first
+//! superbatches do not run at cold-storage speed.
+//!
+//! Whether a file is already in the page cache cannot be asked portably, so a
+//! few reads spread through the file are timed first; if they come back at
+//! memory speed the full pass is skipped. A false "cold" only costs one extra
+//! sequential read.
+
+use std::{
+    fs,
+    io::{Read, Seek, SeekFrom},
+    time::{Duration, Instant},
+};
+
+use crate::data::DataError;
+
+/// Size of one read, both for the probe and the full pass.
+pub const CHUNK_BYTES: usize = 4 << 20;
+/// Reads spread through the file to guess whether it is cached.
+pub const PROBE_READS: u64 = 8;
+/// Probe throughput taken to mean the page cache answered, not the disk.
+pub const CACHED_BYTES_PER_SEC: f64 = 4e9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Warmed {
+    /// Bytes read by the full pass; 0 when it was skipped.
+    pub bytes_read: u64,
+    pub already_cached: bool,
+    pub elapsed: Duration,
+}
+
+/// Reads `path` sequentially to the end, calling `progress(read, total)`
+/// after each chunk, unless the probe finds it cached already.
+pub fn warm(path: &str, mut progress: impl FnMut(u64, u64)) -> Result<Warmed, DataError> {
+    let io_error = |error| DataError::Io { path: path.to_string(), error };
+    let start = Instant::now();
+    let mut file = fs::File::open(path).map_err(io_error)?;
+    let total = file.metadata().map_err(io_error)?.len();
+    let mut buf = vec![0u8; CHUNK_BYTES];
+
+    if probe_bytes_per_sec(&mut file, total, &mut buf).map_err(io_error)? >= CACHED_BYTES_PER_SEC {
+        return Ok(Warmed { bytes_read: 0, already_cached: true, elapsed: start.elapsed() });
+    }
+
+    file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    let mut bytes_read = 0;
+    loop {
+        let n = file.read(&mut buf).map_err(io_error)?;
+        if n == 0 {
+            break;
+        }
+        bytes_read += n as u64;
+        progress(bytes_read, total);
+    }
+    Ok(Warmed { bytes_read, already_cached: false, elapsed: start.elapsed() })
+}
+
+/// Throughput of [`PROBE_READS`] chunk reads spread evenly through the file.
+/// Files no bigger than the probe count as cold, reading them is cheap anyway.
+fn probe_bytes_per_sec(file: &mut fs::File, total: u64, buf: &mut [u8]) -> std::io::Result<f64> {
+    let chunk = buf.len() as u64;
+    if total <= PROBE_READS * chunk {
+        return Ok(0.0);
+    }
+    let start = Instant::now();
+    let mut read = 0;
+    for i in 0..PROBE_READS {
+        file.seek(SeekFrom::Start(i * (total - chunk) / (PROBE_READS - 1)))?;
+        file.read_exact(buf)?;
+        read += chunk;
+    }
+    Ok(read as f64 / start.elapsed().as_secs_f64().max(1e-9))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn reads_the_whole_file_once() {
+        // below the probe size, so it always counts as cold
+        let path = temp_path("warm-cache.data");
+        let len = CHUNK_BYTES as u64 + 1000;
+        fs::write(&path, vec![7u8; len as usize]).unwrap();
+        let mut progress = Vec::new();
+        let warmed = warm(path.to_str().unwrap(), |read, total| progress.push((read, total))).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((warmed.bytes_read, warmed.already_cached), (len, false));
+        assert_eq!(progress.last(), Some(&(len, len)));
+        assert!(progress.windows(2).all(|w| w[0].0 < w[1].0), "{:?}", progress);
+    }
+
+    #[test]
+    fn a_missing_file_is_an_io_error() {
+        let error = warm("does-not-exist.data", |_, _| {}).unwrap_err();
+        assert!(matches!(error, DataError::Io { ref path, .. } if path == "does-not-exist.data"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Summary statistics over flat weight tensors.
+
+use crate::net::FloatNet;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub mean_abs: f32,
+}
+
+pub fn stats(values: &[f32]) -> WeightStats {
+    let mut min = f32::INFINITY;
+    let mut max = f32::NEG_INFINITY;
+    let mut sum = 0.0f64;
+    let mut sum_abs = 0.0f64;
+    for &v in values {
+        min = min.min(v);
+        max = max.max(v);
+        sum += f64::from(v);
+        sum_abs += f64::from(v.abs());
+    }
+    let n = values.len().max(1) as f64;
+    WeightStats { min, max, mean: (sum / n) as f32, mean_abs: (sum_abs / n) as f32 }
+}
+
+/// First NaN or infinite value, as `(index, value)`.
+pub fn find_non_finite(values: &[f32]) -> Option<(usize, f32)> {
+    values.iter().copied().enumerate().find(|(_, v)| !v.is_finite())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn bin_width(&self) -> f32 {
+        (self.max - self.min) / self.counts.len() as f32
+    }
+}
+
+/// Equal-width histogram over `[min, max]` of the values; the max lands in the
+/// last bin. Non-finite values are skipped.
+pub fn histogram(values: &[f32], bins: usize) -> Histogram {
+    let finite = || values.iter().copied().filter(|v| v.is_finite());
+    let min = finite().fold(f32::INFINITY, f32::min);
+    let max = finite().fold(f32::NEG_INFINITY, f32::max);
+    let bins = bins.max(1);
+    let mut counts = vec![0; bins];
+    if min > max {
+        return Histogram { min: 0.0, max: 0.0, counts };
+    }
+
+    let width = (max - min) / bins as f32;
+    for v in finite() {
+        let bin = if width > 0.0 { ((v - min) / width) as usize } else { 0 };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    Histogram { min, max, counts }
+}
+
+/// Input features whose merged weights (`l0w` plus the factoriser `l0f`)
+/// are all within `threshold` of zero. Both tensors are column-major with one
+/// `hl_size` column per feature; feature `i` reads factoriser column `i % 768`.
+pub fn near_zero_features(l0w: &[f32], l0f: &[f32], hl_size: usize, threshold: f32) -> Vec<usize> {
+    l0w.chunks(hl_size)
+        .enumerate()
+        .filter(|(feature, column)| {
+            let factoriser = &l0f[(feature % 768) * hl_size..][..hl_size];
+            column.iter().zip(factoriser).all(|(w, f)| (w + f).abs() < threshold)
+        })
+        .map(|(feature, _)| feature)
+        .collect()
+}
+
+/// `tensor,bin_start,bin_end,count` rows, with a header.
+pub fn histogram_csv(histograms: &[(&str, Histogram)]) -> String {
+    let mut out = String::from("tensor,bin_start,bin_end,count\n");
+    for (id, h) in histograms {
+        for (i, count) in h.counts.iter().enumerate() {
+            let start = h.min + i as f32 * h.bin_width();
+            out.push_str(&format!("{},{},{},{}\n", id, start, start + h.bin_width(), count));
+        }
+    }
+    out
+}
+
+/// Per-tensor difference between two nets, see [`diff`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorDiff {
+    pub id: &'static str,
+    pub mean_abs: f32,
+    pub max_abs: f32,
+    /// Euclidean norm of the difference.
+    pub l2: f64,
+}
+
+/// `b - a` for every tensor the nets share, requiring the same lengths.
+pub fn diff(a: &FloatNet, b: &FloatNet) -> Result<Vec<TensorDiff>, String> {
+    a.tensor_ids()
+        .into_iter()
+        .map(|id| {
+            let (x, y) = (a.get(id).unwrap(), b.get(id).ok_or_else(|| format!("{} is missing", id))?);
+            if x.len() != y.len() {
+                return Err(format!("{} has {} values in the first net and {} in the second", id, x.len(), y.len()));
+            }
+            let (mut sum_abs, mut max_abs, mut sum_sq) = (0.0f64, 0.0f32, 0.0f64);
+            for (&x, &y) in x.iter().zip(y) {
+                let d = (y - x).abs();
+                sum_abs += f64::from(d);
+                max_abs = max_abs.max(d);
+                sum_sq += f64::from(d) * f64::from(d);
+            }
+            Ok(TensorDiff { id, mean_abs: (sum_abs / x.len().max(1) as f64) as f32, max_abs, l2: sum_sq.sqrt() })
+        })
+        .collect()
+}
+
+/// The [`diff`] table with a line for the L2 distance over all tensors.
+pub fn diff_table(diffs: &[TensorDiff]) -> String {
+    let mut out = format!("{:<6} {:>12} {:>12} {:>12}\n", "tensor", "mean |d|", "max |d|", "L2");
+    for d in diffs {
+        out.push_str(&format!("{:<6} {:>12.6} {:>12.6} {:>12.6}\n", d.id, d.mean_abs, d.max_abs, d.l2));
+    }
+    let total = diffs.iter().map(|d| d.l2 * d.l2).sum::<f64>().sqrt();
+    out.push_str(&format!("overall L2 distance: {:.6}\n", total));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_first_nan_or_infinity() {
+        assert_eq!(find_non_finite(&[0.5, -1.0, 0.0]), None);
+        assert_eq!(find_non_finite(&[0.5, f32::INFINITY, f32::NAN]), Some((1, f32::INFINITY)));
+        let (index, value) = find_non_finite(&[0.5, 1.0, f32::NAN]).unwrap();
+        assert_eq!(index, 2);
+        assert!(value.is_nan());
+    }
+
+    #[test]
+    fn histogram_bins_equal_widths_with_the_max_in_the_last_bin() {
+        let h = histogram(&[0.0, 0.1, 0.3, 0.5, 0.6, 0.9, 1.0, f32::NAN], 4);
+        assert_eq!((h.min, h.max), (0.0, 1.0));
+        assert_eq!(h.counts, vec![2, 1, 2, 2]);
+        assert!((h.bin_width() - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn histogram_of_a_constant_or_empty_tensor() {
+        assert_eq!(histogram(&[0.5; 3], 4).counts, vec![3, 0, 0, 0]);
+        assert_eq!(histogram(&[], 2), Histogram { min: 0.0, max: 0.0, counts: vec![0, 0] });
+    }
+
+    #[test]
+    fn histogram_csv_has_a_row_per_bin() {
+        let csv = histogram_csv(&[("l1b", histogram(&[0.0, 1.0], 2))]);
+        assert_eq!(csv, "tensor,bin_start,bin_end,count\nl1b,0,0.5,1\nl1b,0.5,1,1\n");
+    }
+
+    #[test]
+    fn near_zero_columns_count_the_merged_factoriser() {
+        let hl_size = 2;
+        // 2 buckets of 768 features; feature i reads factoriser column i % 768
+        let mut l0w = vec![0.5; 2 * 768 * hl_size];
+        let l0f = vec![0.0; 768 * hl_size];
+        l0w[..hl_size].copy_from_slice(&[0.001, -0.001]);
+        l0w[768 * hl_size + 5 * hl_size..][..hl_size].copy_from_slice(&[0.0, 0.0]);
+        assert_eq!(near_zero_features(&l0w, &l0f, hl_size, 0.01), vec![0, 768 + 5]);
+
+        // the factoriser cancels feature 3 in both buckets, the others stay
+        let mut l0f = l0f;
+        l0f[3 * hl_size..][..hl_size].copy_from_slice(&[-0.5, -0.5]);
+        assert_eq!(near_zero_features(&l0w, &l0f, hl_size, 0.01), vec![0, 3, 768 + 3, 768 + 5]);
+    }
+
+    #[test]
+    fn one_large_weight_keeps_a_column() {
+        let (l0w, l0f) = (vec![0.0, 0.02, 0.0, 0.0], vec![0.0; 768 * 2]);
+        assert_eq!(near_zero_features(&l0w, &l0f, 2, 0.01), vec![1]);
+    }
+
+    /// The tensors of a dual-perspective hl-2 net with one input and two
+    /// output buckets, all `value`.
+    fn tiny_net(value: f32) -> FloatNet {
+        FloatNet {
+            l0w: vec![value; 768 * 2],
+            l0f: vec![value; 768 * 2],
+            l0b: vec![value; 2],
+            l1w: vec![value; 8],
+            l1b: vec![value; 2],
+            l1f: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn diffs_each_tensor_of_two_nets() {
+        let a = tiny_net(0.25);
+        let mut b = a.clone();
+        b.l1w[3] += 0.5;
+        b.l0b = vec![0.15; 2];
+        let diffs = diff(&a, &b).unwrap();
+        assert_eq!(diffs.iter().map(|d| d.id).collect::<Vec<_>>(), ["l0w", "l0f", "l0b", "l1w", "l1b"]);
+        let by_id = |id| diffs.iter().find(|d| d.id == id).unwrap();
+        assert_eq!((by_id("l1w").mean_abs, by_id("l1w").max_abs, by_id("l1w").l2), (0.0625, 0.5, 0.5));
+        assert!((by_id("l0b").mean_abs - 0.1).abs() < 1e-6 && (by_id("l0b").l2 - 0.02f64.sqrt()).abs() < 1e-6);
+        assert!(["l0w", "l0f", "l1b"].iter().all(|&id| by_id(id).l2 == 0.0));
+
+        let table = diff_table(&diffs);
+        assert!(table.contains("l1w        0.062500     0.500000     0.500000\n"), "{}", table);
+        assert!(table.ends_with("overall L2 distance: 0.519615\n"), "{}", table);
+    }
+
+    #[test]
+    fn a_net_diffs_to_zero_with_itself() {
+        let diffs = diff(&tiny_net(0.3), &tiny_net(0.3)).unwrap();
+        assert!(diffs.iter().all(|d| d == &TensorDiff { id: d.id, mean_abs: 0.0, max_abs: 0.0, l2: 0.0 }));
+    }
+
+    #[test]
+    fn nets_of_different_shapes_do_not_diff() {
+        let mut wider = tiny_net(0.0);
+        wider.l0b.push(0.0);
+        assert_eq!(
+            diff(&tiny_net(0.0), &wider),
+            Err("l0b has 2 values in the first net and 3 in the second".to_string())
+        );
+    }
+}